@@ -53,6 +53,12 @@ class Node {
 
   bool get isOnline => status == 'online';
 
+  // 连接异常中断或长时间无心跳，需要关注
+  bool get isUnreachable => status == 'unreachable';
+
+  // 节点关闭时主动注销，不需要告警
+  bool get isDecommissioned => status == 'decommissioned';
+
   String get lastHeartbeatFormatted {
     if (lastHeartbeat == null) return '从未心跳';
     final now = DateTime.now();
@@ -172,7 +172,11 @@ class _NodeDetailPageState extends State<NodeDetailPage> {
                         width: 12,
                         height: 12,
                         decoration: BoxDecoration(
-                          color: _node!.isOnline ? Colors.green : Colors.grey,
+                          color: _node!.isOnline
+                              ? Colors.green
+                              : _node!.isUnreachable
+                                  ? Colors.red
+                                  : Colors.grey,
                           shape: BoxShape.circle,
                         ),
                       ),
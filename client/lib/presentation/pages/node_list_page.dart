@@ -236,7 +236,7 @@ class _NodeListPageState extends State<NodeListPage> {
     return Card(
       margin: const EdgeInsets.symmetric(horizontal: 16, vertical: 8),
       child: ListTile(
-        leading: _buildStatusIndicator(node.isOnline),
+        leading: _buildStatusIndicator(node),
         title: Text(
           node.hostname,
           style: const TextStyle(fontWeight: FontWeight.bold),
@@ -267,12 +267,18 @@ class _NodeListPageState extends State<NodeListPage> {
     );
   }
 
-  Widget _buildStatusIndicator(bool isOnline) {
+  Widget _buildStatusIndicator(Node node) {
+    // 失联节点标红，正常离线和已注销的节点显示为灰色
+    final color = node.isOnline
+        ? Colors.green
+        : node.isUnreachable
+            ? Colors.red
+            : Colors.grey;
     return Container(
       width: 12,
       height: 12,
       decoration: BoxDecoration(
-        color: isOnline ? Colors.green : Colors.grey,
+        color: color,
         shape: BoxShape.circle,
       ),
     );
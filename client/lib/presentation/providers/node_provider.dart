@@ -126,8 +126,11 @@ class NodeProvider with ChangeNotifier {
   // 获取在线节点数量
   int get onlineCount => _nodes.where((node) => node.isOnline).length;
 
-  // 获取离线节点数量
-  int get offlineCount => _nodes.where((node) => !node.isOnline).length;
+  // 获取离线节点数量（不含已注销的节点）
+  int get offlineCount => _nodes.where((node) => !node.isOnline && !node.isDecommissioned).length;
+
+  // 获取失联节点数量
+  int get unreachableCount => _nodes.where((node) => node.isUnreachable).length;
 
   // 根据状态过滤节点
   List<Node> getNodesByStatus(String status) {
@@ -0,0 +1,39 @@
+//! 构建脚本：将git提交和构建时间写入编译期环境变量`SM_GIT_SHA`、`SM_BUILD_TIME`
+//!
+//! core和node共用，`--version`和启动日志通过`env!`读取
+
+use std::process::Command;
+
+fn main() {
+    let git = |args: &[&str]| {
+        Command::new("git")
+            .args(args)
+            .output()
+            .ok()
+            .filter(|output| output.status.success())
+            .and_then(|output| String::from_utf8(output.stdout).ok())
+            .map(|value| value.trim().to_string())
+            .filter(|value| !value.is_empty())
+    };
+
+    let git_sha = git(&["rev-parse", "--short=12", "HEAD"]).unwrap_or_else(|| "unknown".to_string());
+    println!("cargo:rustc-env=SM_GIT_SHA={}", git_sha);
+
+    // 提交变化时重新生成（分支切换修改HEAD，新提交修改分支引用）
+    if let Some(git_dir) = git(&["rev-parse", "--absolute-git-dir"]) {
+        println!("cargo:rerun-if-changed={}/HEAD", git_dir);
+        if let Some(head_ref) = git(&["symbolic-ref", "-q", "HEAD"]) {
+            println!("cargo:rerun-if-changed={}/{}", git_dir, head_ref);
+        }
+        println!("cargo:rerun-if-changed={}/packed-refs", git_dir);
+    }
+
+    // 支持SOURCE_DATE_EPOCH以便可重复构建
+    println!("cargo:rerun-if-env-changed=SOURCE_DATE_EPOCH");
+    let build_time = std::env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|epoch| epoch.trim().parse::<i64>().ok())
+        .and_then(|epoch| chrono::DateTime::from_timestamp(epoch, 0))
+        .unwrap_or_else(chrono::Utc::now);
+    println!("cargo:rustc-env=SM_BUILD_TIME={}", build_time.format("%Y-%m-%dT%H:%M:%SZ"));
+}
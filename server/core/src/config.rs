@@ -0,0 +1,472 @@
+use std::fmt;
+use std::net::IpAddr;
+use std::str::FromStr;
+
+use tracing::warn;
+
+/// Core服务配置（从环境变量读取）
+#[derive(Debug, Clone)]
+pub struct CoreConfig {
+    /// HTTP/WebSocket监听地址
+    pub bind_address: String,
+    /// 全局共享令牌，用于监控客户端以及未签发独立令牌的节点
+    pub shared_token: String,
+    /// 是否允许尚未签发独立令牌的节点使用全局共享令牌连接
+    pub allow_shared_node_token: bool,
+    /// 允许节点连接的来源网段，未配置时不限制；配置后不在列表中的地址一律拒绝
+    pub node_allowed_networks: Option<Vec<IpNetwork>>,
+    /// 允许建立监控连接的浏览器来源（`Origin`请求头），未配置时只允许与请求`Host`相同的来源，`*`表示不限制
+    pub monitor_allowed_origins: Option<Vec<String>>,
+    /// 是否信任反向代理设置的`X-Real-IP`/`X-Forwarded-For`请求头来确定客户端地址
+    pub trust_proxy: bool,
+    /// REST API JWT签名密钥（未配置时启动时随机生成，重启后已签发的令牌失效）
+    pub jwt_secret: String,
+    /// JWT有效期（秒）
+    pub jwt_expiry_secs: i64,
+    /// 管理员用户名
+    pub admin_username: String,
+    /// 管理员密码（未配置时启动时随机生成并输出到标准错误）
+    pub admin_password: String,
+    /// 后台清理任务执行间隔（秒）
+    pub cleanup_interval_secs: u64,
+    /// 超过该时间（分钟）没有心跳的节点视为离线
+    pub node_stale_minutes: i64,
+    /// 监控数据保留天数
+    pub metrics_retention_days: i64,
+    /// 命令记录保留天数
+    pub command_retention_days: i64,
+    /// 事件日志保留天数
+    pub event_retention_days: i64,
+    /// Prometheus抓取令牌（未配置时指标导出端点无需认证）
+    pub metrics_token: Option<String>,
+    /// 告警通知Webhook地址（未配置时只记录日志）
+    pub alert_webhook_url: Option<String>,
+    /// 告警恢复前指标需持续正常的最短时间（秒），避免指标抖动时反复通知
+    pub alert_resolve_secs: i64,
+    /// 指标偏离近期基线超过多少倍标准差时判定为异常，0表示关闭异常检测
+    pub anomaly_sigma: f64,
+    /// 异常检测基线的时间窗口（秒），更早的样本权重按指数衰减
+    pub anomaly_window_secs: i64,
+    /// 向节点发送WebSocket ping的间隔（秒）
+    pub node_ping_interval_secs: u64,
+    /// 超过该时间（秒）未收到节点的pong响应则断开连接
+    pub node_pong_timeout_secs: u64,
+    /// 节点连接中断后等待其携带会话恢复令牌重连的时间（秒），期间不标记离线，0表示立即处理
+    pub session_resume_grace_secs: u64,
+    /// 单条WebSocket消息（含解压后）的最大字节数
+    pub max_message_bytes: usize,
+    /// 每个节点连接每秒允许的消息数
+    pub node_rate_limit_per_sec: u32,
+    /// 每个节点连接允许的突发消息数
+    pub node_rate_limit_burst: u32,
+    /// 监控客户端广播通道可缓冲的消息数，客户端落后超过该数量时重新发送完整快照
+    pub client_broadcast_capacity: usize,
+    /// 数据库连接池最大连接数
+    pub db_max_connections: u32,
+    /// 数据库连接池保持的最小连接数
+    pub db_min_connections: u32,
+    /// 从连接池获取连接的超时时间（秒）
+    pub db_acquire_timeout_secs: u64,
+    /// 空闲连接的回收时间（秒），0表示不回收
+    pub db_idle_timeout_secs: u64,
+    /// SQLite数据库被锁定时的等待时间（秒）
+    pub db_busy_timeout_secs: u64,
+    /// 定期数据库维护（增量回收空闲空间、更新统计信息、截断WAL日志）的间隔（秒），0表示不执行
+    pub db_maintenance_interval_secs: u64,
+}
+
+impl CoreConfig {
+    /// 从环境变量加载配置，未设置时使用默认值
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+        Self {
+            bind_address: env_string("SM_CORE_BIND")
+                .map(|value| value.trim().to_string())
+                .unwrap_or(defaults.bind_address),
+            shared_token: env_string("SM_CORE_SHARED_TOKEN").unwrap_or(defaults.shared_token),
+            allow_shared_node_token: env_bool("SM_CORE_ALLOW_SHARED_NODE_TOKEN")
+                .unwrap_or(defaults.allow_shared_node_token),
+            node_allowed_networks: env_string("SM_CORE_NODE_ALLOWED_CIDRS").map(|value| parse_networks(&value)),
+            monitor_allowed_origins: env_string("SM_CORE_MONITOR_ALLOWED_ORIGINS").map(|value| parse_origins(&value)),
+            trust_proxy: env_bool("SM_CORE_TRUST_PROXY").unwrap_or(defaults.trust_proxy),
+            jwt_secret: env_string("SM_CORE_JWT_SECRET").unwrap_or_else(|| {
+                warn!("⚠️ 未配置SM_CORE_JWT_SECRET，使用随机密钥，重启后需重新登录");
+                defaults.jwt_secret
+            }),
+            jwt_expiry_secs: env_i64("SM_CORE_JWT_EXPIRY_SECS")
+                .filter(|secs| *secs > 0)
+                .unwrap_or(defaults.jwt_expiry_secs),
+            admin_username: env_string("SM_CORE_ADMIN_USERNAME").unwrap_or(defaults.admin_username),
+            admin_password: env_string("SM_CORE_ADMIN_PASSWORD").unwrap_or_else(|| {
+                // 密码只输出一次到标准错误，不写入日志，避免随日志收集系统扩散
+                warn!("⚠️ 未配置SM_CORE_ADMIN_PASSWORD，已生成临时管理员密码，见标准错误输出");
+                eprintln!("临时管理员密码: {}", defaults.admin_password);
+                defaults.admin_password
+            }),
+            cleanup_interval_secs: env_i64("SM_CORE_CLEANUP_INTERVAL_SECS")
+                .filter(|secs| *secs > 0)
+                .map(|secs| secs as u64)
+                .unwrap_or(defaults.cleanup_interval_secs),
+            node_stale_minutes: env_i64("SM_CORE_NODE_STALE_MINUTES")
+                .filter(|minutes| *minutes > 0)
+                .unwrap_or(defaults.node_stale_minutes),
+            metrics_retention_days: env_i64("SM_CORE_METRICS_RETENTION_DAYS")
+                .filter(|days| *days > 0)
+                .unwrap_or(defaults.metrics_retention_days),
+            command_retention_days: env_i64("SM_CORE_COMMAND_RETENTION_DAYS")
+                .filter(|days| *days > 0)
+                .unwrap_or(defaults.command_retention_days),
+            event_retention_days: env_i64("SM_CORE_EVENT_RETENTION_DAYS")
+                .filter(|days| *days > 0)
+                .unwrap_or(defaults.event_retention_days),
+            metrics_token: env_string("SM_CORE_METRICS_TOKEN"),
+            alert_webhook_url: env_string("SM_CORE_ALERT_WEBHOOK_URL"),
+            alert_resolve_secs: env_i64("SM_CORE_ALERT_RESOLVE_SECS")
+                .filter(|secs| *secs >= 0)
+                .unwrap_or(defaults.alert_resolve_secs),
+            anomaly_sigma: env_f64("SM_CORE_ANOMALY_SIGMA")
+                .filter(|sigma| *sigma >= 0.0)
+                .unwrap_or(defaults.anomaly_sigma),
+            anomaly_window_secs: env_i64("SM_CORE_ANOMALY_WINDOW_SECS")
+                .filter(|secs| *secs > 0)
+                .unwrap_or(defaults.anomaly_window_secs),
+            node_ping_interval_secs: env_i64("SM_CORE_NODE_PING_INTERVAL_SECS")
+                .filter(|secs| *secs > 0)
+                .map(|secs| secs as u64)
+                .unwrap_or(defaults.node_ping_interval_secs),
+            node_pong_timeout_secs: env_i64("SM_CORE_NODE_PONG_TIMEOUT_SECS")
+                .filter(|secs| *secs > 0)
+                .map(|secs| secs as u64)
+                .unwrap_or(defaults.node_pong_timeout_secs),
+            session_resume_grace_secs: env_i64("SM_CORE_SESSION_RESUME_GRACE_SECS")
+                .filter(|secs| *secs >= 0)
+                .map(|secs| secs as u64)
+                .unwrap_or(defaults.session_resume_grace_secs),
+            max_message_bytes: env_i64("SM_CORE_MAX_MESSAGE_BYTES")
+                .filter(|bytes| *bytes > 0)
+                .map(|bytes| bytes as usize)
+                .unwrap_or(defaults.max_message_bytes),
+            node_rate_limit_per_sec: env_i64("SM_CORE_NODE_RATE_LIMIT_PER_SEC")
+                .filter(|rate| *rate > 0)
+                .map(|rate| rate as u32)
+                .unwrap_or(defaults.node_rate_limit_per_sec),
+            node_rate_limit_burst: env_i64("SM_CORE_NODE_RATE_LIMIT_BURST")
+                .filter(|burst| *burst > 0)
+                .map(|burst| burst as u32)
+                .unwrap_or(defaults.node_rate_limit_burst),
+            client_broadcast_capacity: env_i64("SM_CORE_CLIENT_BROADCAST_CAPACITY")
+                .filter(|capacity| *capacity > 0)
+                .map(|capacity| capacity as usize)
+                .unwrap_or(defaults.client_broadcast_capacity),
+            db_max_connections: env_i64("SM_CORE_DB_MAX_CONNECTIONS")
+                .filter(|count| *count > 0)
+                .map(|count| count as u32)
+                .unwrap_or(defaults.db_max_connections),
+            db_min_connections: env_i64("SM_CORE_DB_MIN_CONNECTIONS")
+                .filter(|count| *count >= 0)
+                .map(|count| count as u32)
+                .unwrap_or(defaults.db_min_connections),
+            db_acquire_timeout_secs: env_i64("SM_CORE_DB_ACQUIRE_TIMEOUT_SECS")
+                .filter(|secs| *secs > 0)
+                .map(|secs| secs as u64)
+                .unwrap_or(defaults.db_acquire_timeout_secs),
+            db_idle_timeout_secs: env_i64("SM_CORE_DB_IDLE_TIMEOUT_SECS")
+                .filter(|secs| *secs >= 0)
+                .map(|secs| secs as u64)
+                .unwrap_or(defaults.db_idle_timeout_secs),
+            db_busy_timeout_secs: env_i64("SM_CORE_DB_BUSY_TIMEOUT_SECS")
+                .filter(|secs| *secs >= 0)
+                .map(|secs| secs as u64)
+                .unwrap_or(defaults.db_busy_timeout_secs),
+            db_maintenance_interval_secs: env_i64("SM_CORE_DB_MAINTENANCE_INTERVAL_SECS")
+                .filter(|secs| *secs >= 0)
+                .map(|secs| secs as u64)
+                .unwrap_or(defaults.db_maintenance_interval_secs),
+        }
+    }
+}
+
+impl CoreConfig {
+    /// 节点连接的来源地址是否在允许的网段内
+    pub fn is_node_address_allowed(&self, ip: IpAddr) -> bool {
+        match &self.node_allowed_networks {
+            Some(networks) => networks.iter().any(|network| network.contains(ip)),
+            None => true,
+        }
+    }
+
+    /// 监控连接的来源是否允许
+    ///
+    /// 浏览器发起的WebSocket连接总是携带`Origin`，没有该请求头的连接来自非浏览器客户端，不做限制
+    pub fn is_monitor_origin_allowed(&self, origin: Option<&str>, host: Option<&str>) -> bool {
+        let Some(origin) = origin else {
+            return true;
+        };
+        let origin = normalize_origin(origin);
+        match &self.monitor_allowed_origins {
+            Some(origins) => origins.iter().any(|allowed| allowed == "*" || *allowed == origin),
+            // 同源：Origin中的主机和端口与请求的Host一致
+            None => match (origin.split_once("://"), host) {
+                (Some((_, authority)), Some(host)) => authority == host.trim().to_ascii_lowercase(),
+                _ => false,
+            },
+        }
+    }
+}
+
+impl Default for CoreConfig {
+    fn default() -> Self {
+        Self {
+            bind_address: "0.0.0.0:20002".to_string(),
+            shared_token: "default-token".to_string(),
+            allow_shared_node_token: true,
+            node_allowed_networks: None,
+            monitor_allowed_origins: None,
+            trust_proxy: false,
+            jwt_secret: random_secret(),
+            jwt_expiry_secs: 24 * 60 * 60,
+            admin_username: "admin".to_string(),
+            admin_password: random_secret(),
+            cleanup_interval_secs: 60,
+            node_stale_minutes: 5,
+            metrics_retention_days: 30,
+            command_retention_days: 30,
+            event_retention_days: 90,
+            metrics_token: None,
+            alert_webhook_url: None,
+            alert_resolve_secs: 60,
+            anomaly_sigma: 3.0,
+            anomaly_window_secs: 60 * 60,
+            node_ping_interval_secs: 20,
+            node_pong_timeout_secs: 30,
+            session_resume_grace_secs: 15,
+            max_message_bytes: 1024 * 1024,
+            node_rate_limit_per_sec: 20,
+            node_rate_limit_burst: 100,
+            client_broadcast_capacity: 1000,
+            db_max_connections: 10,
+            db_min_connections: 0,
+            db_acquire_timeout_secs: 30,
+            db_idle_timeout_secs: 600,
+            db_busy_timeout_secs: 5,
+            db_maintenance_interval_secs: 60 * 60,
+        }
+    }
+}
+
+/// 读取非空字符串环境变量
+fn env_string(key: &str) -> Option<String> {
+    std::env::var(key).ok().filter(|value| !value.trim().is_empty())
+}
+
+/// 读取整数环境变量，无法解析的值会被忽略并记录警告
+fn env_i64(key: &str) -> Option<i64> {
+    let value = env_string(key)?;
+    match value.trim().parse() {
+        Ok(parsed) => Some(parsed),
+        Err(_) => {
+            warn!("⚠️ 无法解析环境变量 {}={}，使用默认值", key, value);
+            None
+        }
+    }
+}
+
+/// 读取浮点数环境变量，无法解析的值会被忽略并记录警告
+fn env_f64(key: &str) -> Option<f64> {
+    let value = env_string(key)?;
+    match value.trim().parse::<f64>() {
+        Ok(parsed) if parsed.is_finite() => Some(parsed),
+        _ => {
+            warn!("⚠️ 无法解析环境变量 {}={}，使用默认值", key, value);
+            None
+        }
+    }
+}
+
+/// 生成随机密钥
+fn random_secret() -> String {
+    uuid::Uuid::new_v4().simple().to_string()
+}
+
+/// 读取布尔环境变量，无法识别的值会被忽略并记录警告
+fn env_bool(key: &str) -> Option<bool> {
+    let value = env_string(key)?;
+    match value.trim().to_ascii_lowercase().as_str() {
+        "1" | "true" | "yes" | "on" => Some(true),
+        "0" | "false" | "no" | "off" => Some(false),
+        _ => {
+            warn!("⚠️ 无法解析环境变量 {}={}，使用默认值", key, value);
+            None
+        }
+    }
+}
+
+/// CIDR网段（如`10.8.0.0/24`），省略前缀长度时表示单个地址
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IpNetwork {
+    address: IpAddr,
+    prefix_len: u8,
+}
+
+impl IpNetwork {
+    /// 判断地址是否属于该网段，IPv4映射的IPv6地址按IPv4处理
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.address, ip.to_canonical()) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - u32::from(self.prefix_len)).unwrap_or(0);
+                u32::from(network) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(network), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - u32::from(self.prefix_len)).unwrap_or(0);
+                u128::from(network) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for IpNetwork {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (address, prefix_len) = match value.split_once('/') {
+            Some((address, prefix_len)) => (address, Some(prefix_len)),
+            None => (value, None),
+        };
+        let address: IpAddr = address.trim().parse().map_err(|_| format!("无效的IP地址: {}", address))?;
+        let max_prefix_len = if address.is_ipv4() { 32 } else { 128 };
+        let prefix_len = match prefix_len {
+            Some(prefix_len) => prefix_len
+                .trim()
+                .parse::<u8>()
+                .ok()
+                .filter(|len| *len <= max_prefix_len)
+                .ok_or_else(|| format!("无效的前缀长度: {}", prefix_len))?,
+            None => max_prefix_len,
+        };
+
+        Ok(Self { address, prefix_len })
+    }
+}
+
+impl fmt::Display for IpNetwork {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.address, self.prefix_len)
+    }
+}
+
+/// 解析逗号分隔的网段列表，无效项会被忽略并记录警告
+fn parse_networks(value: &str) -> Vec<IpNetwork> {
+    let networks: Vec<IpNetwork> = value
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .filter_map(|item| match item.parse() {
+            Ok(network) => Some(network),
+            Err(e) => {
+                warn!("⚠️ 忽略SM_CORE_NODE_ALLOWED_CIDRS中的无效网段 {}: {}", item, e);
+                None
+            }
+        })
+        .collect();
+
+    if networks.is_empty() {
+        warn!("⚠️ SM_CORE_NODE_ALLOWED_CIDRS中没有有效网段，所有节点连接都将被拒绝");
+    }
+    networks
+}
+
+/// 统一来源的格式（如`https://Dash.example.com/`与`https://dash.example.com`视为相同）
+fn normalize_origin(origin: &str) -> String {
+    origin.trim().trim_end_matches('/').to_ascii_lowercase()
+}
+
+/// 解析逗号分隔的来源列表
+fn parse_origins(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(normalize_origin)
+        .filter(|origin| !origin.is_empty())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(value: &str) -> IpAddr {
+        value.parse().unwrap()
+    }
+
+    #[test]
+    fn test_ip_network_contains() {
+        let vpn: IpNetwork = "10.8.0.0/24".parse().unwrap();
+        assert!(vpn.contains(ip("10.8.0.17")));
+        assert!(!vpn.contains(ip("10.8.1.17")));
+        // 双栈监听时IPv4地址以映射形式出现
+        assert!(vpn.contains(ip("::ffff:10.8.0.17")));
+        assert!(!vpn.contains(ip("fd00::1")));
+
+        let single: IpNetwork = "192.168.1.10".parse().unwrap();
+        assert_eq!(single.to_string(), "192.168.1.10/32");
+        assert!(single.contains(ip("192.168.1.10")));
+        assert!(!single.contains(ip("192.168.1.11")));
+
+        let any: IpNetwork = "0.0.0.0/0".parse().unwrap();
+        assert!(any.contains(ip("203.0.113.5")));
+
+        let v6: IpNetwork = "fd00:1::/32".parse().unwrap();
+        assert!(v6.contains(ip("fd00:1:ffff::1")));
+        assert!(!v6.contains(ip("fd00:2::1")));
+
+        assert!("10.8.0.0/33".parse::<IpNetwork>().is_err());
+        assert!("10.8.0/24".parse::<IpNetwork>().is_err());
+        assert!("fd00::/129".parse::<IpNetwork>().is_err());
+    }
+
+    #[test]
+    fn test_node_address_allowed() {
+        let open = CoreConfig::default();
+        assert!(open.is_node_address_allowed(ip("203.0.113.5")));
+
+        let restricted = CoreConfig {
+            node_allowed_networks: Some(parse_networks("10.8.0.0/24, bogus, 127.0.0.1")),
+            ..CoreConfig::default()
+        };
+        assert_eq!(restricted.node_allowed_networks.as_ref().unwrap().len(), 2);
+        assert!(restricted.is_node_address_allowed(ip("10.8.0.2")));
+        assert!(restricted.is_node_address_allowed(ip("127.0.0.1")));
+        assert!(!restricted.is_node_address_allowed(ip("203.0.113.5")));
+
+        // 配置了但没有有效网段时拒绝所有节点
+        let closed = CoreConfig {
+            node_allowed_networks: Some(parse_networks("bogus")),
+            ..CoreConfig::default()
+        };
+        assert!(!closed.is_node_address_allowed(ip("127.0.0.1")));
+    }
+
+    #[test]
+    fn test_monitor_origin_allowed() {
+        // 未配置时只允许同源的浏览器连接
+        let same_origin = CoreConfig::default();
+        assert!(same_origin.is_monitor_origin_allowed(None, Some("core.example.com:20002")));
+        assert!(same_origin.is_monitor_origin_allowed(Some("http://Core.example.com:20002"), Some("core.example.com:20002")));
+        assert!(!same_origin.is_monitor_origin_allowed(Some("https://evil.example"), Some("core.example.com:20002")));
+        assert!(!same_origin.is_monitor_origin_allowed(Some("null"), Some("core.example.com:20002")));
+
+        let restricted = CoreConfig {
+            monitor_allowed_origins: Some(parse_origins("https://dash.example.com/, http://localhost:8080")),
+            ..CoreConfig::default()
+        };
+        assert!(restricted.is_monitor_origin_allowed(Some("https://dash.example.com"), Some("core:20002")));
+        assert!(restricted.is_monitor_origin_allowed(Some("http://localhost:8080"), None));
+        assert!(!restricted.is_monitor_origin_allowed(Some("http://localhost:8081"), Some("localhost:8081")));
+
+        let open = CoreConfig {
+            monitor_allowed_origins: Some(parse_origins("*")),
+            ..CoreConfig::default()
+        };
+        assert!(open.is_monitor_origin_allowed(Some("https://anywhere.example"), None));
+    }
+}
@@ -1,23 +1,103 @@
 use anyhow::Result;
-use sqlx::{sqlite::SqliteConnectOptions, SqlitePool, Row};
+use serde::{Deserialize, Serialize};
+use sqlx::{pool::PoolOptions, sqlite::{SqliteAutoVacuum, SqliteConnectOptions}, Row};
+use std::path::PathBuf;
 use std::str::FromStr;
-use tracing::{info, error};
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+use super::migrations::{MigrationManager, LATEST_VERSION};
+use super::{Db, DbPool};
+use crate::config::CoreConfig;
 
 pub struct Database {
-    pub pool: SqlitePool,
+    pub pool: DbPool,
+    /// 同一时间只执行一次数据库维护
+    maintenance_lock: tokio::sync::Mutex<()>,
+    /// 关键写入的健康状态，由`write_with_retry`更新
+    pub(super) write_health: super::WriteHealthTracker,
+}
+
+/// 数据库维护方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VacuumMode {
+    /// 释放空闲页并按需更新统计信息，开销小，适合定期执行（auto_vacuum为INCREMENTAL时才会缩小文件）
+    Incremental,
+    /// 重建整个数据库文件，回收全部空间、整理碎片并更新统计信息，期间阻塞写入
+    Full,
 }
 
+/// 数据库维护结果
+#[derive(Debug, Clone, Serialize)]
+pub struct MaintenanceReport {
+    pub mode: VacuumMode,
+    pub size_before_bytes: i64,
+    pub size_after_bytes: i64,
+    /// 维护后剩余的空闲页数
+    pub free_pages: i64,
+    pub duration_ms: i64,
+}
 
-pub async fn initialize_database() -> Result<Database> {
+/// 在线生成的数据库备份文件
+#[derive(Debug)]
+pub struct DatabaseBackup {
+    pub path: PathBuf,
+    pub size_bytes: u64,
+    /// 备份对应的数据库结构版本
+    pub schema_version: i32,
+}
+
+/// 数据库连接池参数
+#[derive(Debug, Clone)]
+pub struct PoolSettings {
+    pub max_connections: u32,
+    pub min_connections: u32,
+    pub acquire_timeout: Duration,
+    /// 空闲连接回收时间，None表示不回收
+    pub idle_timeout: Option<Duration>,
+    /// SQLite数据库被锁定时的等待时间
+    pub busy_timeout: Duration,
+}
+
+impl PoolSettings {
+    pub fn from_config(config: &CoreConfig) -> Self {
+        Self {
+            max_connections: config.db_max_connections,
+            min_connections: config.db_min_connections.min(config.db_max_connections),
+            acquire_timeout: Duration::from_secs(config.db_acquire_timeout_secs),
+            idle_timeout: (config.db_idle_timeout_secs > 0)
+                .then(|| Duration::from_secs(config.db_idle_timeout_secs)),
+            busy_timeout: Duration::from_secs(config.db_busy_timeout_secs),
+        }
+    }
+}
+
+impl Default for PoolSettings {
+    fn default() -> Self {
+        Self::from_config(&CoreConfig::default())
+    }
+}
+
+pub async fn initialize_database(settings: &PoolSettings) -> Result<Database> {
     let database_url = std::env::var("DATABASE_URL")
         .unwrap_or_else(|_| "sqlite:./data/server_manager.db".to_string());
     
+    // 目前只实现了SQLite后端，其他数据库地址直接给出明确的错误
+    let scheme = database_url.split(':').next().unwrap_or_default();
+    if scheme != "sqlite" {
+        return Err(anyhow::anyhow!(
+            "暂不支持的数据库类型: {}（DATABASE_URL目前仅支持sqlite:）",
+            scheme
+        ));
+    }
+    
     // 确保数据目录存在
     if let Some(parent) = std::path::Path::new(&database_url.replace("sqlite:", "")).parent() {
         std::fs::create_dir_all(parent)?;
     }
     
-    let database = Database::new(&database_url).await?;
+    let database = Database::connect(&database_url, settings).await?;
     
     // 验证数据库连接
     database.health_check().await?;
@@ -26,182 +106,148 @@ pub async fn initialize_database() -> Result<Database> {
 }
 
 impl Database {
-    /// 初始化数据库连接池
+    /// 使用默认连接池参数初始化数据库（测试用）
+    #[cfg(test)]
     pub async fn new(database_url: &str) -> Result<Self> {
+        Self::connect(database_url, &PoolSettings::default()).await
+    }
+
+    /// 初始化数据库连接池
+    pub async fn connect(database_url: &str, settings: &PoolSettings) -> Result<Self> {
         info!("正在连接数据库: {}", database_url);
         
         // 配置SQLite连接选项
         let options = SqliteConnectOptions::from_str(database_url)?
             .create_if_missing(true)
+            .busy_timeout(settings.busy_timeout)
+            // 删除数据后可通过incremental_vacuum释放空闲页；已有数据库在下一次完整VACUUM后生效
+            .auto_vacuum(SqliteAutoVacuum::Incremental)
             .pragma("journal_mode", "WAL")  // 启用WAL模式提高并发性能
             .pragma("synchronous", "NORMAL")  // 平衡安全性和性能
             .pragma("foreign_keys", "ON");    // 启用外键约束
         
-        // 创建连接池
-        let pool = SqlitePool::connect_with(options).await?;
+        // 迁移在独立的单连接上执行：SQLite连接会缓存表结构，迁移若分散在连接池的多个连接上，
+        // 之后其他连接可能按旧表结构准备`RETURNING *`等语句，返回的列数与模型不一致
+        let migration_pool = PoolOptions::<Db>::new()
+            .max_connections(1)
+            .connect_with(options.clone())
+            .await?;
+        let migrated = Self::migrate(&migration_pool).await;
+        migration_pool.close().await;
+        migrated?;
         
-        let db = Database { pool };
+        // 创建连接池
+        let pool = PoolOptions::<Db>::new()
+            .max_connections(settings.max_connections)
+            .min_connections(settings.min_connections)
+            .acquire_timeout(settings.acquire_timeout)
+            .idle_timeout(settings.idle_timeout)
+            .connect_with(options)
+            .await?;
+        info!(
+            "🗄️ 数据库连接池: 最大连接数 {}, 获取超时 {}秒",
+            settings.max_connections,
+            settings.acquire_timeout.as_secs()
+        );
         
-        // 运行数据库迁移
-        db.migrate().await?;
+        let db = Database {
+            pool,
+            maintenance_lock: tokio::sync::Mutex::new(()),
+            write_health: Default::default(),
+        };
         
         info!("✅ 数据库连接成功建立");
         Ok(db)
     }
     
     /// 运行数据库迁移
-    pub async fn migrate(&self) -> Result<()> {
+    async fn migrate(pool: &DbPool) -> Result<()> {
         info!("正在执行数据库迁移...");
         
-        // 检查是否已经初始化
-        let table_count: i64 = sqlx::query("SELECT COUNT(*) as count FROM sqlite_master WHERE type='table'")
-            .fetch_one(&self.pool)
-            .await?
-            .get("count");
-            
-        let is_fresh_database = table_count == 0;
-        
-        if !is_fresh_database {
-            info!("检测到现有数据库，执行渐进式迁移...");
-            self.migrate_existing_database().await?;
-        }
-        
-        // 创建nodes表
-        sqlx::query(r#"
-            CREATE TABLE IF NOT EXISTS nodes (
-                id INTEGER PRIMARY KEY AUTOINCREMENT,
-                node_id TEXT UNIQUE NOT NULL,
-                hostname TEXT NOT NULL,
-                ip_address TEXT NOT NULL,
-                os_info TEXT,
-                status TEXT DEFAULT 'offline',
-                last_heartbeat DATETIME,
-                registered_at DATETIME DEFAULT CURRENT_TIMESTAMP,
-                updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
-            )
-        "#)
-        .execute(&self.pool)
-        .await?;
-        
-        // 创建node_metrics表
-        sqlx::query(r#"
-            CREATE TABLE IF NOT EXISTS node_metrics (
-                id INTEGER PRIMARY KEY AUTOINCREMENT,
-                node_id TEXT NOT NULL,
-                metric_time DATETIME NOT NULL,
-                cpu_usage REAL,
-                memory_usage REAL,
-                disk_usage REAL,
-                disk_total INTEGER,
-                disk_available INTEGER,
-                load_average REAL,
-                memory_total INTEGER,
-                memory_available INTEGER,
-                uptime INTEGER,
-                created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
-                FOREIGN KEY (node_id) REFERENCES nodes(node_id) ON DELETE CASCADE
-            )
-        "#)
-        .execute(&self.pool)
-        .await?;
-        
-        // 创建commands表  
-        sqlx::query(r#"
-            CREATE TABLE IF NOT EXISTS commands (
-                id INTEGER PRIMARY KEY AUTOINCREMENT,
-                command_id TEXT UNIQUE NOT NULL,
-                command_text TEXT NOT NULL,
-                target_node_id TEXT NOT NULL,
-                status TEXT DEFAULT 'pending',
-                created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
-                started_at DATETIME,
-                completed_at DATETIME,
-                FOREIGN KEY (target_node_id) REFERENCES nodes(node_id) ON DELETE CASCADE
-            )
-        "#)
-        .execute(&self.pool)
-        .await?;
-        
-        // 创建command_results表
-        sqlx::query(r#"
-            CREATE TABLE IF NOT EXISTS command_results (
-                id INTEGER PRIMARY KEY AUTOINCREMENT,
-                command_id TEXT NOT NULL,
-                stdout TEXT,
-                stderr TEXT,
-                exit_code INTEGER,
-                execution_time_ms INTEGER,
-                created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
-                FOREIGN KEY (command_id) REFERENCES commands(command_id) ON DELETE CASCADE
-            )
-        "#)
-        .execute(&self.pool)
-        .await?;
-        
-        // 创建索引提高查询性能
-        sqlx::query("CREATE INDEX IF NOT EXISTS idx_node_metrics_time ON node_metrics(node_id, metric_time)")
-            .execute(&self.pool)
-            .await?;
-            
-        sqlx::query("CREATE INDEX IF NOT EXISTS idx_commands_status ON commands(status, created_at)")
-            .execute(&self.pool)
+        MigrationManager::new(pool.clone())
+            .migrate_to_version(LATEST_VERSION)
             .await?;
         
         info!("✅ 数据库迁移完成");
         Ok(())
     }
-
-    /// 迁移现有数据库
-    async fn migrate_existing_database(&self) -> Result<()> {
-        info!("正在更新现有数据库表结构...");
-        
-        // 检查node_metrics表是否缺少新字段
-        let table_info = sqlx::query("PRAGMA table_info(node_metrics)")
-            .fetch_all(&self.pool)
-            .await?;
-        
-        let column_names: Vec<String> = table_info.iter()
-            .map(|row| row.get::<String, _>("name"))
-            .collect();
-        
-        // 检查并添加缺失的字段
-        if !column_names.contains(&"disk_total".to_string()) {
-            info!("添加 disk_total 字段...");
-            sqlx::query("ALTER TABLE node_metrics ADD COLUMN disk_total INTEGER")
-                .execute(&self.pool)
-                .await?;
-        }
-        
-        if !column_names.contains(&"disk_available".to_string()) {
-            info!("添加 disk_available 字段...");
-            sqlx::query("ALTER TABLE node_metrics ADD COLUMN disk_available INTEGER")
-                .execute(&self.pool)
-                .await?;
-        }
-        
-        if !column_names.contains(&"memory_total".to_string()) {
-            info!("添加 memory_total 字段...");
-            sqlx::query("ALTER TABLE node_metrics ADD COLUMN memory_total INTEGER")
-                .execute(&self.pool)
-                .await?;
+    
+    /// 关闭数据库：将WAL日志写回主数据库文件后关闭连接池
+    pub async fn close(&self) {
+        if let Err(e) = sqlx::query("PRAGMA wal_checkpoint(TRUNCATE)").execute(&self.pool).await {
+            warn!("⚠️ WAL检查点失败: {}", e);
         }
-        
-        if !column_names.contains(&"memory_available".to_string()) {
-            info!("添加 memory_available 字段...");
-            sqlx::query("ALTER TABLE node_metrics ADD COLUMN memory_available INTEGER")
-                .execute(&self.pool)
-                .await?;
+        self.pool.close().await;
+        info!("✅ 数据库连接已关闭");
+    }
+    
+    /// 执行数据库维护：回收删除数据后的空闲空间、更新查询统计信息，最后把WAL日志写回主文件并截断
+    ///
+    /// 已有维护正在进行时返回None
+    pub async fn run_maintenance(&self, mode: VacuumMode) -> Result<Option<MaintenanceReport>> {
+        let Ok(_guard) = self.maintenance_lock.try_lock() else {
+            return Ok(None);
+        };
+        let started = Instant::now();
+        let size_before_bytes = self.file_size().await?;
+
+        match mode {
+            VacuumMode::Incremental => {
+                sqlx::query("PRAGMA incremental_vacuum").execute(&self.pool).await?;
+                sqlx::query("PRAGMA optimize").execute(&self.pool).await?;
+            }
+            VacuumMode::Full => {
+                sqlx::query("VACUUM").execute(&self.pool).await?;
+                sqlx::query("ANALYZE").execute(&self.pool).await?;
+            }
         }
-        
-        if !column_names.contains(&"uptime".to_string()) {
-            info!("添加 uptime 字段...");
-            sqlx::query("ALTER TABLE node_metrics ADD COLUMN uptime INTEGER")
-                .execute(&self.pool)
-                .await?;
+        sqlx::query("PRAGMA wal_checkpoint(TRUNCATE)").execute(&self.pool).await?;
+
+        let free_pages: i64 = sqlx::query_scalar("SELECT freelist_count FROM pragma_freelist_count()")
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(Some(MaintenanceReport {
+            mode,
+            size_before_bytes,
+            size_after_bytes: self.file_size().await?,
+            free_pages,
+            duration_ms: started.elapsed().as_millis() as i64,
+        }))
+    }
+
+    /// 在线备份数据库，在数据库文件所在目录生成一个一致的快照文件
+    ///
+    /// WAL模式下直接复制正在写入的数据库文件会得到损坏的备份；`VACUUM INTO`在单个读事务中导出完整数据库，
+    /// 不阻塞写入。与数据库维护互斥执行，调用方负责在使用后删除备份文件
+    pub async fn backup(&self) -> Result<DatabaseBackup> {
+        let _guard = self.maintenance_lock.lock().await;
+
+        let database_file: String = sqlx::query_scalar("SELECT file FROM pragma_database_list WHERE name = 'main'")
+            .fetch_one(&self.pool)
+            .await?;
+        // 内存数据库没有文件路径，备份写入临时目录
+        let directory = std::path::Path::new(&database_file)
+            .parent()
+            .filter(|_| !database_file.is_empty())
+            .map(|parent| parent.to_path_buf())
+            .unwrap_or_else(std::env::temp_dir);
+        let path = directory.join(format!(".sm_backup_{}.db", uuid::Uuid::new_v4()));
+
+        let schema_version = MigrationManager::new(self.pool.clone()).get_current_version().await?;
+        if let Err(e) = sqlx::query("VACUUM INTO ?").bind(path.to_string_lossy()).execute(&self.pool).await {
+            let _ = std::fs::remove_file(&path);
+            return Err(e.into());
         }
-        
-        info!("✅ 数据库表结构更新完成");
-        Ok(())
+
+        let size_bytes = tokio::fs::metadata(&path).await?.len();
+        Ok(DatabaseBackup { path, size_bytes, schema_version })
+    }
+
+    /// 数据库文件大小（页数 × 页大小，不含WAL日志）
+    async fn file_size(&self) -> Result<i64> {
+        Ok(sqlx::query_scalar("SELECT page_count * page_size FROM pragma_page_count(), pragma_page_size()")
+            .fetch_one(&self.pool)
+            .await?)
     }
     
     /// 检查数据库连接状态
@@ -242,4 +288,61 @@ pub struct DatabaseStats {
     pub total_nodes: i64,
     pub online_nodes: i64,
     pub total_commands: i64,
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::TempDatabaseFile;
+
+    #[tokio::test]
+    async fn test_run_maintenance_reclaims_space() {
+        let db_file = TempDatabaseFile::new("maintenance");
+        let db = db_file.open().await;
+
+        // 新建的数据库使用增量auto_vacuum
+        let auto_vacuum: i64 = sqlx::query_scalar("PRAGMA auto_vacuum").fetch_one(&db.pool).await.unwrap();
+        assert_eq!(auto_vacuum, 2);
+
+        sqlx::query("CREATE TABLE filler (data BLOB)").execute(&db.pool).await.unwrap();
+        sqlx::query("INSERT INTO filler SELECT randomblob(4096) FROM (WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n WHERE i < 500) SELECT i FROM n)")
+            .execute(&db.pool)
+            .await
+            .unwrap();
+        sqlx::query("DELETE FROM filler").execute(&db.pool).await.unwrap();
+
+        let report = db.run_maintenance(VacuumMode::Incremental).await.unwrap().unwrap();
+        assert!(report.size_after_bytes < report.size_before_bytes);
+        assert_eq!(report.free_pages, 0);
+
+        let report = db.run_maintenance(VacuumMode::Full).await.unwrap().unwrap();
+        assert_eq!(report.mode, VacuumMode::Full);
+        assert_eq!(report.free_pages, 0);
+
+        // 维护进行中时不重复执行
+        let _guard = db.maintenance_lock.lock().await;
+        assert!(db.run_maintenance(VacuumMode::Full).await.unwrap().is_none());
+        drop(_guard);
+    }
+
+    #[tokio::test]
+    async fn test_backup_is_consistent_snapshot() {
+        let db_file = TempDatabaseFile::new("backup");
+        let db = db_file.open().await;
+        sqlx::query("INSERT INTO nodes (node_id, hostname, ip_address) VALUES ('node-1', 'host-1', '10.0.0.1')")
+            .execute(&db.pool)
+            .await
+            .unwrap();
+
+        // 数据仍在WAL日志中时备份也包含完整数据
+        let backup = db.backup().await.unwrap();
+        let backup_file = TempDatabaseFile(backup.path.clone());
+        assert_eq!(backup.schema_version, LATEST_VERSION);
+        assert_eq!(backup.path.parent(), db_file.0.parent());
+        assert_eq!(backup.size_bytes, std::fs::metadata(&backup.path).unwrap().len());
+
+        let copy = backup_file.open().await;
+        let stats = copy.get_stats().await.unwrap();
+        assert_eq!(stats.total_nodes, 1);
+    }
+}
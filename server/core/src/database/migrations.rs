@@ -1,17 +1,186 @@
 use anyhow::Result;
-use sqlx::{SqlitePool, Row};
+use sqlx::Row;
 use tracing::info;
 
+use super::DbPool;
+
+/// 当前代码所需的数据库版本
+pub const LATEST_VERSION: i32 = 14;
+
+/// v1: 基础表结构
+const V1_BASE_SCHEMA: &[&str] = &[
+    r#"
+    CREATE TABLE IF NOT EXISTS nodes (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        node_id TEXT UNIQUE NOT NULL,
+        hostname TEXT NOT NULL,
+        ip_address TEXT NOT NULL,
+        os_info TEXT,
+        status TEXT DEFAULT 'offline',
+        last_heartbeat DATETIME,
+        registered_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+        updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
+    )
+    "#,
+    r#"
+    CREATE TABLE IF NOT EXISTS node_metrics (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        node_id TEXT NOT NULL,
+        metric_time DATETIME NOT NULL,
+        cpu_usage REAL,
+        memory_usage REAL,
+        disk_usage REAL,
+        load_average REAL,
+        created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+        FOREIGN KEY (node_id) REFERENCES nodes(node_id) ON DELETE CASCADE
+    )
+    "#,
+    r#"
+    CREATE TABLE IF NOT EXISTS commands (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        command_id TEXT UNIQUE NOT NULL,
+        command_text TEXT NOT NULL,
+        target_node_id TEXT NOT NULL,
+        status TEXT DEFAULT 'pending',
+        created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+        started_at DATETIME,
+        completed_at DATETIME,
+        FOREIGN KEY (target_node_id) REFERENCES nodes(node_id) ON DELETE CASCADE
+    )
+    "#,
+    r#"
+    CREATE TABLE IF NOT EXISTS command_results (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        command_id TEXT NOT NULL,
+        stdout TEXT,
+        stderr TEXT,
+        exit_code INTEGER,
+        execution_time_ms INTEGER,
+        created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+        FOREIGN KEY (command_id) REFERENCES commands(command_id) ON DELETE CASCADE
+    )
+    "#,
+    "CREATE INDEX IF NOT EXISTS idx_node_metrics_time ON node_metrics(node_id, metric_time)",
+    "CREATE INDEX IF NOT EXISTS idx_commands_status ON commands(status, created_at)",
+];
+
+/// v2: 监控数据增加磁盘/内存容量和运行时间字段
+const V2_METRIC_CAPACITY: &[&str] = &[
+    "ALTER TABLE node_metrics ADD COLUMN disk_total INTEGER",
+    "ALTER TABLE node_metrics ADD COLUMN disk_available INTEGER",
+    "ALTER TABLE node_metrics ADD COLUMN memory_total INTEGER",
+    "ALTER TABLE node_metrics ADD COLUMN memory_available INTEGER",
+    "ALTER TABLE node_metrics ADD COLUMN uptime INTEGER",
+];
+
+/// v3: 节点标签字段（JSON数组）
+const V3_NODE_TAGS: &[&str] = &[
+    "ALTER TABLE nodes ADD COLUMN tags TEXT NOT NULL DEFAULT '[]'",
+];
+
+/// v4: 节点独立令牌表（不关联nodes表，允许在节点首次连接前预先签发令牌）
+const V4_NODE_TOKENS: &[&str] = &[
+    r#"
+    CREATE TABLE IF NOT EXISTS node_tokens (
+        node_id TEXT PRIMARY KEY,
+        token_hash TEXT NOT NULL,
+        created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+        updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
+    )
+    "#,
+];
+
+/// v5: 告警规则表（node_id为`*`时匹配所有节点）
+const V5_ALERT_RULES: &[&str] = &[
+    r#"
+    CREATE TABLE IF NOT EXISTS alert_rules (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        node_id TEXT NOT NULL DEFAULT '*',
+        metric TEXT NOT NULL,
+        comparison TEXT NOT NULL,
+        threshold REAL NOT NULL,
+        duration_secs INTEGER NOT NULL DEFAULT 0,
+        enabled BOOLEAN NOT NULL DEFAULT 1,
+        created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+    )
+    "#,
+];
+
+/// v6: 节点硬件信息字段
+const V6_NODE_HARDWARE: &[&str] = &[
+    "ALTER TABLE nodes ADD COLUMN cpu_count INTEGER",
+    "ALTER TABLE nodes ADD COLUMN total_memory INTEGER",
+];
+
+/// v7: 节点操作系统信息拆分为独立字段，便于按系统或内核版本过滤
+const V7_NODE_OS_FIELDS: &[&str] = &[
+    "ALTER TABLE nodes ADD COLUMN os_name TEXT",
+    "ALTER TABLE nodes ADD COLUMN os_version TEXT",
+    "ALTER TABLE nodes ADD COLUMN kernel_version TEXT",
+];
+
+/// v8: 节点上报期望的监控数据保留天数
+const V8_NODE_METRICS_RETENTION: &[&str] = &[
+    "ALTER TABLE nodes ADD COLUMN metrics_retention_days INTEGER",
+];
+
+/// v9: 管理员为节点单独设置的监控数据保留天数
+const V9_NODE_METRICS_RETENTION_OVERRIDE: &[&str] = &[
+    "ALTER TABLE nodes ADD COLUMN metrics_retention_override_days INTEGER",
+];
+
+/// v10: 节点代理版本
+const V10_NODE_AGENT_VERSION: &[&str] = &[
+    "ALTER TABLE nodes ADD COLUMN agent_version TEXT",
+];
+
+/// v11: 定时命令（计划执行时间和cron表达式），schedule_id记录周期命令每次运行所属的计划
+const V11_COMMAND_SCHEDULE: &[&str] = &[
+    "ALTER TABLE commands ADD COLUMN scheduled_at DATETIME",
+    "ALTER TABLE commands ADD COLUMN cron TEXT",
+    "ALTER TABLE commands ADD COLUMN schedule_id TEXT",
+    "CREATE INDEX IF NOT EXISTS idx_commands_scheduled ON commands(status, scheduled_at)",
+];
+
+/// v12: 按写入时间统计和查询监控数据（如最近24小时的数据量、最早和最晚的写入时间）
+const V12_METRICS_CREATED_AT_INDEX: &[&str] = &[
+    "CREATE INDEX IF NOT EXISTS idx_node_metrics_created_at ON node_metrics(created_at)",
+];
+
+/// v13: 网络和磁盘I/O的累计字节数，用于计算吞吐速率
+const V13_METRIC_IO_COUNTERS: &[&str] = &[
+    "ALTER TABLE node_metrics ADD COLUMN network_rx_bytes INTEGER",
+    "ALTER TABLE node_metrics ADD COLUMN network_tx_bytes INTEGER",
+    "ALTER TABLE node_metrics ADD COLUMN disk_read_bytes INTEGER",
+    "ALTER TABLE node_metrics ADD COLUMN disk_write_bytes INTEGER",
+];
+
+/// v14: 事件日志（如节点重启），node_id可为空且不随节点删除，保留完整的事件时间线；details为事件相关数据的JSON对象
+const V14_EVENTS: &[&str] = &[
+    r#"
+    CREATE TABLE IF NOT EXISTS events (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        node_id TEXT,
+        event_type TEXT NOT NULL,
+        details TEXT NOT NULL DEFAULT '{}',
+        occurred_at DATETIME NOT NULL,
+        created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+    )
+    "#,
+    "CREATE INDEX IF NOT EXISTS idx_events_time ON events(occurred_at)",
+    "CREATE INDEX IF NOT EXISTS idx_events_node_time ON events(node_id, occurred_at)",
+];
+
 /// 数据库迁移版本管理
 pub struct MigrationManager {
-    pool: SqlitePool,
+    pool: DbPool,
 }
 
 impl MigrationManager {
-    pub fn new(pool: SqlitePool) -> Self {
+    pub fn new(pool: DbPool) -> Self {
         Self { pool }
     }
-    
+
     /// 获取当前数据库版本
     pub async fn get_current_version(&self) -> Result<i32> {
         // 创建版本表如果不存在
@@ -24,79 +193,173 @@ impl MigrationManager {
         "#)
         .execute(&self.pool)
         .await?;
-        
+
         // 获取最新版本
         let result = sqlx::query("SELECT MAX(version) as version FROM schema_version")
             .fetch_one(&self.pool)
             .await?;
-            
+
         let version: Option<i32> = result.get("version");
-        Ok(version.unwrap_or(0))
+        match version {
+            Some(version) => Ok(version),
+            None => self.baseline_legacy_database().await,
+        }
     }
-    
+
     /// 应用迁移到指定版本
     pub async fn migrate_to_version(&self, target_version: i32) -> Result<()> {
         let current_version = self.get_current_version().await?;
-        
+
         if current_version >= target_version {
             info!("数据库已是最新版本 v{}", current_version);
             return Ok(());
         }
-        
+
         for version in (current_version + 1)..=target_version {
             info!("应用迁移 v{}", version);
             self.apply_migration(version).await?;
         }
-        
+
         Ok(())
     }
-    
+
     /// 应用单个迁移
     async fn apply_migration(&self, version: i32) -> Result<()> {
-        let mut tx = self.pool.begin().await?;
-        
-        match version {
-            1 => {
-                // v1: 基础表结构 (已在connection.rs中创建)
-                info!("v1: 基础表结构已创建");
-            }
-            2 => {
-                // v2: 添加节点标签字段 (示例未来迁移)
-                sqlx::query("ALTER TABLE nodes ADD COLUMN tags TEXT")
-                    .execute(&mut *tx)
-                    .await?;
-                info!("v2: 添加节点标签字段");
-            }
+        let statements = match version {
+            1 => V1_BASE_SCHEMA,
+            2 => V2_METRIC_CAPACITY,
+            3 => V3_NODE_TAGS,
+            4 => V4_NODE_TOKENS,
+            5 => V5_ALERT_RULES,
+            6 => V6_NODE_HARDWARE,
+            7 => V7_NODE_OS_FIELDS,
+            8 => V8_NODE_METRICS_RETENTION,
+            9 => V9_NODE_METRICS_RETENTION_OVERRIDE,
+            10 => V10_NODE_AGENT_VERSION,
+            11 => V11_COMMAND_SCHEDULE,
+            12 => V12_METRICS_CREATED_AT_INDEX,
+            13 => V13_METRIC_IO_COUNTERS,
+            14 => V14_EVENTS,
             _ => {
                 return Err(anyhow::anyhow!("未知的迁移版本: {}", version));
             }
+        };
+
+        let mut tx = self.pool.begin().await?;
+
+        for statement in statements {
+            sqlx::query(statement).execute(&mut *tx).await?;
         }
-        
+
         // 记录迁移版本
         sqlx::query("INSERT INTO schema_version (version) VALUES (?)")
             .bind(version)
             .execute(&mut *tx)
             .await?;
-            
+
         tx.commit().await?;
+        info!("✅ 迁移 v{} 完成", version);
         Ok(())
     }
-    
-    /// 回滚到指定版本 (谨慎使用)
-    pub async fn rollback_to_version(&self, target_version: i32) -> Result<()> {
-        let current_version = self.get_current_version().await?;
-        
-        if current_version <= target_version {
-            return Ok(());
+
+    /// 为引入版本表之前创建的数据库确定基线版本
+    ///
+    /// 旧版本在启动时直接建表并按需补列，没有记录版本号。这里按表结构推断其对应的版本并记录下来，
+    /// 之后的升级只依赖schema_version
+    async fn baseline_legacy_database(&self) -> Result<i32> {
+        if !self.table_exists("nodes").await? {
+            return Ok(0);
         }
-        
-        // 这里只是示例，实际回滚需要更复杂的逻辑
-        sqlx::query("DELETE FROM schema_version WHERE version > ?")
-            .bind(target_version)
+
+        let version = if !self.column_exists("node_metrics", "uptime").await? {
+            1
+        } else if !self.column_exists("nodes", "tags").await? {
+            2
+        } else if !self.table_exists("node_tokens").await? {
+            3
+        } else {
+            // 版本表引入之后新增的结构都通过迁移创建，旧数据库最多对应v4
+            4
+        };
+
+        info!("检测到未记录版本的现有数据库，基线版本: v{}", version);
+        sqlx::query("INSERT INTO schema_version (version) VALUES (?)")
+            .bind(version)
             .execute(&self.pool)
             .await?;
-            
-        info!("回滚到版本 v{}", target_version);
-        Ok(())
+
+        Ok(version)
+    }
+
+    async fn table_exists(&self, table: &str) -> Result<bool> {
+        let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name = ?")
+            .bind(table)
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(count > 0)
+    }
+
+    async fn column_exists(&self, table: &str, column: &str) -> Result<bool> {
+        let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM pragma_table_info(?) WHERE name = ?")
+            .bind(table)
+            .bind(column)
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(count > 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::TempDatabaseFile;
+
+    #[tokio::test]
+    async fn test_fresh_database_reaches_latest_version() {
+        let db_file = TempDatabaseFile::new("migration");
+        let db = db_file.open().await;
+
+        let manager = MigrationManager::new(db.pool.clone());
+        assert_eq!(manager.get_current_version().await.unwrap(), LATEST_VERSION);
+        assert!(manager.column_exists("node_metrics", "uptime").await.unwrap());
+        assert!(manager.column_exists("nodes", "tags").await.unwrap());
+        assert!(manager.table_exists("node_tokens").await.unwrap());
+        assert!(manager.column_exists("nodes", "total_memory").await.unwrap());
+        assert!(manager.column_exists("nodes", "kernel_version").await.unwrap());
+        assert!(manager.column_exists("commands", "scheduled_at").await.unwrap());
+
+        // 按写入时间的统计查询使用索引，而不是扫描整张表
+        let plan: Vec<String> = sqlx::query(
+            "EXPLAIN QUERY PLAN SELECT COUNT(*) FROM node_metrics WHERE created_at > datetime('now', '-1 day')"
+        )
+            .fetch_all(&db.pool)
+            .await
+            .unwrap()
+            .iter()
+            .map(|row| row.get("detail"))
+            .collect();
+        assert!(plan.iter().any(|detail| detail.contains("idx_node_metrics_created_at")), "{:?}", plan);
+
+        // 重复执行迁移不会出错
+        manager.migrate_to_version(LATEST_VERSION).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_legacy_database_is_baselined_and_upgraded() {
+        let db_file = TempDatabaseFile::new("migration");
+        let url = format!("{}?mode=rwc", db_file.url());
+
+        // 模拟引入版本表之前的v1结构
+        let pool = DbPool::connect(&url).await.unwrap();
+        for statement in V1_BASE_SCHEMA {
+            sqlx::query(statement).execute(&pool).await.unwrap();
+        }
+        pool.close().await;
+
+        let db = db_file.open().await;
+        let manager = MigrationManager::new(db.pool.clone());
+        assert_eq!(manager.get_current_version().await.unwrap(), LATEST_VERSION);
+        assert!(manager.column_exists("node_metrics", "memory_total").await.unwrap());
+        assert!(manager.column_exists("nodes", "tags").await.unwrap());
     }
-}
\ No newline at end of file
+}
@@ -1,4 +1,47 @@
 pub mod connection;
 pub mod migrations;
+pub mod retry;
 
-pub use connection::*;
\ No newline at end of file
+pub use connection::*;
+pub use retry::*;
+
+/// 当前使用的数据库类型
+///
+/// 模型层只依赖`DbPool`，切换后端时需要修改这里、`Database::connect`中的连接选项以及方言相关的SQL
+pub type Db = sqlx::Sqlite;
+
+/// 数据库连接池
+pub type DbPool = sqlx::Pool<Db>;
+
+/// 测试用的临时数据库文件，离开作用域时（包括测试panic时）删除数据库及其WAL、SHM文件
+///
+/// 应先于使用它的`Database`声明，保证连接池先被释放
+#[cfg(test)]
+pub struct TempDatabaseFile(pub std::path::PathBuf);
+
+#[cfg(test)]
+impl TempDatabaseFile {
+    /// 在系统临时目录下生成不重复的数据库文件路径，`name`用于区分所属的测试
+    pub fn new(name: &str) -> Self {
+        Self(std::env::temp_dir().join(format!("sm_{}_test_{}.db", name, uuid::Uuid::new_v4())))
+    }
+
+    /// 数据库连接地址
+    pub fn url(&self) -> String {
+        format!("sqlite:{}", self.0.display())
+    }
+
+    /// 打开该文件上的数据库（执行迁移）
+    pub async fn open(&self) -> Database {
+        Database::new(&self.url()).await.unwrap()
+    }
+}
+
+#[cfg(test)]
+impl Drop for TempDatabaseFile {
+    fn drop(&mut self) {
+        for suffix in ["", "-wal", "-shm"] {
+            std::fs::remove_file(format!("{}{}", self.0.display(), suffix)).ok();
+        }
+    }
+}
@@ -0,0 +1,185 @@
+use std::future::Future;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use tracing::{error, warn};
+
+use super::Database;
+
+/// 临时性错误时写入的最大尝试次数（含第一次）
+const MAX_WRITE_ATTEMPTS: u32 = 3;
+
+/// 第一次重试前的等待时间，之后每次翻倍
+const INITIAL_BACKOFF: Duration = Duration::from_millis(100);
+
+/// SQLite主结果码：数据库文件被其他连接锁定
+const SQLITE_BUSY: i32 = 5;
+/// SQLite主结果码：同一连接内的表锁冲突
+const SQLITE_LOCKED: i32 = 6;
+
+/// 判断错误是否为锁冲突、连接池繁忙等稍后重试即可恢复的临时性错误
+///
+/// 磁盘已满、只读、文件损坏等错误重试也不会成功，按致命错误处理
+pub fn is_transient_error(error: &anyhow::Error) -> bool {
+    match error.downcast_ref::<sqlx::Error>() {
+        Some(sqlx::Error::PoolTimedOut) => true,
+        Some(sqlx::Error::Database(db_error)) => db_error
+            .code()
+            .and_then(|code| code.parse::<i32>().ok())
+            // 扩展结果码（如SQLITE_BUSY_SNAPSHOT）的低8位是主结果码
+            .is_some_and(|code| matches!(code & 0xff, SQLITE_BUSY | SQLITE_LOCKED)),
+        _ => false,
+    }
+}
+
+/// 关键写入（监控数据、心跳）的健康状态
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct WriteHealth {
+    /// 连续失败的写入次数，成功写入后清零
+    pub consecutive_failures: u64,
+    /// 启动以来因临时性错误重试的次数
+    pub retries: u64,
+    pub last_error: Option<String>,
+    pub last_failure_at: Option<DateTime<Utc>>,
+    pub last_success_at: Option<DateTime<Utc>>,
+}
+
+impl WriteHealth {
+    /// 最近一次关键写入是否失败
+    pub fn is_failing(&self) -> bool {
+        self.consecutive_failures > 0
+    }
+}
+
+/// 关键写入的健康状态记录
+#[derive(Debug, Default)]
+pub struct WriteHealthTracker {
+    state: Mutex<WriteHealth>,
+}
+
+impl WriteHealthTracker {
+    pub fn snapshot(&self) -> WriteHealth {
+        self.state.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    fn update(&self, f: impl FnOnce(&mut WriteHealth)) {
+        f(&mut self.state.lock().unwrap_or_else(|e| e.into_inner()));
+    }
+}
+
+impl Database {
+    /// 执行关键写入，遇到临时性错误时按指数退避重试，并记录写入健康状态
+    ///
+    /// `write`每次尝试都会重新调用，需要自行准备每次写入的数据
+    pub async fn write_with_retry<T, F, Fut>(&self, operation: &str, mut write: F) -> Result<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let mut backoff = INITIAL_BACKOFF;
+        let mut attempt = 1;
+        loop {
+            match write().await {
+                Ok(value) => {
+                    self.write_health.update(|health| {
+                        health.consecutive_failures = 0;
+                        health.last_success_at = Some(Utc::now());
+                    });
+                    return Ok(value);
+                }
+                Err(e) if attempt < MAX_WRITE_ATTEMPTS && is_transient_error(&e) => {
+                    warn!("⏳ {}遇到临时性数据库错误，{}毫秒后重试({}/{}): {}", operation, backoff.as_millis(), attempt, MAX_WRITE_ATTEMPTS - 1, e);
+                    self.write_health.update(|health| health.retries += 1);
+                    tokio::time::sleep(backoff).await;
+                    backoff *= 2;
+                    attempt += 1;
+                }
+                Err(e) => {
+                    if !is_transient_error(&e) {
+                        error!("❌ {}失败（不可重试的数据库错误）: {}", operation, e);
+                    }
+                    self.write_health.update(|health| {
+                        health.consecutive_failures += 1;
+                        health.last_error = Some(format!("{}: {}", operation, e));
+                        health.last_failure_at = Some(Utc::now());
+                    });
+                    return Err(e);
+                }
+            }
+        }
+    }
+
+    /// 关键写入的健康状态
+    pub fn write_health(&self) -> WriteHealth {
+        self.write_health.snapshot()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::TempDatabaseFile;
+    use sqlx::sqlite::SqliteConnectOptions;
+    use sqlx::{ConnectOptions, Connection};
+    use std::str::FromStr;
+
+    #[tokio::test]
+    async fn test_write_with_retry() {
+        let db_file = TempDatabaseFile::new("retry");
+        let url = db_file.url();
+        let db = db_file.open().await;
+
+        // 另一个连接持有写锁时，不等待的写入返回SQLITE_BUSY
+        let mut holder = SqliteConnectOptions::from_str(&url).unwrap().connect().await.unwrap();
+        sqlx::query("BEGIN IMMEDIATE").execute(&mut holder).await.unwrap();
+        let mut writer = SqliteConnectOptions::from_str(&url)
+            .unwrap()
+            .busy_timeout(Duration::ZERO)
+            .connect()
+            .await
+            .unwrap();
+        let busy = sqlx::query("INSERT INTO schema_version (version) VALUES (999)")
+            .execute(&mut writer)
+            .await
+            .unwrap_err();
+        assert!(is_transient_error(&busy.into()));
+        sqlx::query("ROLLBACK").execute(&mut holder).await.unwrap();
+        holder.close().await.unwrap();
+        writer.close().await.unwrap();
+
+        assert!(is_transient_error(&sqlx::Error::PoolTimedOut.into()));
+        assert!(!is_transient_error(&anyhow::anyhow!("database or disk is full")));
+
+        // 临时性错误重试后成功
+        let mut calls = 0;
+        let value = db
+            .write_with_retry("测试写入", || {
+                calls += 1;
+                let result = if calls < 3 { Err(sqlx::Error::PoolTimedOut.into()) } else { Ok(calls) };
+                async move { result }
+            })
+            .await
+            .unwrap();
+        assert_eq!(value, 3);
+        let health = db.write_health();
+        assert_eq!((health.retries, health.consecutive_failures), (2, 0));
+        assert!(!health.is_failing());
+
+        // 致命错误不重试，并报告写入失败
+        let mut calls = 0;
+        let result: Result<()> = db
+            .write_with_retry("测试写入", || {
+                calls += 1;
+                async { Err(anyhow::anyhow!("database or disk is full")) }
+            })
+            .await;
+        assert!(result.is_err());
+        assert_eq!(calls, 1);
+        let health = db.write_health();
+        assert!(health.is_failing());
+        assert!(health.last_error.unwrap().contains("disk is full"));
+    }
+}
@@ -1,40 +1,64 @@
+mod config;
 mod database;
 mod models;
 mod services;
 use anyhow::Result;
+use clap::Parser;
 use axum::{
-    extract::{
-        ws::{Message, WebSocket, WebSocketUpgrade},
-        State,
-    },
-    response::IntoResponse,
-    routing::{get, delete},
+    middleware,
+    routing::{get, delete, post, put},
     Router,
 };
-use database::Database;
+use std::net::SocketAddr;
 use std::sync::Arc;
-use tokio::sync::Mutex;
 use tracing::{info, warn, error};
-use tracing_subscriber;
 
 use crate::services::{
+    alerts::{create_alert_rule, delete_alert_rule, get_alert_rules},
+    auth::{issue_node_token, login, require_auth, revoke_node_token},
+    cleanup::{export_database_backup, run_database_maintenance},
+    commands::{cancel_command, create_command, get_command, get_node_commands},
+    events::{get_events, get_node_events},
     metrics::{
-        get_all_latest_metrics, get_latest_metrics, get_metrics_summary, 
-        get_node_metrics, get_system_metrics_stats
+        export_node_metrics, get_all_latest_metrics, get_ingestion_stats, get_latest_metrics, get_metrics_summary, 
+        get_node_metrics, get_prometheus_metrics, get_system_metrics_stats, import_node_metrics
+    },
+    nodes::{
+        bulk_delete_nodes, cleanup_stale_nodes, delete_node, get_node, get_node_stats, get_nodes, get_overview,
+        update_node_retention, update_node_tags
     },
-    nodes::{cleanup_stale_nodes, delete_node, get_node, get_node_stats, get_nodes}, 
     websocket::{health_check, websocket_handler}
 };
 
+/// 版本信息（包含构建时的git提交和时间）
+pub const VERSION: &str = concat!(env!("CARGO_PKG_VERSION"), " (", env!("SM_GIT_SHA"), ", ", env!("SM_BUILD_TIME"), ")");
+
+/// Server Manager Core服务
+///
+/// 配置通过SM_CORE_*环境变量设置
+#[derive(Parser)]
+#[command(version = VERSION, about)]
+struct Cli {}
+
 #[tokio::main]
 async fn main() -> Result<()> {
+    Cli::parse();
+    
     // 初始化日志
     tracing_subscriber::fmt::init();
     
-    info!("🚀 Server Manager Core 启动中...");
+    info!("🚀 Server Manager Core {} 启动中...", VERSION);
+    
+    // 加载配置
+    let config = config::CoreConfig::from_env();
+    
+    // 在初始化其他组件前校验监听地址，配置错误时尽早退出
+    let bind_addr: SocketAddr = config.bind_address.parse().map_err(|e| {
+        anyhow::anyhow!("无效的监听地址 SM_CORE_BIND={}（示例: 0.0.0.0:20002）: {}", config.bind_address, e)
+    })?;
     
     // 初始化数据库连接
-    let database = match database::initialize_database().await {
+    let database = match database::initialize_database(&database::PoolSettings::from_config(&config)).await {
         Ok(db) => {
             info!("✅ 数据库初始化成功");
             db
@@ -58,39 +82,150 @@ async fn main() -> Result<()> {
         }
     }
     
+    if config.allow_shared_node_token {
+        info!("🔑 允许未签发独立令牌的节点使用全局共享令牌连接");
+    }
+    if let Some(networks) = &config.node_allowed_networks {
+        let networks: Vec<String> = networks.iter().map(ToString::to_string).collect();
+        info!("🛡️ 仅允许以下网段的节点连接: {}", networks.join(", "));
+    }
+    if let Some(origins) = &config.monitor_allowed_origins {
+        info!("🛡️ 允许以下来源的浏览器监控连接: {}", origins.join(", "));
+    }
+
     // 创建共享状态
-    let shared_state = Arc::new(crate::services::nodes::AppState::new(database));
+    let shared_state = Arc::new(crate::services::nodes::AppState::new(database, config));
+    if let Err(e) = shared_state.alert_engine.reload(&shared_state.database.pool).await {
+        error!("❌ 加载告警规则失败: {}", e);
+    }
     
-    // 创建路由
-    let app = Router::new()
-        // WebSocket路由 (节点连接)
-        .route("/api/v1/ws", get(websocket_handler))
-        // WebSocket路由 (客户端监控)
-        .route("/ws/client", get(websocket_handler))
-        // 健康检查
-        .route("/api/v1/health", get(health_check))
+    // 恢复重启前仍然存活的节点连接视图，避免节点重连前显示为全部离线
+    match models::Node::find_alive(&shared_state.database.pool, shared_state.config.node_stale_minutes).await {
+        Ok(nodes) => {
+            let restored = shared_state.connection_manager.restore_connections(&nodes).await;
+            if restored > 0 {
+                info!("♻️ 已恢复 {} 个存活节点的连接状态", restored);
+            }
+        }
+        Err(e) => warn!("⚠️ 恢复节点连接状态失败: {}", e),
+    }
+    
+    // 启动后台清理任务
+    services::cleanup::spawn_cleanup_task(shared_state.clone());
+    services::cleanup::spawn_maintenance_task(shared_state.clone());
+    services::scheduler::spawn_scheduler_task(shared_state.clone());
+    
+    // 需要JWT认证的REST API
+    let protected_routes = Router::new()
         // 节点管理API
         .route("/api/v1/nodes", get(get_nodes))
         .route("/api/v1/nodes/{node_id}", get(get_node))
         .route("/api/v1/nodes/{node_id}", delete(delete_node))
+        .route("/api/v1/nodes/bulk-delete", post(bulk_delete_nodes))
+        .route("/api/v1/nodes/{node_id}/tags", put(update_node_tags))
+        .route("/api/v1/nodes/{node_id}/retention", put(update_node_retention))
+        .route("/api/v1/nodes/{node_id}/events", get(get_node_events))
         .route("/api/v1/nodes/stats", get(get_node_stats))
         .route("/api/v1/nodes/cleanup", get(cleanup_stale_nodes))
+        .route("/api/v1/overview", get(get_overview))
+        // 节点令牌管理API
+        .route("/api/v1/nodes/{node_id}/token", post(issue_node_token))
+        .route("/api/v1/nodes/{node_id}/token", delete(revoke_node_token))
+        // 命令下发与历史API
+        .route("/api/v1/nodes/{node_id}/commands", post(create_command))
+        .route("/api/v1/nodes/{node_id}/commands", get(get_node_commands))
+        .route("/api/v1/commands/{command_id}", get(get_command))
+        .route("/api/v1/commands/{command_id}", delete(cancel_command))
         // 监控数据API
         .route("/api/v1/nodes/{node_id}/metrics/latest", get(get_latest_metrics))
         .route("/api/v1/nodes/{node_id}/metrics", get(get_node_metrics))
         .route("/api/v1/nodes/{node_id}/metrics/summary", get(get_metrics_summary))
+        .route("/api/v1/nodes/{node_id}/metrics/export", get(export_node_metrics))
+        .route("/api/v1/nodes/{node_id}/metrics/import", post(import_node_metrics))
         .route("/api/v1/metrics/latest", get(get_all_latest_metrics))
         .route("/api/v1/metrics/stats", get(get_system_metrics_stats))
-        .with_state(shared_state);
+        .route("/api/v1/stats/ingestion", get(get_ingestion_stats))
+        // 事件日志API
+        .route("/api/v1/events", get(get_events))
+        // 数据库维护API
+        .route("/api/v1/database/maintenance", post(run_database_maintenance))
+        .route("/api/v1/database/backup", get(export_database_backup))
+        // 告警规则API
+        .route("/api/v1/alerts/rules", get(get_alert_rules))
+        .route("/api/v1/alerts/rules", post(create_alert_rule))
+        .route("/api/v1/alerts/rules/{rule_id}", delete(delete_alert_rule))
+        .route_layer(middleware::from_fn_with_state(shared_state.clone(), require_auth));
+
+    // 创建路由
+    let app = Router::new()
+        // WebSocket路由 (节点连接，使用节点令牌认证)
+        .route("/api/v1/ws", get(websocket_handler))
+        // WebSocket路由 (客户端监控)
+        .route("/ws/client", get(websocket_handler))
+        // 健康检查
+        .route("/api/v1/health", get(health_check))
+        // 管理员登录
+        .route("/api/v1/auth/login", post(login))
+        // Prometheus指标导出（可选独立令牌认证）
+        .route("/api/v1/metrics/prometheus", get(get_prometheus_metrics))
+        .merge(protected_routes)
+        .with_state(shared_state.clone());
     
     // 启动WebSocket服务器
-    let listener = tokio::net::TcpListener::bind("0.0.0.0:20002").await?;
-    info!("🌐 WebSocket服务器启动成功，监听端口: 20002");
+    let listener = tokio::net::TcpListener::bind(bind_addr)
+        .await
+        .map_err(|e| anyhow::anyhow!("无法监听 {}: {}", bind_addr, e))?;
+    info!("🌐 WebSocket服务器启动成功，监听地址: {}", bind_addr);
     
-    // 启动服务器
-    axum::serve(listener, app).await?;
+    // 启动服务器，收到关闭信号后停止接受新连接并通知现有WebSocket会话
+    // 提供对端地址，供节点连接的来源网段校验使用
+    axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>())
+        .with_graceful_shutdown(shutdown_signal(shared_state.clone()))
+        .await?;
     
     info!("👋 Core服务正在关闭...");
     
+    // 等待WebSocket会话完成离线处理后再关闭数据库
+    if !shared_state.wait_for_sessions(SHUTDOWN_DRAIN_TIMEOUT).await {
+        warn!("⚠️ 等待WebSocket连接关闭超时，强制退出");
+    }
+    shared_state.database.close().await;
+    
     Ok(())
 }
+
+/// 关闭时等待WebSocket会话结束的最长时间
+const SHUTDOWN_DRAIN_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// 等待Ctrl+C或SIGTERM，然后通知所有WebSocket会话
+async fn shutdown_signal(state: Arc<crate::services::nodes::AppState>) {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            error!("❌ 监听Ctrl+C信号失败: {}", e);
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(e) => {
+                error!("❌ 监听SIGTERM信号失败: {}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+    }
+
+    info!("🛑 收到关闭信号，正在断开所有WebSocket连接...");
+    state.begin_shutdown();
+}
@@ -0,0 +1,88 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use anyhow::Result;
+
+use crate::database::DbPool;
+
+/// 匹配所有节点的规则node_id
+pub const ALL_NODES: &str = "*";
+
+/// 告警规则
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct AlertRule {
+    pub id: i64,
+    /// 节点ID，`*`表示所有节点
+    pub node_id: String,
+    /// 监控指标：cpu_usage / memory_usage / disk_usage / load_average
+    pub metric: String,
+    /// 比较方式：> / >= / < / <=
+    pub comparison: String,
+    pub threshold: f64,
+    /// 持续超过阈值多少秒后触发
+    pub duration_secs: i64,
+    pub enabled: bool,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AlertRuleCreate {
+    pub node_id: Option<String>,
+    pub metric: String,
+    pub comparison: String,
+    pub threshold: f64,
+    pub duration_secs: Option<i64>,
+}
+
+impl AlertRule {
+    /// 创建告警规则
+    pub async fn create(pool: &DbPool, rule_data: AlertRuleCreate) -> Result<AlertRule> {
+        let rule = sqlx::query_as::<_, AlertRule>(r#"
+            INSERT INTO alert_rules (node_id, metric, comparison, threshold, duration_secs)
+            VALUES (?, ?, ?, ?, ?)
+            RETURNING *
+        "#)
+        .bind(rule_data.node_id.as_deref().unwrap_or(ALL_NODES))
+        .bind(&rule_data.metric)
+        .bind(&rule_data.comparison)
+        .bind(rule_data.threshold)
+        .bind(rule_data.duration_secs.unwrap_or(0))
+        .fetch_one(pool)
+        .await?;
+
+        Ok(rule)
+    }
+
+    /// 获取所有告警规则
+    pub async fn find_all(pool: &DbPool) -> Result<Vec<AlertRule>> {
+        let rules = sqlx::query_as::<_, AlertRule>("SELECT * FROM alert_rules ORDER BY id")
+            .fetch_all(pool)
+            .await?;
+
+        Ok(rules)
+    }
+
+    /// 获取已启用的告警规则
+    pub async fn find_enabled(pool: &DbPool) -> Result<Vec<AlertRule>> {
+        let rules = sqlx::query_as::<_, AlertRule>("SELECT * FROM alert_rules WHERE enabled = 1 ORDER BY id")
+            .fetch_all(pool)
+            .await?;
+
+        Ok(rules)
+    }
+
+    /// 删除告警规则
+    pub async fn delete(pool: &DbPool, id: i64) -> Result<bool> {
+        let result = sqlx::query("DELETE FROM alert_rules WHERE id = ?")
+            .bind(id)
+            .execute(pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// 规则是否适用于指定节点
+    pub fn applies_to(&self, node_id: &str) -> bool {
+        self.node_id == ALL_NODES || self.node_id == node_id
+    }
+}
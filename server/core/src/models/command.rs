@@ -1,8 +1,10 @@
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use sqlx::{FromRow, SqlitePool};
+use sqlx::FromRow;
 use anyhow::Result;
 
+use crate::database::DbPool;
+
 #[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
 pub struct Command {
     pub id: i64,
@@ -13,6 +15,12 @@ pub struct Command {
     pub created_at: DateTime<Utc>,
     pub started_at: Option<DateTime<Utc>>,
     pub completed_at: Option<DateTime<Utc>>,
+    /// 计划执行时间（UTC），周期命令为下一次执行时间
+    pub scheduled_at: Option<DateTime<Utc>>,
+    /// 周期执行的cron表达式（UTC），设置后该命令作为计划，每次到期时创建一条新命令执行
+    pub cron: Option<String>,
+    /// 由周期计划创建的命令所属计划的command_id
+    pub schedule_id: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
@@ -31,6 +39,10 @@ pub struct CommandCreate {
     pub command_id: String,
     pub command_text: String,
     pub target_node_id: String,
+    /// 设置后命令以scheduled状态创建，到期后才下发
+    pub scheduled_at: Option<DateTime<Utc>>,
+    pub cron: Option<String>,
+    pub schedule_id: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -48,38 +60,56 @@ pub struct CommandWithResult {
     pub result: Option<CommandResult>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum CommandStatus {
+    /// 等待计划时间到达
+    Scheduled,
     Pending,
     Running,
     Success,
     Failed,
     Timeout,
+    /// 计划在执行前被取消
+    Cancelled,
 }
 
-impl ToString for CommandStatus {
-    fn to_string(&self) -> String {
-        match self {
-            CommandStatus::Pending => "pending".to_string(),
-            CommandStatus::Running => "running".to_string(),
-            CommandStatus::Success => "success".to_string(),
-            CommandStatus::Failed => "failed".to_string(),
-            CommandStatus::Timeout => "timeout".to_string(),
-        }
+impl std::fmt::Display for CommandStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let status = match self {
+            CommandStatus::Scheduled => "scheduled",
+            CommandStatus::Pending => "pending",
+            CommandStatus::Running => "running",
+            CommandStatus::Success => "success",
+            CommandStatus::Failed => "failed",
+            CommandStatus::Timeout => "timeout",
+            CommandStatus::Cancelled => "cancelled",
+        };
+        write!(f, "{}", status)
     }
 }
 
 impl Command {
     /// 创建新命令
-    pub async fn create(pool: &SqlitePool, command_data: CommandCreate) -> Result<Command> {
+    pub async fn create(pool: &DbPool, command_data: CommandCreate) -> Result<Command> {
+        let status = if command_data.scheduled_at.is_some() {
+            CommandStatus::Scheduled
+        } else {
+            CommandStatus::Pending
+        };
+        
         let command = sqlx::query_as::<_, Command>(r#"
-            INSERT INTO commands (command_id, command_text, target_node_id)
-            VALUES (?, ?, ?)
+            INSERT INTO commands (command_id, command_text, target_node_id, status, scheduled_at, cron, schedule_id)
+            VALUES (?, ?, ?, ?, ?, ?, ?)
             RETURNING *
         "#)
         .bind(&command_data.command_id)
         .bind(&command_data.command_text)
         .bind(&command_data.target_node_id)
+        .bind(status.to_string())
+        .bind(command_data.scheduled_at)
+        .bind(&command_data.cron)
+        .bind(&command_data.schedule_id)
         .fetch_one(pool)
         .await?;
         
@@ -87,7 +117,7 @@ impl Command {
     }
     
     /// 根据command_id查找命令
-    pub async fn find_by_id(pool: &SqlitePool, command_id: &str) -> Result<Option<Command>> {
+    pub async fn find_by_id(pool: &DbPool, command_id: &str) -> Result<Option<Command>> {
         let command = sqlx::query_as::<_, Command>("SELECT * FROM commands WHERE command_id = ?")
             .bind(command_id)
             .fetch_optional(pool)
@@ -97,9 +127,9 @@ impl Command {
     }
     
     /// 获取待执行的命令
-    pub async fn find_pending(pool: &SqlitePool, node_id: &str) -> Result<Vec<Command>> {
+    pub async fn find_pending(pool: &DbPool, node_id: &str) -> Result<Vec<Command>> {
         let commands = sqlx::query_as::<_, Command>(
-            "SELECT * FROM commands WHERE target_node_id = ? AND status = 'pending' ORDER BY created_at ASC"
+            "SELECT * FROM commands WHERE target_node_id = ? AND status = 'pending' ORDER BY created_at ASC, id ASC"
         )
         .bind(node_id)
         .fetch_all(pool)
@@ -108,27 +138,75 @@ impl Command {
         Ok(commands)
     }
     
-    /// 获取指定节点的命令历史
-    pub async fn find_by_node(pool: &SqlitePool, node_id: &str, limit: Option<i64>) -> Result<Vec<Command>> {
-        let mut sql = "SELECT * FROM commands WHERE target_node_id = ? ORDER BY created_at DESC".to_string();
-        
-        if limit.is_some() {
-            sql.push_str(" LIMIT ?");
-        }
+    /// 获取计划时间已到的定时命令
+    pub async fn find_due(pool: &DbPool, now: DateTime<Utc>) -> Result<Vec<Command>> {
+        let commands = sqlx::query_as::<_, Command>(
+            "SELECT * FROM commands WHERE status = 'scheduled' AND scheduled_at <= ? ORDER BY scheduled_at ASC"
+        )
+        .bind(now)
+        .fetch_all(pool)
+        .await?;
         
-        let mut query_builder = sqlx::query_as::<_, Command>(&sql).bind(node_id);
+        Ok(commands)
+    }
+    
+    /// 更新周期命令的下一次执行时间
+    pub async fn reschedule(pool: &DbPool, command_id: &str, scheduled_at: DateTime<Utc>) -> Result<()> {
+        sqlx::query("UPDATE commands SET scheduled_at = ? WHERE command_id = ? AND status = 'scheduled'")
+            .bind(scheduled_at)
+            .bind(command_id)
+            .execute(pool)
+            .await?;
         
-        if let Some(limit) = limit {
-            query_builder = query_builder.bind(limit);
-        }
+        Ok(())
+    }
+    
+    /// 取消尚未执行的定时命令，返回是否取消成功（命令不存在或已开始执行时返回false）
+    pub async fn cancel(pool: &DbPool, command_id: &str) -> Result<bool> {
+        let result = sqlx::query(
+            "UPDATE commands SET status = 'cancelled', completed_at = CURRENT_TIMESTAMP WHERE command_id = ? AND status = 'scheduled'"
+        )
+        .bind(command_id)
+        .execute(pool)
+        .await?;
         
-        let commands = query_builder.fetch_all(pool).await?;
-        Ok(commands)
+        Ok(result.rows_affected() > 0)
+    }
+    
+    /// 获取指定节点的命令历史（分页，可按状态过滤），同时返回总数
+    pub async fn find_by_node(
+        pool: &DbPool,
+        node_id: &str,
+        status: Option<CommandStatus>,
+        limit: i64,
+        offset: i64,
+    ) -> Result<(Vec<Command>, i64)> {
+        const WHERE_CLAUSE: &str = "WHERE target_node_id = ?1 AND (?2 IS NULL OR status = ?2)";
+        let status = status.map(|status| status.to_string());
+
+        let commands = sqlx::query_as::<_, Command>(&format!(
+            "SELECT * FROM commands {} ORDER BY created_at DESC, id DESC LIMIT ?3 OFFSET ?4",
+            WHERE_CLAUSE
+        ))
+        .bind(node_id)
+        .bind(status.as_deref())
+        .bind(limit.max(0))
+        .bind(offset.max(0))
+        .fetch_all(pool)
+        .await?;
+
+        let total: i64 = sqlx::query_scalar(&format!("SELECT COUNT(*) FROM commands {}", WHERE_CLAUSE))
+            .bind(node_id)
+            .bind(status.as_deref())
+            .fetch_one(pool)
+            .await?;
+
+        Ok((commands, total))
     }
     
     /// 更新命令状态
     pub async fn update_status(
-        pool: &SqlitePool, 
+        pool: &DbPool, 
         command_id: &str, 
         status: CommandStatus
     ) -> Result<()> {
@@ -138,10 +216,10 @@ impl Command {
             CommandStatus::Running => {
                 "UPDATE commands SET status = ?, started_at = CURRENT_TIMESTAMP WHERE command_id = ?"
             }
-            CommandStatus::Success | CommandStatus::Failed | CommandStatus::Timeout => {
+            CommandStatus::Success | CommandStatus::Failed | CommandStatus::Timeout | CommandStatus::Cancelled => {
                 "UPDATE commands SET status = ?, completed_at = CURRENT_TIMESTAMP WHERE command_id = ?"
             }
-            CommandStatus::Pending => {
+            CommandStatus::Scheduled | CommandStatus::Pending => {
                 "UPDATE commands SET status = ? WHERE command_id = ?"
             }
         };
@@ -155,33 +233,10 @@ impl Command {
         Ok(())
     }
     
-    /// 获取所有命令 (分页)
-    pub async fn find_all(pool: &SqlitePool, offset: i64, limit: i64) -> Result<Vec<Command>> {
-        let commands = sqlx::query_as::<_, Command>(
-            "SELECT * FROM commands ORDER BY created_at DESC LIMIT ? OFFSET ?"
-        )
-        .bind(limit)
-        .bind(offset)
-        .fetch_all(pool)
-        .await?;
-        
-        Ok(commands)
-    }
-    
-    /// 删除命令
-    pub async fn delete(pool: &SqlitePool, command_id: &str) -> Result<bool> {
-        let result = sqlx::query("DELETE FROM commands WHERE command_id = ?")
-            .bind(command_id)
-            .execute(pool)
-            .await?;
-            
-        Ok(result.rows_affected() > 0)
-    }
-    
-    /// 清理过期命令
-    pub async fn cleanup_old_commands(pool: &SqlitePool, days_to_keep: i64) -> Result<u64> {
+    /// 清理过期命令（仍在计划中的定时命令不会被清理）
+    pub async fn cleanup_old_commands(pool: &DbPool, days_to_keep: i64) -> Result<u64> {
         let result = sqlx::query(
-            "DELETE FROM commands WHERE created_at < datetime('now', '-' || ? || ' days')"
+            "DELETE FROM commands WHERE created_at < datetime('now', '-' || ? || ' days') AND status != 'scheduled'"
         )
         .bind(days_to_keep)
         .execute(pool)
@@ -193,7 +248,7 @@ impl Command {
 
 impl CommandResult {
     /// 创建命令执行结果
-    pub async fn create(pool: &SqlitePool, result_data: CommandResultCreate) -> Result<CommandResult> {
+    pub async fn create(pool: &DbPool, result_data: CommandResultCreate) -> Result<CommandResult> {
         let result = sqlx::query_as::<_, CommandResult>(r#"
             INSERT INTO command_results (command_id, stdout, stderr, exit_code, execution_time_ms)
             VALUES (?, ?, ?, ?, ?)
@@ -211,7 +266,7 @@ impl CommandResult {
     }
     
     /// 根据command_id查找执行结果
-    pub async fn find_by_command_id(pool: &SqlitePool, command_id: &str) -> Result<Option<CommandResult>> {
+    pub async fn find_by_command_id(pool: &DbPool, command_id: &str) -> Result<Option<CommandResult>> {
         let result = sqlx::query_as::<_, CommandResult>("SELECT * FROM command_results WHERE command_id = ?")
             .bind(command_id)
             .fetch_optional(pool)
@@ -221,7 +276,7 @@ impl CommandResult {
     }
     
     /// 获取命令和结果的组合信息
-    pub async fn get_command_with_result(pool: &SqlitePool, command_id: &str) -> Result<Option<CommandWithResult>> {
+    pub async fn get_command_with_result(pool: &DbPool, command_id: &str) -> Result<Option<CommandWithResult>> {
         let command = Command::find_by_id(pool, command_id).await?;
         
         if let Some(cmd) = command {
@@ -235,9 +290,15 @@ impl CommandResult {
         }
     }
     
-    /// 获取节点的命令历史（包含结果）
-    pub async fn get_node_command_history(pool: &SqlitePool, node_id: &str, limit: Option<i64>) -> Result<Vec<CommandWithResult>> {
-        let commands = Command::find_by_node(pool, node_id, limit).await?;
+    /// 获取节点的命令历史（包含结果，分页），同时返回总数
+    pub async fn get_node_command_history(
+        pool: &DbPool,
+        node_id: &str,
+        status: Option<CommandStatus>,
+        limit: i64,
+        offset: i64,
+    ) -> Result<(Vec<CommandWithResult>, i64)> {
+        let (commands, total) = Command::find_by_node(pool, node_id, status, limit, offset).await?;
         let mut results = Vec::new();
         
         for command in commands {
@@ -248,6 +309,72 @@ impl CommandResult {
             });
         }
         
-        Ok(results)
+        Ok((results, total))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::TempDatabaseFile;
+    use crate::models::{Node, NodeCreate};
+
+    #[tokio::test]
+    async fn test_node_command_history() {
+        let db_file = TempDatabaseFile::new("command");
+        let db = db_file.open().await;
+
+        Node::create(&db.pool, NodeCreate {
+            node_id: "node-1".to_string(),
+            hostname: "host-1".to_string(),
+            ip_address: "10.0.0.1".to_string(),
+            ..Default::default()
+        }).await.unwrap();
+
+        for i in 0..3 {
+            Command::create(&db.pool, CommandCreate {
+                command_id: format!("cmd-{}", i),
+                command_text: format!("echo {}", i),
+                target_node_id: "node-1".to_string(),
+                scheduled_at: None,
+                cron: None,
+                schedule_id: None,
+            }).await.unwrap();
+        }
+        CommandResult::create(&db.pool, CommandResultCreate {
+            command_id: "cmd-2".to_string(),
+            stdout: Some("2\n".to_string()),
+            stderr: None,
+            exit_code: Some(0),
+            execution_time_ms: Some(5),
+        }).await.unwrap();
+
+        // 按时间倒序，最新的命令在前
+        let (history, total) = CommandResult::get_node_command_history(&db.pool, "node-1", None, 2, 0).await.unwrap();
+        assert_eq!(total, 3);
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[0].command.command_id, "cmd-2");
+        assert_eq!(history[0].result.as_ref().and_then(|r| r.exit_code), Some(0));
+        assert!(history[1].result.is_none());
+
+        let (history, _) = CommandResult::get_node_command_history(&db.pool, "node-1", None, 2, 2).await.unwrap();
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].command.command_id, "cmd-0");
+
+        // 按状态过滤时总数同样只统计匹配的命令
+        Command::update_status(&db.pool, "cmd-2", CommandStatus::Success).await.unwrap();
+        Command::update_status(&db.pool, "cmd-0", CommandStatus::Failed).await.unwrap();
+        let (history, total) = CommandResult::get_node_command_history(&db.pool, "node-1", Some(CommandStatus::Failed), 10, 0).await.unwrap();
+        assert_eq!(total, 1);
+        assert_eq!(history[0].command.command_id, "cmd-0");
+        let (history, total) = CommandResult::get_node_command_history(&db.pool, "node-1", Some(CommandStatus::Pending), 10, 0).await.unwrap();
+        assert_eq!(total, 1);
+        assert_eq!(history[0].command.command_id, "cmd-1");
+        let (_, total) = CommandResult::get_node_command_history(&db.pool, "node-1", Some(CommandStatus::Timeout), 10, 0).await.unwrap();
+        assert_eq!(total, 0);
+
+        let single = CommandResult::get_command_with_result(&db.pool, "cmd-2").await.unwrap().unwrap();
+        assert_eq!(single.result.unwrap().stdout.as_deref(), Some("2\n"));
+        assert!(CommandResult::get_command_with_result(&db.pool, "missing").await.unwrap().is_none());
     }
-}
\ No newline at end of file
+}
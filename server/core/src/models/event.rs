@@ -0,0 +1,167 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use anyhow::Result;
+
+use crate::database::DbPool;
+use crate::models::to_sqlite_datetime;
+
+/// 节点注册（包括重连后重新注册）
+pub const EVENT_NODE_REGISTERED: &str = "node_registered";
+/// 节点连接断开
+pub const EVENT_NODE_DISCONNECTED: &str = "node_disconnected";
+/// 节点主动注销
+pub const EVENT_NODE_DEREGISTERED: &str = "node_deregistered";
+/// 节点状态变化（如长时间无心跳被标记为失联）
+pub const EVENT_NODE_STATUS_CHANGE: &str = "node_status_change";
+/// 节点重启
+pub const EVENT_NODE_REBOOT: &str = "node_reboot";
+/// 命令下发到节点
+pub const EVENT_COMMAND_DISPATCHED: &str = "command_dispatched";
+/// 告警触发
+pub const EVENT_ALERT_FIRED: &str = "alert_fired";
+/// 告警恢复
+pub const EVENT_ALERT_RESOLVED: &str = "alert_resolved";
+/// 指标明显偏离近期基线
+pub const EVENT_ANOMALY: &str = "anomaly";
+
+/// 事件日志记录
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct Event {
+    pub id: i64,
+    /// 相关节点，节点删除后仍然保留
+    pub node_id: Option<String>,
+    /// 事件类型，如`node_reboot`
+    pub event_type: String,
+    /// 事件相关数据（数据库中以JSON对象存储）
+    #[sqlx(json)]
+    pub details: serde_json::Value,
+    /// 事件发生时间
+    pub occurred_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// 事件查询条件，未设置的条件不过滤
+#[derive(Debug, Default)]
+pub struct EventFilter {
+    pub node_id: Option<String>,
+    /// 事件类型，匹配其中任意一个
+    pub event_types: Vec<String>,
+    pub start_time: Option<DateTime<Utc>>,
+    pub end_time: Option<DateTime<Utc>>,
+}
+
+impl Event {
+    /// 记录事件
+    pub async fn create(
+        pool: &DbPool,
+        node_id: Option<&str>,
+        event_type: &str,
+        details: &serde_json::Value,
+        occurred_at: DateTime<Utc>,
+    ) -> Result<Event> {
+        let event = sqlx::query_as::<_, Event>(r#"
+            INSERT INTO events (node_id, event_type, details, occurred_at)
+            VALUES (?, ?, ?, ?)
+            RETURNING *
+        "#)
+        .bind(node_id)
+        .bind(event_type)
+        .bind(details.to_string())
+        .bind(to_sqlite_datetime(&occurred_at))
+        .fetch_one(pool)
+        .await?;
+
+        Ok(event)
+    }
+
+    /// 按条件分页查询事件（按发生时间倒序），同时返回符合条件的总数
+    pub async fn find(pool: &DbPool, filter: &EventFilter, limit: i64, offset: i64) -> Result<(Vec<Event>, i64)> {
+        const CONDITIONS: &str = r#"
+            WHERE (?1 IS NULL OR node_id = ?1)
+            AND (?2 = '[]' OR event_type IN (SELECT value FROM json_each(?2)))
+            AND (?3 IS NULL OR occurred_at >= ?3)
+            AND (?4 IS NULL OR occurred_at <= ?4)
+        "#;
+        let event_types = serde_json::to_string(&filter.event_types)?;
+        let start_time = filter.start_time.as_ref().map(to_sqlite_datetime);
+        let end_time = filter.end_time.as_ref().map(to_sqlite_datetime);
+
+        let events = sqlx::query_as::<_, Event>(&format!(
+            "SELECT * FROM events {} ORDER BY occurred_at DESC, id DESC LIMIT ?5 OFFSET ?6",
+            CONDITIONS
+        ))
+        .bind(&filter.node_id)
+        .bind(&event_types)
+        .bind(&start_time)
+        .bind(&end_time)
+        .bind(limit.max(0))
+        .bind(offset.max(0))
+        .fetch_all(pool)
+        .await?;
+
+        let total: i64 = sqlx::query_scalar(&format!("SELECT COUNT(*) FROM events {}", CONDITIONS))
+            .bind(&filter.node_id)
+            .bind(&event_types)
+            .bind(&start_time)
+            .bind(&end_time)
+            .fetch_one(pool)
+            .await?;
+
+        Ok((events, total))
+    }
+
+    /// 删除超过保留天数的事件
+    pub async fn cleanup_old_events(pool: &DbPool, days_to_keep: i64) -> Result<u64> {
+        let result = sqlx::query("DELETE FROM events WHERE occurred_at < datetime('now', '-' || ? || ' days')")
+            .bind(days_to_keep)
+            .execute(pool)
+            .await?;
+
+        Ok(result.rows_affected())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::TempDatabaseFile;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn test_event_filters() {
+        let db_file = TempDatabaseFile::new("event");
+        let db = db_file.open().await;
+
+        let start = DateTime::parse_from_rfc3339("2025-01-21T10:00:00Z").unwrap().with_timezone(&Utc);
+        let minutes = |minutes: i64| start + chrono::Duration::minutes(minutes);
+        Event::create(&db.pool, Some("node-1"), EVENT_NODE_REGISTERED, &json!({ "hostname": "host-1" }), minutes(0)).await.unwrap();
+        Event::create(&db.pool, Some("node-1"), EVENT_ALERT_FIRED, &json!({ "value": 95.0 }), minutes(10)).await.unwrap();
+        Event::create(&db.pool, Some("node-2"), EVENT_NODE_REBOOT, &json!({}), minutes(20)).await.unwrap();
+        // 事件不依赖节点记录，节点不存在或删除后仍可记录和查询
+        Event::create(&db.pool, None, EVENT_NODE_STATUS_CHANGE, &json!({}), minutes(30)).await.unwrap();
+
+        let (events, total) = Event::find(&db.pool, &EventFilter::default(), 2, 0).await.unwrap();
+        assert_eq!(total, 4);
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].event_type, EVENT_NODE_STATUS_CHANGE);
+
+        let filter = EventFilter { node_id: Some("node-1".to_string()), ..Default::default() };
+        let (events, total) = Event::find(&db.pool, &filter, 10, 0).await.unwrap();
+        assert_eq!(total, 2);
+        assert_eq!(events[1].details["hostname"], "host-1");
+
+        let filter = EventFilter {
+            event_types: vec![EVENT_ALERT_FIRED.to_string(), EVENT_NODE_REBOOT.to_string()],
+            start_time: Some(minutes(5)),
+            end_time: Some(minutes(20)),
+            ..Default::default()
+        };
+        let (events, total) = Event::find(&db.pool, &filter, 10, 0).await.unwrap();
+        assert_eq!(total, 2);
+        assert_eq!(events[0].node_id.as_deref(), Some("node-2"));
+
+        // 2025年的事件早于保留期限
+        assert_eq!(Event::cleanup_old_events(&db.pool, 30).await.unwrap(), 4);
+    }
+}
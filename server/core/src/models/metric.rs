@@ -1,8 +1,12 @@
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use sqlx::{FromRow, SqlitePool};
+use sqlx::FromRow;
 use anyhow::Result;
 use sqlx::Row;
+use futures_util::stream::BoxStream;
+
+use crate::database::DbPool;
+
 #[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
 pub struct NodeMetric {
     pub id: i64,
@@ -18,9 +22,15 @@ pub struct NodeMetric {
     pub memory_available: Option<i64>,
     pub uptime: Option<i64>,
     pub created_at: DateTime<Utc>,
+    /// 网络累计接收字节数（节点重启后归零）
+    pub network_rx_bytes: Option<i64>,
+    pub network_tx_bytes: Option<i64>,
+    /// 磁盘累计读取字节数
+    pub disk_read_bytes: Option<i64>,
+    pub disk_write_bytes: Option<i64>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MetricCreate {
     pub node_id: String,
     pub cpu_usage: Option<f64>,
@@ -32,14 +42,12 @@ pub struct MetricCreate {
     pub memory_total: Option<i64>,
     pub memory_available: Option<i64>,
     pub uptime: Option<i64>,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-pub struct MetricQuery {
-    pub node_id: Option<String>,
-    pub start_time: Option<DateTime<Utc>>,
-    pub end_time: Option<DateTime<Utc>>,
-    pub limit: Option<i64>,
+    pub network_rx_bytes: Option<i64>,
+    pub network_tx_bytes: Option<i64>,
+    pub disk_read_bytes: Option<i64>,
+    pub disk_write_bytes: Option<i64>,
+    /// 采集时间，缺省时使用入库时间
+    pub metric_time: Option<DateTime<Utc>>,
 }
 
 #[derive(Debug, Serialize, Deserialize, FromRow)]
@@ -53,21 +61,68 @@ pub struct MetricSummary {
     pub max_disk_usage: Option<f64>,
     pub avg_load_average: Option<f64>,
     pub max_load_average: Option<f64>,
+    #[sqlx(skip)]
+    pub p95_cpu_usage: Option<f64>,
+    #[sqlx(skip)]
+    pub p99_cpu_usage: Option<f64>,
+    #[sqlx(skip)]
+    pub p95_memory_usage: Option<f64>,
+    #[sqlx(skip)]
+    pub p99_memory_usage: Option<f64>,
+    #[sqlx(skip)]
+    pub network_rx_rate: Option<CounterRate>,
+    #[sqlx(skip)]
+    pub network_tx_rate: Option<CounterRate>,
+    #[sqlx(skip)]
+    pub disk_read_rate: Option<CounterRate>,
+    #[sqlx(skip)]
+    pub disk_write_rate: Option<CounterRate>,
+    pub sample_count: i64,
+}
+
+/// 累计计数器（如网络、磁盘字节数）在时间范围内的吞吐速率
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CounterRate {
+    /// 时间范围内累计增加的字节数
+    pub total_bytes: i64,
+    /// 平均速率（总增量 / 总时长）
+    pub avg_bytes_per_sec: f64,
+    /// 相邻两个样本之间的最大速率
+    pub max_bytes_per_sec: f64,
+}
+
+/// 按时间桶聚合的监控数据
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct MetricBucket {
+    /// 时间桶起始时间
+    pub bucket_time: DateTime<Utc>,
+    pub avg_cpu_usage: Option<f64>,
+    pub max_cpu_usage: Option<f64>,
+    pub avg_memory_usage: Option<f64>,
+    pub max_memory_usage: Option<f64>,
+    pub avg_disk_usage: Option<f64>,
+    pub max_disk_usage: Option<f64>,
+    pub avg_load_average: Option<f64>,
+    pub max_load_average: Option<f64>,
     pub sample_count: i64,
 }
 
 impl NodeMetric {
-    /// 创建新的监控记录
-    pub async fn create(pool: &SqlitePool, metric_data: MetricCreate) -> Result<NodeMetric> {
+    /// 创建新的监控记录（未提供采集时间时使用当前时间）
+    pub async fn create(pool: &DbPool, metric_data: MetricCreate) -> Result<NodeMetric> {
+        let metric_time = to_sqlite_datetime(&metric_data.metric_time.unwrap_or_else(Utc::now));
+        
         let metric = sqlx::query_as::<_, NodeMetric>(r#"
             INSERT INTO node_metrics (
                 node_id, metric_time, cpu_usage, memory_usage, disk_usage, 
-                disk_total, disk_available, load_average, memory_total, memory_available, uptime
+                disk_total, disk_available, load_average, memory_total, memory_available, uptime,
+                network_rx_bytes, network_tx_bytes, disk_read_bytes, disk_write_bytes
             )
-            VALUES (?, CURRENT_TIMESTAMP, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
             RETURNING *
         "#)
         .bind(&metric_data.node_id)
+        .bind(metric_time)
         .bind(metric_data.cpu_usage)
         .bind(metric_data.memory_usage)
         .bind(metric_data.disk_usage)
@@ -77,25 +132,33 @@ impl NodeMetric {
         .bind(metric_data.memory_total)
         .bind(metric_data.memory_available)
         .bind(metric_data.uptime)
+        .bind(metric_data.network_rx_bytes)
+        .bind(metric_data.network_tx_bytes)
+        .bind(metric_data.disk_read_bytes)
+        .bind(metric_data.disk_write_bytes)
         .fetch_one(pool)
         .await?;
         
         Ok(metric)
     }
     
-    /// 批量创建监控记录
-    pub async fn create_batch(pool: &SqlitePool, metrics: Vec<MetricCreate>) -> Result<()> {
+    /// 批量创建监控记录（单个事务，保留每条样本的采集时间）
+    pub async fn create_batch(pool: &DbPool, metrics: Vec<MetricCreate>) -> Result<()> {
         let mut tx = pool.begin().await?;
         
         for metric_data in metrics {
+            let metric_time = to_sqlite_datetime(&metric_data.metric_time.unwrap_or_else(Utc::now));
+            
             sqlx::query(r#"
                 INSERT INTO node_metrics (
-                    node_id, metric_time, cpu_usage, memory_usage, disk_usage, 
-                    disk_total, disk_available, load_average, memory_total, memory_available, uptime
+                        node_id, metric_time, cpu_usage, memory_usage, disk_usage, 
+                    disk_total, disk_available, load_average, memory_total, memory_available, uptime,
+                    network_rx_bytes, network_tx_bytes, disk_read_bytes, disk_write_bytes
                 )
-                VALUES (?, CURRENT_TIMESTAMP, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+                VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
             "#)
             .bind(&metric_data.node_id)
+            .bind(metric_time)
             .bind(metric_data.cpu_usage)
             .bind(metric_data.memory_usage)
             .bind(metric_data.disk_usage)
@@ -105,6 +168,10 @@ impl NodeMetric {
             .bind(metric_data.memory_total)
             .bind(metric_data.memory_available)
             .bind(metric_data.uptime)
+            .bind(metric_data.network_rx_bytes)
+            .bind(metric_data.network_tx_bytes)
+            .bind(metric_data.disk_read_bytes)
+            .bind(metric_data.disk_write_bytes)
             .execute(&mut *tx)
             .await?;
         }
@@ -113,58 +180,9 @@ impl NodeMetric {
         Ok(())
     }
     
-    /// 查询监控数据
-    pub async fn find_by_query(pool: &SqlitePool, query: MetricQuery) -> Result<Vec<NodeMetric>> {
-        let mut sql = String::from("SELECT * FROM node_metrics WHERE 1=1");
-        let mut conditions = Vec::new();
-        
-        if query.node_id.is_some() {
-            sql.push_str(" AND node_id = ?");
-            conditions.push("node_id");
-        }
-        
-        if query.start_time.is_some() {
-            sql.push_str(" AND metric_time >= ?");
-            conditions.push("start_time");
-        }
-        
-        if query.end_time.is_some() {
-            sql.push_str(" AND metric_time <= ?");
-            conditions.push("end_time");
-        }
-        
-        sql.push_str(" ORDER BY metric_time DESC");
-        
-        if query.limit.is_some() {
-            sql.push_str(" LIMIT ?");
-            conditions.push("limit");
-        }
-        
-        let mut query_builder = sqlx::query_as::<_, NodeMetric>(&sql);
-        
-        if let Some(ref node_id) = query.node_id {
-            query_builder = query_builder.bind(node_id);
-        }
-        
-        if let Some(start_time) = query.start_time {
-            query_builder = query_builder.bind(start_time);
-        }
-        
-        if let Some(end_time) = query.end_time {
-            query_builder = query_builder.bind(end_time);
-        }
-        
-        if let Some(limit) = query.limit {
-            query_builder = query_builder.bind(limit);
-        }
-        
-        let metrics = query_builder.fetch_all(pool).await?;
-        Ok(metrics)
-    }
-
     /// 根据节点ID和时间范围查询监控数据（带分页）
     pub async fn find_by_node_id_with_range(
-        pool: &SqlitePool,
+        pool: &DbPool,
         node_id: &str,
         start_time: Option<DateTime<Utc>>,
         end_time: Option<DateTime<Utc>>,
@@ -195,13 +213,13 @@ impl NodeMetric {
             .bind(node_id);
         
         if let Some(start_time) = start_time {
-            query_builder = query_builder.bind(start_time);
-            count_builder = count_builder.bind(start_time);
+            query_builder = query_builder.bind(to_sqlite_datetime(&start_time));
+            count_builder = count_builder.bind(to_sqlite_datetime(&start_time));
         }
         
         if let Some(end_time) = end_time {
-            query_builder = query_builder.bind(end_time);
-            count_builder = count_builder.bind(end_time);
+            query_builder = query_builder.bind(to_sqlite_datetime(&end_time));
+            count_builder = count_builder.bind(to_sqlite_datetime(&end_time));
         }
         
         let metrics = query_builder
@@ -219,18 +237,98 @@ impl NodeMetric {
         Ok((metrics, total))
     }
 
+    /// 按固定时间间隔聚合节点监控数据（每个时间桶一行，带分页）
+    pub async fn find_bucketed(
+        pool: &DbPool,
+        node_id: &str,
+        start_time: Option<DateTime<Utc>>,
+        end_time: Option<DateTime<Utc>>,
+        interval_secs: i64,
+        limit: i64,
+        offset: i64,
+    ) -> Result<(Vec<MetricBucket>, i64)> {
+        const BUCKETED: &str = r#"
+            SELECT
+                (CAST(strftime('%s', metric_time) AS INTEGER) / ?4) * ?4 AS bucket,
+                AVG(cpu_usage) AS avg_cpu_usage,
+                MAX(cpu_usage) AS max_cpu_usage,
+                AVG(memory_usage) AS avg_memory_usage,
+                MAX(memory_usage) AS max_memory_usage,
+                AVG(disk_usage) AS avg_disk_usage,
+                MAX(disk_usage) AS max_disk_usage,
+                AVG(load_average) AS avg_load_average,
+                MAX(load_average) AS max_load_average,
+                COUNT(*) AS sample_count
+            FROM node_metrics
+            WHERE node_id = ?1
+            AND (?2 IS NULL OR metric_time >= ?2)
+            AND (?3 IS NULL OR metric_time <= ?3)
+            GROUP BY bucket
+        "#;
+
+        let start = start_time.as_ref().map(to_sqlite_datetime);
+        let end = end_time.as_ref().map(to_sqlite_datetime);
+
+        let buckets = sqlx::query_as::<_, MetricBucket>(&format!(r#"
+            SELECT datetime(bucket, 'unixepoch') AS bucket_time,
+                avg_cpu_usage, max_cpu_usage, avg_memory_usage, max_memory_usage,
+                avg_disk_usage, max_disk_usage, avg_load_average, max_load_average, sample_count
+            FROM ({})
+            ORDER BY bucket DESC
+            LIMIT ?5 OFFSET ?6
+        "#, BUCKETED))
+        .bind(node_id)
+        .bind(&start)
+        .bind(&end)
+        .bind(interval_secs)
+        .bind(limit)
+        .bind(offset)
+        .fetch_all(pool)
+        .await?;
+
+        let total: i64 = sqlx::query_scalar(&format!("SELECT COUNT(*) FROM ({})", BUCKETED))
+            .bind(node_id)
+            .bind(&start)
+            .bind(&end)
+            .bind(interval_secs)
+            .fetch_one(pool)
+            .await?;
+
+        Ok((buckets, total))
+    }
+
+    /// 按时间升序流式读取节点在时间范围内的全部监控数据（不分页，用于导出）
+    pub fn stream_by_node_id_with_range<'a>(
+        pool: &'a DbPool,
+        node_id: &'a str,
+        start_time: Option<DateTime<Utc>>,
+        end_time: Option<DateTime<Utc>>,
+    ) -> BoxStream<'a, Result<NodeMetric, sqlx::Error>> {
+        sqlx::query_as::<_, NodeMetric>(r#"
+            SELECT * FROM node_metrics
+            WHERE node_id = ?1
+            AND (?2 IS NULL OR metric_time >= ?2)
+            AND (?3 IS NULL OR metric_time <= ?3)
+            ORDER BY metric_time ASC
+        "#)
+        .bind(node_id)
+        .bind(start_time.as_ref().map(to_sqlite_datetime))
+        .bind(end_time.as_ref().map(to_sqlite_datetime))
+        .fetch(pool)
+    }
+
     /// 获取节点最新监控数据（别名方法）
-    pub async fn find_latest_by_node_id(pool: &SqlitePool, node_id: &str) -> Result<Option<NodeMetric>> {
+    pub async fn find_latest_by_node_id(pool: &DbPool, node_id: &str) -> Result<Option<NodeMetric>> {
         Self::get_latest_by_node(pool, node_id).await
     }
 
     /// 获取所有节点最新监控数据（别名方法）
-    pub async fn find_all_latest(pool: &SqlitePool) -> Result<Vec<NodeMetric>> {
+    pub async fn find_all_latest(pool: &DbPool) -> Result<Vec<NodeMetric>> {
         Self::get_latest_all_nodes(pool).await
     }
     
     /// 获取节点最新监控数据
-    pub async fn get_latest_by_node(pool: &SqlitePool, node_id: &str) -> Result<Option<NodeMetric>> {
+    pub async fn get_latest_by_node(pool: &DbPool, node_id: &str) -> Result<Option<NodeMetric>> {
         let metric = sqlx::query_as::<_, NodeMetric>(
             "SELECT * FROM node_metrics WHERE node_id = ? ORDER BY metric_time DESC LIMIT 1"
         )
@@ -242,7 +340,7 @@ impl NodeMetric {
     }
     
     /// 获取所有节点的最新监控数据
-    pub async fn get_latest_all_nodes(pool: &SqlitePool) -> Result<Vec<NodeMetric>> {
+    pub async fn get_latest_all_nodes(pool: &DbPool) -> Result<Vec<NodeMetric>> {
         let metrics = sqlx::query_as::<_, NodeMetric>(r#"
             SELECT nm1.* FROM node_metrics nm1
             INNER JOIN (
@@ -260,7 +358,7 @@ impl NodeMetric {
     
     /// 获取监控数据统计摘要
     pub async fn get_summary(
-        pool: &SqlitePool, 
+        pool: &DbPool, 
         node_id: &str, 
         start_time: DateTime<Utc>, 
         end_time: DateTime<Utc>
@@ -282,19 +380,134 @@ impl NodeMetric {
             GROUP BY node_id
         "#)
         .bind(node_id)
-        .bind(start_time)
-        .bind(end_time)
+        .bind(to_sqlite_datetime(&start_time))
+        .bind(to_sqlite_datetime(&end_time))
         .fetch_optional(pool)
         .await?;
         
-        Ok(summary)
+        let Some(mut summary) = summary else {
+            return Ok(None);
+        };
+        
+        // SQLite没有百分位函数，按排序后的位置逐个取值
+        let [p95_cpu, p99_cpu] = Self::percentiles(pool, "cpu_usage", node_id, start_time, end_time, [95.0, 99.0]).await?;
+        let [p95_memory, p99_memory] = Self::percentiles(pool, "memory_usage", node_id, start_time, end_time, [95.0, 99.0]).await?;
+        summary.p95_cpu_usage = p95_cpu;
+        summary.p99_cpu_usage = p99_cpu;
+        summary.p95_memory_usage = p95_memory;
+        summary.p99_memory_usage = p99_memory;
+        
+        summary.network_rx_rate = Self::counter_rate(pool, "network_rx_bytes", node_id, start_time, end_time).await?;
+        summary.network_tx_rate = Self::counter_rate(pool, "network_tx_bytes", node_id, start_time, end_time).await?;
+        summary.disk_read_rate = Self::counter_rate(pool, "disk_read_bytes", node_id, start_time, end_time).await?;
+        summary.disk_write_rate = Self::counter_rate(pool, "disk_write_bytes", node_id, start_time, end_time).await?;
+        
+        Ok(Some(summary))
     }
     
-    /// 清理过期监控数据
-    pub async fn cleanup_old_metrics(pool: &SqlitePool, days_to_keep: i64) -> Result<u64> {
-        let result = sqlx::query(
-            "DELETE FROM node_metrics WHERE metric_time < datetime('now', '-' || ? || ' days')"
-        )
+    /// 计算指定列在时间范围内的百分位数（最近秩法），`column`必须是固定的列名
+    async fn percentiles<const N: usize>(
+        pool: &DbPool,
+        column: &'static str,
+        node_id: &str,
+        start_time: DateTime<Utc>,
+        end_time: DateTime<Utc>,
+        percents: [f64; N],
+    ) -> Result<[Option<f64>; N]> {
+        let filter = format!(
+            "FROM node_metrics WHERE node_id = ? AND metric_time BETWEEN ? AND ? AND {} IS NOT NULL",
+            column
+        );
+        let start = to_sqlite_datetime(&start_time);
+        let end = to_sqlite_datetime(&end_time);
+        
+        let count: i64 = sqlx::query_scalar(&format!("SELECT COUNT(*) {}", filter))
+            .bind(node_id)
+            .bind(&start)
+            .bind(&end)
+            .fetch_one(pool)
+            .await?;
+        
+        let mut values = [None; N];
+        if count == 0 {
+            return Ok(values);
+        }
+        
+        let value_sql = format!("SELECT {} {} ORDER BY {} LIMIT 1 OFFSET ?", column, filter, column);
+        for (value, percent) in values.iter_mut().zip(percents) {
+            *value = sqlx::query_scalar(&value_sql)
+                .bind(node_id)
+                .bind(&start)
+                .bind(&end)
+                .bind(percentile_rank(percent, count))
+                .fetch_optional(pool)
+                .await?;
+        }
+        
+        Ok(values)
+    }
+    
+    /// 按相邻样本的差值计算累计计数器的速率，`column`必须是固定的列名；少于两个样本时返回None
+    ///
+    /// 计数器比上一个样本小时视为已重置（如节点重启），本次增量取当前值
+    async fn counter_rate(
+        pool: &DbPool,
+        column: &'static str,
+        node_id: &str,
+        start_time: DateTime<Utc>,
+        end_time: DateTime<Utc>,
+    ) -> Result<Option<CounterRate>> {
+        let sql = format!(r#"
+            SELECT
+                SUM(delta) AS total_bytes,
+                SUM(delta) * 1.0 / SUM(seconds) AS avg_bytes_per_sec,
+                MAX(delta * 1.0 / seconds) AS max_bytes_per_sec
+            FROM (
+                SELECT
+                    CASE WHEN value >= previous THEN value - previous ELSE value END AS delta,
+                    CAST(strftime('%s', metric_time) AS INTEGER) - CAST(strftime('%s', previous_time) AS INTEGER) AS seconds
+                FROM (
+                    SELECT
+                        {column} AS value,
+                        metric_time,
+                        LAG({column}) OVER (ORDER BY metric_time, id) AS previous,
+                        LAG(metric_time) OVER (ORDER BY metric_time, id) AS previous_time
+                    FROM node_metrics
+                    WHERE node_id = ? AND metric_time BETWEEN ? AND ? AND {column} IS NOT NULL
+                )
+                WHERE previous IS NOT NULL
+            )
+            WHERE seconds > 0
+        "#);
+        let row = sqlx::query(&sql)
+            .bind(node_id)
+            .bind(to_sqlite_datetime(&start_time))
+            .bind(to_sqlite_datetime(&end_time))
+            .fetch_one(pool)
+            .await?;
+        
+        let Some(total_bytes) = row.get::<Option<i64>, _>("total_bytes") else {
+            return Ok(None);
+        };
+        Ok(Some(CounterRate {
+            total_bytes,
+            avg_bytes_per_sec: row.get("avg_bytes_per_sec"),
+            max_bytes_per_sec: row.get("max_bytes_per_sec"),
+        }))
+    }
+    
+    /// 清理过期监控数据，按节点的保留天数（管理员设置优先，其次节点上报）逐节点清理，均未设置时保留`days_to_keep`天
+    pub async fn cleanup_old_metrics(pool: &DbPool, days_to_keep: i64) -> Result<u64> {
+        let result = sqlx::query(r#"
+            DELETE FROM node_metrics
+            WHERE metric_time < datetime('now', '-' || COALESCE(
+                (
+                    SELECT COALESCE(metrics_retention_override_days, metrics_retention_days)
+                    FROM nodes WHERE nodes.node_id = node_metrics.node_id
+                ),
+                ?
+            ) || ' days')
+        "#)
         .bind(days_to_keep)
         .execute(pool)
         .await?;
@@ -302,3 +515,249 @@ impl NodeMetric {
         Ok(result.rows_affected())
     }
 }
+
+/// 最近秩法：第p百分位数对应升序排列后的第ceil(p/100*n)个值，返回从0开始的下标
+fn percentile_rank(percent: f64, count: i64) -> i64 {
+    let rank = (percent / 100.0 * count as f64).ceil() as i64;
+    rank.clamp(1, count) - 1
+}
+
+/// 转换为SQLite CURRENT_TIMESTAMP相同的格式，保证metric_time列可按字符串排序比较
+pub fn to_sqlite_datetime(time: &DateTime<Utc>) -> String {
+    time.format("%Y-%m-%d %H:%M:%S").to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::TempDatabaseFile;
+    use crate::models::{Node, NodeCreate};
+
+    #[tokio::test]
+    async fn test_metric_round_trip_all_columns() {
+        let db_file = TempDatabaseFile::new("metric");
+        let db = db_file.open().await;
+        
+        Node::create(&db.pool, NodeCreate {
+            node_id: "node-1".to_string(),
+            hostname: "host".to_string(),
+            ip_address: "10.0.0.1".to_string(),
+            ..Default::default()
+        }).await.unwrap();
+        
+        let metric_time = DateTime::parse_from_rfc3339("2025-01-21T10:00:00Z").unwrap().with_timezone(&Utc);
+        NodeMetric::create(&db.pool, MetricCreate {
+            node_id: "node-1".to_string(),
+            cpu_usage: Some(12.5),
+            memory_usage: Some(40.0),
+            disk_usage: Some(70.0),
+            disk_total: Some(1000),
+            disk_available: Some(300),
+            load_average: Some(0.5),
+            memory_total: Some(2048),
+            memory_available: Some(1024),
+            uptime: Some(3600),
+            network_rx_bytes: None,
+            network_tx_bytes: None,
+            disk_read_bytes: None,
+            disk_write_bytes: None,
+            metric_time: Some(metric_time),
+        }).await.unwrap();
+        
+        let metric = NodeMetric::get_latest_by_node(&db.pool, "node-1").await.unwrap().unwrap();
+        assert_eq!(metric.metric_time, metric_time);
+        assert_eq!(metric.cpu_usage, Some(12.5));
+        assert_eq!(metric.memory_usage, Some(40.0));
+        assert_eq!(metric.disk_usage, Some(70.0));
+        assert_eq!(metric.disk_total, Some(1000));
+        assert_eq!(metric.disk_available, Some(300));
+        assert_eq!(metric.load_average, Some(0.5));
+        assert_eq!(metric.memory_total, Some(2048));
+        assert_eq!(metric.memory_available, Some(1024));
+        assert_eq!(metric.uptime, Some(3600));
+    }
+
+    #[tokio::test]
+    async fn test_find_bucketed() {
+        let db_file = TempDatabaseFile::new("metric");
+        let db = db_file.open().await;
+        
+        Node::create(&db.pool, NodeCreate {
+            node_id: "node-1".to_string(),
+            hostname: "host".to_string(),
+            ip_address: "10.0.0.1".to_string(),
+            ..Default::default()
+        }).await.unwrap();
+        
+        // 按5分钟聚合后前两条落在同一个时间桶中
+        for (time, cpu) in [("2025-01-21T10:00:00Z", 12.5), ("2025-01-21T10:02:00Z", 30.0), ("2025-01-21T10:06:00Z", 50.0)] {
+            NodeMetric::create(&db.pool, MetricCreate {
+                node_id: "node-1".to_string(),
+                cpu_usage: Some(cpu),
+                memory_usage: None,
+                disk_usage: None,
+                disk_total: None,
+                disk_available: None,
+                load_average: None,
+                memory_total: None,
+                memory_available: None,
+                uptime: None,
+                network_rx_bytes: None,
+                network_tx_bytes: None,
+                disk_read_bytes: None,
+                disk_write_bytes: None,
+                metric_time: Some(DateTime::parse_from_rfc3339(time).unwrap().with_timezone(&Utc)),
+            }).await.unwrap();
+        }
+        
+        let (buckets, total) = NodeMetric::find_bucketed(&db.pool, "node-1", None, None, 300, 100, 0).await.unwrap();
+        assert_eq!(total, 2);
+        assert_eq!(buckets[0].bucket_time, DateTime::parse_from_rfc3339("2025-01-21T10:05:00Z").unwrap().with_timezone(&Utc));
+        assert_eq!(buckets[0].sample_count, 1);
+        assert_eq!(buckets[1].bucket_time, DateTime::parse_from_rfc3339("2025-01-21T10:00:00Z").unwrap().with_timezone(&Utc));
+        assert_eq!(buckets[1].sample_count, 2);
+        assert_eq!(buckets[1].avg_cpu_usage, Some(21.25));
+        assert_eq!(buckets[1].max_cpu_usage, Some(30.0));
+    }
+
+    #[tokio::test]
+    async fn test_cleanup_old_metrics_per_node_retention() {
+        let db_file = TempDatabaseFile::new("metric");
+        let db = db_file.open().await;
+        
+        for node_id in ["default", "critical", "ephemeral"] {
+            Node::create(&db.pool, NodeCreate {
+                node_id: node_id.to_string(),
+                hostname: node_id.to_string(),
+                ip_address: "10.0.0.1".to_string(),
+                ..Default::default()
+            }).await.unwrap();
+            
+            // 各写入一条10天前的数据
+            NodeMetric::create(&db.pool, MetricCreate {
+                node_id: node_id.to_string(),
+                cpu_usage: Some(1.0),
+                memory_usage: None,
+                disk_usage: None,
+                disk_total: None,
+                disk_available: None,
+                load_average: None,
+                memory_total: None,
+                memory_available: None,
+                uptime: None,
+                network_rx_bytes: None,
+                network_tx_bytes: None,
+                disk_read_bytes: None,
+                disk_write_bytes: None,
+                metric_time: Some(Utc::now() - chrono::Duration::days(10)),
+            }).await.unwrap();
+        }
+        Node::set_metrics_retention(&db.pool, "critical", Some(30)).await.unwrap();
+        // 管理员设置优先于节点上报的值
+        Node::set_metrics_retention(&db.pool, "ephemeral", Some(30)).await.unwrap();
+        let ephemeral = Node::set_metrics_retention_override(&db.pool, "ephemeral", Some(1)).await.unwrap().unwrap();
+        assert_eq!(ephemeral.effective_metrics_retention(7), 1);
+        assert!(Node::set_metrics_retention_override(&db.pool, "missing", Some(1)).await.unwrap().is_none());
+        
+        // 全局保留7天：未设置的节点和覆盖为1天的节点被清理，保留30天的节点不受影响
+        assert_eq!(NodeMetric::cleanup_old_metrics(&db.pool, 7).await.unwrap(), 2);
+        assert!(NodeMetric::get_latest_by_node(&db.pool, "default").await.unwrap().is_none());
+        assert!(NodeMetric::get_latest_by_node(&db.pool, "ephemeral").await.unwrap().is_none());
+        assert!(NodeMetric::get_latest_by_node(&db.pool, "critical").await.unwrap().is_some());
+    }
+
+    #[test]
+    fn test_percentile_rank() {
+        assert_eq!(percentile_rank(95.0, 100), 94);
+        assert_eq!(percentile_rank(99.0, 100), 98);
+        assert_eq!(percentile_rank(95.0, 1), 0);
+        assert_eq!(percentile_rank(99.0, 10), 9);
+        assert_eq!(percentile_rank(50.0, 3), 1);
+    }
+
+    #[tokio::test]
+    async fn test_summary_percentiles() {
+        let db_file = TempDatabaseFile::new("metric");
+        let db = db_file.open().await;
+        
+        Node::create(&db.pool, NodeCreate {
+            node_id: "node-1".to_string(),
+            hostname: "host".to_string(),
+            ip_address: "10.0.0.1".to_string(),
+            ..Default::default()
+        }).await.unwrap();
+        
+        let start = DateTime::parse_from_rfc3339("2025-01-21T10:00:00Z").unwrap().with_timezone(&Utc);
+        // 写入CPU为1..=100、内存为其一半的100条样本
+        let samples = (1..=100).map(|i| MetricCreate {
+            node_id: "node-1".to_string(),
+            cpu_usage: Some(i as f64),
+            memory_usage: Some(i as f64 / 2.0),
+            disk_usage: None,
+            disk_total: None,
+            disk_available: None,
+            load_average: None,
+            memory_total: None,
+            memory_available: None,
+            uptime: None,
+            network_rx_bytes: None,
+            network_tx_bytes: None,
+            disk_read_bytes: None,
+            disk_write_bytes: None,
+            metric_time: Some(start + chrono::Duration::seconds(i)),
+        }).collect();
+        NodeMetric::create_batch(&db.pool, samples).await.unwrap();
+        
+        let summary = NodeMetric::get_summary(&db.pool, "node-1", start, start + chrono::Duration::hours(1))
+            .await.unwrap().unwrap();
+        assert_eq!(summary.sample_count, 100);
+        assert_eq!(summary.p95_cpu_usage, Some(95.0));
+        assert_eq!(summary.p99_cpu_usage, Some(99.0));
+        assert_eq!(summary.p95_memory_usage, Some(47.5));
+        assert_eq!(summary.p99_memory_usage, Some(49.5));
+    }
+    #[tokio::test]
+    async fn test_summary_counter_rates() {
+        let db_file = TempDatabaseFile::new("metric");
+        let db = db_file.open().await;
+        
+        Node::create(&db.pool, NodeCreate {
+            node_id: "node-1".to_string(),
+            hostname: "host".to_string(),
+            ip_address: "10.0.0.1".to_string(),
+            ..Default::default()
+        }).await.unwrap();
+        
+        // 每10秒一个样本，第4个样本时节点重启，接收计数器从头开始
+        let start = DateTime::parse_from_rfc3339("2025-01-21T10:00:00Z").unwrap().with_timezone(&Utc);
+        let samples = [1000, 2000, 5000, 500].into_iter().enumerate().map(|(i, rx_bytes)| MetricCreate {
+            node_id: "node-1".to_string(),
+            cpu_usage: Some(10.0),
+            memory_usage: None,
+            disk_usage: None,
+            disk_total: None,
+            disk_available: None,
+            load_average: None,
+            memory_total: None,
+            memory_available: None,
+            uptime: None,
+            network_rx_bytes: Some(rx_bytes),
+            network_tx_bytes: None,
+            disk_read_bytes: (i == 0).then_some(4096),
+            disk_write_bytes: None,
+            metric_time: Some(start + chrono::Duration::seconds(10 * i as i64)),
+        }).collect();
+        NodeMetric::create_batch(&db.pool, samples).await.unwrap();
+        
+        let summary = NodeMetric::get_summary(&db.pool, "node-1", start, start + chrono::Duration::hours(1))
+            .await.unwrap().unwrap();
+        assert_eq!(summary.network_rx_rate, Some(CounterRate {
+            total_bytes: 1000 + 3000 + 500,
+            avg_bytes_per_sec: 150.0,
+            max_bytes_per_sec: 300.0,
+        }));
+        // 没有数据或只有一个样本时无法计算速率
+        assert_eq!(summary.network_tx_rate, None);
+        assert_eq!(summary.disk_read_rate, None);
+    }
+}
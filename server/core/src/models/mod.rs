@@ -1,7 +1,13 @@
 pub mod node;
+pub mod alert;
 pub mod command;
+pub mod event;
 pub mod metric;
+pub mod token;
 
 pub use node::*;
+pub use alert::*;
 pub use command::*;
-pub use metric::*;
\ No newline at end of file
+pub use event::*;
+pub use metric::*;
+pub use token::*;
\ No newline at end of file
@@ -1,8 +1,11 @@
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use sqlx::{FromRow, SqlitePool};
+use sqlx::{FromRow, Row};
 use anyhow::Result;
 
+use crate::database::DbPool;
+use crate::models::NodeMetric;
+
 #[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
 pub struct Node {
     pub id: i64,
@@ -10,18 +13,110 @@ pub struct Node {
     pub hostname: String,
     pub ip_address: String,
     pub os_info: Option<String>,
-    pub status: String,
+    /// 操作系统名称（如Ubuntu）
+    pub os_name: Option<String>,
+    /// 操作系统版本（如22.04）
+    pub os_version: Option<String>,
+    /// 内核版本
+    pub kernel_version: Option<String>,
+    /// 节点代理版本
+    pub agent_version: Option<String>,
+    /// CPU核心数
+    pub cpu_count: Option<i64>,
+    /// 内存总量（字节）
+    pub total_memory: Option<i64>,
+    /// 节点期望的监控数据保留天数，未设置时使用Core的全局配置
+    pub metrics_retention_days: Option<i64>,
+    /// 管理员设置的监控数据保留天数，优先于节点上报的值
+    pub metrics_retention_override_days: Option<i64>,
+    pub status: NodeStatus,
+    /// 节点标签（数据库中以JSON数组存储）
+    #[sqlx(json)]
+    pub tags: Vec<String>,
     pub last_heartbeat: Option<DateTime<Utc>>,
     pub registered_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
+    /// 根据最后心跳时间推算的存活状态（不存储，由`with_liveness`计算）
+    #[sqlx(skip)]
+    #[serde(default)]
+    pub is_alive: bool,
+    /// 距最后心跳的秒数（不存储，由`with_liveness`按Core的当前时间计算），从未心跳时为空
+    #[sqlx(skip)]
+    #[serde(default)]
+    pub seconds_since_heartbeat: Option<i64>,
+    /// 节点当前连接协商的WebSocket协议版本（不存储，由节点查询接口填充），未连接时为空
+    #[sqlx(skip)]
+    #[serde(default)]
+    pub protocol_version: Option<u32>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// 节点状态（数据库中以小写文本存储）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(rename_all = "lowercase")]
+pub enum NodeStatus {
+    /// 已连接且心跳正常
+    Online,
+    /// 连接正常关闭（节点或Core主动关闭连接）
+    Offline,
+    /// 连接异常中断、ping超时或长时间无心跳，节点可能已崩溃或网络不通
+    Unreachable,
+    /// 节点关闭时主动注销，不会再重连，不需要告警
+    Decommissioned,
+}
+
+impl NodeStatus {
+    /// 是否为需要关注的异常状态
+    pub fn needs_attention(self) -> bool {
+        matches!(self, NodeStatus::Unreachable)
+    }
+}
+
+impl std::fmt::Display for NodeStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let status = match self {
+            NodeStatus::Online => "online",
+            NodeStatus::Offline => "offline",
+            NodeStatus::Unreachable => "unreachable",
+            NodeStatus::Decommissioned => "decommissioned",
+        };
+        write!(f, "{}", status)
+    }
+}
+
+/// 各状态的节点数量
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize)]
+pub struct NodeStatusCounts {
+    pub total: i64,
+    pub online: i64,
+    pub offline: i64,
+    pub unreachable: i64,
+    pub decommissioned: i64,
+}
+
+/// 节点及其最新一条监控数据
+#[derive(Debug, Clone, Serialize)]
+pub struct NodeOverview {
+    #[serde(flatten)]
+    pub node: Node,
+    /// 节点尚未上报监控数据时为空
+    pub latest_metric: Option<NodeMetric>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
 pub struct NodeCreate {
     pub node_id: String,
     pub hostname: String,
     pub ip_address: String,
     pub os_info: Option<String>,
+    pub os_name: Option<String>,
+    pub os_version: Option<String>,
+    pub kernel_version: Option<String>,
+    pub agent_version: Option<String>,
+    pub cpu_count: Option<i64>,
+    pub total_memory: Option<i64>,
+    #[serde(default)]
+    pub tags: Vec<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -29,27 +124,96 @@ pub struct NodeUpdate {
     pub hostname: Option<String>,
     pub ip_address: Option<String>,
     pub os_info: Option<String>,
-    pub status: Option<String>,
+    pub os_name: Option<String>,
+    pub os_version: Option<String>,
+    pub kernel_version: Option<String>,
+    pub agent_version: Option<String>,
+    pub cpu_count: Option<i64>,
+    pub total_memory: Option<i64>,
+    pub status: Option<NodeStatus>,
+    pub tags: Option<Vec<String>>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct NodeHeartbeat {
-    pub node_id: String,
-    pub status: String,
+/// 节点列表排序字段
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum NodeSortField {
+    Hostname,
+    LastHeartbeat,
+    #[default]
+    RegisteredAt,
+}
+
+impl NodeSortField {
+    /// 解析排序参数，无法识别的值回退到默认排序字段
+    pub fn from_param(value: Option<&str>) -> Self {
+        match value.map(|v| v.trim().to_ascii_lowercase()).as_deref() {
+            Some("hostname") => Self::Hostname,
+            Some("last_heartbeat") => Self::LastHeartbeat,
+            _ => Self::RegisteredAt,
+        }
+    }
+
+    /// 对应的数据库列名（白名单，可直接拼接到SQL中）
+    fn column(self) -> &'static str {
+        match self {
+            Self::Hostname => "hostname",
+            Self::LastHeartbeat => "last_heartbeat",
+            Self::RegisteredAt => "registered_at",
+        }
+    }
+}
+
+/// 节点列表过滤与排序条件
+#[derive(Debug, Clone, Default)]
+pub struct NodeFilter {
+    pub status: Option<NodeStatus>,
+    /// 按主机名或IP地址进行子串匹配
+    pub search: Option<String>,
+    /// 只返回带有该标签的节点
+    pub tag: Option<String>,
+    /// 按操作系统名称精确匹配（不区分大小写）
+    pub os_name: Option<String>,
+    /// 按操作系统版本前缀匹配（如`22`匹配`22.04`）
+    pub os_version: Option<String>,
+    /// 按内核版本前缀匹配（如`5.15`匹配`5.15.0-91-generic`）
+    pub kernel_version: Option<String>,
+    /// 按节点代理版本精确匹配，便于找出尚未升级的节点
+    pub agent_version: Option<String>,
+    pub sort_by: NodeSortField,
+    pub ascending: bool,
 }
 
 impl Node {
+    /// 根据最后心跳是否在`stale_minutes`内计算存活状态，与存储的status无关，同时计算距最后心跳的秒数
+    pub fn with_liveness(mut self, stale_minutes: i64) -> Self {
+        let elapsed = self.last_heartbeat.map(|heartbeat| Utc::now() - heartbeat);
+        self.is_alive = elapsed.is_some_and(|elapsed| elapsed <= chrono::Duration::minutes(stale_minutes));
+        // 心跳时间由Core写入，不受节点时钟影响，负值只可能来自Core自身的时钟回拨
+        self.seconds_since_heartbeat = elapsed.map(|elapsed| elapsed.num_seconds().max(0));
+        self
+    }
+
     /// 创建新节点
-    pub async fn create(pool: &SqlitePool, node_data: NodeCreate) -> Result<Node> {
+    pub async fn create(pool: &DbPool, node_data: NodeCreate) -> Result<Node> {
         let node = sqlx::query_as::<_, Node>(r#"
-            INSERT INTO nodes (node_id, hostname, ip_address, os_info)
-            VALUES (?, ?, ?, ?)
+            INSERT INTO nodes (
+                node_id, hostname, ip_address, os_info, os_name, os_version, kernel_version,
+                agent_version, cpu_count, total_memory, tags
+            )
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
             RETURNING *
         "#)
         .bind(&node_data.node_id)
         .bind(&node_data.hostname)
         .bind(&node_data.ip_address)
         .bind(&node_data.os_info)
+        .bind(&node_data.os_name)
+        .bind(&node_data.os_version)
+        .bind(&node_data.kernel_version)
+        .bind(&node_data.agent_version)
+        .bind(node_data.cpu_count)
+        .bind(node_data.total_memory)
+        .bind(tags_json(&normalize_tags(node_data.tags))?)
         .fetch_one(pool)
         .await?;
         
@@ -57,7 +221,7 @@ impl Node {
     }
     
     /// 根据node_id查找节点
-    pub async fn find_by_node_id(pool: &SqlitePool, node_id: &str) -> Result<Option<Node>> {
+    pub async fn find_by_node_id(pool: &DbPool, node_id: &str) -> Result<Option<Node>> {
         let node = sqlx::query_as::<_, Node>("SELECT * FROM nodes WHERE node_id = ?")
             .bind(node_id)
             .fetch_optional(pool)
@@ -67,7 +231,7 @@ impl Node {
     }
     
     /// 获取所有节点
-    pub async fn find_all(pool: &SqlitePool) -> Result<Vec<Node>> {
+    pub async fn find_all(pool: &DbPool) -> Result<Vec<Node>> {
         let nodes = sqlx::query_as::<_, Node>("SELECT * FROM nodes ORDER BY registered_at DESC")
             .fetch_all(pool)
             .await?;
@@ -75,22 +239,155 @@ impl Node {
         Ok(nodes)
     }
     
-    /// 获取在线节点
-    pub async fn find_online(pool: &SqlitePool) -> Result<Vec<Node>> {
-        let nodes = sqlx::query_as::<_, Node>(
-            "SELECT * FROM nodes WHERE status = 'online' ORDER BY last_heartbeat DESC"
-        )
+    /// 在一次查询中获取所有节点及其最新监控数据
+    pub async fn find_all_with_latest_metric(pool: &DbPool) -> Result<Vec<NodeOverview>> {
+        // 监控数据列加前缀，避免与节点的同名列冲突
+        let rows = sqlx::query(r#"
+            SELECT n.*,
+                m.id AS m_id, m.metric_time AS m_metric_time, m.created_at AS m_created_at,
+                m.cpu_usage AS m_cpu_usage, m.memory_usage AS m_memory_usage, m.disk_usage AS m_disk_usage,
+                m.disk_total AS m_disk_total, m.disk_available AS m_disk_available,
+                m.load_average AS m_load_average, m.memory_total AS m_memory_total,
+                m.memory_available AS m_memory_available, m.uptime AS m_uptime,
+                m.network_rx_bytes AS m_network_rx_bytes, m.network_tx_bytes AS m_network_tx_bytes,
+                m.disk_read_bytes AS m_disk_read_bytes, m.disk_write_bytes AS m_disk_write_bytes
+            FROM nodes n
+            LEFT JOIN node_metrics m ON m.id = (
+                SELECT id FROM node_metrics
+                WHERE node_id = n.node_id
+                ORDER BY metric_time DESC, id DESC
+                LIMIT 1
+            )
+            ORDER BY n.registered_at DESC
+        "#)
         .fetch_all(pool)
         .await?;
-        
+
+        rows.iter()
+            .map(|row| {
+                let node = Node::from_row(row)?;
+                let latest_metric = match row.try_get::<Option<i64>, _>("m_id")? {
+                    Some(id) => Some(NodeMetric {
+                        id,
+                        node_id: node.node_id.clone(),
+                        metric_time: row.try_get("m_metric_time")?,
+                        cpu_usage: row.try_get("m_cpu_usage")?,
+                        memory_usage: row.try_get("m_memory_usage")?,
+                        disk_usage: row.try_get("m_disk_usage")?,
+                        disk_total: row.try_get("m_disk_total")?,
+                        disk_available: row.try_get("m_disk_available")?,
+                        load_average: row.try_get("m_load_average")?,
+                        memory_total: row.try_get("m_memory_total")?,
+                        memory_available: row.try_get("m_memory_available")?,
+                        uptime: row.try_get("m_uptime")?,
+                        created_at: row.try_get("m_created_at")?,
+                        network_rx_bytes: row.try_get("m_network_rx_bytes")?,
+                        network_tx_bytes: row.try_get("m_network_tx_bytes")?,
+                        disk_read_bytes: row.try_get("m_disk_read_bytes")?,
+                        disk_write_bytes: row.try_get("m_disk_write_bytes")?,
+                    }),
+                    None => None,
+                };
+                Ok(NodeOverview { node, latest_metric })
+            })
+            .collect()
+    }
+    
+    /// 分页获取节点，按过滤条件筛选并排序，返回当前页节点和过滤后的总数
+    pub async fn find_paginated(
+        pool: &DbPool,
+        filter: &NodeFilter,
+        limit: i64,
+        offset: i64,
+    ) -> Result<(Vec<Node>, i64)> {
+        const WHERE_CLAUSE: &str = r#"
+            WHERE (?1 IS NULL OR status = ?1)
+            AND (?2 IS NULL OR hostname LIKE ?2 ESCAPE '\' OR ip_address LIKE ?2 ESCAPE '\')
+            AND (?3 IS NULL OR EXISTS (SELECT 1 FROM json_each(nodes.tags) WHERE value = ?3))
+            AND (?4 IS NULL OR os_name = ?4 COLLATE NOCASE)
+            AND (?5 IS NULL OR os_version LIKE ?5 ESCAPE '\')
+            AND (?6 IS NULL OR kernel_version LIKE ?6 ESCAPE '\')
+            AND (?7 IS NULL OR agent_version = ?7)
+        "#;
+
+        let search = filter
+            .search
+            .as_deref()
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .map(|s| format!("%{}%", escape_like(s)));
+        let tag = filter.tag.as_deref().map(str::trim).filter(|t| !t.is_empty());
+        let os_name = filter.os_name.as_deref().map(str::trim).filter(|s| !s.is_empty());
+        let prefix = |value: Option<&str>| {
+            value
+                .map(str::trim)
+                .filter(|s| !s.is_empty())
+                .map(|s| format!("{}%", escape_like(s)))
+        };
+        let os_version = prefix(filter.os_version.as_deref());
+        let kernel_version = prefix(filter.kernel_version.as_deref());
+        let agent_version = filter.agent_version.as_deref().map(str::trim).filter(|s| !s.is_empty());
+        let direction = if filter.ascending { "ASC" } else { "DESC" };
+        let status = filter.status.map(|status| status.to_string());
+
+        let nodes = sqlx::query_as::<_, Node>(&format!(
+            "SELECT * FROM nodes {} ORDER BY {} {}, id {} LIMIT ?8 OFFSET ?9",
+            WHERE_CLAUSE,
+            filter.sort_by.column(),
+            direction,
+            direction
+        ))
+        .bind(status.as_deref())
+        .bind(search.as_deref())
+        .bind(tag)
+        .bind(os_name)
+        .bind(os_version.as_deref())
+        .bind(kernel_version.as_deref())
+        .bind(agent_version)
+        .bind(limit.max(0))
+        .bind(offset.max(0))
+        .fetch_all(pool)
+        .await?;
+
+        let total: i64 = sqlx::query_scalar(&format!("SELECT COUNT(*) FROM nodes {}", WHERE_CLAUSE))
+            .bind(status.as_deref())
+            .bind(search.as_deref())
+            .bind(tag)
+            .bind(os_name)
+            .bind(os_version.as_deref())
+            .bind(kernel_version.as_deref())
+            .bind(agent_version)
+            .fetch_one(pool)
+            .await?;
+
+        Ok((nodes, total))
+    }
+    
+    /// 获取状态为在线且在`stale_minutes`内有心跳的节点
+    pub async fn find_alive(pool: &DbPool, stale_minutes: i64) -> Result<Vec<Node>> {
+        let nodes = sqlx::query_as::<_, Node>(r#"
+            SELECT * FROM nodes
+            WHERE status = 'online'
+            AND last_heartbeat >= datetime('now', '-' || ? || ' minutes')
+            ORDER BY last_heartbeat DESC
+        "#)
+        .bind(stale_minutes)
+        .fetch_all(pool)
+        .await?;
+
         Ok(nodes)
     }
     
     /// 更新节点信息
-    pub async fn update(pool: &SqlitePool, node_id: &str, update_data: NodeUpdate) -> Result<Option<Node>> {
+    pub async fn update(pool: &DbPool, node_id: &str, update_data: NodeUpdate) -> Result<Option<Node>> {
         // 简化版本的update，避免复杂的动态查询构建
         if update_data.hostname.is_none() && update_data.ip_address.is_none() && 
-           update_data.os_info.is_none() && update_data.status.is_none() {
+           update_data.os_info.is_none() && update_data.os_name.is_none() &&
+           update_data.os_version.is_none() && update_data.kernel_version.is_none() &&
+           update_data.agent_version.is_none() &&
+           update_data.cpu_count.is_none() &&
+           update_data.total_memory.is_none() && update_data.status.is_none() &&
+           update_data.tags.is_none() {
             return Self::find_by_node_id(pool, node_id).await;
         }
         
@@ -100,18 +397,33 @@ impl Node {
             let new_hostname = update_data.hostname.unwrap_or(current_node.hostname);
             let new_ip = update_data.ip_address.unwrap_or(current_node.ip_address);
             let new_os_info = update_data.os_info.or(current_node.os_info);
+            let new_os_name = update_data.os_name.or(current_node.os_name);
+            let new_os_version = update_data.os_version.or(current_node.os_version);
+            let new_kernel_version = update_data.kernel_version.or(current_node.kernel_version);
+            let new_agent_version = update_data.agent_version.or(current_node.agent_version);
+            let new_cpu_count = update_data.cpu_count.or(current_node.cpu_count);
+            let new_total_memory = update_data.total_memory.or(current_node.total_memory);
             let new_status = update_data.status.unwrap_or(current_node.status);
+            let new_tags = update_data.tags.map(normalize_tags).unwrap_or(current_node.tags);
             
             let node = sqlx::query_as::<_, Node>(r#"
                 UPDATE nodes 
-                SET hostname = ?, ip_address = ?, os_info = ?, status = ?, updated_at = CURRENT_TIMESTAMP
+                SET hostname = ?, ip_address = ?, os_info = ?, os_name = ?, os_version = ?, kernel_version = ?,
+                    agent_version = ?, cpu_count = ?, total_memory = ?, status = ?, tags = ?, updated_at = CURRENT_TIMESTAMP
                 WHERE node_id = ? 
                 RETURNING *
             "#)
             .bind(&new_hostname)
             .bind(&new_ip)
             .bind(&new_os_info)
-            .bind(&new_status)
+            .bind(&new_os_name)
+            .bind(&new_os_version)
+            .bind(&new_kernel_version)
+            .bind(&new_agent_version)
+            .bind(new_cpu_count)
+            .bind(new_total_memory)
+            .bind(new_status)
+            .bind(tags_json(&new_tags)?)
             .bind(node_id)
             .fetch_optional(pool)
             .await?;
@@ -122,29 +434,54 @@ impl Node {
         }
     }
     
-    /// 更新心跳
-    pub async fn update_heartbeat(pool: &SqlitePool, node_id: &str) -> Result<()> {
-        sqlx::query(r#"
-            UPDATE nodes 
-            SET last_heartbeat = CURRENT_TIMESTAMP, 
-                status = 'online',
-                updated_at = CURRENT_TIMESTAMP
+    /// 设置节点期望的监控数据保留天数，None表示使用全局配置
+    pub async fn set_metrics_retention(pool: &DbPool, node_id: &str, days: Option<i64>) -> Result<()> {
+        sqlx::query("UPDATE nodes SET metrics_retention_days = ? WHERE node_id = ?")
+            .bind(days)
+            .bind(node_id)
+            .execute(pool)
+            .await?;
+
+        Ok(())
+    }
+    
+    /// 设置管理员指定的监控数据保留天数，None表示取消覆盖；节点不存在时返回None
+    pub async fn set_metrics_retention_override(
+        pool: &DbPool,
+        node_id: &str,
+        days: Option<i64>,
+    ) -> Result<Option<Node>> {
+        let node = sqlx::query_as::<_, Node>(r#"
+            UPDATE nodes
+            SET metrics_retention_override_days = ?, updated_at = CURRENT_TIMESTAMP
             WHERE node_id = ?
+            RETURNING *
         "#)
+        .bind(days)
         .bind(node_id)
-        .execute(pool)
+        .fetch_optional(pool)
         .await?;
-        
-        Ok(())
+
+        Ok(node)
+    }
+
+    /// 实际生效的监控数据保留天数：管理员设置 > 节点上报 > 全局配置
+    pub fn effective_metrics_retention(&self, default_days: i64) -> i64 {
+        self.metrics_retention_override_days
+            .or(self.metrics_retention_days)
+            .unwrap_or(default_days)
     }
     
-    /// 标记节点离线
-    pub async fn mark_offline(pool: &SqlitePool, node_id: &str) -> Result<()> {
+    /// 更新心跳
+    ///
+    /// 已注销的节点只能通过重新注册恢复，其心跳和监控数据不会把状态改回在线
+    pub async fn update_heartbeat(pool: &DbPool, node_id: &str) -> Result<()> {
         sqlx::query(r#"
             UPDATE nodes 
-            SET status = 'offline', 
+            SET last_heartbeat = CURRENT_TIMESTAMP, 
+                status = 'online',
                 updated_at = CURRENT_TIMESTAMP
-            WHERE node_id = ?
+            WHERE node_id = ? AND status != 'decommissioned'
         "#)
         .bind(node_id)
         .execute(pool)
@@ -153,8 +490,30 @@ impl Node {
         Ok(())
     }
     
+    /// 更新节点状态，返回节点状态是否被更新
+    ///
+    /// 已注销的节点只能通过重新注册恢复，断开连接（离线或失联）不会覆盖`decommissioned`状态
+    pub async fn update_status(pool: &DbPool, node_id: &str, status: NodeStatus) -> Result<bool> {
+        let sql = match status {
+            NodeStatus::Offline | NodeStatus::Unreachable => {
+                "UPDATE nodes SET status = ?, updated_at = CURRENT_TIMESTAMP WHERE node_id = ? AND status != 'decommissioned'"
+            }
+            NodeStatus::Online | NodeStatus::Decommissioned => {
+                "UPDATE nodes SET status = ?, updated_at = CURRENT_TIMESTAMP WHERE node_id = ?"
+            }
+        };
+        
+        let result = sqlx::query(sql)
+            .bind(status.to_string())
+            .bind(node_id)
+            .execute(pool)
+            .await?;
+        
+        Ok(result.rows_affected() > 0)
+    }
+    
     /// 删除节点
-    pub async fn delete(pool: &SqlitePool, node_id: &str) -> Result<bool> {
+    pub async fn delete(pool: &DbPool, node_id: &str) -> Result<bool> {
         let result = sqlx::query("DELETE FROM nodes WHERE node_id = ?")
             .bind(node_id)
             .execute(pool)
@@ -163,18 +522,379 @@ impl Node {
         Ok(result.rows_affected() > 0)
     }
     
-    /// 清理长时间无心跳的离线节点
-    pub async fn cleanup_stale_nodes(pool: &SqlitePool, timeout_minutes: i64) -> Result<u64> {
-        let result = sqlx::query(r#"
+    /// 在同一事务中批量删除节点，返回每个节点是否被删除（不存在的节点为false）
+    pub async fn delete_many(pool: &DbPool, node_ids: &[String]) -> Result<Vec<(String, bool)>> {
+        let mut tx = pool.begin().await?;
+        let mut results = Vec::with_capacity(node_ids.len());
+
+        for node_id in node_ids {
+            let result = sqlx::query("DELETE FROM nodes WHERE node_id = ?")
+                .bind(node_id)
+                .execute(&mut *tx)
+                .await?;
+            results.push((node_id.clone(), result.rows_affected() > 0));
+        }
+
+        tx.commit().await?;
+        Ok(results)
+    }
+    
+    /// 按状态统计节点数量
+    ///
+    /// 在线节点需同时满足状态为online且在`stale_minutes`内有心跳；心跳已过期的online节点计为失联，与清理任务的判定保持一致
+    pub async fn count_by_status(pool: &DbPool, stale_minutes: i64) -> Result<NodeStatusCounts> {
+        let row = sqlx::query(r#"
+            SELECT
+                COUNT(*) AS total,
+                COALESCE(SUM(
+                    CASE WHEN status = 'online'
+                        AND last_heartbeat >= datetime('now', '-' || ?1 || ' minutes')
+                    THEN 1 ELSE 0 END
+                ), 0) AS online,
+                COALESCE(SUM(
+                    CASE WHEN status = 'unreachable'
+                        OR (status = 'online' AND (last_heartbeat IS NULL OR last_heartbeat < datetime('now', '-' || ?1 || ' minutes')))
+                    THEN 1 ELSE 0 END
+                ), 0) AS unreachable,
+                COALESCE(SUM(CASE WHEN status = 'decommissioned' THEN 1 ELSE 0 END), 0) AS decommissioned
+            FROM nodes
+        "#)
+        .bind(stale_minutes)
+        .fetch_one(pool)
+        .await?;
+
+        let total: i64 = row.get("total");
+        let online: i64 = row.get("online");
+        let unreachable: i64 = row.get("unreachable");
+        let decommissioned: i64 = row.get("decommissioned");
+        Ok(NodeStatusCounts {
+            total,
+            online,
+            offline: total - online - unreachable - decommissioned,
+            unreachable,
+            decommissioned,
+        })
+    }
+    
+    /// 将长时间无心跳的在线节点标记为失联，返回被标记的节点ID
+    pub async fn cleanup_stale_nodes(pool: &DbPool, timeout_minutes: i64) -> Result<Vec<String>> {
+        let node_ids = sqlx::query_scalar::<_, String>(r#"
             UPDATE nodes 
-            SET status = 'offline' 
+            SET status = 'unreachable', updated_at = CURRENT_TIMESTAMP
             WHERE status = 'online' 
             AND (last_heartbeat IS NULL OR last_heartbeat < datetime('now', '-' || ? || ' minutes'))
+            RETURNING node_id
         "#)
         .bind(timeout_minutes)
-        .execute(pool)
+        .fetch_all(pool)
         .await?;
         
-        Ok(result.rows_affected())
+        Ok(node_ids)
+    }
+}
+
+/// 规范化标签：去除首尾空白、丢弃空标签并去重（保持原有顺序）
+pub fn normalize_tags(tags: Vec<String>) -> Vec<String> {
+    let mut normalized: Vec<String> = Vec::with_capacity(tags.len());
+    for tag in tags {
+        let tag = tag.trim();
+        if !tag.is_empty() && !normalized.iter().any(|t| t == tag) {
+            normalized.push(tag.to_string());
+        }
     }
-}
\ No newline at end of file
+    normalized
+}
+
+/// 将标签序列化为JSON数组字符串
+fn tags_json(tags: &[String]) -> Result<String> {
+    Ok(serde_json::to_string(tags)?)
+}
+
+/// 转义LIKE模式中的通配符
+fn escape_like(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::TempDatabaseFile;
+
+    #[tokio::test]
+    async fn test_find_paginated() {
+        let db_file = TempDatabaseFile::new("node");
+        let db = db_file.open().await;
+
+        for i in 0..5 {
+            Node::create(&db.pool, NodeCreate {
+                node_id: format!("node-{}", i),
+                hostname: format!("host-{}", i),
+                ip_address: format!("10.0.0.{}", i),
+                tags: if i % 2 == 0 { vec!["production".to_string()] } else { Vec::new() },
+                ..Default::default()
+            }).await.unwrap();
+        }
+        Node::update_heartbeat(&db.pool, "node-1").await.unwrap();
+        Node::update_heartbeat(&db.pool, "node-3").await.unwrap();
+
+        let all = NodeFilter::default();
+        let (nodes, total) = Node::find_paginated(&db.pool, &all, 2, 0).await.unwrap();
+        assert_eq!(nodes.len(), 2);
+        assert_eq!(total, 5);
+
+        let (nodes, total) = Node::find_paginated(&db.pool, &all, 2, 4).await.unwrap();
+        assert_eq!(nodes.len(), 1);
+        assert_eq!(total, 5);
+
+        let online = NodeFilter {
+            status: Some(NodeStatus::Online),
+            ..NodeFilter::default()
+        };
+        let (nodes, total) = Node::find_paginated(&db.pool, &online, 50, 0).await.unwrap();
+        assert_eq!(total, 2);
+        assert!(nodes.iter().all(|node| node.status == NodeStatus::Online));
+
+        // 按主机名升序排序
+        let by_hostname = NodeFilter {
+            sort_by: NodeSortField::from_param(Some("hostname")),
+            ascending: true,
+            ..NodeFilter::default()
+        };
+        let (nodes, _) = Node::find_paginated(&db.pool, &by_hostname, 50, 0).await.unwrap();
+        let hostnames: Vec<&str> = nodes.iter().map(|node| node.hostname.as_str()).collect();
+        assert_eq!(hostnames, vec!["host-0", "host-1", "host-2", "host-3", "host-4"]);
+
+        // 按IP地址子串搜索，通配符按字面匹配
+        let search = NodeFilter {
+            search: Some("10.0.0.3".to_string()),
+            ..NodeFilter::default()
+        };
+        let (nodes, total) = Node::find_paginated(&db.pool, &search, 50, 0).await.unwrap();
+        assert_eq!(total, 1);
+        assert_eq!(nodes[0].node_id, "node-3");
+
+        let wildcard = NodeFilter {
+            search: Some("%".to_string()),
+            ..NodeFilter::default()
+        };
+        let (_, total) = Node::find_paginated(&db.pool, &wildcard, 50, 0).await.unwrap();
+        assert_eq!(total, 0);
+
+        assert_eq!(NodeSortField::from_param(Some("id; DROP TABLE nodes")), NodeSortField::RegisteredAt);
+
+        // 按标签过滤
+        let production = NodeFilter {
+            tag: Some("production".to_string()),
+            ..NodeFilter::default()
+        };
+        let (nodes, total) = Node::find_paginated(&db.pool, &production, 50, 0).await.unwrap();
+        assert_eq!(total, 3);
+        assert!(nodes.iter().all(|node| node.tags == vec!["production".to_string()]));
+
+        let updated = Node::update(&db.pool, "node-1", NodeUpdate {
+            hostname: None,
+            ip_address: None,
+            os_info: None,
+            os_name: Some("Ubuntu".to_string()),
+            os_version: Some("22.04".to_string()),
+            kernel_version: Some("5.15.0-91-generic".to_string()),
+            agent_version: Some("0.1.0".to_string()),
+            cpu_count: Some(8),
+            total_memory: None,
+            status: None,
+            tags: Some(vec![" production ".to_string(), "db".to_string(), "db".to_string(), "".to_string()]),
+        }).await.unwrap().unwrap();
+        assert_eq!(updated.tags, vec!["production".to_string(), "db".to_string()]);
+        assert_eq!(updated.cpu_count, Some(8));
+        let (_, total) = Node::find_paginated(&db.pool, &production, 50, 0).await.unwrap();
+        assert_eq!(total, 4);
+
+        // 按操作系统名称（不区分大小写）和版本前缀过滤
+        let ubuntu = NodeFilter {
+            os_name: Some("ubuntu".to_string()),
+            os_version: Some("22".to_string()),
+            kernel_version: Some("5.15".to_string()),
+            ..NodeFilter::default()
+        };
+        let (nodes, total) = Node::find_paginated(&db.pool, &ubuntu, 50, 0).await.unwrap();
+        assert_eq!(total, 1);
+        assert_eq!(nodes[0].node_id, "node-1");
+        assert_eq!(nodes[0].kernel_version.as_deref(), Some("5.15.0-91-generic"));
+
+        let other_kernel = NodeFilter {
+            kernel_version: Some("6.".to_string()),
+            ..NodeFilter::default()
+        };
+        let (_, total) = Node::find_paginated(&db.pool, &other_kernel, 50, 0).await.unwrap();
+        assert_eq!(total, 0);
+
+        // 按代理版本精确匹配
+        let agent = |version: &str| NodeFilter {
+            agent_version: Some(version.to_string()),
+            ..NodeFilter::default()
+        };
+        let (nodes, total) = Node::find_paginated(&db.pool, &agent("0.1.0"), 50, 0).await.unwrap();
+        assert_eq!(total, 1);
+        assert_eq!(nodes[0].agent_version.as_deref(), Some("0.1.0"));
+        let (_, total) = Node::find_paginated(&db.pool, &agent("0.1"), 50, 0).await.unwrap();
+        assert_eq!(total, 0);
+    }
+
+    #[tokio::test]
+    async fn test_count_by_status() {
+        let db_file = TempDatabaseFile::new("node");
+        let db = db_file.open().await;
+
+        for i in 0..3 {
+            Node::create(&db.pool, NodeCreate {
+                node_id: format!("node-{}", i),
+                hostname: format!("host-{}", i),
+                ip_address: format!("10.0.0.{}", i),
+                ..Default::default()
+            }).await.unwrap();
+        }
+        Node::update_heartbeat(&db.pool, "node-0").await.unwrap();
+        Node::update_heartbeat(&db.pool, "node-1").await.unwrap();
+
+        // 状态仍为online但心跳已过期的节点计为失联
+        sqlx::query("UPDATE nodes SET last_heartbeat = datetime('now', '-10 minutes') WHERE node_id = 'node-1'")
+            .execute(&db.pool)
+            .await
+            .unwrap();
+
+        let counts = Node::count_by_status(&db.pool, 5).await.unwrap();
+        assert_eq!(counts, NodeStatusCounts { total: 3, online: 1, offline: 1, unreachable: 1, decommissioned: 0 });
+        for (node_id, alive, minutes) in [("node-0", true, Some(0)), ("node-1", false, Some(10)), ("node-2", false, None)] {
+            let node = Node::find_by_node_id(&db.pool, node_id).await.unwrap().unwrap().with_liveness(5);
+            assert_eq!(node.is_alive, alive, "{}", node_id);
+            assert_eq!(node.seconds_since_heartbeat.map(|seconds| seconds / 60), minutes, "{}", node_id);
+        }
+        let alive: Vec<String> = Node::find_alive(&db.pool, 5).await.unwrap().into_iter().map(|node| node.node_id).collect();
+        assert_eq!(alive, vec!["node-0"]);
+    }
+
+    #[test]
+    fn test_node_status() {
+        assert_eq!(NodeStatus::Unreachable.to_string(), "unreachable");
+        assert_eq!(serde_json::to_value(NodeStatus::Decommissioned).unwrap(), "decommissioned");
+        assert_eq!(serde_json::from_str::<NodeStatus>("\"offline\"").unwrap(), NodeStatus::Offline);
+        assert!(serde_json::from_str::<NodeStatus>("\"error\"").is_err());
+        assert!(NodeStatus::Unreachable.needs_attention());
+        assert!(!NodeStatus::Decommissioned.needs_attention());
+        assert!(!NodeStatus::Offline.needs_attention());
+    }
+
+    #[tokio::test]
+    async fn test_update_status() {
+        let db_file = TempDatabaseFile::new("node");
+        let db = db_file.open().await;
+
+        Node::create(&db.pool, NodeCreate {
+            node_id: "node-0".to_string(),
+            hostname: "host-0".to_string(),
+            ip_address: "10.0.0.1".to_string(),
+            ..Default::default()
+        }).await.unwrap();
+        Node::update_heartbeat(&db.pool, "node-0").await.unwrap();
+
+        assert!(Node::update_status(&db.pool, "node-0", NodeStatus::Decommissioned).await.unwrap());
+        assert!(!Node::update_status(&db.pool, "missing", NodeStatus::Decommissioned).await.unwrap());
+
+        // 断开连接和清理任务都不会把已注销的节点改为离线或失联
+        assert!(!Node::update_status(&db.pool, "node-0", NodeStatus::Offline).await.unwrap());
+        assert!(!Node::update_status(&db.pool, "node-0", NodeStatus::Unreachable).await.unwrap());
+        // 心跳也不会把已注销的节点改回在线
+        Node::update_heartbeat(&db.pool, "node-0").await.unwrap();
+        let node = Node::find_by_node_id(&db.pool, "node-0").await.unwrap().unwrap();
+        assert_eq!(node.status, NodeStatus::Decommissioned);
+        sqlx::query("UPDATE nodes SET last_heartbeat = datetime('now', '-10 minutes')")
+            .execute(&db.pool)
+            .await
+            .unwrap();
+        assert!(Node::cleanup_stale_nodes(&db.pool, 5).await.unwrap().is_empty());
+        let node = Node::find_by_node_id(&db.pool, "node-0").await.unwrap().unwrap();
+        assert_eq!(node.status, NodeStatus::Decommissioned);
+        let counts = Node::count_by_status(&db.pool, 5).await.unwrap();
+        assert_eq!(counts.decommissioned, 1);
+
+        // 重新注册后恢复在线
+        assert!(Node::update_status(&db.pool, "node-0", NodeStatus::Online).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_delete_many() {
+        let db_file = TempDatabaseFile::new("node");
+        let db = db_file.open().await;
+
+        for i in 0..3 {
+            Node::create(&db.pool, NodeCreate {
+                node_id: format!("node-{}", i),
+                hostname: format!("host-{}", i),
+                ip_address: format!("10.0.0.{}", i),
+                ..Default::default()
+            }).await.unwrap();
+        }
+        sqlx::query("INSERT INTO node_metrics (node_id, metric_time, cpu_usage) VALUES ('node-0', CURRENT_TIMESTAMP, 1.0)")
+            .execute(&db.pool)
+            .await
+            .unwrap();
+
+        let node_ids = vec!["node-0".to_string(), "node-1".to_string(), "missing".to_string()];
+        let results = Node::delete_many(&db.pool, &node_ids).await.unwrap();
+        assert_eq!(results, vec![
+            ("node-0".to_string(), true),
+            ("node-1".to_string(), true),
+            ("missing".to_string(), false),
+        ]);
+
+        let (nodes, total) = Node::find_paginated(&db.pool, &NodeFilter::default(), 50, 0).await.unwrap();
+        assert_eq!(total, 1);
+        assert_eq!(nodes[0].node_id, "node-2");
+
+        // 外键级联删除监控数据
+        let metrics: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM node_metrics")
+            .fetch_one(&db.pool)
+            .await
+            .unwrap();
+        assert_eq!(metrics, 0);
+    }
+
+    #[tokio::test]
+    async fn test_find_all_with_latest_metric() {
+        let db_file = TempDatabaseFile::new("node");
+        let db = db_file.open().await;
+
+        for i in 0..2 {
+            Node::create(&db.pool, NodeCreate {
+                node_id: format!("node-{}", i),
+                hostname: format!("host-{}", i),
+                ip_address: format!("10.0.0.{}", i),
+                ..Default::default()
+            }).await.unwrap();
+        }
+        sqlx::query(r#"
+            INSERT INTO node_metrics (node_id, metric_time, cpu_usage, uptime) VALUES
+                ('node-0', '2025-01-21 10:00:00', 10.0, 100),
+                ('node-0', '2025-01-21 10:01:00', 20.0, 160)
+        "#)
+        .execute(&db.pool)
+        .await
+        .unwrap();
+
+        let mut overview = Node::find_all_with_latest_metric(&db.pool).await.unwrap();
+        overview.sort_by(|a, b| a.node.node_id.cmp(&b.node.node_id));
+        assert_eq!(overview.len(), 2);
+
+        let latest = overview[0].latest_metric.as_ref().unwrap();
+        assert_eq!(latest.node_id, "node-0");
+        assert_eq!(latest.cpu_usage, Some(20.0));
+        assert_eq!(latest.uptime, Some(160));
+        assert_eq!(latest.metric_time.to_rfc3339(), "2025-01-21T10:01:00+00:00");
+        assert!(overview[1].latest_metric.is_none());
+
+        // 节点字段与最新监控数据一起序列化
+        let value = serde_json::to_value(&overview[1]).unwrap();
+        assert_eq!(value["hostname"], "host-1");
+        assert!(value["latest_metric"].is_null());
+    }
+}
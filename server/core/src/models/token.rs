@@ -0,0 +1,102 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::FromRow;
+use anyhow::Result;
+
+use crate::database::DbPool;
+use crate::services::auth::secrets_match;
+
+/// 节点认证令牌（仅保存哈希值）
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct NodeToken {
+    pub node_id: String,
+    #[serde(skip_serializing)]
+    pub token_hash: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl NodeToken {
+    /// 为节点签发新令牌（已有令牌会被替换），返回明文令牌
+    pub async fn issue(pool: &DbPool, node_id: &str) -> Result<String> {
+        let token = generate_token();
+
+        sqlx::query(r#"
+            INSERT INTO node_tokens (node_id, token_hash)
+            VALUES (?, ?)
+            ON CONFLICT(node_id) DO UPDATE SET
+                token_hash = excluded.token_hash,
+                updated_at = CURRENT_TIMESTAMP
+        "#)
+        .bind(node_id)
+        .bind(hash_token(&token))
+        .execute(pool)
+        .await?;
+
+        Ok(token)
+    }
+
+    /// 根据node_id查找令牌
+    pub async fn find_by_node_id(pool: &DbPool, node_id: &str) -> Result<Option<NodeToken>> {
+        let token = sqlx::query_as::<_, NodeToken>("SELECT * FROM node_tokens WHERE node_id = ?")
+            .bind(node_id)
+            .fetch_optional(pool)
+            .await?;
+
+        Ok(token)
+    }
+
+    /// 吊销节点令牌
+    pub async fn revoke(pool: &DbPool, node_id: &str) -> Result<bool> {
+        let result = sqlx::query("DELETE FROM node_tokens WHERE node_id = ?")
+            .bind(node_id)
+            .execute(pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// 校验明文令牌是否与保存的哈希匹配
+    pub fn matches(&self, token: &str) -> bool {
+        secrets_match(&hash_token(token), &self.token_hash)
+    }
+}
+
+/// 生成随机令牌
+fn generate_token() -> String {
+    format!("{}{}", uuid::Uuid::new_v4().simple(), uuid::Uuid::new_v4().simple())
+}
+
+/// 计算令牌的SHA-256哈希（十六进制）
+pub fn hash_token(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::TempDatabaseFile;
+
+    #[tokio::test]
+    async fn test_node_token_issue_rotate_revoke() {
+        let db_file = TempDatabaseFile::new("token");
+        let db = db_file.open().await;
+
+        let first = NodeToken::issue(&db.pool, "node-1").await.unwrap();
+        let stored = NodeToken::find_by_node_id(&db.pool, "node-1").await.unwrap().unwrap();
+        assert_ne!(stored.token_hash, first);
+        assert!(stored.matches(&first));
+        assert!(!stored.matches("default-token"));
+
+        // 轮换后旧令牌失效
+        let second = NodeToken::issue(&db.pool, "node-1").await.unwrap();
+        let stored = NodeToken::find_by_node_id(&db.pool, "node-1").await.unwrap().unwrap();
+        assert!(stored.matches(&second));
+        assert!(!stored.matches(&first));
+
+        assert!(NodeToken::revoke(&db.pool, "node-1").await.unwrap());
+        assert!(NodeToken::find_by_node_id(&db.pool, "node-1").await.unwrap().is_none());
+        assert!(!NodeToken::revoke(&db.pool, "node-1").await.unwrap());
+    }
+}
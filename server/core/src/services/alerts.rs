@@ -0,0 +1,475 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use serde_json::json;
+use tokio::sync::{Mutex, RwLock};
+use tracing::{error, info, warn};
+
+use crate::database::DbPool;
+use crate::models::{AlertRule, AlertRuleCreate, NodeMetric, EVENT_ALERT_FIRED, EVENT_ALERT_RESOLVED};
+use crate::services::events::record_event;
+use crate::services::nodes::{AppState, ClientBroadcastMessage, NodeServiceResponse};
+
+/// 可配置告警的监控指标
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlertMetric {
+    CpuUsage,
+    MemoryUsage,
+    DiskUsage,
+    LoadAverage,
+}
+
+impl AlertMetric {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "cpu_usage" => Some(Self::CpuUsage),
+            "memory_usage" => Some(Self::MemoryUsage),
+            "disk_usage" => Some(Self::DiskUsage),
+            "load_average" => Some(Self::LoadAverage),
+            _ => None,
+        }
+    }
+
+    /// 从监控数据中取出对应指标的值
+    pub fn value_of(self, metric: &NodeMetric) -> Option<f64> {
+        match self {
+            Self::CpuUsage => metric.cpu_usage,
+            Self::MemoryUsage => metric.memory_usage,
+            Self::DiskUsage => metric.disk_usage,
+            Self::LoadAverage => metric.load_average,
+        }
+    }
+}
+
+/// 阈值比较方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    GreaterThan,
+    GreaterOrEqual,
+    LessThan,
+    LessOrEqual,
+}
+
+impl Comparison {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            ">" => Some(Self::GreaterThan),
+            ">=" => Some(Self::GreaterOrEqual),
+            "<" => Some(Self::LessThan),
+            "<=" => Some(Self::LessOrEqual),
+            _ => None,
+        }
+    }
+
+    /// 指标值是否越过阈值
+    pub fn breached(self, value: f64, threshold: f64) -> bool {
+        match self {
+            Self::GreaterThan => value > threshold,
+            Self::GreaterOrEqual => value >= threshold,
+            Self::LessThan => value < threshold,
+            Self::LessOrEqual => value <= threshold,
+        }
+    }
+}
+
+/// 告警事件类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertEventKind {
+    AlertFired,
+    AlertResolved,
+}
+
+/// 告警触发或恢复事件
+#[derive(Debug, Clone, Serialize)]
+pub struct AlertEvent {
+    pub event: AlertEventKind,
+    pub rule: AlertRule,
+    pub node_id: String,
+    pub value: f64,
+    pub timestamp: DateTime<Utc>,
+}
+
+/// 单个规则在单个节点上的告警状态
+#[derive(Debug, Default)]
+struct RuleState {
+    firing: bool,
+    /// 开始持续越过阈值的时间
+    breach_since: Option<DateTime<Utc>>,
+    /// 告警中指标开始恢复正常的时间
+    recover_since: Option<DateTime<Utc>>,
+}
+
+/// 告警引擎：缓存已启用的规则，按每个新样本评估并去抖动
+pub struct AlertEngine {
+    rules: RwLock<Vec<AlertRule>>,
+    states: Mutex<HashMap<(i64, String), RuleState>>,
+    resolve_after: Duration,
+    webhook_url: Option<String>,
+    http: reqwest::Client,
+}
+
+impl AlertEngine {
+    pub fn new(webhook_url: Option<String>, resolve_after_secs: i64) -> Self {
+        Self {
+            rules: RwLock::new(Vec::new()),
+            states: Mutex::new(HashMap::new()),
+            resolve_after: Duration::seconds(resolve_after_secs),
+            webhook_url,
+            http: reqwest::Client::builder()
+                .timeout(std::time::Duration::from_secs(10))
+                .build()
+                .unwrap_or_default(),
+        }
+    }
+
+    /// 从数据库重新加载已启用的规则，并丢弃已删除规则的状态
+    pub async fn reload(&self, pool: &DbPool) -> anyhow::Result<()> {
+        let rules = AlertRule::find_enabled(pool).await?;
+        let rule_ids: Vec<i64> = rules.iter().map(|rule| rule.id).collect();
+
+        self.states.lock().await.retain(|(rule_id, _), _| rule_ids.contains(rule_id));
+        info!("🔔 已加载 {} 条告警规则", rules.len());
+        *self.rules.write().await = rules;
+        Ok(())
+    }
+
+    /// 使用新样本评估所有适用的规则，返回状态发生变化的告警事件
+    pub async fn evaluate(&self, metric: &NodeMetric) -> Vec<AlertEvent> {
+        let rules = self.rules.read().await;
+        let mut states = self.states.lock().await;
+        let now = metric.metric_time;
+        let mut events = Vec::new();
+
+        for rule in rules.iter().filter(|rule| rule.applies_to(&metric.node_id)) {
+            let (Some(alert_metric), Some(comparison)) =
+                (AlertMetric::parse(&rule.metric), Comparison::parse(&rule.comparison))
+            else {
+                continue;
+            };
+            let Some(value) = alert_metric.value_of(metric) else {
+                continue;
+            };
+
+            let state = states.entry((rule.id, metric.node_id.clone())).or_default();
+            let duration = Duration::seconds(rule.duration_secs.max(0));
+
+            let kind = if comparison.breached(value, rule.threshold) {
+                state.recover_since = None;
+                let since = *state.breach_since.get_or_insert(now);
+                if !state.firing && now - since >= duration {
+                    state.firing = true;
+                    Some(AlertEventKind::AlertFired)
+                } else {
+                    None
+                }
+            } else {
+                state.breach_since = None;
+                if state.firing {
+                    // 恢复同样需要持续一段时间，避免指标在阈值附近抖动时反复通知
+                    let since = *state.recover_since.get_or_insert(now);
+                    if now - since >= duration.max(self.resolve_after) {
+                        state.firing = false;
+                        state.recover_since = None;
+                        Some(AlertEventKind::AlertResolved)
+                    } else {
+                        None
+                    }
+                } else {
+                    None
+                }
+            };
+
+            if let Some(event) = kind {
+                events.push(AlertEvent {
+                    event,
+                    rule: rule.clone(),
+                    node_id: metric.node_id.clone(),
+                    value,
+                    timestamp: now,
+                });
+            }
+        }
+
+        events
+    }
+
+    /// 异步发送Webhook通知（不阻塞消息处理）
+    fn notify_webhook(&self, event: &AlertEvent) {
+        let Some(url) = self.webhook_url.clone() else {
+            return;
+        };
+        let client = self.http.clone();
+        let payload = json!({
+            "event": event.event,
+            "node_id": event.node_id,
+            "metric": event.rule.metric,
+            "comparison": event.rule.comparison,
+            "threshold": event.rule.threshold,
+            "value": event.value,
+            "rule": event.rule,
+            "timestamp": event.timestamp.to_rfc3339()
+        });
+
+        tokio::spawn(async move {
+            match client.post(&url).json(&payload).send().await {
+                Ok(response) if response.status().is_success() => {}
+                Ok(response) => warn!("⚠️ 告警Webhook返回错误状态: {}", response.status()),
+                Err(e) => error!("❌ 发送告警Webhook失败: {}", e),
+            }
+        });
+    }
+}
+
+/// 新监控数据入库后评估告警规则并发送通知
+pub async fn process_metric(state: &AppState, metric: &NodeMetric) {
+    for event in state.alert_engine.evaluate(metric).await {
+        match event.event {
+            AlertEventKind::AlertFired => warn!(
+                "🚨 告警触发: 节点 {} {} {} {} (当前值: {})",
+                event.node_id, event.rule.metric, event.rule.comparison, event.rule.threshold, event.value
+            ),
+            AlertEventKind::AlertResolved => info!(
+                "✅ 告警恢复: 节点 {} {} (当前值: {})",
+                event.node_id, event.rule.metric, event.value
+            ),
+        }
+        let event_type = match event.event {
+            AlertEventKind::AlertFired => EVENT_ALERT_FIRED,
+            AlertEventKind::AlertResolved => EVENT_ALERT_RESOLVED,
+        };
+        let details = json!({
+            "rule_id": event.rule.id,
+            "metric": event.rule.metric,
+            "comparison": event.rule.comparison,
+            "threshold": event.rule.threshold,
+            "value": event.value
+        });
+        record_event(state, Some(&event.node_id), event_type, details).await;
+        state.broadcast_to_clients(ClientBroadcastMessage {
+            message_type: event_type.to_string(),
+            id: uuid::Uuid::new_v4().to_string(),
+            timestamp: Utc::now().to_rfc3339(),
+            data: json!({
+                "node_id": event.node_id,
+                "rule": event.rule,
+                "value": event.value,
+                "timestamp": event.timestamp.to_rfc3339()
+            }),
+        });
+        state.alert_engine.notify_webhook(&event);
+    }
+}
+
+/// 获取告警规则列表
+pub async fn get_alert_rules(
+    State(state): State<Arc<AppState>>,
+) -> impl IntoResponse {
+    let db = &state.database;
+
+    match AlertRule::find_all(&db.pool).await {
+        Ok(rules) => (StatusCode::OK, Json(NodeServiceResponse::success(rules, "获取告警规则成功"))),
+        Err(e) => {
+            error!("获取告警规则失败: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(NodeServiceResponse::error("获取告警规则失败")),
+            )
+        }
+    }
+}
+
+/// 创建告警规则
+pub async fn create_alert_rule(
+    State(state): State<Arc<AppState>>,
+    Json(request): Json<AlertRuleCreate>,
+) -> impl IntoResponse {
+    if AlertMetric::parse(&request.metric).is_none() {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(NodeServiceResponse::error(
+                "不支持的指标，可选值: cpu_usage, memory_usage, disk_usage, load_average",
+            )),
+        );
+    }
+    if Comparison::parse(&request.comparison).is_none() {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(NodeServiceResponse::error("不支持的比较方式，可选值: >, >=, <, <=")),
+        );
+    }
+    if !request.threshold.is_finite() {
+        return (StatusCode::BAD_REQUEST, Json(NodeServiceResponse::error("阈值必须是有效数字")));
+    }
+    if request.duration_secs.is_some_and(|secs| secs < 0) {
+        return (StatusCode::BAD_REQUEST, Json(NodeServiceResponse::error("持续时间不能为负数")));
+    }
+
+    let db = &state.database;
+
+    match AlertRule::create(&db.pool, request).await {
+        Ok(rule) => {
+            info!("🔔 新增告警规则 #{}: {} {} {} {}", rule.id, rule.node_id, rule.metric, rule.comparison, rule.threshold);
+            if let Err(e) = state.alert_engine.reload(&db.pool).await {
+                error!("重新加载告警规则失败: {}", e);
+            }
+            (StatusCode::OK, Json(NodeServiceResponse::success(rule, "创建告警规则成功")))
+        }
+        Err(e) => {
+            error!("创建告警规则失败: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(NodeServiceResponse::error("创建告警规则失败")),
+            )
+        }
+    }
+}
+
+/// 删除告警规则
+pub async fn delete_alert_rule(
+    State(state): State<Arc<AppState>>,
+    Path(rule_id): Path<i64>,
+) -> impl IntoResponse {
+    let db = &state.database;
+
+    match AlertRule::delete(&db.pool, rule_id).await {
+        Ok(true) => {
+            info!("🗑️ 告警规则已删除: #{}", rule_id);
+            if let Err(e) = state.alert_engine.reload(&db.pool).await {
+                error!("重新加载告警规则失败: {}", e);
+            }
+            (StatusCode::OK, Json(NodeServiceResponse::success((), "告警规则删除成功")))
+        }
+        Ok(false) => (StatusCode::NOT_FOUND, Json(NodeServiceResponse::error("告警规则不存在"))),
+        Err(e) => {
+            error!("删除告警规则失败: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(NodeServiceResponse::error("删除告警规则失败")),
+            )
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::TempDatabaseFile;
+
+    fn rule(duration_secs: i64) -> AlertRule {
+        AlertRule {
+            id: 1,
+            node_id: "*".to_string(),
+            metric: "cpu_usage".to_string(),
+            comparison: ">".to_string(),
+            threshold: 90.0,
+            duration_secs,
+            enabled: true,
+            created_at: Utc::now(),
+        }
+    }
+
+    fn sample(seconds: i64, cpu_usage: f64) -> NodeMetric {
+        let metric_time = DateTime::parse_from_rfc3339("2025-01-21T10:00:00Z").unwrap().with_timezone(&Utc)
+            + Duration::seconds(seconds);
+        NodeMetric {
+            id: 0,
+            node_id: "node-1".to_string(),
+            metric_time,
+            cpu_usage: Some(cpu_usage),
+            memory_usage: None,
+            disk_usage: None,
+            disk_total: None,
+            disk_available: None,
+            load_average: None,
+            memory_total: None,
+            memory_available: None,
+            uptime: None,
+            network_rx_bytes: None,
+            network_tx_bytes: None,
+            disk_read_bytes: None,
+            disk_write_bytes: None,
+            created_at: metric_time,
+        }
+    }
+
+    async fn kinds(engine: &AlertEngine, seconds: i64, cpu_usage: f64) -> Vec<AlertEventKind> {
+        engine.evaluate(&sample(seconds, cpu_usage)).await.into_iter().map(|e| e.event).collect()
+    }
+
+    #[tokio::test]
+    async fn test_alert_fires_after_duration_and_resolves_after_debounce() {
+        let engine = AlertEngine::new(None, 30);
+        *engine.rules.write().await = vec![rule(20)];
+
+        assert!(kinds(&engine, 0, 95.0).await.is_empty());
+        assert!(kinds(&engine, 10, 95.0).await.is_empty());
+        assert_eq!(kinds(&engine, 20, 95.0).await, vec![AlertEventKind::AlertFired]);
+        // 已触发的告警不会重复通知
+        assert!(kinds(&engine, 30, 99.0).await.is_empty());
+
+        // 短暂恢复后再次越过阈值，不产生恢复事件
+        assert!(kinds(&engine, 40, 50.0).await.is_empty());
+        assert!(kinds(&engine, 50, 95.0).await.is_empty());
+
+        assert!(kinds(&engine, 60, 50.0).await.is_empty());
+        assert!(kinds(&engine, 80, 50.0).await.is_empty());
+        assert_eq!(kinds(&engine, 90, 50.0).await, vec![AlertEventKind::AlertResolved]);
+        assert!(kinds(&engine, 100, 50.0).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_alert_rule_scope() {
+        let engine = AlertEngine::new(None, 0);
+        let mut other_node = rule(0);
+        other_node.node_id = "node-2".to_string();
+        *engine.rules.write().await = vec![other_node];
+
+        assert!(kinds(&engine, 0, 95.0).await.is_empty());
+        assert!(Comparison::parse("<=").unwrap().breached(10.0, 10.0));
+        assert!(AlertMetric::parse("network_rx").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_process_metric_broadcasts_alert_events() {
+        let db_file = TempDatabaseFile::new("alerts");
+        let db = db_file.open().await;
+        let pool = db.pool.clone();
+
+        let state = AppState::new(db, crate::config::CoreConfig {
+            alert_resolve_secs: 0,
+            ..Default::default()
+        });
+        AlertRule::create(&pool, AlertRuleCreate {
+            node_id: None,
+            metric: "cpu_usage".to_string(),
+            comparison: ">=".to_string(),
+            threshold: 90.0,
+            duration_secs: None,
+        }).await.unwrap();
+        state.alert_engine.reload(&pool).await.unwrap();
+        let mut receiver = state.client_broadcaster.subscribe();
+
+        process_metric(&state, &sample(0, 92.5)).await;
+        let fired = receiver.try_recv().unwrap();
+        assert_eq!(fired.message_type, "alert_fired");
+        assert_eq!(fired.data["node_id"], "node-1");
+        assert_eq!(fired.data["value"], 92.5);
+        assert_eq!(fired.data["rule"]["metric"], "cpu_usage");
+        assert_eq!(fired.data["timestamp"], "2025-01-21T10:00:00+00:00");
+
+        process_metric(&state, &sample(10, 40.0)).await;
+        let resolved = receiver.try_recv().unwrap();
+        assert_eq!(resolved.message_type, "alert_resolved");
+        assert_eq!(resolved.data["value"], 40.0);
+        assert!(receiver.try_recv().is_err());
+    }
+}
@@ -0,0 +1,222 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::json;
+use tokio::sync::Mutex;
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::models::{NodeMetric, EVENT_ANOMALY};
+use crate::services::alerts::AlertMetric;
+use crate::services::events::record_event;
+use crate::services::nodes::{AppState, ClientBroadcastMessage};
+
+/// 参与异常检测的指标
+const ANOMALY_METRICS: &[(&str, AlertMetric)] = &[
+    ("cpu_usage", AlertMetric::CpuUsage),
+    ("memory_usage", AlertMetric::MemoryUsage),
+];
+
+/// 基线至少包含这么多样本后才开始判断，避免刚连接时样本太少误报
+const MIN_BASELINE_SAMPLES: u32 = 60;
+
+/// 标准差下限（百分点），指标长期不变时方差接近0，微小波动也会被放大成异常
+const MIN_STD_DEV: f64 = 1.0;
+
+/// 检测到的指标异常
+#[derive(Debug, Clone, Serialize)]
+pub struct MetricAnomaly {
+    pub metric: &'static str,
+    pub value: f64,
+    /// 基线均值
+    pub mean: f64,
+    /// 基线标准差
+    pub std_dev: f64,
+    /// 偏离基线的标准差倍数，负数表示低于基线
+    pub z_score: f64,
+}
+
+/// 单个节点单个指标的基线，以指数加权方式维护均值和方差
+#[derive(Debug, Clone)]
+struct Baseline {
+    mean: f64,
+    variance: f64,
+    samples: u32,
+    last_time: DateTime<Utc>,
+    /// 是否处于异常状态，恢复到基线范围内之前不重复上报
+    anomalous: bool,
+}
+
+impl Baseline {
+    fn new(value: f64, time: DateTime<Utc>) -> Self {
+        Self { mean: value, variance: 0.0, samples: 1, last_time: time, anomalous: false }
+    }
+
+    /// 加入新样本，样本权重随距上一个样本的时间增加，节点离线较久后基线会迅速贴近新的水平
+    fn update(&mut self, value: f64, time: DateTime<Utc>, window_secs: f64) {
+        let elapsed = (time - self.last_time).num_milliseconds().max(0) as f64 / 1000.0;
+        let decay = 1.0 - (-elapsed / window_secs).exp();
+        // 样本较少时退化为算术平均，避免初始样本在基线中占比过高
+        let alpha = decay.max(1.0 / f64::from(self.samples + 1));
+
+        let diff = value - self.mean;
+        let increment = alpha * diff;
+        self.mean += increment;
+        self.variance = (1.0 - alpha) * (self.variance + diff * increment);
+        self.samples = self.samples.saturating_add(1);
+        self.last_time = self.last_time.max(time);
+    }
+}
+
+/// 异常检测器：按节点维护cpu/内存的近期基线，新样本偏离基线超过`sigma`倍标准差时判定为异常
+pub struct AnomalyDetector {
+    baselines: Mutex<HashMap<(String, &'static str), Baseline>>,
+    sigma: f64,
+    window_secs: f64,
+}
+
+impl AnomalyDetector {
+    /// `sigma`为0时关闭检测
+    pub fn new(sigma: f64, window_secs: i64) -> Self {
+        Self {
+            baselines: Mutex::new(HashMap::new()),
+            sigma,
+            window_secs: window_secs.max(1) as f64,
+        }
+    }
+
+    /// 使用新样本检查异常并更新基线，返回新进入异常状态的指标
+    pub async fn evaluate(&self, metric: &NodeMetric) -> Vec<MetricAnomaly> {
+        if self.sigma <= 0.0 {
+            return Vec::new();
+        }
+
+        let mut baselines = self.baselines.lock().await;
+        let mut anomalies = Vec::new();
+
+        for (name, alert_metric) in ANOMALY_METRICS {
+            let Some(value) = alert_metric.value_of(metric).filter(|value| value.is_finite()) else {
+                continue;
+            };
+            let Some(baseline) = baselines.get_mut(&(metric.node_id.clone(), *name)) else {
+                baselines.insert((metric.node_id.clone(), *name), Baseline::new(value, metric.metric_time));
+                continue;
+            };
+
+            if baseline.samples >= MIN_BASELINE_SAMPLES {
+                let std_dev = baseline.variance.sqrt().max(MIN_STD_DEV);
+                let z_score = (value - baseline.mean) / std_dev;
+                let outlier = z_score.abs() > self.sigma;
+                if outlier && !baseline.anomalous {
+                    anomalies.push(MetricAnomaly { metric: name, value, mean: baseline.mean, std_dev, z_score });
+                }
+                baseline.anomalous = outlier;
+            }
+            baseline.update(value, metric.metric_time, self.window_secs);
+        }
+
+        anomalies
+    }
+}
+
+/// 检查新样本是否偏离节点的近期基线，异常时记录事件并广播`anomaly`
+pub async fn process_metric(state: &AppState, metric: &NodeMetric) {
+    for anomaly in state.anomaly_detector.evaluate(metric).await {
+        warn!(
+            "📈 指标异常: 节点 {} {} = {:.2} (基线 {:.2} ± {:.2}，偏离 {:.1}σ)",
+            metric.node_id, anomaly.metric, anomaly.value, anomaly.mean, anomaly.std_dev, anomaly.z_score
+        );
+
+        let details = json!(anomaly);
+        record_event(state, Some(&metric.node_id), EVENT_ANOMALY, details.clone()).await;
+
+        state.broadcast_to_clients(ClientBroadcastMessage {
+            message_type: EVENT_ANOMALY.to_string(),
+            id: Uuid::new_v4().to_string(),
+            timestamp: Utc::now().to_rfc3339(),
+            data: json!({
+                "node_id": metric.node_id,
+                "metric": anomaly.metric,
+                "value": anomaly.value,
+                "mean": anomaly.mean,
+                "std_dev": anomaly.std_dev,
+                "z_score": anomaly.z_score,
+                "timestamp": metric.metric_time.to_rfc3339()
+            }),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    fn sample(seconds: i64, cpu_usage: f64) -> NodeMetric {
+        let start = DateTime::parse_from_rfc3339("2025-01-21T10:00:00Z").unwrap().with_timezone(&Utc);
+        NodeMetric {
+            id: 0,
+            node_id: "node-1".to_string(),
+            metric_time: start + Duration::seconds(seconds),
+            cpu_usage: Some(cpu_usage),
+            memory_usage: None,
+            disk_usage: None,
+            disk_total: None,
+            disk_available: None,
+            load_average: None,
+            memory_total: None,
+            memory_available: None,
+            uptime: None,
+            created_at: start,
+            network_rx_bytes: None,
+            network_tx_bytes: None,
+            disk_read_bytes: None,
+            disk_write_bytes: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_detect_anomaly() {
+        let detector = AnomalyDetector::new(3.0, 3600);
+
+        // 基线样本不足时不判断
+        for i in 0..10 {
+            assert!(detector.evaluate(&sample(i * 3, 20.0 + (i % 2) as f64 * 4.0)).await.is_empty());
+        }
+        assert!(detector.evaluate(&sample(30, 90.0)).await.is_empty());
+
+        // 在20~24之间波动的基线
+        let mut t = 33;
+        for i in 0..200 {
+            assert!(detector.evaluate(&sample(t, 20.0 + (i % 2) as f64 * 4.0)).await.is_empty(), "sample {}", i);
+            t += 3;
+        }
+
+        let anomalies = detector.evaluate(&sample(t, 60.0)).await;
+        assert_eq!(anomalies.len(), 1);
+        assert_eq!(anomalies[0].metric, "cpu_usage");
+        assert!(anomalies[0].z_score > 3.0);
+        assert!((anomalies[0].mean - 22.0).abs() < 2.0);
+
+        // 持续异常时不重复上报，恢复后再次异常才上报
+        assert!(detector.evaluate(&sample(t + 3, 60.0)).await.is_empty());
+        assert!(detector.evaluate(&sample(t + 6, 22.0)).await.is_empty());
+        assert_eq!(detector.evaluate(&sample(t + 9, 60.0)).await.len(), 1);
+
+        // 指标长期不变时，小幅波动不算异常
+        let flat = AnomalyDetector::new(3.0, 3600);
+        for i in 0..100 {
+            flat.evaluate(&sample(i * 3, 5.0)).await;
+        }
+        assert!(flat.evaluate(&sample(300, 7.0)).await.is_empty());
+        assert_eq!(flat.evaluate(&sample(303, 15.0)).await.len(), 1);
+
+        // sigma为0时关闭检测
+        let disabled = AnomalyDetector::new(0.0, 3600);
+        for i in 0..100 {
+            disabled.evaluate(&sample(i * 3, 5.0)).await;
+        }
+        assert!(disabled.evaluate(&sample(300, 95.0)).await.is_empty());
+    }
+}
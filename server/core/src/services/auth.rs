@@ -0,0 +1,292 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, Request, State},
+    http::{header, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::Utc;
+use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use subtle::ConstantTimeEq;
+use tracing::{debug, error, info, warn};
+
+use crate::config::CoreConfig;
+use crate::models::NodeToken;
+use crate::services::nodes::{AppState, NodeServiceResponse};
+use crate::services::websocket::{close_frame, CLOSE_CODE_UNAUTHORIZED};
+
+/// 登录请求
+#[derive(Debug, Deserialize)]
+pub struct LoginRequest {
+    pub username: String,
+    pub password: String,
+}
+
+/// JWT载荷
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Claims {
+    pub sub: String,
+    pub iat: i64,
+    pub exp: i64,
+}
+
+/// 签发JWT
+pub fn issue_jwt(config: &CoreConfig, subject: &str) -> jsonwebtoken::errors::Result<String> {
+    let now = Utc::now().timestamp();
+    let claims = Claims {
+        sub: subject.to_string(),
+        iat: now,
+        exp: now + config.jwt_expiry_secs,
+    };
+
+    encode(
+        &Header::default(),
+        &claims,
+        &EncodingKey::from_secret(config.jwt_secret.as_bytes()),
+    )
+}
+
+/// 校验JWT（签名与过期时间）
+pub fn verify_jwt(config: &CoreConfig, token: &str) -> jsonwebtoken::errors::Result<Claims> {
+    let data = decode::<Claims>(
+        token,
+        &DecodingKey::from_secret(config.jwt_secret.as_bytes()),
+        &Validation::default(),
+    )?;
+
+    Ok(data.claims)
+}
+
+/// 以恒定时间比较两个密钥，比较耗时与内容和长度无关，避免通过响应时间逐字节猜测密钥
+///
+/// 先分别计算SHA-256摘要再比较，使长度不同的输入同样比较完整的32字节
+pub fn secrets_match(presented: &str, expected: &str) -> bool {
+    let presented = Sha256::digest(presented.as_bytes());
+    let expected = Sha256::digest(expected.as_bytes());
+    presented.ct_eq(&expected).into()
+}
+
+/// 管理员登录，校验成功后返回JWT
+pub async fn login(
+    State(state): State<Arc<AppState>>,
+    Json(request): Json<LoginRequest>,
+) -> impl IntoResponse {
+    let config = &state.config;
+    // 用户名和密码都完成比较后再判断，避免短路求值暴露用户名是否正确
+    let username_ok = secrets_match(&request.username, &config.admin_username);
+    let password_ok = secrets_match(&request.password, &config.admin_password);
+    if !(username_ok & password_ok) {
+        warn!("❌ 登录失败: {}", request.username);
+        return (
+            StatusCode::UNAUTHORIZED,
+            Json(NodeServiceResponse::error("用户名或密码错误")),
+        );
+    }
+
+    match issue_jwt(config, &request.username) {
+        Ok(token) => {
+            info!("🔐 管理员登录成功: {}", request.username);
+            (
+                StatusCode::OK,
+                Json(NodeServiceResponse::success(
+                    json!({
+                        "token": token,
+                        "token_type": "Bearer",
+                        "expires_in": config.jwt_expiry_secs
+                    }),
+                    "登录成功",
+                )),
+            )
+        }
+        Err(e) => {
+            error!("签发JWT失败: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(NodeServiceResponse::error("登录失败")),
+            )
+        }
+    }
+}
+
+/// REST API认证中间件，要求请求携带有效的`Authorization: Bearer <JWT>`
+pub async fn require_auth(
+    State(state): State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let token = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(str::trim);
+
+    let Some(token) = token else {
+        return unauthorized("缺少认证令牌");
+    };
+
+    match verify_jwt(&state.config, token) {
+        Ok(claims) => {
+            debug!("REST API认证通过: {}", claims.sub);
+            next.run(request).await
+        }
+        Err(e) => {
+            warn!("❌ REST API认证失败: {}", e);
+            unauthorized("认证令牌无效或已过期")
+        }
+    }
+}
+
+/// 构建401响应
+fn unauthorized(message: &str) -> Response {
+    (
+        StatusCode::UNAUTHORIZED,
+        [(header::WWW_AUTHENTICATE, "Bearer")],
+        Json(NodeServiceResponse::<()>::error(message)),
+    )
+        .into_response()
+}
+
+/// 节点令牌校验结果
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NodeAuthResult {
+    /// 令牌与节点的独立令牌匹配
+    Authorized,
+    /// 节点未签发独立令牌，使用全局共享令牌通过
+    SharedToken,
+    /// 令牌无效或节点不允许使用全局共享令牌
+    Rejected,
+}
+
+/// 校验节点连接令牌
+///
+/// 已签发独立令牌的节点必须使用该令牌；未签发的节点仅在允许时可使用全局共享令牌
+pub async fn verify_node_token(state: &AppState, node_id: &str, token: &str) -> NodeAuthResult {
+    let registered = {
+        let db = &state.database;
+        NodeToken::find_by_node_id(&db.pool, node_id).await
+    };
+
+    match registered {
+        Ok(Some(node_token)) => {
+            if node_token.matches(token) {
+                NodeAuthResult::Authorized
+            } else {
+                NodeAuthResult::Rejected
+            }
+        }
+        Ok(None) => {
+            if state.config.allow_shared_node_token && secrets_match(token, &state.config.shared_token) {
+                NodeAuthResult::SharedToken
+            } else {
+                NodeAuthResult::Rejected
+            }
+        }
+        Err(e) => {
+            error!("查询节点令牌失败: {}", e);
+            NodeAuthResult::Rejected
+        }
+    }
+}
+
+/// 为节点签发（或轮换）独立令牌，明文令牌只在此处返回一次
+pub async fn issue_node_token(
+    State(state): State<Arc<AppState>>,
+    Path(node_id): Path<String>,
+) -> impl IntoResponse {
+    if node_id.trim().is_empty() {
+        return (StatusCode::BAD_REQUEST, Json(NodeServiceResponse::error("节点ID不能为空")));
+    }
+
+    let db = &state.database;
+
+    match NodeToken::issue(&db.pool, &node_id).await {
+        Ok(token) => {
+            info!("🔑 已为节点签发令牌: {}", node_id);
+            (
+                StatusCode::OK,
+                Json(NodeServiceResponse::success(
+                    json!({ "node_id": node_id, "token": token }),
+                    "节点令牌签发成功",
+                )),
+            )
+        }
+        Err(e) => {
+            error!("签发节点令牌失败: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(NodeServiceResponse::error("签发节点令牌失败")),
+            )
+        }
+    }
+}
+
+/// 吊销节点独立令牌
+pub async fn revoke_node_token(
+    State(state): State<Arc<AppState>>,
+    Path(node_id): Path<String>,
+) -> impl IntoResponse {
+    let db = &state.database;
+
+    match NodeToken::revoke(&db.pool, &node_id).await {
+        Ok(true) => {
+            warn!("🔒 已吊销节点令牌: {}", node_id);
+            // 断开使用旧令牌建立的连接
+            state
+                .connection_manager
+                .send_to_node(&node_id, close_frame(CLOSE_CODE_UNAUTHORIZED, "节点令牌已吊销"))
+                .await;
+            (StatusCode::OK, Json(NodeServiceResponse::success((), "节点令牌已吊销")))
+        }
+        Ok(false) => (StatusCode::NOT_FOUND, Json(NodeServiceResponse::error("节点未签发令牌"))),
+        Err(e) => {
+            error!("吊销节点令牌失败: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(NodeServiceResponse::error("吊销节点令牌失败")),
+            )
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_jwt_round_trip() {
+        let config = CoreConfig::default();
+        let token = issue_jwt(&config, "admin").unwrap();
+        let claims = verify_jwt(&config, &token).unwrap();
+        assert_eq!(claims.sub, "admin");
+        assert_eq!(claims.exp - claims.iat, config.jwt_expiry_secs);
+
+        // 不同密钥签发的令牌无法通过校验
+        let other = CoreConfig::default();
+        assert!(verify_jwt(&other, &token).is_err());
+    }
+
+    #[test]
+    fn test_jwt_expired() {
+        let config = CoreConfig {
+            jwt_expiry_secs: -3600,
+            ..CoreConfig::default()
+        };
+        let token = issue_jwt(&config, "admin").unwrap();
+        assert!(verify_jwt(&config, &token).is_err());
+    }
+
+    #[test]
+    fn test_secrets_match() {
+        assert!(secrets_match("s3cret", "s3cret"));
+        assert!(!secrets_match("s3cret", "s3creT"));
+        assert!(!secrets_match("s3cre", "s3cret"));
+        assert!(!secrets_match("", "s3cret"));
+        assert!(secrets_match("", ""));
+    }
+}
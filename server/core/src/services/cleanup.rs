@@ -0,0 +1,269 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use axum::{
+    body::Body,
+    extract::{Query, State},
+    http::{header, HeaderName, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::Utc;
+use futures_util::stream;
+use serde::Deserialize;
+use serde_json::json;
+use tokio::io::AsyncReadExt;
+use tokio::task::JoinHandle;
+use tracing::{debug, error, info, warn};
+
+use crate::database::VacuumMode;
+use crate::models::{Command, Event, Node, NodeMetric, NodeStatus, EVENT_NODE_STATUS_CHANGE};
+use crate::services::events::record_event;
+use crate::services::nodes::{AppState, NodeServiceResponse};
+
+/// 单次清理的结果统计
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CleanupReport {
+    pub stale_nodes: u64,
+    pub pruned_metrics: u64,
+    pub pruned_commands: u64,
+    pub pruned_events: u64,
+    pub inactive_connections: usize,
+}
+
+/// 启动后台定时清理任务
+pub fn spawn_cleanup_task(state: Arc<AppState>) -> JoinHandle<()> {
+    let period = Duration::from_secs(state.config.cleanup_interval_secs);
+    info!("🧹 后台清理任务已启动，执行间隔: {}秒", period.as_secs());
+
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(period);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+        loop {
+            interval.tick().await;
+            match run_cleanup(&state).await {
+                Ok(report) if report != CleanupReport::default() => {
+                    info!(
+                        "🧹 清理完成: 离线节点 {} 个, 过期监控数据 {} 条, 过期命令 {} 条, 过期事件 {} 条, 无活动连接 {} 个",
+                        report.stale_nodes,
+                        report.pruned_metrics,
+                        report.pruned_commands,
+                        report.pruned_events,
+                        report.inactive_connections
+                    );
+                }
+                Ok(_) => debug!("清理完成，没有需要处理的数据"),
+                Err(e) => error!("❌ 后台清理失败: {}", e),
+            }
+        }
+    })
+}
+
+/// 启动定期数据库维护任务，回收过期数据删除后留下的空闲空间；间隔为0时不启动
+pub fn spawn_maintenance_task(state: Arc<AppState>) -> Option<JoinHandle<()>> {
+    if state.config.db_maintenance_interval_secs == 0 {
+        info!("🗜️ 定期数据库维护已关闭");
+        return None;
+    }
+    let period = Duration::from_secs(state.config.db_maintenance_interval_secs);
+    info!("🗜️ 定期数据库维护已启动，执行间隔: {}秒", period.as_secs());
+
+    Some(tokio::spawn(async move {
+        // 启动后等待一个周期再执行，避免与启动时的迁移和节点重连争用数据库
+        let mut interval = tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+        loop {
+            interval.tick().await;
+            match state.database.run_maintenance(VacuumMode::Incremental).await {
+                Ok(Some(report)) if report.size_after_bytes < report.size_before_bytes => info!(
+                    "🗜️ 数据库维护完成: {} -> {} 字节，耗时{}毫秒",
+                    report.size_before_bytes, report.size_after_bytes, report.duration_ms
+                ),
+                Ok(Some(report)) => debug!("数据库维护完成，耗时{}毫秒", report.duration_ms),
+                Ok(None) => debug!("数据库维护正在进行，跳过本次定期维护"),
+                Err(e) => error!("❌ 数据库维护失败: {}", e),
+            }
+        }
+    }))
+}
+
+/// 数据库维护请求参数
+#[derive(Debug, Deserialize)]
+pub struct MaintenanceQuery {
+    /// 维护方式，默认完整VACUUM
+    pub mode: Option<VacuumMode>,
+}
+
+/// 手动执行数据库维护（如大量删除数据后回收磁盘空间）
+pub async fn run_database_maintenance(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<MaintenanceQuery>,
+) -> impl IntoResponse {
+    let mode = query.mode.unwrap_or(VacuumMode::Full);
+    info!("🗜️ 手动执行数据库维护: {:?}", mode);
+
+    match state.database.run_maintenance(mode).await {
+        Ok(Some(report)) => {
+            info!(
+                "✅ 数据库维护完成: {} -> {} 字节，耗时{}毫秒",
+                report.size_before_bytes, report.size_after_bytes, report.duration_ms
+            );
+            (StatusCode::OK, Json(NodeServiceResponse::success(report, "数据库维护完成")))
+        }
+        Ok(None) => {
+            warn!("⚠️ 数据库维护正在进行，忽略本次请求");
+            (StatusCode::CONFLICT, Json(NodeServiceResponse::error("数据库维护正在进行，请稍后再试")))
+        }
+        Err(e) => {
+            error!("❌ 数据库维护失败: {}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, Json(NodeServiceResponse::error("数据库维护失败")))
+        }
+    }
+}
+
+/// 备份文件流式下载时每次读取的大小
+const BACKUP_CHUNK_SIZE: usize = 64 * 1024;
+
+/// 下载完成或客户端断开后删除临时备份文件
+struct TempBackupFile(PathBuf);
+
+impl Drop for TempBackupFile {
+    fn drop(&mut self) {
+        if let Err(e) = std::fs::remove_file(&self.0) {
+            warn!("⚠️ 删除临时备份文件 {} 失败: {}", self.0.display(), e);
+        }
+    }
+}
+
+/// 在线备份数据库并作为文件下载，响应头`X-Schema-Version`为备份对应的数据库结构版本
+pub async fn export_database_backup(State(state): State<Arc<AppState>>) -> Response {
+    let backup = match state.database.backup().await {
+        Ok(backup) => backup,
+        Err(e) => {
+            error!("❌ 数据库备份失败: {}", e);
+            return (StatusCode::INTERNAL_SERVER_ERROR, Json(NodeServiceResponse::<()>::error("数据库备份失败"))).into_response();
+        }
+    };
+    let temp_file = TempBackupFile(backup.path);
+    let file = match tokio::fs::File::open(&temp_file.0).await {
+        Ok(file) => file,
+        Err(e) => {
+            error!("❌ 读取备份文件失败: {}", e);
+            return (StatusCode::INTERNAL_SERVER_ERROR, Json(NodeServiceResponse::<()>::error("数据库备份失败"))).into_response();
+        }
+    };
+    info!("💾 数据库备份完成: {} 字节，结构版本 v{}", backup.size_bytes, backup.schema_version);
+
+    let body = Body::from_stream(stream::unfold(Some((file, temp_file)), |state| async move {
+        let (mut file, temp_file) = state?;
+        let mut chunk = vec![0; BACKUP_CHUNK_SIZE];
+        match file.read(&mut chunk).await {
+            Ok(0) => None,
+            Ok(read) => {
+                chunk.truncate(read);
+                Some((Ok(chunk), Some((file, temp_file))))
+            }
+            Err(e) => {
+                error!("❌ 读取备份文件失败: {}", e);
+                Some((Err(e), None))
+            }
+        }
+    }));
+
+    let filename = format!("server_manager_backup_{}.db", Utc::now().format("%Y%m%d_%H%M%S"));
+    (
+        [
+            (header::CONTENT_TYPE, "application/vnd.sqlite3".to_string()),
+            (header::CONTENT_DISPOSITION, format!("attachment; filename=\"{}\"", filename)),
+            (header::CONTENT_LENGTH, backup.size_bytes.to_string()),
+            (HeaderName::from_static("x-schema-version"), backup.schema_version.to_string()),
+        ],
+        body,
+    )
+        .into_response()
+}
+
+/// 执行一次清理：标记无心跳节点失联、删除过期数据、清理无活动连接
+pub async fn run_cleanup(state: &AppState) -> Result<CleanupReport> {
+    let config = &state.config;
+
+    let (stale_node_ids, pruned_metrics, pruned_commands, pruned_events) = {
+        let db = &state.database;
+        (
+            Node::cleanup_stale_nodes(&db.pool, config.node_stale_minutes).await?,
+            NodeMetric::cleanup_old_metrics(&db.pool, config.metrics_retention_days).await?,
+            Command::cleanup_old_commands(&db.pool, config.command_retention_days).await?,
+            Event::cleanup_old_events(&db.pool, config.event_retention_days).await?,
+        )
+    };
+
+    // 与正常断开一致：移除连接并通知监控客户端
+    for node_id in &stale_node_ids {
+        state.connection_manager.remove_connection(node_id).await;
+        state.broadcast_node_status(node_id, NodeStatus::Unreachable);
+        let details = json!({ "status": NodeStatus::Unreachable, "reason": "heartbeat_timeout" });
+        record_event(state, Some(node_id), EVENT_NODE_STATUS_CHANGE, details).await;
+    }
+
+    let inactive_connections = state
+        .connection_manager
+        .cleanup_inactive_connections(config.node_stale_minutes)
+        .await;
+
+    Ok(CleanupReport {
+        stale_nodes: stale_node_ids.len() as u64,
+        pruned_metrics,
+        pruned_commands,
+        pruned_events,
+        inactive_connections,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::CoreConfig;
+    use crate::database::TempDatabaseFile;
+    use crate::models::NodeCreate;
+
+    #[tokio::test]
+    async fn test_run_cleanup_marks_stale_nodes_unreachable() {
+        let db_file = TempDatabaseFile::new("cleanup");
+        let db = db_file.open().await;
+        let pool = db.pool.clone();
+
+        for node_id in ["fresh", "stale"] {
+            Node::create(&pool, NodeCreate {
+                node_id: node_id.to_string(),
+                hostname: node_id.to_string(),
+                ip_address: "10.0.0.1".to_string(),
+                ..Default::default()
+            }).await.unwrap();
+            Node::update_heartbeat(&pool, node_id).await.unwrap();
+        }
+        sqlx::query("UPDATE nodes SET last_heartbeat = datetime('now', '-1 hour') WHERE node_id = 'stale'")
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        let state = AppState::new(db, CoreConfig::default());
+        let mut receiver = state.client_broadcaster.subscribe();
+        let report = run_cleanup(&state).await.unwrap();
+        assert_eq!(report.stale_nodes, 1);
+
+        let message = receiver.try_recv().unwrap();
+        assert_eq!(message.message_type, "node_status_change");
+        assert_eq!(message.data["node_id"], "stale");
+        assert_eq!(message.data["status"], "unreachable");
+        assert!(receiver.try_recv().is_err());
+
+        let stale = Node::find_by_node_id(&pool, "stale").await.unwrap().unwrap();
+        let fresh = Node::find_by_node_id(&pool, "fresh").await.unwrap().unwrap();
+        assert_eq!(stale.status, NodeStatus::Unreachable);
+        assert_eq!(fresh.status, NodeStatus::Online);
+    }
+}
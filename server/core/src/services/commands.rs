@@ -0,0 +1,516 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::{
+    extract::{ws::Message, Path, Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use serde_json::json;
+use tokio::sync::{oneshot, Mutex};
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::models::{Command, CommandCreate, CommandResult, CommandStatus, EVENT_COMMAND_DISPATCHED};
+use crate::services::events::record_event;
+use crate::services::nodes::{ensure_node_exists, AppState, ClientBroadcastMessage, NodeServiceResponse};
+use crate::services::scheduler::next_cron_run;
+
+/// 下发命令请求
+#[derive(Debug, Deserialize)]
+pub struct CreateCommandRequest {
+    pub command: String,
+    /// 计划执行时间，不设置时立即下发；与cron同时设置时作为首次执行的最早时间
+    pub scheduled_at: Option<DateTime<Utc>>,
+    /// 周期执行的cron表达式（UTC，5位或带秒的6位）
+    pub cron: Option<String>,
+}
+
+/// 下发命令查询参数
+#[derive(Debug, Deserialize)]
+pub struct CreateCommandQuery {
+    /// 是否等待命令执行结果后再返回
+    pub wait: Option<bool>,
+    /// 等待超时时间（秒）
+    pub timeout: Option<u64>,
+}
+
+/// 等待命令结果的默认超时时间（秒）
+const DEFAULT_WAIT_TIMEOUT_SECS: u64 = 30;
+/// 等待命令结果的最大超时时间（秒）
+const MAX_WAIT_TIMEOUT_SECS: u64 = 300;
+
+/// 等待命令结果的请求登记表
+///
+/// 以command_id为键保存oneshot发送端，节点上报结果后由WebSocket处理逻辑通知等待中的HTTP请求
+#[derive(Default)]
+pub struct CommandWaiters {
+    waiters: Mutex<HashMap<String, oneshot::Sender<()>>>,
+}
+
+impl CommandWaiters {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 登记等待指定命令的结果
+    pub async fn register(&self, command_id: &str) -> oneshot::Receiver<()> {
+        let (tx, rx) = oneshot::channel();
+        let mut waiters = self.waiters.lock().await;
+        // 顺便清理调用方已放弃等待（如HTTP连接断开）的登记
+        waiters.retain(|_, sender| !sender.is_closed());
+        waiters.insert(command_id.to_string(), tx);
+        rx
+    }
+
+    /// 通知等待者命令已完成
+    pub async fn complete(&self, command_id: &str) {
+        if let Some(sender) = self.waiters.lock().await.remove(command_id) {
+            let _ = sender.send(());
+        }
+    }
+
+    /// 取消等待
+    pub async fn cancel(&self, command_id: &str) {
+        self.waiters.lock().await.remove(command_id);
+    }
+}
+
+/// 广播给监控客户端的命令输出最大字节数（完整输出可通过命令查询接口获取）
+const BROADCAST_OUTPUT_MAX_BYTES: usize = 4096;
+
+/// 截断命令输出，保证不截断多字节字符，返回截断后的内容和是否发生截断
+fn truncate_output(output: &str, max_bytes: usize) -> (&str, bool) {
+    if output.len() <= max_bytes {
+        return (output, false);
+    }
+    let mut end = max_bytes;
+    while !output.is_char_boundary(end) {
+        end -= 1;
+    }
+    (&output[..end], true)
+}
+
+/// 向监控客户端转发命令的实时输出片段
+pub fn broadcast_command_output(state: &AppState, node_id: &str, command_id: &str, seq: u64, stream: &str, data: &str) {
+    let (data, truncated) = truncate_output(data, BROADCAST_OUTPUT_MAX_BYTES);
+
+    state.broadcast_to_clients(ClientBroadcastMessage {
+        message_type: "command_output".to_string(),
+        id: Uuid::new_v4().to_string(),
+        timestamp: chrono::Utc::now().to_rfc3339(),
+        data: json!({
+            "command_id": command_id,
+            "node_id": node_id,
+            "seq": seq,
+            "stream": stream,
+            "data": data,
+            "truncated": truncated
+        }),
+    });
+}
+
+/// 向监控客户端广播命令执行结果
+pub fn broadcast_command_result(state: &AppState, node_id: &str, result: &CommandResult, status: &str) {
+    let (stdout, stdout_truncated) = truncate_output(result.stdout.as_deref().unwrap_or(""), BROADCAST_OUTPUT_MAX_BYTES);
+    let (stderr, stderr_truncated) = truncate_output(result.stderr.as_deref().unwrap_or(""), BROADCAST_OUTPUT_MAX_BYTES);
+    let now = chrono::Utc::now().to_rfc3339();
+
+    state.broadcast_to_clients(ClientBroadcastMessage {
+        message_type: "command_result".to_string(),
+        id: Uuid::new_v4().to_string(),
+        timestamp: now.clone(),
+        data: json!({
+            "command_id": result.command_id,
+            "node_id": node_id,
+            "status": status,
+            "exit_code": result.exit_code,
+            "execution_time_ms": result.execution_time_ms,
+            "stdout": stdout,
+            "stdout_truncated": stdout_truncated,
+            "stderr": stderr,
+            "stderr_truncated": stderr_truncated,
+            "timestamp": now
+        }),
+    });
+}
+
+/// 命令历史查询参数
+#[derive(Debug, Deserialize)]
+pub struct CommandHistoryQuery {
+    /// 按命令状态过滤：scheduled / pending / running / success / failed / timeout / cancelled
+    pub status: Option<CommandStatus>,
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+/// 构建推送给节点的命令消息
+pub fn build_command_message(command: &Command) -> Message {
+    let message = json!({
+        "type": "command",
+        "id": Uuid::new_v4().to_string(),
+        "timestamp": chrono::Utc::now().to_rfc3339(),
+        "data": {
+            "command_id": command.command_id,
+            "command": command.command_text
+        }
+    });
+
+    Message::Text(message.to_string().into())
+}
+
+/// 节点在线时推送命令并标记为执行中，否则保持pending状态等待节点重连，返回命令当前状态
+pub async fn dispatch_command(state: &AppState, command: &Command) -> CommandStatus {
+    let delivered = state
+        .connection_manager
+        .send_to_node(&command.target_node_id, build_command_message(command))
+        .await;
+
+    if delivered {
+        if let Err(e) = Command::update_status(&state.database.pool, &command.command_id, CommandStatus::Running).await {
+            error!("更新命令状态失败: {}", e);
+        }
+        info!("📤 命令已下发: {} -> {}", command.command_id, command.target_node_id);
+        let details = json!({
+            "command_id": command.command_id,
+            "command": command.command_text,
+            "schedule_id": command.schedule_id
+        });
+        record_event(state, Some(&command.target_node_id), EVENT_COMMAND_DISPATCHED, details).await;
+        CommandStatus::Running
+    } else {
+        warn!("⚠️ 节点不在线，命令保持待执行: {} -> {}", command.command_id, command.target_node_id);
+        CommandStatus::Pending
+    }
+}
+
+/// 节点注册后下发其离线期间积压的待执行命令
+pub async fn dispatch_pending_commands(state: &AppState, node_id: &str) {
+    let commands = match Command::find_pending(&state.database.pool, node_id).await {
+        Ok(commands) => commands,
+        Err(e) => {
+            error!("查询待执行命令失败: {}", e);
+            return;
+        }
+    };
+
+    if !commands.is_empty() {
+        info!("📬 下发节点离线期间积压的 {} 条命令: {}", commands.len(), node_id);
+    }
+    for command in &commands {
+        dispatch_command(state, command).await;
+    }
+}
+
+/// 向节点下发命令
+///
+/// 设置`scheduled_at`或`cron`时命令加入计划，由定时任务在到期时下发
+pub async fn create_command(
+    State(state): State<Arc<AppState>>,
+    Path(node_id): Path<String>,
+    Query(query): Query<CreateCommandQuery>,
+    Json(request): Json<CreateCommandRequest>,
+) -> impl IntoResponse {
+    let command_text = request.command.trim();
+    if command_text.is_empty() {
+        return (StatusCode::BAD_REQUEST, Json(NodeServiceResponse::error("命令不能为空")));
+    }
+
+    let now = Utc::now();
+    if request.scheduled_at.is_some_and(|scheduled_at| scheduled_at <= now) && request.cron.is_none() {
+        return (StatusCode::BAD_REQUEST, Json(NodeServiceResponse::error("计划执行时间必须晚于当前时间")));
+    }
+    let cron = request.cron.as_deref().map(str::trim).filter(|cron| !cron.is_empty());
+    // 周期命令的首次执行时间为不早于scheduled_at的下一个匹配时间
+    let scheduled_at = match cron {
+        Some(cron) => match next_cron_run(cron, request.scheduled_at.map_or(now, |start| start.max(now))) {
+            Ok(next_run) => Some(next_run),
+            Err(e) => {
+                return (StatusCode::BAD_REQUEST, Json(NodeServiceResponse::error(&format!("无效的cron表达式: {}", e))));
+            }
+        },
+        None => request.scheduled_at,
+    };
+    if scheduled_at.is_some() && query.wait.unwrap_or(false) {
+        return (StatusCode::BAD_REQUEST, Json(NodeServiceResponse::error("定时命令不支持等待执行结果")));
+    }
+
+    let db = &state.database;
+
+    if let Err(response) = ensure_node_exists(&db.pool, &node_id).await {
+        return response;
+    }
+
+    let command_data = CommandCreate {
+        command_id: Uuid::new_v4().to_string(),
+        command_text: command_text.to_string(),
+        target_node_id: node_id.clone(),
+        scheduled_at,
+        cron: cron.map(str::to_string),
+        schedule_id: None,
+    };
+
+    let command = match Command::create(&db.pool, command_data).await {
+        Ok(command) => command,
+        Err(e) => {
+            error!("创建命令失败: {}", e);
+            return (StatusCode::INTERNAL_SERVER_ERROR, Json(NodeServiceResponse::error("下发命令失败")));
+        }
+    };
+
+    if let Some(scheduled_at) = command.scheduled_at {
+        info!("🗓️ 命令已加入计划: {} -> {} ({})", command.command_id, node_id, scheduled_at.to_rfc3339());
+        let response_data = json!({
+            "command_id": command.command_id,
+            "node_id": node_id,
+            "status": CommandStatus::Scheduled.to_string(),
+            "scheduled_at": scheduled_at,
+            "cron": command.cron,
+            "delivered": false
+        });
+        return (StatusCode::OK, Json(NodeServiceResponse::success(response_data, "命令已加入计划")));
+    }
+
+    // 先登记等待再推送，避免结果在登记前返回
+    let waiter = if query.wait.unwrap_or(false) {
+        Some(state.command_waiters.register(&command.command_id).await)
+    } else {
+        None
+    };
+
+    let status = dispatch_command(&state, &command).await;
+    let delivered = matches!(status, CommandStatus::Running);
+
+    if let Some(waiter) = waiter {
+        let timeout_secs = query
+            .timeout
+            .unwrap_or(DEFAULT_WAIT_TIMEOUT_SECS)
+            .clamp(1, MAX_WAIT_TIMEOUT_SECS);
+
+        match tokio::time::timeout(Duration::from_secs(timeout_secs), waiter).await {
+            Ok(Ok(())) => {
+                return match CommandResult::get_command_with_result(&db.pool, &command.command_id).await {
+                    Ok(Some(command_with_result)) => {
+                        let response_data = json!({
+                            "command_id": command.command_id,
+                            "node_id": node_id,
+                            "status": command_with_result.command.status,
+                            "delivered": delivered,
+                            "completed": true,
+                            "result": command_with_result.result
+                        });
+                        (StatusCode::OK, Json(NodeServiceResponse::success(response_data, "命令执行完成")))
+                    }
+                    Ok(None) => (StatusCode::NOT_FOUND, Json(NodeServiceResponse::error("命令不存在"))),
+                    Err(e) => {
+                        error!("获取命令结果失败: {}", e);
+                        (StatusCode::INTERNAL_SERVER_ERROR, Json(NodeServiceResponse::error("获取命令结果失败")))
+                    }
+                };
+            }
+            _ => {
+                state.command_waiters.cancel(&command.command_id).await;
+                warn!("⏰ 等待命令结果超时: {} ({}秒)", command.command_id, timeout_secs);
+                let response_data = json!({
+                    "command_id": command.command_id,
+                    "node_id": node_id,
+                    "status": status.to_string(),
+                    "delivered": delivered,
+                    "completed": false
+                });
+                return (StatusCode::OK, Json(NodeServiceResponse::success(response_data, "等待命令结果超时，可稍后查询命令状态")));
+            }
+        }
+    }
+
+    let response_data = json!({
+        "command_id": command.command_id,
+        "node_id": node_id,
+        "status": status.to_string(),
+        "delivered": delivered
+    });
+
+    (StatusCode::OK, Json(NodeServiceResponse::success(response_data, "命令下发成功")))
+}
+
+/// 获取节点的命令历史（包含执行结果）
+pub async fn get_node_commands(
+    State(state): State<Arc<AppState>>,
+    Path(node_id): Path<String>,
+    Query(query): Query<CommandHistoryQuery>,
+) -> impl IntoResponse {
+    let db = &state.database;
+
+    if let Err(response) = ensure_node_exists(&db.pool, &node_id).await {
+        return response;
+    }
+
+    let limit = query.limit.unwrap_or(20);
+    let offset = query.offset.unwrap_or(0);
+
+    match CommandResult::get_node_command_history(&db.pool, &node_id, query.status, limit, offset).await {
+        Ok((commands, total)) => {
+            let response_data = json!({
+                "commands": commands,
+                "total": total,
+                "limit": limit,
+                "offset": offset
+            });
+
+            (StatusCode::OK, Json(NodeServiceResponse::success(response_data, "获取命令历史成功")))
+        }
+        Err(e) => {
+            error!("获取命令历史失败: {}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, Json(NodeServiceResponse::error("获取命令历史失败")))
+        }
+    }
+}
+
+/// 获取单条命令及其执行结果
+pub async fn get_command(
+    State(state): State<Arc<AppState>>,
+    Path(command_id): Path<String>,
+) -> impl IntoResponse {
+    let db = &state.database;
+
+    match CommandResult::get_command_with_result(&db.pool, &command_id).await {
+        Ok(Some(command)) => (StatusCode::OK, Json(NodeServiceResponse::success(json!(command), "获取命令成功"))),
+        Ok(None) => (StatusCode::NOT_FOUND, Json(NodeServiceResponse::error("命令不存在"))),
+        Err(e) => {
+            error!("获取命令失败: {}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, Json(NodeServiceResponse::error("获取命令失败")))
+        }
+    }
+}
+
+/// 取消尚未执行的定时命令（周期命令取消后不再创建新的执行）
+pub async fn cancel_command(
+    State(state): State<Arc<AppState>>,
+    Path(command_id): Path<String>,
+) -> impl IntoResponse {
+    let db = &state.database;
+
+    match Command::cancel(&db.pool, &command_id).await {
+        Ok(true) => {
+            info!("🚫 定时命令已取消: {}", command_id);
+            let response_data = json!({
+                "command_id": command_id,
+                "status": CommandStatus::Cancelled.to_string()
+            });
+            (StatusCode::OK, Json(NodeServiceResponse::success(response_data, "定时命令已取消")))
+        }
+        Ok(false) => match Command::find_by_id(&db.pool, &command_id).await {
+            Ok(Some(_)) => (StatusCode::CONFLICT, Json(NodeServiceResponse::error("只能取消尚未执行的定时命令"))),
+            Ok(None) => (StatusCode::NOT_FOUND, Json(NodeServiceResponse::error("命令不存在"))),
+            Err(e) => {
+                error!("获取命令失败: {}", e);
+                (StatusCode::INTERNAL_SERVER_ERROR, Json(NodeServiceResponse::error("取消命令失败")))
+            }
+        },
+        Err(e) => {
+            error!("取消命令失败: {}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, Json(NodeServiceResponse::error("取消命令失败")))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_command_waiters() {
+        let waiters = CommandWaiters::new();
+
+        let waiter = waiters.register("cmd-1").await;
+        waiters.complete("cmd-1").await;
+        assert!(waiter.await.is_ok());
+
+        // 未登记的命令完成时不做任何事
+        waiters.complete("cmd-unknown").await;
+
+        // 取消后不再收到通知
+        let waiter = waiters.register("cmd-2").await;
+        waiters.cancel("cmd-2").await;
+        assert!(waiter.await.is_err());
+
+        // 已放弃的登记在下次登记时被清理
+        drop(waiters.register("cmd-3").await);
+        let _waiter = waiters.register("cmd-4").await;
+        assert_eq!(waiters.waiters.lock().await.len(), 1);
+    }
+
+    #[test]
+    fn test_command_history_query() {
+        let uri: axum::http::Uri = "/api/v1/nodes/node-1/commands?status=failed&limit=5".parse().unwrap();
+        let Query(query) = Query::<CommandHistoryQuery>::try_from_uri(&uri).unwrap();
+        assert_eq!(query.status, Some(CommandStatus::Failed));
+        assert_eq!(query.limit, Some(5));
+
+        // 未知的状态值被拒绝，而不是忽略过滤条件
+        let uri: axum::http::Uri = "/api/v1/nodes/node-1/commands?status=done".parse().unwrap();
+        assert!(Query::<CommandHistoryQuery>::try_from_uri(&uri).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_dispatch_pending_commands() {
+        use crate::config::CoreConfig;
+        use crate::database::TempDatabaseFile;
+        use crate::models::{Node, NodeCreate};
+
+        let db_file = TempDatabaseFile::new("commands");
+        let db = db_file.open().await;
+        let pool = db.pool.clone();
+
+        Node::create(&pool, NodeCreate {
+            node_id: "node-1".to_string(),
+            hostname: "host-1".to_string(),
+            ip_address: "10.0.0.1".to_string(),
+            ..Default::default()
+        }).await.unwrap();
+        for i in 0..2 {
+            Command::create(&pool, CommandCreate {
+                command_id: format!("cmd-{}", i),
+                command_text: format!("echo {}", i),
+                target_node_id: "node-1".to_string(),
+                scheduled_at: None,
+                cron: None,
+                schedule_id: None,
+            }).await.unwrap();
+        }
+
+        // 节点离线时命令保持待执行
+        let state = AppState::new(db, CoreConfig::default());
+        dispatch_pending_commands(&state, "node-1").await;
+        assert_eq!(Command::find_pending(&pool, "node-1").await.unwrap().len(), 2);
+
+        // 节点重连后按创建顺序推送并标记为执行中
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        state.connection_manager.register_sender("node-1".to_string(), tx).await;
+        dispatch_pending_commands(&state, "node-1").await;
+        for i in 0..2 {
+            let Some(Message::Text(text)) = rx.recv().await else {
+                panic!("未收到命令消息");
+            };
+            let message: serde_json::Value = serde_json::from_str(&text).unwrap();
+            assert_eq!(message["type"], "command");
+            assert_eq!(message["data"]["command_id"], format!("cmd-{}", i));
+        }
+        assert!(Command::find_pending(&pool, "node-1").await.unwrap().is_empty());
+        let command = Command::find_by_id(&pool, "cmd-0").await.unwrap().unwrap();
+        assert_eq!(command.status, "running");
+    }
+
+    #[test]
+    fn test_truncate_output() {
+        assert_eq!(truncate_output("hello", 10), ("hello", false));
+        assert_eq!(truncate_output("hello", 5), ("hello", false));
+        assert_eq!(truncate_output("hello world", 5), ("hello", true));
+        // 不在多字节字符中间截断
+        assert_eq!(truncate_output("磁盘已满", 4), ("磁", true));
+    }
+}
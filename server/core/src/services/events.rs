@@ -0,0 +1,241 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use chrono::{DateTime, Duration, Utc};
+use serde::Deserialize;
+use serde_json::json;
+use tracing::{error, warn};
+use uuid::Uuid;
+
+use crate::models::{Event, EventFilter, NodeMetric, EVENT_NODE_REBOOT};
+use crate::services::nodes::{ensure_node_exists, AppState, ClientBroadcastMessage, NodeServiceResponse};
+
+/// 推算的启动时间晚于上一次超过该值时判定节点已重启，容忍时间戳取整和时钟微调带来的误差
+const REBOOT_TOLERANCE_SECS: i64 = 60;
+
+/// 节点事件查询参数
+#[derive(Debug, Deserialize)]
+pub struct NodeEventQuery {
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+/// 事件日志查询参数
+#[derive(Debug, Deserialize)]
+pub struct EventQuery {
+    pub node_id: Option<String>,
+    /// 事件类型，多个类型用逗号分隔
+    pub event_type: Option<String>,
+    pub start_time: Option<String>,
+    pub end_time: Option<String>,
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+/// 记录当前时间发生的事件，写入失败只记录日志，不影响调用方的处理
+pub async fn record_event(state: &AppState, node_id: Option<&str>, event_type: &str, details: serde_json::Value) {
+    if let Err(e) = Event::create(&state.database.pool, node_id, event_type, &details, Utc::now()).await {
+        error!("❌ 记录事件失败: {} ({}): {}", event_type, node_id.unwrap_or("-"), e);
+    }
+}
+
+/// 检测到的节点重启
+#[derive(Debug, Clone, PartialEq)]
+pub struct NodeReboot {
+    /// 根据采集时间和uptime推算的本次启动时间
+    pub boot_time: DateTime<Utc>,
+    pub previous_uptime: i64,
+    pub uptime: i64,
+}
+
+/// 根据采集时间减去uptime推算启动时间，比较相邻两个样本判断节点是否在期间重启
+///
+/// 除了uptime变小，也能发现节点离线较长时间后重启、uptime已超过上一个样本的情况
+pub fn detect_reboot(previous: &NodeMetric, current: &NodeMetric) -> Option<NodeReboot> {
+    let (previous_uptime, uptime) = (previous.uptime?, current.uptime?);
+    if current.metric_time <= previous.metric_time {
+        return None;
+    }
+
+    let previous_boot = previous.metric_time - Duration::seconds(previous_uptime);
+    let boot_time = current.metric_time - Duration::seconds(uptime);
+    (boot_time - previous_boot > Duration::seconds(REBOOT_TOLERANCE_SECS))
+        .then_some(NodeReboot { boot_time, previous_uptime, uptime })
+}
+
+/// 对比节点的上一个样本检查是否重启，重启时记录事件并广播`node_reboot`
+pub async fn process_metric(state: &AppState, previous: Option<&NodeMetric>, metric: &NodeMetric) {
+    let Some(reboot) = previous.and_then(|previous| detect_reboot(previous, metric)) else {
+        return;
+    };
+    warn!(
+        "🔄 节点 {} 已重启: 启动时间 {}，uptime {}秒 -> {}秒",
+        metric.node_id,
+        reboot.boot_time.to_rfc3339(),
+        reboot.previous_uptime,
+        reboot.uptime
+    );
+
+    let details = json!({
+        "boot_time": reboot.boot_time.to_rfc3339(),
+        "previous_uptime": reboot.previous_uptime,
+        "uptime": reboot.uptime
+    });
+    if let Err(e) = Event::create(&state.database.pool, Some(&metric.node_id), EVENT_NODE_REBOOT, &details, metric.metric_time).await {
+        error!("❌ 记录节点重启事件失败: {}", e);
+    }
+
+    state.broadcast_to_clients(ClientBroadcastMessage {
+        message_type: EVENT_NODE_REBOOT.to_string(),
+        id: Uuid::new_v4().to_string(),
+        timestamp: Utc::now().to_rfc3339(),
+        data: json!({
+            "node_id": metric.node_id,
+            "boot_time": reboot.boot_time.to_rfc3339(),
+            "previous_uptime": reboot.previous_uptime,
+            "uptime": reboot.uptime,
+            "detected_at": metric.metric_time.to_rfc3339()
+        }),
+    });
+}
+
+/// 获取节点的事件记录（如重启）
+pub async fn get_node_events(
+    State(state): State<Arc<AppState>>,
+    Path(node_id): Path<String>,
+    Query(query): Query<NodeEventQuery>,
+) -> impl IntoResponse {
+    let db = &state.database;
+
+    if let Err(response) = ensure_node_exists(&db.pool, &node_id).await {
+        return response;
+    }
+
+    let limit = query.limit.unwrap_or(50);
+    let offset = query.offset.unwrap_or(0);
+    let filter = EventFilter { node_id: Some(node_id), ..Default::default() };
+
+    match Event::find(&db.pool, &filter, limit, offset).await {
+        Ok((events, total)) => {
+            let response_data = json!({
+                "events": events,
+                "total": total,
+                "limit": limit,
+                "offset": offset
+            });
+
+            (StatusCode::OK, Json(NodeServiceResponse::success(response_data, "获取节点事件成功")))
+        }
+        Err(e) => {
+            error!("获取节点事件失败: {}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, Json(NodeServiceResponse::error("获取节点事件失败")))
+        }
+    }
+}
+
+/// 查询事件日志，可按节点、事件类型和时间范围过滤，用于还原故障期间的时间线
+pub async fn get_events(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<EventQuery>,
+) -> impl IntoResponse {
+    let db = &state.database;
+
+    let mut times = [None, None];
+    for (time, (value, name)) in times.iter_mut().zip([(&query.start_time, "开始时间"), (&query.end_time, "结束时间")]) {
+        if let Some(value) = value {
+            match DateTime::parse_from_rfc3339(value) {
+                Ok(parsed) => *time = Some(parsed.with_timezone(&Utc)),
+                Err(_) => {
+                    let message = format!("{}格式错误，请使用RFC 3339格式", name);
+                    return (StatusCode::BAD_REQUEST, Json(NodeServiceResponse::error(&message)));
+                }
+            }
+        }
+    }
+    let [start_time, end_time] = times;
+
+    let filter = EventFilter {
+        node_id: query.node_id,
+        event_types: query
+            .event_type
+            .iter()
+            .flat_map(|types| types.split(','))
+            .map(str::trim)
+            .filter(|event_type| !event_type.is_empty())
+            .map(str::to_string)
+            .collect(),
+        start_time,
+        end_time,
+    };
+    let limit = query.limit.unwrap_or(100);
+    let offset = query.offset.unwrap_or(0);
+
+    match Event::find(&db.pool, &filter, limit, offset).await {
+        Ok((events, total)) => {
+            let response_data = json!({
+                "events": events,
+                "total": total,
+                "limit": limit,
+                "offset": offset
+            });
+
+            (StatusCode::OK, Json(NodeServiceResponse::success(response_data, "获取事件日志成功")))
+        }
+        Err(e) => {
+            error!("获取事件日志失败: {}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, Json(NodeServiceResponse::error("获取事件日志失败")))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(seconds: i64, uptime: Option<i64>) -> NodeMetric {
+        let start = DateTime::parse_from_rfc3339("2025-01-21T10:00:00Z").unwrap().with_timezone(&Utc);
+        NodeMetric {
+            id: 0,
+            node_id: "node-1".to_string(),
+            metric_time: start + Duration::seconds(seconds),
+            cpu_usage: None,
+            memory_usage: None,
+            disk_usage: None,
+            disk_total: None,
+            disk_available: None,
+            load_average: None,
+            memory_total: None,
+            memory_available: None,
+            uptime,
+            created_at: start,
+            network_rx_bytes: None,
+            network_tx_bytes: None,
+            disk_read_bytes: None,
+            disk_write_bytes: None,
+        }
+    }
+
+    #[test]
+    fn test_detect_reboot() {
+        let previous = sample(0, Some(86_400));
+        // uptime正常增长（允许时间戳取整带来的几秒误差）
+        assert_eq!(detect_reboot(&previous, &sample(30, Some(86_428))), None);
+
+        // uptime回到较小的值
+        let reboot = detect_reboot(&previous, &sample(120, Some(45))).unwrap();
+        assert_eq!((reboot.previous_uptime, reboot.uptime), (86_400, 45));
+        assert_eq!(reboot.boot_time.to_rfc3339(), "2025-01-21T10:01:15+00:00");
+
+        // 离线两天后重启，uptime已超过上一个样本
+        assert!(detect_reboot(&previous, &sample(2 * 86_400, Some(90_000))).is_some());
+
+        // 缺少uptime或样本时间早于上一个样本时不判断
+        assert_eq!(detect_reboot(&previous, &sample(120, None)), None);
+        assert_eq!(detect_reboot(&previous, &sample(-60, Some(10))), None);
+    }
+}
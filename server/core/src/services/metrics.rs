@@ -1,19 +1,31 @@
 use std::sync::Arc;
 
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Instant;
+
 use axum::{
+    body::{Body, Bytes},
     extract::{Path, Query, State},
-    response::IntoResponse,
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
     Json,
 };
 use chrono::{DateTime, Utc};
+use futures_util::{stream, Stream, StreamExt};
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 use sqlx::Row;
-use tracing::{debug, error, info};
+use tokio::sync::mpsc;
+use tracing::{error, info, warn};
 
-use crate::database::Database;
-use crate::models::NodeMetric;
-use crate::services::nodes::{AppState, NodeServiceResponse};
+use crate::database::DbPool;
+use crate::models::{MetricCreate, Node, NodeMetric};
+use crate::services::auth::secrets_match;
+use crate::services::nodes::{ensure_node_exists, AppState, NodeServiceResponse};
+use crate::services::websocket::MetricData;
 
 /// 监控数据查询参数
 #[derive(Debug, Deserialize)]
@@ -22,6 +34,41 @@ pub struct MetricsQuery {
     pub end_time: Option<String>,
     pub limit: Option<i64>,
     pub offset: Option<i64>,
+    /// 聚合时间间隔（如`30s`、`5m`、`1h`、`1d`），设置后按时间桶返回平均值和最大值
+    pub interval: Option<String>,
+    /// 只返回的指标字段，多个字段用逗号分隔（如`cpu_usage,memory_usage`）
+    pub fields: Option<String>,
+}
+
+/// 可以通过`fields`参数选择返回的指标字段
+const METRIC_FIELDS: &[&str] = &[
+    "cpu_usage",
+    "memory_usage",
+    "disk_usage",
+    "disk_total",
+    "disk_available",
+    "load_average",
+    "memory_total",
+    "memory_available",
+    "uptime",
+    "network_rx_bytes",
+    "network_tx_bytes",
+    "disk_read_bytes",
+    "disk_write_bytes",
+];
+
+/// 选择字段时始终保留的标识和时间字段
+const METRIC_KEY_FIELDS: &[&str] = &["id", "node_id", "metric_time", "bucket_time", "sample_count"];
+
+/// 监控数据导出查询参数
+#[derive(Debug, Deserialize)]
+pub struct MetricsExportQuery {
+    /// 导出格式：csv，缺省时返回与历史数据接口相同的JSON
+    pub format: Option<String>,
+    pub start_time: Option<String>,
+    pub end_time: Option<String>,
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
 }
 
 /// 监控数据统计查询参数
@@ -36,18 +83,18 @@ pub async fn get_latest_metrics(
     State(state): State<Arc<AppState>>,
     Path(node_id): Path<String>,
 ) -> impl IntoResponse {
-    let db = state.database.lock().await;
+    let db = &state.database;
     
     match NodeMetric::find_latest_by_node_id(&db.pool, &node_id).await {
         Ok(Some(metric)) => {
-            Json(NodeServiceResponse::success(metric, "获取最新监控数据成功"))
+            (StatusCode::OK, Json(NodeServiceResponse::success(metric, "获取最新监控数据成功")))
         }
         Ok(None) => {
-            Json(NodeServiceResponse::error("该节点暂无监控数据"))
+            (StatusCode::NOT_FOUND, Json(NodeServiceResponse::error("该节点暂无监控数据")))
         }
         Err(e) => {
             error!("获取最新监控数据失败: {}", e);
-            Json(NodeServiceResponse::error("获取监控数据失败"))
+            (StatusCode::INTERNAL_SERVER_ERROR, Json(NodeServiceResponse::error("获取监控数据失败")))
         }
     }
 }
@@ -58,19 +105,51 @@ pub async fn get_node_metrics(
     Path(node_id): Path<String>,
     Query(query): Query<MetricsQuery>,
 ) -> impl IntoResponse {
-    let db = state.database.lock().await;
+    let db = &state.database;
     
-    // 解析时间参数
-    let start_time = query.start_time.as_ref()
-        .and_then(|s| DateTime::parse_from_rfc3339(s).ok())
-        .map(|dt| dt.with_timezone(&Utc));
+    if let Err(response) = ensure_node_exists(&db.pool, &node_id).await {
+        return response;
+    }
     
-    let end_time = query.end_time.as_ref()
-        .and_then(|s| DateTime::parse_from_rfc3339(s).ok())
-        .map(|dt| dt.with_timezone(&Utc));
+    // 解析时间参数
+    let start_time = parse_optional_time(query.start_time.as_deref());
+    let end_time = parse_optional_time(query.end_time.as_deref());
     
     let limit = query.limit.unwrap_or(100);
     let offset = query.offset.unwrap_or(0);
+    let fields = query.fields.as_deref().map(parse_metric_fields);
+    
+    if let Some(interval) = query.interval.as_deref() {
+        let Some(interval_secs) = parse_interval(interval) else {
+            return (StatusCode::BAD_REQUEST, Json(NodeServiceResponse::error("聚合间隔格式错误，示例: 30s, 5m, 1h, 1d")));
+        };
+        
+        return match NodeMetric::find_bucketed(
+            &db.pool,
+            &node_id,
+            start_time,
+            end_time,
+            interval_secs,
+            limit,
+            offset
+        ).await {
+            Ok((buckets, total)) => {
+                let response_data = json!({
+                    "metrics": project_metric_fields(&buckets, fields.as_deref()),
+                    "total": total,
+                    "limit": limit,
+                    "offset": offset,
+                    "interval_secs": interval_secs
+                });
+                
+                (StatusCode::OK, Json(NodeServiceResponse::success(response_data, "获取聚合监控数据成功")))
+            }
+            Err(e) => {
+                error!("获取聚合监控数据失败: {}", e);
+                (StatusCode::INTERNAL_SERVER_ERROR, Json(NodeServiceResponse::error("获取监控数据失败")))
+            }
+        };
+    }
     
     match NodeMetric::find_by_node_id_with_range(
         &db.pool, 
@@ -82,37 +161,365 @@ pub async fn get_node_metrics(
     ).await {
         Ok((metrics, total)) => {
             let response_data = json!({
-                "metrics": metrics,
+                "metrics": project_metric_fields(&metrics, fields.as_deref()),
                 "total": total,
                 "limit": limit,
                 "offset": offset
             });
             
-            Json(NodeServiceResponse::success(response_data, "获取监控历史数据成功"))
+            (StatusCode::OK, Json(NodeServiceResponse::success(response_data, "获取监控历史数据成功")))
         }
         Err(e) => {
             error!("获取监控历史数据失败: {}", e);
-            Json(NodeServiceResponse::error("获取监控数据失败"))
+            (StatusCode::INTERNAL_SERVER_ERROR, Json(NodeServiceResponse::error("获取监控数据失败")))
+        }
+    }
+}
+
+/// CSV表头（与csv_row字段顺序一致）
+const CSV_HEADER: &str = "metric_time,cpu_usage,memory_usage,disk_usage,disk_total,disk_available,load_average,memory_total,memory_available,uptime,network_rx_bytes,network_tx_bytes,disk_read_bytes,disk_write_bytes\n";
+
+/// 导出节点监控历史数据
+///
+/// `format=csv`时按时间升序逐行流式输出时间范围内的全部数据，不在内存中缓存整个结果
+pub async fn export_node_metrics(
+    State(state): State<Arc<AppState>>,
+    Path(node_id): Path<String>,
+    Query(query): Query<MetricsExportQuery>,
+) -> Response {
+    match query.format.as_deref().map(str::to_ascii_lowercase).as_deref() {
+        None | Some("json") => {
+            let query = MetricsQuery {
+                start_time: query.start_time,
+                end_time: query.end_time,
+                limit: query.limit,
+                offset: query.offset,
+                interval: None,
+                fields: None,
+            };
+            get_node_metrics(State(state), Path(node_id), Query(query)).await.into_response()
+        }
+        Some("csv") => {
+            if let Err(response) = ensure_node_exists::<()>(&state.database.pool, &node_id).await {
+                return response.into_response();
+            }
+            let start_time = parse_optional_time(query.start_time.as_deref());
+            let end_time = parse_optional_time(query.end_time.as_deref());
+            let pool = state.database.pool.clone();
+            let filename = format!("{}_metrics.csv", sanitize_filename(&node_id));
+
+            let (tx, rx) = mpsc::channel::<Result<String, std::io::Error>>(64);
+            tokio::spawn(async move {
+                if tx.send(Ok(CSV_HEADER.to_string())).await.is_err() {
+                    return;
+                }
+
+                let mut rows = NodeMetric::stream_by_node_id_with_range(&pool, &node_id, start_time, end_time);
+                while let Some(row) = rows.next().await {
+                    let chunk = match row {
+                        Ok(metric) => Ok(csv_row(&metric)),
+                        Err(e) => {
+                            error!("导出监控数据失败: {}", e);
+                            Err(std::io::Error::other(e))
+                        }
+                    };
+                    let failed = chunk.is_err();
+                    // 客户端断开或查询出错时停止导出
+                    if tx.send(chunk).await.is_err() || failed {
+                        break;
+                    }
+                }
+            });
+
+            let body = Body::from_stream(stream::unfold(rx, |mut rx| async move {
+                rx.recv().await.map(|chunk| (chunk, rx))
+            }));
+
+            (
+                [
+                    (header::CONTENT_TYPE, "text/csv; charset=utf-8".to_string()),
+                    (header::CONTENT_DISPOSITION, format!("attachment; filename=\"{}\"", filename)),
+                ],
+                body,
+            )
+                .into_response()
+        }
+        Some(_) => (StatusCode::BAD_REQUEST, Json(NodeServiceResponse::<()>::error("不支持的导出格式，可选值: csv, json"))).into_response(),
+    }
+}
+
+/// 将一条监控数据格式化为CSV行
+fn csv_row(metric: &NodeMetric) -> String {
+    fn field<T: ToString>(value: Option<T>) -> String {
+        value.map(|v| v.to_string()).unwrap_or_default()
+    }
+
+    format!(
+        "{},{},{},{},{},{},{},{},{},{},{},{},{},{}\n",
+        metric.metric_time.to_rfc3339(),
+        field(metric.cpu_usage),
+        field(metric.memory_usage),
+        field(metric.disk_usage),
+        field(metric.disk_total),
+        field(metric.disk_available),
+        field(metric.load_average),
+        field(metric.memory_total),
+        field(metric.memory_available),
+        field(metric.uptime),
+        field(metric.network_rx_bytes),
+        field(metric.network_tx_bytes),
+        field(metric.disk_read_bytes),
+        field(metric.disk_write_bytes)
+    )
+}
+
+/// 文件名中只保留字母、数字、`-`和`_`
+fn sanitize_filename(name: &str) -> String {
+    name.chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+        .collect()
+}
+
+/// 导入时每个事务写入的样本数
+const IMPORT_BATCH_SIZE: usize = 500;
+
+/// 导入数据单行的最大长度
+const MAX_IMPORT_LINE_BYTES: usize = 64 * 1024;
+
+/// 导入结果中最多列出的失败行数
+const MAX_IMPORT_ERRORS: usize = 100;
+
+/// 导入数据中的一行，字段与导出的JSON记录相同（`id`、`node_id`、`created_at`等其他字段会被忽略）
+#[derive(Debug, Deserialize)]
+struct MetricImportLine {
+    metric_time: DateTime<Utc>,
+    #[serde(flatten)]
+    metric: MetricData,
+}
+
+/// 导入失败的行
+#[derive(Debug, Serialize)]
+pub struct MetricImportError {
+    /// 行号（从1开始）
+    pub line: usize,
+    pub reason: String,
+}
+
+/// 监控数据导入结果
+#[derive(Debug, Default, Serialize)]
+pub struct MetricImportReport {
+    pub imported: usize,
+    pub failed: usize,
+    /// 失败的行，最多列出前100行
+    pub errors: Vec<MetricImportError>,
+}
+
+/// 逐行解析JSON Lines格式的监控数据，按批次在事务中写入
+struct MetricImporter<'a> {
+    pool: &'a DbPool,
+    node_id: &'a str,
+    line_number: usize,
+    pending: Vec<MetricCreate>,
+    report: MetricImportReport,
+}
+
+impl<'a> MetricImporter<'a> {
+    fn new(pool: &'a DbPool, node_id: &'a str) -> Self {
+        Self { pool, node_id, line_number: 0, pending: Vec::new(), report: MetricImportReport::default() }
+    }
+
+    /// 读取完整的数据流，超长的行记为失败并跳过到下一个换行
+    async fn import<S, E>(&mut self, mut body: S) -> anyhow::Result<()>
+    where
+        S: Stream<Item = Result<Bytes, E>> + Unpin,
+        E: std::error::Error + Send + Sync + 'static,
+    {
+        let mut buffer = Vec::new();
+        let mut skipping = false;
+
+        while let Some(chunk) = body.next().await {
+            for piece in chunk?.split_inclusive(|byte| *byte == b'\n') {
+                if skipping {
+                    skipping = !piece.ends_with(b"\n");
+                    continue;
+                }
+                buffer.extend_from_slice(piece);
+                if buffer.ends_with(b"\n") || buffer.len() > MAX_IMPORT_LINE_BYTES {
+                    skipping = !buffer.ends_with(b"\n");
+                    self.push_line(&buffer);
+                    buffer.clear();
+                }
+                if self.pending.len() >= IMPORT_BATCH_SIZE {
+                    self.flush().await?;
+                }
+            }
+        }
+        if !skipping {
+            self.push_line(&buffer);
+        }
+        self.flush().await
+    }
+
+    fn push_line(&mut self, line: &[u8]) {
+        self.line_number += 1;
+        match parse_import_line(self.node_id, line) {
+            Ok(Some(metric)) => self.pending.push(metric),
+            Ok(None) => {}
+            Err(reason) => {
+                self.report.failed += 1;
+                if self.report.errors.len() < MAX_IMPORT_ERRORS {
+                    self.report.errors.push(MetricImportError { line: self.line_number, reason });
+                }
+            }
+        }
+    }
+
+    async fn flush(&mut self) -> anyhow::Result<()> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        let metrics = std::mem::take(&mut self.pending);
+        let count = metrics.len();
+        NodeMetric::create_batch(self.pool, metrics).await?;
+        self.report.imported += count;
+        Ok(())
+    }
+}
+
+/// 解析一行导入数据，空行返回None
+fn parse_import_line(node_id: &str, line: &[u8]) -> Result<Option<MetricCreate>, String> {
+    if line.len() > MAX_IMPORT_LINE_BYTES {
+        return Err(format!("行长度超过{}字节", MAX_IMPORT_LINE_BYTES));
+    }
+    let line = std::str::from_utf8(line).map_err(|_| "不是有效的UTF-8文本".to_string())?.trim();
+    if line.is_empty() {
+        return Ok(None);
+    }
+    let MetricImportLine { metric_time, mut metric } =
+        serde_json::from_str(line).map_err(|e| format!("JSON格式错误: {}", e))?;
+
+    // 与实时上报使用相同的校验：略超100的百分比截断为100，会被丢弃的数值使整行导入失败
+    let rejected: Vec<String> = metric
+        .sanitize()
+        .into_iter()
+        .filter(|issue| issue.action == "dropped")
+        .map(|issue| format!("{}数值无效({}): {}", issue.field, issue.value, issue.reason))
+        .collect();
+    if !rejected.is_empty() {
+        return Err(rejected.join("; "));
+    }
+
+    Ok(Some(metric.into_metric_create(node_id, Some(metric_time))))
+}
+
+/// 导入节点的历史监控数据（JSON Lines，每行一条带`metric_time`的记录）
+///
+/// 请求体按行流式解析，每500条在一个事务中写入；格式错误的行跳过并在结果中列出行号。
+/// 导入的是历史数据，不参与告警评估，也不计入实时写入速率
+pub async fn import_node_metrics(
+    State(state): State<Arc<AppState>>,
+    Path(node_id): Path<String>,
+    body: Body,
+) -> impl IntoResponse {
+    let pool = &state.database.pool;
+    if let Err(response) = ensure_node_exists(pool, &node_id).await {
+        return response;
+    }
+
+    let mut importer = MetricImporter::new(pool, &node_id);
+    let result = importer.import(body.into_data_stream()).await;
+    let report = importer.report;
+    match result {
+        Ok(()) => {
+            info!("📥 导入监控数据: {} (成功{}条，失败{}条)", node_id, report.imported, report.failed);
+            (StatusCode::OK, Json(NodeServiceResponse::success(report, "导入监控数据完成")))
+        }
+        Err(e) => {
+            error!("❌ 导入监控数据失败: {} (已导入{}条): {}", node_id, report.imported, e);
+            let message = format!("导入监控数据失败，已导入{}条", report.imported);
+            (StatusCode::INTERNAL_SERVER_ERROR, Json(NodeServiceResponse::error(&message)))
+        }
+    }
+}
+
+/// 解析聚合间隔（支持s/m/h/d单位，纯数字按秒处理），返回秒数
+fn parse_interval(value: &str) -> Option<i64> {
+    let value = value.trim();
+    let (number, unit) = match value.char_indices().last()? {
+        (idx, c) if c.is_ascii_alphabetic() => (&value[..idx], c.to_ascii_lowercase()),
+        _ => (value, 's'),
+    };
+    let multiplier = match unit {
+        's' => 1,
+        'm' => 60,
+        'h' => 60 * 60,
+        'd' => 24 * 60 * 60,
+        _ => return None,
+    };
+    number
+        .parse::<i64>()
+        .ok()
+        .filter(|n| *n > 0)
+        .and_then(|n| n.checked_mul(multiplier))
+}
+
+/// 解析逗号分隔的指标字段，未知字段记录警告后忽略
+fn parse_metric_fields(value: &str) -> Vec<&'static str> {
+    let mut fields = Vec::new();
+    for name in value.split(',').map(str::trim).filter(|name| !name.is_empty()) {
+        match METRIC_FIELDS.iter().find(|field| **field == name) {
+            Some(field) if !fields.contains(field) => fields.push(*field),
+            Some(_) => {}
+            None => warn!("⚠️ 忽略未知的监控指标字段: {}", name),
         }
     }
+    fields
+}
+
+/// 只保留选择的指标字段（聚合数据中对应的`avg_`和`max_`字段），未选择字段时原样返回
+fn project_metric_fields<T: Serialize>(rows: &[T], fields: Option<&[&str]>) -> serde_json::Value {
+    let Some(fields) = fields else {
+        return json!(rows);
+    };
+    let keep = |key: &str| {
+        let metric = key.strip_prefix("avg_").or_else(|| key.strip_prefix("max_")).unwrap_or(key);
+        METRIC_KEY_FIELDS.contains(&key) || fields.contains(&metric)
+    };
+
+    rows.iter()
+        .map(|row| match json!(row) {
+            serde_json::Value::Object(mut object) => {
+                object.retain(|key, _| keep(key));
+                serde_json::Value::Object(object)
+            }
+            other => other,
+        })
+        .collect()
+}
+
+/// 解析可选的RFC 3339时间参数，格式错误时忽略
+fn parse_optional_time(value: Option<&str>) -> Option<DateTime<Utc>> {
+    value
+        .and_then(|s| DateTime::parse_from_rfc3339(s).ok())
+        .map(|dt| dt.with_timezone(&Utc))
 }
 
 /// 获取所有节点最新监控数据
 pub async fn get_all_latest_metrics(
     State(state): State<Arc<AppState>>,
 ) -> impl IntoResponse {
-    let db = state.database.lock().await;
+    let db = &state.database;
     
     match NodeMetric::find_all_latest(&db.pool).await {
         Ok(metrics) => {
             let response_data = json!({
                 "metrics": metrics
             });
-            Json(NodeServiceResponse::success(response_data, "获取所有节点最新监控数据成功"))
+            (StatusCode::OK, Json(NodeServiceResponse::success(response_data, "获取所有节点最新监控数据成功")))
         }
         Err(e) => {
             error!("获取所有节点最新监控数据失败: {}", e);
-            Json(NodeServiceResponse::error("获取监控数据失败"))
+            (StatusCode::INTERNAL_SERVER_ERROR, Json(NodeServiceResponse::error("获取监控数据失败")))
         }
     }
 }
@@ -123,14 +530,18 @@ pub async fn get_metrics_summary(
     Path(node_id): Path<String>,
     Query(query): Query<MetricsSummaryQuery>,
 ) -> impl IntoResponse {
-    let db = state.database.lock().await;
+    let db = &state.database;
+    
+    if let Err(response) = ensure_node_exists(&db.pool, &node_id).await {
+        return response;
+    }
     
     // 解析时间参数
     let start_time = match DateTime::parse_from_rfc3339(&query.start_time) {
         Ok(dt) => dt.with_timezone(&Utc),
         Err(e) => {
             error!("解析开始时间失败: {}", e);
-            return Json(NodeServiceResponse::error("开始时间格式错误，请使用RFC 3339格式"));
+            return (StatusCode::BAD_REQUEST, Json(NodeServiceResponse::error("开始时间格式错误，请使用RFC 3339格式")));
         }
     };
     
@@ -138,30 +549,215 @@ pub async fn get_metrics_summary(
         Ok(dt) => dt.with_timezone(&Utc),
         Err(e) => {
             error!("解析结束时间失败: {}", e);
-            return Json(NodeServiceResponse::error("结束时间格式错误，请使用RFC 3339格式"));
+            return (StatusCode::BAD_REQUEST, Json(NodeServiceResponse::error("结束时间格式错误，请使用RFC 3339格式")));
         }
     };
     
     if start_time >= end_time {
-        return Json(NodeServiceResponse::error("开始时间必须早于结束时间"));
+        return (StatusCode::BAD_REQUEST, Json(NodeServiceResponse::error("开始时间必须早于结束时间")));
     }
     
     match NodeMetric::get_summary(&db.pool, &node_id, start_time, end_time).await {
         Ok(summary) => {
-            Json(NodeServiceResponse::success(summary, "获取监控数据统计摘要成功"))
+            (StatusCode::OK, Json(NodeServiceResponse::success(summary, "获取监控数据统计摘要成功")))
         }
         Err(e) => {
             error!("获取监控数据统计摘要失败: {}", e);
-            Json(NodeServiceResponse::error("获取统计摘要失败"))
+            (StatusCode::INTERNAL_SERVER_ERROR, Json(NodeServiceResponse::error("获取统计摘要失败")))
+        }
+    }
+}
+
+/// Prometheus指标定义：(指标名, 说明, 取值函数)
+type PrometheusMetric = (&'static str, &'static str, fn(&NodeMetric) -> Option<f64>);
+
+const PROMETHEUS_GAUGES: &[PrometheusMetric] = &[
+    ("node_cpu_usage", "CPU使用率（百分比）", |m| m.cpu_usage),
+    ("node_memory_usage", "内存使用率（百分比）", |m| m.memory_usage),
+    ("node_memory_total_bytes", "内存总量（字节）", |m| m.memory_total.map(|v| v as f64)),
+    ("node_memory_available_bytes", "可用内存（字节）", |m| m.memory_available.map(|v| v as f64)),
+    ("node_disk_usage", "磁盘使用率（百分比）", |m| m.disk_usage),
+    ("node_disk_total_bytes", "磁盘总量（字节）", |m| m.disk_total.map(|v| v as f64)),
+    ("node_disk_available_bytes", "可用磁盘空间（字节）", |m| m.disk_available.map(|v| v as f64)),
+    ("node_load_average", "系统负载", |m| m.load_average),
+    ("node_uptime_seconds", "系统运行时间（秒）", |m| m.uptime.map(|v| v as f64)),
+];
+
+/// 节点启动以来的累计字节数，节点重启后从0开始，Prometheus按计数器重置处理
+const PROMETHEUS_COUNTERS: &[PrometheusMetric] = &[
+    ("node_network_receive_bytes_total", "网络累计接收字节数", |m| m.network_rx_bytes.map(|v| v as f64)),
+    ("node_network_transmit_bytes_total", "网络累计发送字节数", |m| m.network_tx_bytes.map(|v| v as f64)),
+    ("node_disk_read_bytes_total", "磁盘累计读取字节数", |m| m.disk_read_bytes.map(|v| v as f64)),
+    ("node_disk_written_bytes_total", "磁盘累计写入字节数", |m| m.disk_write_bytes.map(|v| v as f64)),
+];
+
+/// 以Prometheus文本格式导出所有节点的最新监控数据
+///
+/// 该端点不使用JWT认证，便于Prometheus抓取；配置`SM_CORE_METRICS_TOKEN`后需携带对应的Bearer令牌
+pub async fn get_prometheus_metrics(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> Response {
+    if let Some(expected) = &state.config.metrics_token {
+        let presented = headers
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "));
+        if !presented.is_some_and(|presented| secrets_match(presented, expected)) {
+            return (StatusCode::UNAUTHORIZED, "unauthorized\n").into_response();
+        }
+    }
+
+    let db = &state.database;
+
+    let metrics = match NodeMetric::find_all_latest(&db.pool).await {
+        Ok(metrics) => metrics,
+        Err(e) => {
+            error!("导出Prometheus指标失败: {}", e);
+            return (StatusCode::INTERNAL_SERVER_ERROR, "failed to load metrics\n").into_response();
+        }
+    };
+
+    let hostnames: HashMap<String, String> = match Node::find_all(&db.pool).await {
+        Ok(nodes) => nodes.into_iter().map(|node| (node.node_id, node.hostname)).collect(),
+        Err(e) => {
+            error!("导出Prometheus指标失败: {}", e);
+            return (StatusCode::INTERNAL_SERVER_ERROR, "failed to load nodes\n").into_response();
+        }
+    };
+
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4; charset=utf-8")],
+        render_prometheus(&metrics, &hostnames),
+    )
+        .into_response()
+}
+
+/// 渲染Prometheus文本格式
+fn render_prometheus(metrics: &[NodeMetric], hostnames: &HashMap<String, String>) -> String {
+    let mut output = String::new();
+
+    let definitions = PROMETHEUS_GAUGES
+        .iter()
+        .map(|definition| ("gauge", definition))
+        .chain(PROMETHEUS_COUNTERS.iter().map(|definition| ("counter", definition)));
+    for (metric_type, (name, help, value_of)) in definitions {
+        let _ = writeln!(output, "# HELP {} {}", name, help);
+        let _ = writeln!(output, "# TYPE {} {}", name, metric_type);
+
+        for metric in metrics {
+            let Some(value) = value_of(metric) else {
+                continue;
+            };
+            let hostname = hostnames.get(&metric.node_id).map(String::as_str).unwrap_or("");
+            let _ = writeln!(
+                output,
+                "{}{{node_id=\"{}\",hostname=\"{}\"}} {}",
+                name,
+                escape_label_value(&metric.node_id),
+                escape_label_value(hostname),
+                value
+            );
+        }
+    }
+
+    output
+}
+
+/// 转义Prometheus标签值中的反斜杠、双引号和换行
+fn escape_label_value(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+/// 写入速率统计保留的时间窗口（秒）
+const INGESTION_WINDOW_SECS: u64 = 60;
+
+/// 监控数据写入速率统计
+///
+/// 保存成功的样本数按秒计入环形窗口，用于计算最近10秒和60秒的平均写入速率
+pub struct IngestionStats {
+    started_at: Instant,
+    total: AtomicU64,
+    /// 每个桶记录（启动后的秒数, 该秒写入的样本数）
+    buckets: Mutex<[(u64, u64); INGESTION_WINDOW_SECS as usize]>,
+}
+
+/// 写入速率快照
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct IngestionSnapshot {
+    /// 启动以来写入的样本总数
+    pub total_samples: u64,
+    /// 最近10秒的平均写入速率（样本/秒）
+    pub samples_per_sec_10s: f64,
+    /// 最近60秒的平均写入速率（样本/秒）
+    pub samples_per_sec_60s: f64,
+}
+
+impl IngestionStats {
+    pub fn new() -> Self {
+        Self {
+            started_at: Instant::now(),
+            total: AtomicU64::new(0),
+            buckets: Mutex::new([(0, 0); INGESTION_WINDOW_SECS as usize]),
+        }
+    }
+
+    /// 记录成功写入的样本数
+    pub fn record(&self, samples: u64) {
+        self.record_at(self.started_at.elapsed().as_secs(), samples);
+    }
+
+    /// 当前的写入速率
+    pub fn snapshot(&self) -> IngestionSnapshot {
+        self.snapshot_at(self.started_at.elapsed().as_secs())
+    }
+
+    fn record_at(&self, second: u64, samples: u64) {
+        self.total.fetch_add(samples, Ordering::Relaxed);
+        let mut buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+        let bucket = &mut buckets[(second % INGESTION_WINDOW_SECS) as usize];
+        if bucket.0 != second {
+            *bucket = (second, 0);
         }
+        bucket.1 += samples;
     }
+
+    fn snapshot_at(&self, second: u64) -> IngestionSnapshot {
+        let buckets = *self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+        // 启动不足一个窗口时按实际运行时间计算，当前这一秒计入窗口
+        let rate = |window: u64| {
+            let window = window.min(second + 1);
+            let samples: u64 = buckets
+                .iter()
+                .filter(|(bucket_second, _)| *bucket_second <= second && second - *bucket_second < window)
+                .map(|(_, samples)| samples)
+                .sum();
+            samples as f64 / window as f64
+        };
+        IngestionSnapshot {
+            total_samples: self.total.load(Ordering::Relaxed),
+            samples_per_sec_10s: rate(10),
+            samples_per_sec_60s: rate(INGESTION_WINDOW_SECS),
+        }
+    }
+}
+
+impl Default for IngestionStats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// 获取监控数据的实时写入速率
+pub async fn get_ingestion_stats(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    (StatusCode::OK, Json(NodeServiceResponse::success(state.ingestion.snapshot(), "获取写入速率成功")))
 }
 
 /// 获取系统监控统计信息
 pub async fn get_system_metrics_stats(
     State(state): State<Arc<AppState>>,
 ) -> impl IntoResponse {
-    let db = state.database.lock().await;
+    let db = &state.database;
     
     // 获取总监控数据数量
     let total_metrics: i64 = match sqlx::query("SELECT COUNT(*) as count FROM node_metrics")
@@ -171,7 +767,7 @@ pub async fn get_system_metrics_stats(
         Ok(row) => row.get("count"),
         Err(e) => {
             error!("获取总监控数据数量失败: {}", e);
-            return Json(NodeServiceResponse::error("获取统计信息失败"));
+            return (StatusCode::INTERNAL_SERVER_ERROR, Json(NodeServiceResponse::error("获取统计信息失败")));
         }
     };
     
@@ -185,7 +781,7 @@ pub async fn get_system_metrics_stats(
         Ok(row) => row.get("count"),
         Err(e) => {
             error!("获取最近24小时数据量失败: {}", e);
-            return Json(NodeServiceResponse::error("获取统计信息失败"));
+            return (StatusCode::INTERNAL_SERVER_ERROR, Json(NodeServiceResponse::error("获取统计信息失败")));
         }
     };
     
@@ -219,14 +815,36 @@ pub async fn get_system_metrics_stats(
         }
     });
     
-    Json(NodeServiceResponse::success(stats, "获取系统监控统计信息成功"))
+    (StatusCode::OK, Json(NodeServiceResponse::success(stats, "获取系统监控统计信息成功")))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use axum::http::StatusCode;
-    use std::sync::Arc;
+
+    #[test]
+    fn test_ingestion_stats() {
+        let stats = IngestionStats::new();
+        assert_eq!(stats.snapshot_at(0).samples_per_sec_60s, 0.0);
+
+        // 启动后第5秒：按5秒的运行时间计算
+        stats.record_at(2, 30);
+        stats.record_at(4, 20);
+        let snapshot = stats.snapshot_at(4);
+        assert_eq!(snapshot.total_samples, 50);
+        assert_eq!(snapshot.samples_per_sec_10s, 10.0);
+        assert_eq!(snapshot.samples_per_sec_60s, 10.0);
+
+        // 超出窗口的样本不再计入速率，总数保持累计
+        stats.record_at(100, 60);
+        let snapshot = stats.snapshot_at(105);
+        assert_eq!(snapshot.total_samples, 110);
+        assert_eq!(snapshot.samples_per_sec_10s, 6.0);
+        assert_eq!(snapshot.samples_per_sec_60s, 1.0);
+        // 与过期样本落在同一个桶时覆盖旧值（第2秒的30条不再计入）
+        stats.record_at(62, 5);
+        assert_eq!(stats.snapshot_at(62).samples_per_sec_60s, 25.0 / 60.0);
+    }
 
     #[tokio::test]
     async fn test_metrics_query_validation() {
@@ -236,6 +854,8 @@ mod tests {
             end_time: Some("2025-01-21T11:00:00Z".to_string()),
             limit: Some(100),
             offset: Some(0),
+            interval: None,
+            fields: None,
         };
         
         assert!(valid_query.start_time.is_some());
@@ -247,6 +867,8 @@ mod tests {
             end_time: Some("2025-01-21T11:00:00Z".to_string()),
             limit: Some(100),
             offset: Some(0),
+            interval: None,
+            fields: None,
         };
         
         // 验证时间解析会失败
@@ -279,4 +901,178 @@ mod tests {
         
         assert!(start_time > end_time);
     }
+
+    #[test]
+    fn test_render_prometheus() {
+        let metric = NodeMetric {
+            id: 1,
+            node_id: "node-1".to_string(),
+            metric_time: Utc::now(),
+            cpu_usage: Some(42.5),
+            memory_usage: Some(60.0),
+            disk_usage: None,
+            disk_total: None,
+            disk_available: None,
+            load_average: Some(1.5),
+            memory_total: Some(2048),
+            memory_available: Some(1024),
+            uptime: Some(3600),
+            network_rx_bytes: Some(123456),
+            network_tx_bytes: Some(654321),
+            disk_read_bytes: None,
+            disk_write_bytes: None,
+            created_at: Utc::now(),
+        };
+        let hostnames = HashMap::from([("node-1".to_string(), "web \"01\"".to_string())]);
+
+        let output = render_prometheus(&[metric], &hostnames);
+        assert!(output.contains("# TYPE node_cpu_usage gauge"));
+        assert!(output.contains("node_cpu_usage{node_id=\"node-1\",hostname=\"web \\\"01\\\"\"} 42.5"));
+        assert!(output.contains("node_uptime_seconds{node_id=\"node-1\",hostname=\"web \\\"01\\\"\"} 3600"));
+        assert!(output.contains("# TYPE node_network_receive_bytes_total counter"));
+        assert!(output.contains("node_network_receive_bytes_total{node_id=\"node-1\",hostname=\"web \\\"01\\\"\"} 123456"));
+        assert!(output.contains("node_network_transmit_bytes_total{node_id=\"node-1\",hostname=\"web \\\"01\\\"\"} 654321"));
+        // 缺失的指标不输出样本行
+        assert!(!output.contains("node_disk_usage{"));
+        assert!(!output.contains("node_disk_read_bytes_total{"));
+    }
+
+    #[test]
+    fn test_csv_row() {
+        let metric_time = DateTime::parse_from_rfc3339("2025-01-21T10:00:00Z").unwrap().with_timezone(&Utc);
+        let metric = NodeMetric {
+            id: 1,
+            node_id: "node-1".to_string(),
+            metric_time,
+            cpu_usage: Some(12.5),
+            memory_usage: Some(40.0),
+            disk_usage: None,
+            disk_total: Some(1000),
+            disk_available: None,
+            load_average: Some(0.5),
+            memory_total: Some(2048),
+            memory_available: Some(1024),
+            uptime: Some(3600),
+            network_rx_bytes: Some(500),
+            network_tx_bytes: Some(600),
+            disk_read_bytes: None,
+            disk_write_bytes: Some(700),
+            created_at: metric_time,
+        };
+
+        assert_eq!(csv_row(&metric), "2025-01-21T10:00:00+00:00,12.5,40,,1000,,0.5,2048,1024,3600,500,600,,700\n");
+        assert_eq!(CSV_HEADER.matches(',').count(), csv_row(&metric).matches(',').count());
+        assert_eq!(sanitize_filename("node/../1 a"), "node____1_a");
+    }
+
+    #[test]
+    fn test_parse_interval() {
+        assert_eq!(parse_interval("30s"), Some(30));
+        assert_eq!(parse_interval("5m"), Some(300));
+        assert_eq!(parse_interval("1H"), Some(3600));
+        assert_eq!(parse_interval("1d"), Some(86400));
+        assert_eq!(parse_interval("90"), Some(90));
+        assert_eq!(parse_interval("0m"), None);
+        assert_eq!(parse_interval("5w"), None);
+        assert_eq!(parse_interval("m"), None);
+        assert_eq!(parse_interval(""), None);
+    }
+
+    #[test]
+    fn test_project_metric_fields() {
+        let fields = parse_metric_fields(" cpu_usage,cpu,memory_usage,,cpu_usage ");
+        assert_eq!(fields, vec!["cpu_usage", "memory_usage"]);
+
+        let metric_time = DateTime::parse_from_rfc3339("2025-01-21T10:00:00Z").unwrap().with_timezone(&Utc);
+        let metric = NodeMetric {
+            id: 1,
+            node_id: "node-1".to_string(),
+            metric_time,
+            cpu_usage: Some(12.5),
+            memory_usage: None,
+            disk_usage: Some(40.0),
+            disk_total: None,
+            disk_available: None,
+            load_average: None,
+            memory_total: None,
+            memory_available: None,
+            uptime: Some(3600),
+            created_at: metric_time,
+            network_rx_bytes: None,
+            network_tx_bytes: None,
+            disk_read_bytes: None,
+            disk_write_bytes: None,
+        };
+        let projected = project_metric_fields(std::slice::from_ref(&metric), Some(&["cpu_usage"]));
+        let keys: Vec<&String> = projected[0].as_object().unwrap().keys().collect();
+        assert_eq!(keys, ["cpu_usage", "id", "metric_time", "node_id"]);
+        assert_eq!(projected[0]["cpu_usage"], 12.5);
+
+        let bucket = crate::models::MetricBucket {
+            bucket_time: metric_time,
+            avg_cpu_usage: Some(10.0),
+            max_cpu_usage: Some(20.0),
+            avg_memory_usage: Some(30.0),
+            max_memory_usage: Some(40.0),
+            avg_disk_usage: None,
+            max_disk_usage: None,
+            avg_load_average: None,
+            max_load_average: None,
+            sample_count: 3,
+        };
+        let projected = project_metric_fields(&[bucket], Some(&["cpu_usage"]));
+        let keys: Vec<&String> = projected[0].as_object().unwrap().keys().collect();
+        assert_eq!(keys, ["avg_cpu_usage", "bucket_time", "max_cpu_usage", "sample_count"]);
+
+        // 未选择字段时返回全部字段
+        assert_eq!(project_metric_fields(&[metric], None)[0]["uptime"], 3600);
+    }
+
+    #[tokio::test]
+    async fn test_import_metric_lines() {
+        use crate::database::TempDatabaseFile;
+        use crate::models::NodeCreate;
+
+        let db_file = TempDatabaseFile::new("import");
+        let db = db_file.open().await;
+        Node::create(&db.pool, NodeCreate {
+            node_id: "node-1".to_string(),
+            hostname: "host-1".to_string(),
+            ip_address: "10.0.0.1".to_string(),
+            ..Default::default()
+        }).await.unwrap();
+
+        let oversized = format!("{{\"metric_time\":\"{}\"}}\n", "x".repeat(MAX_IMPORT_LINE_BYTES));
+        // 第一行跨越两个数据块，最后一行没有换行符
+        let chunks = vec![
+            "{\"metric_time\":\"2025-01-21T10:00:00Z\",\"cpu_usage\":",
+            "12.5,\"id\":7,\"node_id\":\"other\"}\n\n",
+            "not json\n",
+            "{\"cpu_usage\":1.0}\n",
+            "{\"metric_time\":\"2025-01-21T10:01:00Z\",\"memory_total\":-1}\n",
+            &oversized,
+            // 与实时数据相同的校验：超出范围的百分比使该行失败，略超100的截断为100
+            "{\"metric_time\":\"2025-01-21T10:03:00Z\",\"cpu_usage\":5000}\n",
+            "{\"metric_time\":\"2025-01-21T10:04:00Z\",\"cpu_usage\":103}\n",
+            "{\"metric_time\":\"2025-01-21T10:02:00Z\",\"uptime\":60}",
+        ];
+        let body = stream::iter(chunks.into_iter().map(|chunk| Ok::<_, std::io::Error>(Bytes::from(chunk.to_string()))));
+
+        let mut importer = MetricImporter::new(&db.pool, "node-1");
+        importer.import(body).await.unwrap();
+        let report = importer.report;
+        assert_eq!(report.imported, 3);
+        assert_eq!(report.failed, 5);
+        let lines: Vec<usize> = report.errors.iter().map(|error| error.line).collect();
+        assert_eq!(lines, vec![3, 4, 5, 6, 7]);
+        assert!(report.errors[2].reason.starts_with("memory_total"), "{}", report.errors[2].reason);
+        assert!(report.errors[4].reason.starts_with("cpu_usage"), "{}", report.errors[4].reason);
+
+        let (metrics, total) = NodeMetric::find_by_node_id_with_range(&db.pool, "node-1", None, None, 10, 0).await.unwrap();
+        assert_eq!(total, 3);
+        assert!(metrics.iter().any(|metric| metric.cpu_usage == Some(12.5)
+            && metric.metric_time.to_rfc3339() == "2025-01-21T10:00:00+00:00"));
+        assert!(metrics.iter().any(|metric| metric.cpu_usage == Some(100.0)
+            && metric.metric_time.to_rfc3339() == "2025-01-21T10:04:00+00:00"));
+    }
 }
@@ -1,3 +1,11 @@
 pub mod websocket;
+pub mod websocket_error;
 pub mod nodes;
 pub mod metrics;
+pub mod commands;
+pub mod auth;
+pub mod cleanup;
+pub mod scheduler;
+pub mod alerts;
+pub mod anomaly;
+pub mod events;
@@ -1,21 +1,28 @@
 use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::Instant;
 
 use axum::{
-    extract::{Path, Query, State},
+    extract::{ws::Message, Path, Query, State},
+    http::StatusCode,
     response::IntoResponse,
     Json,
 };
 use chrono::Utc;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
-use tokio::sync::{broadcast, Mutex, RwLock};
+use tokio::sync::{broadcast, mpsc, watch, Mutex, RwLock};
 use tracing::{debug, error, info, warn};
-use uuid::Uuid;
-use sqlx::Row;
 
-use crate::database::Database;
-use crate::models::{Node, NodeCreate, NodeUpdate, NodeMetric, MetricCreate};
+use crate::config::CoreConfig;
+use crate::database::{Database, DbPool};
+use crate::models::{Node, NodeFilter, NodeMetric, NodeOverview, NodeSortField, NodeStatus, NodeUpdate, EVENT_NODE_STATUS_CHANGE};
+use crate::services::alerts::AlertEngine;
+use crate::services::anomaly::AnomalyDetector;
+use crate::services::commands::CommandWaiters;
+use crate::services::events::record_event;
+use crate::services::metrics::IngestionStats;
+use crate::services::websocket::MIN_PROTOCOL_VERSION;
 
 /// 活跃连接信息
 #[derive(Debug, Clone, Serialize)]
@@ -23,24 +30,159 @@ pub struct ActiveConnection {
     pub node_id: String,
     pub connected_at: chrono::DateTime<Utc>,
     pub last_activity: chrono::DateTime<Utc>,
-    pub status: String,
+    pub status: NodeStatus,
+    /// 连接时协商的协议版本，处理逻辑可据此兼容旧版节点
+    pub protocol_version: u32,
+}
+
+/// 令牌桶限流器
+#[derive(Debug, Clone)]
+pub struct TokenBucket {
+    capacity: f64,
+    tokens: f64,
+    refill_per_sec: f64,
+    last_refill: Instant,
+    /// 连续被限流的消息数
+    throttled: u32,
+}
+
+/// 限流判定结果
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RateDecision {
+    /// 允许处理
+    Allowed,
+    /// 超出速率，丢弃该消息
+    Throttled,
+    /// 连续被限流的消息超过突发容量，应断开连接
+    Exceeded,
+}
+
+impl TokenBucket {
+    pub fn new(rate_per_sec: u32, burst: u32) -> Self {
+        let capacity = f64::from(burst.max(1));
+        Self {
+            capacity,
+            tokens: capacity,
+            refill_per_sec: f64::from(rate_per_sec),
+            last_refill: Instant::now(),
+            throttled: 0,
+        }
+    }
+
+    /// 尝试消耗一个令牌
+    pub fn acquire(&mut self) -> RateDecision {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.refill_per_sec).min(self.capacity);
+        self.last_refill = now;
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            self.throttled = 0;
+            RateDecision::Allowed
+        } else {
+            self.throttled = self.throttled.saturating_add(1);
+            if f64::from(self.throttled) > self.capacity {
+                RateDecision::Exceeded
+            } else {
+                RateDecision::Throttled
+            }
+        }
+    }
 }
 
 /// 连接管理器
 #[derive(Debug, Clone)]
 pub struct ConnectionManager {
     connections: Arc<RwLock<HashMap<String, ActiveConnection>>>,
+    senders: Arc<RwLock<HashMap<String, mpsc::UnboundedSender<Message>>>>,
+    rate_limiters: Arc<Mutex<HashMap<String, TokenBucket>>>,
+    /// 各节点当前会话的恢复令牌，节点断线重连时出示以延续会话
+    resume_tokens: Arc<RwLock<HashMap<String, String>>>,
+    rate_limit_per_sec: u32,
+    rate_limit_burst: u32,
 }
 
 impl ConnectionManager {
-    pub fn new() -> Self {
+    /// 创建连接管理器，`rate_limit_per_sec`和`rate_limit_burst`为每个节点连接的消息速率限制
+    pub fn new(rate_limit_per_sec: u32, rate_limit_burst: u32) -> Self {
         Self {
             connections: Arc::new(RwLock::new(HashMap::new())),
+            senders: Arc::new(RwLock::new(HashMap::new())),
+            rate_limiters: Arc::new(Mutex::new(HashMap::new())),
+            resume_tokens: Arc::new(RwLock::new(HashMap::new())),
+            rate_limit_per_sec,
+            rate_limit_burst,
+        }
+    }
+
+    /// 注册节点的消息发送通道（新连接同时重置该节点的限流状态）
+    pub async fn register_sender(&self, node_id: String, sender: mpsc::UnboundedSender<Message>) {
+        self.rate_limiters.lock().await.insert(
+            node_id.clone(),
+            TokenBucket::new(self.rate_limit_per_sec, self.rate_limit_burst),
+        );
+        let mut senders = self.senders.write().await;
+        senders.insert(node_id, sender);
+    }
+
+    /// 对节点连接收到的消息进行限流判定
+    pub async fn check_rate(&self, node_id: &str) -> RateDecision {
+        self.rate_limiters
+            .lock()
+            .await
+            .entry(node_id.to_string())
+            .or_insert_with(|| TokenBucket::new(self.rate_limit_per_sec, self.rate_limit_burst))
+            .acquire()
+    }
+
+    /// 移除节点的消息发送通道
+    ///
+    /// 仅当当前登记的通道就是`sender`时才移除，避免同一节点重连后旧连接的清理误删新通道
+    pub async fn remove_sender(&self, node_id: &str, sender: &mpsc::UnboundedSender<Message>) -> bool {
+        let mut senders = self.senders.write().await;
+        match senders.get(node_id) {
+            Some(current) if current.same_channel(sender) => {
+                senders.remove(node_id);
+                self.rate_limiters.lock().await.remove(node_id);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// 为节点的新连接签发会话恢复令牌，返回重连时出示的令牌是否有效以及新令牌
+    ///
+    /// 出示的令牌与上次签发的一致说明节点在延续断线前的会话；每次连接都会轮换令牌，旧令牌随即失效
+    pub async fn start_session(&self, node_id: &str, resume_token: Option<&str>) -> (bool, String) {
+        let token = uuid::Uuid::new_v4().to_string();
+        let mut tokens = self.resume_tokens.write().await;
+        let resumed = resume_token.is_some_and(|presented| tokens.get(node_id).is_some_and(|current| current == presented));
+        tokens.insert(node_id.to_string(), token.clone());
+        (resumed, token)
+    }
+
+    /// 作废节点的会话恢复令牌，节点下次连接视为新会话
+    pub async fn end_session(&self, node_id: &str) {
+        self.resume_tokens.write().await.remove(node_id);
+    }
+
+    /// 获取当前保持WebSocket连接的节点数量
+    pub async fn connected_count(&self) -> usize {
+        self.senders.read().await.len()
+    }
+
+    /// 向指定节点发送消息
+    pub async fn send_to_node(&self, node_id: &str, message: Message) -> bool {
+        let senders = self.senders.read().await;
+        match senders.get(node_id) {
+            Some(sender) => sender.send(message).is_ok(),
+            None => false,
         }
     }
 
     /// 添加新连接
-    pub async fn add_connection(&self, node_id: String) {
+    pub async fn add_connection(&self, node_id: String, protocol_version: u32) {
         let mut connections = self.connections.write().await;
         connections.insert(
             node_id.clone(),
@@ -48,31 +190,49 @@ impl ConnectionManager {
                 node_id: node_id.clone(),
                 connected_at: Utc::now(),
                 last_activity: Utc::now(),
-                status: "online".to_string(),
+                status: NodeStatus::Online,
+                protocol_version,
             },
         );
         info!("✅ 节点连接已添加: {}", node_id);
     }
 
-    /// 更新连接活动时间
-    pub async fn update_activity(&self, node_id: &str) -> bool {
+    /// 根据数据库中仍然存活的节点恢复连接视图（Core重启后使用）
+    ///
+    /// 恢复的连接以最后心跳时间作为活动时间，节点未重连时会被无活动连接清理自然移除
+    pub async fn restore_connections(&self, nodes: &[Node]) -> usize {
         let mut connections = self.connections.write().await;
-        if let Some(connection) = connections.get_mut(node_id) {
-            connection.last_activity = Utc::now();
-            connection.status = "online".to_string();
-            debug!("🔄 更新节点活动时间: {}", node_id);
-            true
-        } else {
-            false
+        let mut restored = 0;
+        for node in nodes {
+            let Some(last_heartbeat) = node.last_heartbeat else {
+                continue;
+            };
+            if connections.contains_key(&node.node_id) {
+                continue;
+            }
+            connections.insert(
+                node.node_id.clone(),
+                ActiveConnection {
+                    node_id: node.node_id.clone(),
+                    connected_at: last_heartbeat,
+                    last_activity: last_heartbeat,
+                    status: NodeStatus::Online,
+                    // 节点重连注册后更新为实际协商的版本
+                    protocol_version: MIN_PROTOCOL_VERSION,
+                },
+            );
+            restored += 1;
         }
+        restored
     }
 
-    /// 更新连接状态
-    pub async fn update_status(&self, node_id: &str, status: &str) -> bool {
+    /// 更新连接活动时间
+    pub async fn update_activity(&self, node_id: &str) -> bool {
         let mut connections = self.connections.write().await;
         if let Some(connection) = connections.get_mut(node_id) {
-            connection.status = status.to_string();
             connection.last_activity = Utc::now();
+            connection.status = NodeStatus::Online;
+            debug!("🔄 更新节点活动时间: {}", node_id);
             true
         } else {
             false
@@ -83,23 +243,17 @@ impl ConnectionManager {
     pub async fn remove_connection(&self, node_id: &str) -> bool {
         let mut connections = self.connections.write().await;
         if let Some(connection) = connections.get_mut(node_id) {
-            connection.status = "offline".to_string();
+            connection.status = NodeStatus::Offline;
             connections.remove(node_id).is_some()
         } else {
             false
         }
     }
 
-    /// 获取所有活跃连接
-    pub async fn get_connections(&self) -> Vec<ActiveConnection> {
+    /// 获取节点连接协商的协议版本
+    pub async fn protocol_version(&self, node_id: &str) -> Option<u32> {
         let connections = self.connections.read().await;
-        connections.values().cloned().collect()
-    }
-
-    /// 获取特定节点的连接信息
-    pub async fn get_connection(&self, node_id: &str) -> Option<ActiveConnection> {
-        let connections = self.connections.read().await;
-        connections.get(node_id).cloned()
+        connections.get(node_id).map(|conn| conn.protocol_version)
     }
 
     /// 清理长时间无活动的连接
@@ -116,7 +270,7 @@ impl ConnectionManager {
         
         for node_id in &inactive_nodes {
             if let Some(connection) = connections.get_mut(node_id) {
-                connection.status = "offline".to_string();
+                connection.status = NodeStatus::Offline;
             }
             connections.remove(node_id);
             warn!("🧹 清理长时间无活动的连接: {}", node_id);
@@ -124,32 +278,47 @@ impl ConnectionManager {
         
         inactive_nodes.len()
     }
-
-    /// 获取在线节点数量
-    pub async fn get_online_count(&self) -> usize {
-        let connections = self.connections.read().await;
-        connections.values()
-            .filter(|conn| conn.status == "online")
-            .count()
-    }
-
-    /// 获取离线节点数量
-    pub async fn get_offline_count(&self) -> usize {
-        let connections = self.connections.read().await;
-        connections.values()
-            .filter(|conn| conn.status == "offline")
-            .count()
-    }
 }
 
 /// 节点查询参数
 #[derive(Debug, Deserialize)]
 pub struct NodeQuery {
-    pub status: Option<String>,
+    /// 按节点状态过滤：online / offline / unreachable / decommissioned
+    pub status: Option<NodeStatus>,
+    /// 排序字段：hostname / last_heartbeat / registered_at（默认）
+    pub sort_by: Option<String>,
+    /// 排序方向：asc / desc（默认）
+    pub order: Option<String>,
+    /// 按主机名或IP地址搜索
+    pub search: Option<String>,
+    /// 按标签过滤
+    pub tag: Option<String>,
+    /// 按操作系统名称过滤
+    pub os_name: Option<String>,
+    /// 按操作系统版本前缀过滤
+    pub os_version: Option<String>,
+    /// 按内核版本前缀过滤
+    pub kernel_version: Option<String>,
+    /// 按节点代理版本过滤
+    pub agent_version: Option<String>,
     pub limit: Option<i64>,
     pub offset: Option<i64>,
 }
 
+/// 节点列表默认返回的条数
+const NODE_LIST_DEFAULT_LIMIT: i64 = 50;
+/// 节点列表单次最多返回的条数
+const NODE_LIST_MAX_LIMIT: i64 = 500;
+
+impl NodeQuery {
+    /// 规范化后的分页参数
+    pub fn page(&self) -> (i64, i64) {
+        let limit = self.limit.unwrap_or(NODE_LIST_DEFAULT_LIMIT).clamp(1, NODE_LIST_MAX_LIMIT);
+        let offset = self.offset.unwrap_or(0).max(0);
+        (limit, offset)
+    }
+}
+
 /// 节点服务响应
 #[derive(Debug, Serialize)]
 pub struct NodeServiceResponse<T> {
@@ -179,49 +348,64 @@ impl<T> NodeServiceResponse<T> {
     }
 }
 
+/// 确认节点存在，不存在（404）或查询失败（500）时返回对应的错误响应
+pub async fn ensure_node_exists<T>(
+    pool: &DbPool,
+    node_id: &str,
+) -> Result<(), (StatusCode, Json<NodeServiceResponse<T>>)> {
+    match Node::find_by_node_id(pool, node_id).await {
+        Ok(Some(_)) => Ok(()),
+        Ok(None) => Err((StatusCode::NOT_FOUND, Json(NodeServiceResponse::error("节点不存在")))),
+        Err(e) => {
+            error!("查询节点失败: {}", e);
+            Err((StatusCode::INTERNAL_SERVER_ERROR, Json(NodeServiceResponse::error("查询节点失败"))))
+        }
+    }
+}
+
 /// 获取节点列表
 pub async fn get_nodes(
     State(state): State<Arc<AppState>>,
     Query(query): Query<NodeQuery>,
 ) -> impl IntoResponse {
-    let db = state.database.lock().await;
-    
-    let nodes = match Node::find_all(&db.pool).await {
-        Ok(nodes) => nodes,
+    let db = &state.database;
+
+    let (limit, offset) = query.page();
+
+    let filter = NodeFilter {
+        status: query.status,
+        search: query.search.clone(),
+        tag: query.tag.clone(),
+        os_name: query.os_name.clone(),
+        os_version: query.os_version.clone(),
+        kernel_version: query.kernel_version.clone(),
+        agent_version: query.agent_version.clone(),
+        sort_by: NodeSortField::from_param(query.sort_by.as_deref()),
+        ascending: query.order.as_deref().is_some_and(|order| order.eq_ignore_ascii_case("asc")),
+    };
+
+    let (nodes, total) = match Node::find_paginated(&db.pool, &filter, limit, offset).await {
+        Ok(result) => result,
         Err(e) => {
             error!("获取节点列表失败: {}", e);
-            return Json(NodeServiceResponse::error("获取节点列表失败"));
+            return (StatusCode::INTERNAL_SERVER_ERROR, Json(NodeServiceResponse::error("获取节点列表失败")));
         }
     };
 
-    // 根据状态过滤
-    let filtered_nodes: Vec<Node> = if let Some(status) = &query.status {
-        nodes.into_iter()
-            .filter(|node| node.status == *status)
-            .collect()
-    } else {
-        nodes
-    };
-
-    // 分页处理
-    let limit = query.limit.unwrap_or(50);
-    let offset = query.offset.unwrap_or(0);
-    
-    let total = filtered_nodes.len();
-    let paginated_nodes: Vec<Node> = filtered_nodes
-        .into_iter()
-        .skip(offset as usize)
-        .take(limit as usize)
-        .collect();
+    let stale_minutes = state.config.node_stale_minutes;
+    let mut nodes: Vec<Node> = nodes.into_iter().map(|node| node.with_liveness(stale_minutes)).collect();
+    for node in &mut nodes {
+        node.protocol_version = state.connection_manager.protocol_version(&node.node_id).await;
+    }
 
     let response_data = json!({
-        "nodes": paginated_nodes,
+        "nodes": nodes,
         "total": total,
         "limit": limit,
         "offset": offset
     });
 
-    Json(NodeServiceResponse::success(response_data, "获取节点列表成功"))
+    (StatusCode::OK, Json(NodeServiceResponse::success(response_data, "获取节点列表成功")))
 }
 
 /// 获取单个节点信息
@@ -229,18 +413,108 @@ pub async fn get_node(
     State(state): State<Arc<AppState>>,
     Path(node_id): Path<String>,
 ) -> impl IntoResponse {
-    let db = state.database.lock().await;
+    let db = &state.database;
     
     match Node::find_by_node_id(&db.pool, &node_id).await {
         Ok(Some(node)) => {
-            Json(NodeServiceResponse::success(node, "获取节点信息成功"))
+            let mut node = node.with_liveness(state.config.node_stale_minutes);
+            node.protocol_version = state.connection_manager.protocol_version(&node_id).await;
+            (StatusCode::OK, Json(NodeServiceResponse::success(node, "获取节点信息成功")))
         }
         Ok(None) => {
-            Json(NodeServiceResponse::error("节点不存在"))
+            (StatusCode::NOT_FOUND, Json(NodeServiceResponse::error("节点不存在")))
         }
         Err(e) => {
             error!("获取节点信息失败: {}", e);
-            Json(NodeServiceResponse::error("获取节点信息失败"))
+            (StatusCode::INTERNAL_SERVER_ERROR, Json(NodeServiceResponse::error("获取节点信息失败")))
+        }
+    }
+}
+
+/// 设置节点标签请求
+#[derive(Debug, Deserialize)]
+pub struct UpdateTagsRequest {
+    pub tags: Vec<String>,
+}
+
+/// 设置节点标签（整体替换）
+pub async fn update_node_tags(
+    State(state): State<Arc<AppState>>,
+    Path(node_id): Path<String>,
+    Json(request): Json<UpdateTagsRequest>,
+) -> impl IntoResponse {
+    let db = &state.database;
+
+    let update_data = NodeUpdate {
+        hostname: None,
+        ip_address: None,
+        os_info: None,
+        os_name: None,
+        os_version: None,
+        kernel_version: None,
+        agent_version: None,
+        cpu_count: None,
+        total_memory: None,
+        status: None,
+        tags: Some(request.tags),
+    };
+
+    match Node::update(&db.pool, &node_id, update_data).await {
+        Ok(Some(node)) => {
+            info!("🏷️ 节点标签已更新: {} -> {:?}", node_id, node.tags);
+            let node = node.with_liveness(state.config.node_stale_minutes);
+            (StatusCode::OK, Json(NodeServiceResponse::success(node, "节点标签更新成功")))
+        }
+        Ok(None) => (StatusCode::NOT_FOUND, Json(NodeServiceResponse::error("节点不存在"))),
+        Err(e) => {
+            error!("更新节点标签失败: {}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, Json(NodeServiceResponse::error("更新节点标签失败")))
+        }
+    }
+}
+
+/// 设置节点监控数据保留天数请求，`days`为null时取消覆盖
+#[derive(Debug, Deserialize)]
+pub struct UpdateRetentionRequest {
+    pub days: Option<i64>,
+}
+
+/// 节点监控数据保留天数上限
+const MAX_METRICS_RETENTION_DAYS: i64 = 3650;
+
+/// 设置节点的监控数据保留天数（覆盖节点上报值和全局配置）
+pub async fn update_node_retention(
+    State(state): State<Arc<AppState>>,
+    Path(node_id): Path<String>,
+    Json(request): Json<UpdateRetentionRequest>,
+) -> impl IntoResponse {
+    if let Some(days) = request.days {
+        if !(1..=MAX_METRICS_RETENTION_DAYS).contains(&days) {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(NodeServiceResponse::error(&format!("保留天数必须在1到{}之间", MAX_METRICS_RETENTION_DAYS))),
+            );
+        }
+    }
+
+    let db = &state.database;
+
+    match Node::set_metrics_retention_override(&db.pool, &node_id, request.days).await {
+        Ok(Some(node)) => {
+            let effective_days = node.effective_metrics_retention(state.config.metrics_retention_days);
+            info!("🗓️ 节点监控数据保留天数已更新: {} -> {}天", node_id, effective_days);
+            let response_data = json!({
+                "node_id": node.node_id,
+                "metrics_retention_override_days": node.metrics_retention_override_days,
+                "metrics_retention_days": node.metrics_retention_days,
+                "effective_retention_days": effective_days
+            });
+            (StatusCode::OK, Json(NodeServiceResponse::success(response_data, "保留天数更新成功")))
+        }
+        Ok(None) => (StatusCode::NOT_FOUND, Json(NodeServiceResponse::error("节点不存在"))),
+        Err(e) => {
+            error!("更新节点保留天数失败: {}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, Json(NodeServiceResponse::error("更新节点保留天数失败")))
         }
     }
 }
@@ -250,69 +524,179 @@ pub async fn delete_node(
     State(state): State<Arc<AppState>>,
     Path(node_id): Path<String>,
 ) -> impl IntoResponse {
-    let db = state.database.lock().await;
+    let db = &state.database;
     
     match Node::delete(&db.pool, &node_id).await {
         Ok(true) => {
             info!("🗑️ 节点已删除: {}", node_id);
-            Json(NodeServiceResponse::success((), "节点删除成功"))
+            (StatusCode::OK, Json(NodeServiceResponse::success((), "节点删除成功")))
         }
         Ok(false) => {
-            Json(NodeServiceResponse::error("节点不存在"))
+            (StatusCode::NOT_FOUND, Json(NodeServiceResponse::error("节点不存在")))
         }
         Err(e) => {
             error!("删除节点失败: {}", e);
-            Json(NodeServiceResponse::error("删除节点失败"))
+            (StatusCode::INTERNAL_SERVER_ERROR, Json(NodeServiceResponse::error("删除节点失败")))
         }
     }
 }
 
+/// 单次批量删除的最大节点数
+const BULK_DELETE_MAX_NODES: usize = 1000;
+
+/// 批量删除节点请求
+#[derive(Debug, Deserialize)]
+pub struct BulkDeleteRequest {
+    pub node_ids: Vec<String>,
+}
+
+/// 批量删除节点（在同一事务中执行）
+pub async fn bulk_delete_nodes(
+    State(state): State<Arc<AppState>>,
+    Json(request): Json<BulkDeleteRequest>,
+) -> impl IntoResponse {
+    if request.node_ids.is_empty() {
+        return (StatusCode::BAD_REQUEST, Json(NodeServiceResponse::error("节点列表不能为空")));
+    }
+    if request.node_ids.len() > BULK_DELETE_MAX_NODES {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(NodeServiceResponse::error(&format!("单次最多删除{}个节点", BULK_DELETE_MAX_NODES))),
+        );
+    }
+
+    let db = &state.database;
+
+    let results = match Node::delete_many(&db.pool, &request.node_ids).await {
+        Ok(results) => results,
+        Err(e) => {
+            error!("批量删除节点失败: {}", e);
+            return (StatusCode::INTERNAL_SERVER_ERROR, Json(NodeServiceResponse::error("批量删除节点失败")));
+        }
+    };
+
+    let deleted = results.iter().filter(|(_, deleted)| *deleted).count();
+    info!("🗑️ 批量删除节点: {}/{}", deleted, results.len());
+
+    let results: Vec<serde_json::Value> = results
+        .into_iter()
+        .map(|(node_id, deleted)| {
+            if deleted {
+                json!({ "node_id": node_id, "success": true })
+            } else {
+                json!({ "node_id": node_id, "success": false, "error": "节点不存在" })
+            }
+        })
+        .collect();
+
+    let response_data = json!({
+        "results": results,
+        "deleted": deleted,
+        "total": results.len()
+    });
+
+    (StatusCode::OK, Json(NodeServiceResponse::success(response_data, "批量删除节点完成")))
+}
+
 /// 获取节点统计信息
 pub async fn get_node_stats(
     State(state): State<Arc<AppState>>,
 ) -> impl IntoResponse {
-    let db = state.database.lock().await;
+    let db = &state.database;
     let connection_manager = &state.connection_manager;
     
-    let total_nodes = match sqlx::query("SELECT COUNT(*) as count FROM nodes")
-        .fetch_one(&db.pool)
-        .await
-    {
-        Ok(row) => row.get::<i64, _>("count"),
+    // 在线状态以数据库为准，与节点列表一致，不受Core重启后连接管理器为空的影响
+    let counts = match Node::count_by_status(&db.pool, state.config.node_stale_minutes).await {
+        Ok(counts) => counts,
         Err(e) => {
-            error!("获取节点总数失败: {}", e);
-            return Json(NodeServiceResponse::error("获取统计信息失败"));
+            error!("获取节点统计信息失败: {}", e);
+            return (StatusCode::INTERNAL_SERVER_ERROR, Json(NodeServiceResponse::error("获取统计信息失败")));
         }
     };
 
-    let online_nodes = connection_manager.get_online_count().await;
-    let offline_nodes = connection_manager.get_offline_count().await;
-
     let stats = json!({
-        "total_nodes": total_nodes,
-        "online_nodes": online_nodes,
-        "offline_nodes": offline_nodes,
-        "connection_count": online_nodes + offline_nodes
+        "total_nodes": counts.total,
+        "online_nodes": counts.online,
+        "offline_nodes": counts.offline,
+        // 异常断开或长时间无心跳的节点，需要关注
+        "unreachable_nodes": counts.unreachable,
+        // 已主动注销的节点，不需要告警
+        "decommissioned_nodes": counts.decommissioned,
+        // 当前与本Core保持WebSocket连接的节点数
+        "connection_count": connection_manager.connected_count().await
     });
 
-    Json(NodeServiceResponse::success(stats, "获取节点统计信息成功"))
+    (StatusCode::OK, Json(NodeServiceResponse::success(stats, "获取节点统计信息成功")))
+}
+
+/// 获取节点总览：所有节点及其最新监控数据，以及在线节点的平均CPU和内存使用率
+///
+/// 仪表盘只需一次请求即可渲染，无需逐个节点查询最新数据
+pub async fn get_overview(
+    State(state): State<Arc<AppState>>,
+) -> impl IntoResponse {
+    let db = &state.database;
+    
+    let nodes: Vec<NodeOverview> = match Node::find_all_with_latest_metric(&db.pool).await {
+        Ok(nodes) => nodes
+            .into_iter()
+            .map(|overview| NodeOverview {
+                node: overview.node.with_liveness(state.config.node_stale_minutes),
+                ..overview
+            })
+            .collect(),
+        Err(e) => {
+            error!("获取节点总览失败: {}", e);
+            return (StatusCode::INTERNAL_SERVER_ERROR, Json(NodeServiceResponse::error("获取节点总览失败")));
+        }
+    };
+    
+    // 在线判定与节点统计一致：状态为online且心跳未过期
+    let online: Vec<&NodeOverview> = nodes
+        .iter()
+        .filter(|overview| overview.node.status == NodeStatus::Online && overview.node.is_alive)
+        .collect();
+    let average = |value: fn(&NodeMetric) -> Option<f64>| {
+        let values: Vec<f64> = online
+            .iter()
+            .filter_map(|overview| overview.latest_metric.as_ref().and_then(value))
+            .collect();
+        (!values.is_empty()).then(|| values.iter().sum::<f64>() / values.len() as f64)
+    };
+    
+    let response_data = json!({
+        "total_nodes": nodes.len(),
+        "online_nodes": online.len(),
+        "avg_cpu_usage": average(|metric| metric.cpu_usage),
+        "avg_memory_usage": average(|metric| metric.memory_usage),
+        "nodes": nodes
+    });
+    
+    (StatusCode::OK, Json(NodeServiceResponse::success(response_data, "获取节点总览成功")))
 }
 
 /// 清理长时间无活动的节点
 pub async fn cleanup_stale_nodes(
     State(state): State<Arc<AppState>>,
 ) -> impl IntoResponse {
-    let db = state.database.lock().await;
+    let db = &state.database;
     
     // 清理数据库中的过期节点
-    match Node::cleanup_stale_nodes(&db.pool, 30).await {
-        Ok(cleaned_count) => {
+    match Node::cleanup_stale_nodes(&db.pool, state.config.node_stale_minutes).await {
+        Ok(stale_nodes) => {
+            let cleaned_count = stale_nodes.len();
+            for node_id in &stale_nodes {
+                state.connection_manager.remove_connection(node_id).await;
+                state.broadcast_node_status(node_id, NodeStatus::Unreachable);
+                let details = json!({ "status": NodeStatus::Unreachable, "reason": "heartbeat_timeout" });
+                record_event(&state, Some(node_id), EVENT_NODE_STATUS_CHANGE, details).await;
+            }
             info!("🧹 清理了 {} 个过期节点", cleaned_count);
-            Json(NodeServiceResponse::success(cleaned_count, "清理过期节点成功"))
+            (StatusCode::OK, Json(NodeServiceResponse::success(cleaned_count, "清理过期节点成功")))
         }
         Err(e) => {
             error!("清理过期节点失败: {}", e);
-            Json(NodeServiceResponse::error("清理过期节点失败"))
+            (StatusCode::INTERNAL_SERVER_ERROR, Json(NodeServiceResponse::error("清理过期节点失败")))
         }
     }
 }
@@ -331,66 +715,233 @@ pub struct ClientBroadcastMessage {
 /// 应用状态（包含连接管理器和客户端广播器）
 #[derive(Clone)]
 pub struct AppState {
-    pub database: Arc<Mutex<Database>>,
+    /// 数据库（连接池本身支持并发访问，无需额外加锁）
+    pub database: Arc<Database>,
     pub connection_manager: Arc<ConnectionManager>,
     pub client_broadcaster: broadcast::Sender<ClientBroadcastMessage>,
+    pub config: Arc<CoreConfig>,
+    pub alert_engine: Arc<AlertEngine>,
+    /// 按节点近期基线检测指标异常
+    pub anomaly_detector: Arc<AnomalyDetector>,
+    /// Core进程启动时间
+    pub started_at: Instant,
+    /// 等待命令执行结果的HTTP请求
+    pub command_waiters: Arc<CommandWaiters>,
+    /// 监控数据写入速率统计
+    pub ingestion: Arc<IngestionStats>,
+    /// 关闭信号，WebSocket会话持有接收端直到会话结束
+    shutdown: Arc<watch::Sender<bool>>,
 }
 
 impl AppState {
-    pub fn new(database: Database) -> Self {
-        let (broadcaster, _) = broadcast::channel(1000); // 支持1000条消息缓冲
+    pub fn new(database: Database, config: CoreConfig) -> Self {
+        let (broadcaster, _) = broadcast::channel(config.client_broadcast_capacity);
+        let alert_engine = AlertEngine::new(config.alert_webhook_url.clone(), config.alert_resolve_secs);
+        let anomaly_detector = AnomalyDetector::new(config.anomaly_sigma, config.anomaly_window_secs);
         Self {
-            database: Arc::new(Mutex::new(database)),
-            connection_manager: Arc::new(ConnectionManager::new()),
+            database: Arc::new(database),
+            connection_manager: Arc::new(ConnectionManager::new(
+                config.node_rate_limit_per_sec,
+                config.node_rate_limit_burst,
+            )),
             client_broadcaster: broadcaster,
+            config: Arc::new(config),
+            alert_engine: Arc::new(alert_engine),
+            anomaly_detector: Arc::new(anomaly_detector),
+            started_at: Instant::now(),
+            command_waiters: Arc::new(CommandWaiters::new()),
+            ingestion: Arc::new(IngestionStats::new()),
+            shutdown: Arc::new(watch::channel(false).0),
         }
     }
+
+    /// 订阅关闭信号
+    pub fn shutdown_receiver(&self) -> watch::Receiver<bool> {
+        self.shutdown.subscribe()
+    }
+
+    /// 通知所有WebSocket会话服务即将关闭
+    pub fn begin_shutdown(&self) {
+        self.shutdown.send_replace(true);
+    }
+
+    /// 等待所有WebSocket会话结束，超时返回false
+    pub async fn wait_for_sessions(&self, timeout: std::time::Duration) -> bool {
+        let deadline = tokio::time::Instant::now() + timeout;
+        while self.shutdown.receiver_count() > 0 {
+            if tokio::time::Instant::now() >= deadline {
+                return false;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        }
+        true
+    }
     
     /// 广播消息给所有客户端
+    ///
+    /// 没有监控客户端连接时发送会失败，这是正常情况，只记录调试日志
     pub fn broadcast_to_clients(&self, message: ClientBroadcastMessage) {
-        if let Err(e) = self.client_broadcaster.send(message) {
-            warn!("广播消息失败: {}", e);
+        let message_type = message.message_type.clone();
+        if self.client_broadcaster.send(message).is_err() {
+            debug!("没有订阅的监控客户端，丢弃广播消息: {}", message_type);
         }
     }
+
+    /// 广播节点状态变化
+    pub fn broadcast_node_status(&self, node_id: &str, status: NodeStatus) {
+        let now = Utc::now().to_rfc3339();
+        self.broadcast_to_clients(ClientBroadcastMessage {
+            message_type: "node_status_change".to_string(),
+            id: uuid::Uuid::new_v4().to_string(),
+            timestamp: now.clone(),
+            data: json!({
+                "node_id": node_id,
+                "status": status,
+                "timestamp": now
+            }),
+        });
+        info!("📢 广播节点状态变化: {} -> {}", node_id, status);
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use sqlx::SqlitePool;
+    use crate::services::websocket::PROTOCOL_VERSION;
 
     #[tokio::test]
     async fn test_connection_manager() {
-        let manager = ConnectionManager::new();
+        let manager = ConnectionManager::new(20, 100);
+        
+        let (sender, _receiver) = mpsc::unbounded_channel();
         
         // 测试添加连接
-        manager.add_connection("test-node-1".to_string()).await;
-        assert_eq!(manager.get_online_count().await, 1);
+        manager.add_connection("test-node-1".to_string(), PROTOCOL_VERSION).await;
+        manager.register_sender("test-node-1".to_string(), sender.clone()).await;
+        assert_eq!(manager.connected_count().await, 1);
         
         // 测试更新活动时间
         assert!(manager.update_activity("test-node-1").await);
-        
-        // 测试获取连接
-        let connection = manager.get_connection("test-node-1").await;
-        assert!(connection.is_some());
-        assert_eq!(connection.unwrap().node_id, "test-node-1");
+        assert!(!manager.update_activity("missing").await);
+        assert_eq!(manager.protocol_version("test-node-1").await, Some(PROTOCOL_VERSION));
         
         // 测试移除连接
         assert!(manager.remove_connection("test-node-1").await);
-        assert_eq!(manager.get_online_count().await, 0);
+        assert!(manager.remove_sender("test-node-1", &sender).await);
+        assert!(!manager.update_activity("test-node-1").await);
+        assert_eq!(manager.connected_count().await, 0);
     }
 
     #[tokio::test]
     async fn test_connection_manager_cleanup() {
-        let manager = ConnectionManager::new();
+        let manager = ConnectionManager::new(20, 100);
         
         // 添加测试连接
-        manager.add_connection("test-node-1".to_string()).await;
+        manager.add_connection("test-node-1".to_string(), PROTOCOL_VERSION).await;
         
         // 清理应该不会移除刚刚添加的连接
         let cleaned = manager.cleanup_inactive_connections(1).await;
         assert_eq!(cleaned, 0);
-        assert_eq!(manager.get_online_count().await, 1);
+        assert!(manager.update_activity("test-node-1").await);
+    }
+
+    #[tokio::test]
+    async fn test_connection_manager_restore() {
+        let manager = ConnectionManager::new(20, 100);
+        let node = |node_id: &str, minutes_ago: Option<i64>| Node {
+            id: 0,
+            node_id: node_id.to_string(),
+            hostname: node_id.to_string(),
+            ip_address: "10.0.0.1".to_string(),
+            os_info: None,
+            os_name: None,
+            os_version: None,
+            kernel_version: None,
+            agent_version: None,
+            cpu_count: None,
+            total_memory: None,
+            metrics_retention_days: None,
+            metrics_retention_override_days: None,
+            status: NodeStatus::Online,
+            last_heartbeat: minutes_ago.map(|minutes| Utc::now() - chrono::Duration::minutes(minutes)),
+            registered_at: Utc::now(),
+            updated_at: Utc::now(),
+            tags: Vec::new(),
+            is_alive: false,
+            seconds_since_heartbeat: None,
+            protocol_version: None,
+        };
+
+        manager.add_connection("connected".to_string(), PROTOCOL_VERSION).await;
+        let nodes = vec![node("connected", Some(0)), node("recent", Some(0)), node("old", Some(3)), node("never", None)];
+        assert_eq!(manager.restore_connections(&nodes).await, 2);
+        assert_eq!(manager.protocol_version("connected").await, Some(PROTOCOL_VERSION));
+        assert_eq!(manager.protocol_version("recent").await, Some(MIN_PROTOCOL_VERSION));
+        assert_eq!(manager.protocol_version("never").await, None);
+        // 恢复的连接没有发送通道，不计入实际连接数
+        assert_eq!(manager.connected_count().await, 0);
+
+        // 未重连的节点按最后心跳时间被清理
+        assert_eq!(manager.cleanup_inactive_connections(2).await, 1);
+        assert_eq!(manager.protocol_version("old").await, None);
+        assert!(manager.protocol_version("recent").await.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_connection_manager_senders() {
+        let manager = ConnectionManager::new(20, 100);
+        
+        // 未注册通道时发送失败
+        assert!(!manager.send_to_node("test-node-1", Message::Text("hello".into())).await);
+        
+        // 注册通道后可以发送
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        manager.register_sender("test-node-1".to_string(), tx.clone()).await;
+        assert!(manager.send_to_node("test-node-1", Message::Text("hello".into())).await);
+        assert!(matches!(rx.recv().await, Some(Message::Text(text)) if text.as_str() == "hello"));
+        
+        // 同一节点重连后，旧连接的清理不应移除新通道
+        let (new_tx, _new_rx) = mpsc::unbounded_channel();
+        manager.register_sender("test-node-1".to_string(), new_tx.clone()).await;
+        assert!(!manager.remove_sender("test-node-1", &tx).await);
+        assert!(manager.send_to_node("test-node-1", Message::Text("hello".into())).await);
+        
+        // 移除当前通道后发送失败
+        assert!(manager.remove_sender("test-node-1", &new_tx).await);
+        assert!(!manager.send_to_node("test-node-1", Message::Text("hello".into())).await);
+    }
+
+    #[tokio::test]
+    async fn test_connection_manager_sessions() {
+        let manager = ConnectionManager::new(20, 100);
+
+        // 首次连接没有可恢复的会话
+        let (resumed, token) = manager.start_session("node-1", None).await;
+        assert!(!resumed);
+
+        // 出示上次签发的令牌即可恢复，令牌随之轮换
+        let (resumed, rotated) = manager.start_session("node-1", Some(&token)).await;
+        assert!(resumed);
+        assert_ne!(rotated, token);
+        assert!(!manager.start_session("node-1", Some(&token)).await.0);
+
+        // 令牌只对签发的节点有效
+        let (_, token) = manager.start_session("node-1", None).await;
+        assert!(!manager.start_session("node-2", Some(&token)).await.0);
+
+        // 会话结束后令牌失效
+        manager.end_session("node-1").await;
+        assert!(!manager.start_session("node-1", Some(&token)).await.0);
+    }
+
+    #[test]
+    fn test_node_query_page() {
+        let query = |uri: &str| Query::<NodeQuery>::try_from_uri(&uri.parse().unwrap()).unwrap().0;
+
+        assert_eq!(query("/api/v1/nodes").page(), (NODE_LIST_DEFAULT_LIMIT, 0));
+        assert_eq!(query("/api/v1/nodes?limit=20&offset=40").page(), (20, 40));
+        assert_eq!(query("/api/v1/nodes?limit=-1&offset=-5").page(), (1, 0));
+        assert_eq!(query("/api/v1/nodes?limit=1000000").page(), (NODE_LIST_MAX_LIMIT, 0));
     }
 
     #[test]
@@ -407,4 +958,21 @@ mod tests {
         assert_eq!(error_response.message, "操作失败");
         assert!(error_response.data.is_none());
     }
+
+    #[test]
+    fn test_token_bucket() {
+        // 不补充令牌，只测试突发容量
+        let mut bucket = TokenBucket::new(0, 2);
+        assert_eq!(bucket.acquire(), RateDecision::Allowed);
+        assert_eq!(bucket.acquire(), RateDecision::Allowed);
+        assert_eq!(bucket.acquire(), RateDecision::Throttled);
+        assert_eq!(bucket.acquire(), RateDecision::Throttled);
+        assert_eq!(bucket.acquire(), RateDecision::Exceeded);
+
+        // 补充令牌后恢复
+        let mut bucket = TokenBucket::new(1000, 1);
+        assert_eq!(bucket.acquire(), RateDecision::Allowed);
+        std::thread::sleep(std::time::Duration::from_millis(5));
+        assert_eq!(bucket.acquire(), RateDecision::Allowed);
+    }
 }
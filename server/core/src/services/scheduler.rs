@@ -0,0 +1,151 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use croner::Cron;
+use tokio::task::JoinHandle;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::models::{Command, CommandCreate, CommandStatus};
+use crate::services::commands::dispatch_command;
+use crate::services::nodes::AppState;
+
+/// 检查到期定时命令的间隔
+const SCHEDULER_INTERVAL: Duration = Duration::from_secs(1);
+
+/// 计算cron表达式在`after`之后的下一次执行时间（UTC，支持5位或带秒的6位表达式）
+pub fn next_cron_run(expression: &str, after: DateTime<Utc>) -> Result<DateTime<Utc>> {
+    let cron = Cron::new(expression).with_seconds_optional().parse()?;
+    Ok(cron.find_next_occurrence(&after, false)?)
+}
+
+/// 启动定时命令调度任务
+pub fn spawn_scheduler_task(state: Arc<AppState>) -> JoinHandle<()> {
+    info!("🗓️ 定时命令调度任务已启动");
+
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(SCHEDULER_INTERVAL);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+        loop {
+            interval.tick().await;
+            if let Err(e) = run_due_commands(&state, Utc::now()).await {
+                error!("❌ 调度定时命令失败: {}", e);
+            }
+        }
+    })
+}
+
+/// 下发`now`时已到期的定时命令，返回下发（或因节点离线进入待执行）的命令数
+///
+/// 单次命令到期后转为普通命令下发；周期命令每次到期时创建一条新命令下发，并把计划推迟到下一次执行时间
+pub async fn run_due_commands(state: &AppState, now: DateTime<Utc>) -> Result<usize> {
+    let pool = &state.database.pool;
+    let due = Command::find_due(pool, now).await?;
+
+    for command in &due {
+        let run = match command.cron.as_deref() {
+            Some(expression) => {
+                let run = Command::create(pool, CommandCreate {
+                    command_id: Uuid::new_v4().to_string(),
+                    command_text: command.command_text.clone(),
+                    target_node_id: command.target_node_id.clone(),
+                    scheduled_at: None,
+                    cron: None,
+                    schedule_id: Some(command.command_id.clone()),
+                }).await?;
+
+                match next_cron_run(expression, now) {
+                    Ok(next_run) => Command::reschedule(pool, &command.command_id, next_run).await?,
+                    Err(e) => {
+                        warn!("⚠️ 周期命令 {} 无法计算下一次执行时间，已取消: {}", command.command_id, e);
+                        Command::cancel(pool, &command.command_id).await?;
+                    }
+                }
+                run
+            }
+            None => {
+                Command::update_status(pool, &command.command_id, CommandStatus::Pending).await?;
+                command.clone()
+            }
+        };
+
+        info!("⏰ 定时命令到期: {} -> {}", run.command_id, run.target_node_id);
+        dispatch_command(state, &run).await;
+    }
+
+    Ok(due.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::CoreConfig;
+    use crate::database::TempDatabaseFile;
+    use crate::models::{Node, NodeCreate};
+
+    #[test]
+    fn test_next_cron_run() {
+        let after = DateTime::parse_from_rfc3339("2025-01-21T10:00:30Z").unwrap().with_timezone(&Utc);
+        let next = next_cron_run("*/5 * * * *", after).unwrap();
+        assert_eq!(next.to_rfc3339(), "2025-01-21T10:05:00+00:00");
+        // 带秒的表达式
+        let next = next_cron_run("0 0 12 * * *", after).unwrap();
+        assert_eq!(next.to_rfc3339(), "2025-01-21T12:00:00+00:00");
+        assert!(next_cron_run("not a cron", after).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_run_due_commands() {
+        let db_file = TempDatabaseFile::new("scheduler");
+        let db = db_file.open().await;
+        let pool = db.pool.clone();
+
+        Node::create(&pool, NodeCreate {
+            node_id: "node-1".to_string(),
+            hostname: "host-1".to_string(),
+            ip_address: "10.0.0.1".to_string(),
+            ..Default::default()
+        }).await.unwrap();
+
+        let now = Utc::now();
+        let scheduled = |command_id: &str, minutes: i64, cron: Option<&str>| CommandCreate {
+            command_id: command_id.to_string(),
+            command_text: "uptime".to_string(),
+            target_node_id: "node-1".to_string(),
+            scheduled_at: Some(now + chrono::Duration::minutes(minutes)),
+            cron: cron.map(str::to_string),
+            schedule_id: None,
+        };
+        Command::create(&pool, scheduled("once", -1, None)).await.unwrap();
+        Command::create(&pool, scheduled("later", 10, None)).await.unwrap();
+        Command::create(&pool, scheduled("hourly", -1, Some("0 * * * *"))).await.unwrap();
+
+        let state = AppState::new(db, CoreConfig::default());
+        assert_eq!(run_due_commands(&state, now).await.unwrap(), 2);
+
+        // 节点不在线，到期的命令进入待执行，等待节点重连后下发
+        let once = Command::find_by_id(&pool, "once").await.unwrap().unwrap();
+        assert_eq!(once.status, "pending");
+        let later = Command::find_by_id(&pool, "later").await.unwrap().unwrap();
+        assert_eq!(later.status, "scheduled");
+
+        // 周期命令保持计划状态并推迟到下一次执行时间，每次到期创建一条新命令
+        let hourly = Command::find_by_id(&pool, "hourly").await.unwrap().unwrap();
+        assert_eq!(hourly.status, "scheduled");
+        assert!(hourly.scheduled_at.unwrap() > now);
+        let pending = Command::find_pending(&pool, "node-1").await.unwrap();
+        assert_eq!(pending.len(), 2);
+        assert!(pending.iter().any(|command| command.schedule_id.as_deref() == Some("hourly")));
+
+        assert_eq!(run_due_commands(&state, now).await.unwrap(), 0);
+
+        // 取消后不再执行
+        assert!(Command::cancel(&pool, "later").await.unwrap());
+        assert!(!Command::cancel(&pool, "once").await.unwrap());
+        let due = Command::find_due(&pool, now + chrono::Duration::minutes(15)).await.unwrap();
+        assert!(due.iter().all(|command| command.command_id != "later"));
+    }
+}
@@ -1,22 +1,31 @@
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashSet};
+use std::net::{IpAddr, SocketAddr};
 use std::sync::Arc;
+use std::time::Duration;
 
 use axum::{
     extract::{
-        ws::{Message, WebSocket},
-        Query, State, WebSocketUpgrade,
+        ws::{CloseFrame, Message, WebSocket},
+        ConnectInfo, Query, State, WebSocketUpgrade,
     },
-    response::IntoResponse,
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
 };
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use serde_json::json;
-use tokio::sync::{broadcast, mpsc, Mutex, RwLock};
+use tokio::sync::{broadcast, mpsc};
+use tokio::time::Instant;
 use tracing::{debug, error, info, warn};
 use uuid::Uuid;
 
-use crate::database::Database;
-use crate::models::{Node, NodeCreate, NodeUpdate, NodeMetric, MetricCreate, Command, CommandCreate, CommandResultCreate, CommandStatus};
-use crate::services::nodes::{AppState, ConnectionManager, ClientBroadcastMessage};
+use crate::models::{Command, CommandResult, CommandResultCreate, CommandStatus, MetricCreate, NodeMetric, NodeStatus};
+use crate::services::auth::{secrets_match, verify_node_token, NodeAuthResult};
+use crate::models::{EVENT_NODE_DEREGISTERED, EVENT_NODE_DISCONNECTED, EVENT_NODE_REGISTERED};
+use crate::services::commands::{broadcast_command_output, broadcast_command_result};
+use crate::services::events::record_event;
+use crate::services::nodes::{AppState, ClientBroadcastMessage, RateDecision};
+use crate::services::websocket_error::{DbOperation, WebSocketError};
 
 /// WebSocket连接查询参数
 #[derive(Debug, Deserialize)]
@@ -25,6 +34,14 @@ pub struct WebSocketQuery {
     node_id: Option<String>,
     #[serde(rename = "type")]
     connection_type: Option<String>,
+    /// 节点请求的消息压缩方式
+    compression: Option<String>,
+    /// 节点请求的消息编码方式，未提供时为JSON
+    encoding: Option<String>,
+    /// 客户端支持的协议版本，未提供时视为v1
+    protocol_version: Option<u32>,
+    /// 节点上次连接时获得的会话恢复令牌
+    resume_token: Option<String>,
 }
 
 /// WebSocket消息类型
@@ -37,48 +54,274 @@ pub struct WebSocketMessage {
     pub data: serde_json::Value,
 }
 
+/// 节点消息压缩方式（连接时通过`compression`查询参数协商）
+pub const COMPRESSION_GZIP: &str = "gzip";
+
+/// 默认的JSON文本消息编码
+pub const ENCODING_JSON: &str = "json";
+
+/// MessagePack二进制消息编码（连接时通过`encoding`查询参数协商）
+pub const ENCODING_MSGPACK: &str = "msgpack";
+
+/// WebSocket协议版本，消息格式发生不兼容变化时递增
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// 仍然兼容的最低协议版本
+pub const MIN_PROTOCOL_VERSION: u32 = 1;
+
+/// 协商连接使用的协议版本
+///
+/// 协商前的旧版节点不声明版本，按v1处理；客户端版本高于Core时使用Core支持的最高版本，由客户端按欢迎消息中的版本降级
+fn negotiate_protocol_version(requested: Option<u32>) -> Result<u32, String> {
+    let requested = requested.unwrap_or(1);
+    if requested < MIN_PROTOCOL_VERSION {
+        return Err(format!(
+            "不支持的协议版本v{}，Core支持v{}~v{}",
+            requested, MIN_PROTOCOL_VERSION, PROTOCOL_VERSION
+        ));
+    }
+    Ok(requested.min(PROTOCOL_VERSION))
+}
+
+/// 节点可以发送的消息类型
+const NODE_MESSAGE_TYPES: &[&str] = &[
+    "node_register",
+    "node_deregister",
+    "heartbeat",
+    "metrics",
+    "metrics_batch",
+    "command_output",
+    "command_result",
+];
+
+/// Core可能推送给节点的消息类型
+const NODE_SERVER_MESSAGE_TYPES: &[&str] = &[
+    "register_response",
+    "deregister_response",
+    "heartbeat_ack",
+    "metrics_response",
+    "metrics_batch_response",
+    "metric_validation",
+    "command",
+    "command_received",
+    "register_required",
+    "server_shutdown",
+    "error",
+];
+
+/// 监控客户端可以发送的消息类型
+const CLIENT_MESSAGE_TYPES: &[&str] = &["ping", "get_history", "subscribe", "unsubscribe"];
+
+/// Core可能推送给监控客户端的消息类型
+const CLIENT_SERVER_MESSAGE_TYPES: &[&str] = &[
+    "nodes_update",
+    "metrics_update",
+    "node_status_change",
+    "node_reboot",
+    "command_output",
+    "command_result",
+    "alert_fired",
+    "alert_resolved",
+    "anomaly",
+    "pong",
+    "history_response",
+    "subscription_response",
+    "resync",
+    "server_shutdown",
+    "error",
+];
+
+/// 服务端能力声明，随欢迎消息下发，便于不同版本的节点和客户端按需启用功能
+#[derive(Debug, Serialize)]
+pub struct ServerCapabilities {
+    pub protocol_version: u32,
+    pub server_version: &'static str,
+    /// 服务端接受的消息类型
+    pub accepts: &'static [&'static str],
+    /// 服务端可能发送的消息类型
+    pub sends: &'static [&'static str],
+    /// 支持的消息压缩方式
+    pub compression: &'static [&'static str],
+    /// 支持的消息编码方式
+    pub encodings: &'static [&'static str],
+}
+
+impl ServerCapabilities {
+    /// 节点连接的能力声明
+    pub fn for_node() -> Self {
+        Self {
+            protocol_version: PROTOCOL_VERSION,
+            server_version: env!("CARGO_PKG_VERSION"),
+            accepts: NODE_MESSAGE_TYPES,
+            sends: NODE_SERVER_MESSAGE_TYPES,
+            compression: &[COMPRESSION_GZIP],
+            encodings: &[ENCODING_JSON, ENCODING_MSGPACK],
+        }
+    }
+
+    /// 监控客户端连接的能力声明
+    pub fn for_monitor() -> Self {
+        Self {
+            protocol_version: PROTOCOL_VERSION,
+            server_version: env!("CARGO_PKG_VERSION"),
+            accepts: CLIENT_MESSAGE_TYPES,
+            sends: CLIENT_SERVER_MESSAGE_TYPES,
+            compression: &[],
+            encodings: &[ENCODING_JSON],
+        }
+    }
+}
+
+/// 认证失败时使用的WebSocket关闭码
+pub const CLOSE_CODE_UNAUTHORIZED: u16 = 4001;
+
+/// 协议版本不兼容时使用的WebSocket关闭码
+pub const CLOSE_CODE_UNSUPPORTED_PROTOCOL: u16 = 4002;
+
+/// 违反策略（如持续超出消息速率限制）时使用的WebSocket关闭码
+pub const CLOSE_CODE_POLICY_VIOLATION: u16 = 1008;
+
+/// 服务关闭时使用的WebSocket关闭码（Going Away）
+pub const CLOSE_CODE_GOING_AWAY: u16 = 1001;
+
+/// 构建带关闭码和原因的Close帧
+pub fn close_frame(code: u16, reason: &str) -> Message {
+    Message::Close(Some(CloseFrame {
+        code,
+        reason: reason.into(),
+    }))
+}
+
 /// WebSocket处理函数
 pub async fn websocket_handler(
     ws: WebSocketUpgrade,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
     Query(query): Query<WebSocketQuery>,
     State(state): State<Arc<AppState>>,
-) -> impl IntoResponse {
-    info!("🔌 新的WebSocket连接请求: {:?}", query);
+) -> Response {
+    let client_ip = resolve_client_ip(&headers, peer, state.config.trust_proxy);
+    info!(
+        "🔌 新的WebSocket连接请求: 类型={:?}, 节点ID={:?}, 来源={}",
+        query.connection_type, query.node_id, client_ip
+    );
     
-    // 简单的token验证（MVP版本使用固定token）
-    if let Some(token) = &query.token {
-        if token != "default-token" {
-            warn!("❌ 无效的token: {}", token);
-            return axum::response::Response::new("Invalid token".into());
+    // 超过大小上限的帧在协议层直接拒绝，不会被完整读入内存
+    let ws = ws.max_message_size(state.config.max_message_bytes);
+    let connection_type = query.connection_type.as_deref().unwrap_or("node");
+
+    // 来源地址不在允许网段内的节点直接拒绝升级，不进行令牌校验
+    if connection_type != "monitor" && !state.config.is_node_address_allowed(client_ip) {
+        warn!("❌ 拒绝节点连接，来源地址不在允许的网段内: {} (节点: {:?})", client_ip, query.node_id);
+        return (StatusCode::FORBIDDEN, "来源地址不允许连接").into_response();
+    }
+    // 浏览器中的监控连接校验Origin，防止运维人员访问的其他网站借用其浏览器连接Core
+    if connection_type == "monitor" {
+        let header = |name| headers.get(name).and_then(|value| value.to_str().ok());
+        let origin = header(axum::http::header::ORIGIN);
+        if !state.config.is_monitor_origin_allowed(origin, header(axum::http::header::HOST)) {
+            warn!("❌ 拒绝监控连接，来源不在允许列表中: {:?} (地址: {})", origin, client_ip);
+            return (StatusCode::FORBIDDEN, "来源不允许连接").into_response();
         }
+    }
+    let token = query.token.as_deref().unwrap_or_default();
+
+    // 认证失败时先完成升级再发送关闭帧，便于客户端拿到明确的关闭码和原因
+    let rejection = if token.is_empty() {
+        Some("缺少token参数")
+    } else if connection_type == "monitor" {
+        (!secrets_match(token, &state.config.shared_token)).then_some("无效的token")
     } else {
-        warn!("❌ 缺少token参数");
-        return axum::response::Response::new("Token required".into());
+        match &query.node_id {
+            Some(node_id) => match verify_node_token(&state, node_id, token).await {
+                NodeAuthResult::Authorized => None,
+                NodeAuthResult::SharedToken => {
+                    debug!("节点使用全局共享令牌连接: {}", node_id);
+                    None
+                }
+                NodeAuthResult::Rejected => Some("节点令牌无效"),
+            },
+            None => Some("缺少node_id参数"),
+        }
+    };
+
+    if let Some(reason) = rejection {
+        warn!("❌ WebSocket认证失败: {} (节点: {:?})", reason, query.node_id);
+        return ws.on_upgrade(move |mut socket| async move {
+            let _ = socket.send(close_frame(CLOSE_CODE_UNAUTHORIZED, reason)).await;
+        }).into_response();
     }
     
+    let protocol_version = match negotiate_protocol_version(query.protocol_version) {
+        Ok(version) => version,
+        Err(reason) => {
+            warn!("❌ WebSocket协议版本不兼容: {} (节点: {:?})", reason, query.node_id);
+            return ws.on_upgrade(move |mut socket| async move {
+                let _ = socket.send(close_frame(CLOSE_CODE_UNSUPPORTED_PROTOCOL, &reason)).await;
+            }).into_response();
+        }
+    };
+    
     // 根据连接类型分发处理
-    let connection_type = query.connection_type.as_deref().unwrap_or("node");
     match connection_type {
         "monitor" => {
             info!("📱 客户端监控连接");
-            ws.on_upgrade(|socket| handle_client_websocket(socket, state, query))
+            ws.on_upgrade(move |socket| handle_client_websocket(socket, state, query, protocol_version)).into_response()
         }
         _ => {
             info!("🤖 节点代理连接");
-            ws.on_upgrade(|socket| handle_websocket(socket, state, query))
+            ws.on_upgrade(move |socket| handle_websocket(socket, state, query, protocol_version, client_ip)).into_response()
         }
     }
 }
 
+/// 确定连接的客户端地址
+///
+/// 启用`trust_proxy`时依次使用反向代理设置的`X-Real-IP`和`X-Forwarded-For`最后一项（即代理看到的对端地址），
+/// 请求头缺失或无法解析时使用TCP对端地址
+fn resolve_client_ip(headers: &HeaderMap, peer: SocketAddr, trust_proxy: bool) -> IpAddr {
+    if trust_proxy {
+        let header_ip = |name: &str, pick_last: bool| {
+            let value = headers.get(name)?.to_str().ok()?;
+            let item = if pick_last { value.rsplit(',').next()? } else { value };
+            item.trim().parse::<IpAddr>().ok()
+        };
+        if let Some(ip) = header_ip("x-real-ip", false).or_else(|| header_ip("x-forwarded-for", true)) {
+            return ip.to_canonical();
+        }
+    }
+    peer.ip().to_canonical()
+}
+
 /// 处理WebSocket连接
 pub async fn handle_websocket(
     mut socket: WebSocket,
     state: Arc<AppState>,
     query: WebSocketQuery,
+    protocol_version: u32,
+    client_ip: IpAddr,
 ) {
     let node_id = query.node_id.unwrap_or_else(|| Uuid::new_v4().to_string());
     info!("✅ WebSocket连接已建立, 节点ID: {}", node_id);
 
+    // 节点断线重连时出示上次的恢复令牌，有效时视为同一会话的延续
+    let (resumed, resume_token) = state.connection_manager.start_session(&node_id, query.resume_token.as_deref()).await;
+    if resumed {
+        info!("🔁 节点恢复断线前的会话: {}", node_id);
+    }
+    let mut session = NodeSession { protocol_version, client_ip, resumed };
+
+    // 节点请求MessagePack编码时在欢迎消息中确认，之后节点的二进制消息均按MessagePack解析
+    let msgpack = query.encoding.as_deref() == Some(ENCODING_MSGPACK);
+    let encoding = if msgpack { ENCODING_MSGPACK } else { ENCODING_JSON };
+
+    // 节点请求压缩时在欢迎消息中确认，之后节点可以发送gzip压缩的二进制消息；未确认时节点继续发送文本消息
+    // gzip消息同样使用二进制帧，因此与MessagePack编码不同时启用
+    let compression = query
+        .compression
+        .as_deref()
+        .filter(|requested| *requested == COMPRESSION_GZIP && !msgpack);
+
     // 发送欢迎消息
     let welcome_msg = json!({
         "type": "welcome",
@@ -86,7 +329,13 @@ pub async fn handle_websocket(
         "timestamp": chrono::Utc::now().to_rfc3339(),
         "data": {
             "message": "欢迎连接到Server Manager Core",
-            "node_id": node_id
+            "node_id": node_id,
+            "compression": compression,
+            "encoding": encoding,
+            "protocol_version": protocol_version,
+            "capabilities": ServerCapabilities::for_node(),
+            "resume_token": resume_token,
+            "resumed": resumed
         }
     });
     
@@ -95,134 +344,424 @@ pub async fn handle_websocket(
         return;
     }
 
-    // 处理消息循环
-    while let Some(Ok(msg)) = socket.recv().await {
-        match msg {
-            Message::Text(text) => {
-                if let Err(e) = handle_message(&text, &mut socket, &state, &node_id).await {
+    let mut shutdown = state.shutdown_receiver();
+
+    // 注册发送通道，供服务端主动向节点推送消息
+    let (tx, mut rx) = mpsc::unbounded_channel::<Message>();
+    state.connection_manager.register_sender(node_id.clone(), tx.clone()).await;
+
+    // 定期发送ping，超时未收到pong说明节点已失联（如网络中断导致的半开连接）
+    let ping_period = Duration::from_secs(state.config.node_ping_interval_secs);
+    let pong_timeout = Duration::from_secs(state.config.node_pong_timeout_secs);
+    let mut ping_interval = tokio::time::interval_at(Instant::now() + ping_period, ping_period);
+    let mut awaiting_pong_since: Option<Instant> = None;
+
+    // 处理消息循环 - 同时监听节点消息、待推送消息和ping定时器
+    // 循环结束时得到节点断开后的状态：正常关闭为离线，连接异常中断为失联
+    let disconnect_status = loop {
+        tokio::select! {
+            node_msg = socket.recv() => {
+                let payload = match node_msg {
+                    Some(Ok(Message::Text(text))) => NodePayload::Json(text.to_string()),
+                    Some(Ok(Message::Binary(data))) => {
+                        match NodePayload::from_binary(&data, msgpack, state.config.max_message_bytes) {
+                            Ok(payload) => payload,
+                            Err(e) => {
+                                warn!("⚠️ 无法解析节点的二进制消息, 节点ID: {}: {}", node_id, e);
+                                if report_error(&mut socket, None, WebSocketError::InvalidBinary(e)).await.is_err() {
+                                    break NodeStatus::Unreachable;
+                                }
+                                continue;
+                            }
+                        }
+                    }
+                    Some(Ok(Message::Close(_))) => {
+                        info!("🔌 WebSocket连接关闭, 节点ID: {}", node_id);
+                        break NodeStatus::Offline;
+                    }
+                    Some(Ok(Message::Pong(_))) => {
+                        awaiting_pong_since = None;
+                        continue;
+                    }
+                    // 节点的ping由axum自动回复pong
+                    Some(Ok(Message::Ping(_))) => continue,
+                    Some(Err(e)) => {
+                        error!("节点消息错误: {}", e);
+                        break NodeStatus::Unreachable;
+                    }
+                    None => break NodeStatus::Unreachable,
+                };
+
+                match state.connection_manager.check_rate(&node_id).await {
+                    RateDecision::Allowed => {}
+                    RateDecision::Throttled => {
+                        warn!("⚠️ 节点消息过于频繁，已丢弃, 节点ID: {}", node_id);
+                        let error = WebSocketError::RateLimited { limit: state.config.node_rate_limit_per_sec };
+                        if report_error(&mut socket, None, error).await.is_err() {
+                            break NodeStatus::Unreachable;
+                        }
+                        continue;
+                    }
+                    RateDecision::Exceeded => {
+                        warn!("❌ 节点持续超出消息速率限制，断开连接, 节点ID: {}", node_id);
+                        let _ = socket.send(close_frame(CLOSE_CODE_POLICY_VIOLATION, "消息速率超限")).await;
+                        break NodeStatus::Offline;
+                    }
+                }
+
+                if let Err(e) = handle_message(&payload, &mut socket, &state, &node_id, &mut session).await {
                     error!("处理消息失败: {}", e);
-                    break;
+                    break match e {
+                        WebSocketError::Send(_) => NodeStatus::Unreachable,
+                        _ => NodeStatus::Offline,
+                    };
                 }
             }
-            Message::Close(_) => {
-                info!("🔌 WebSocket连接关闭, 节点ID: {}", node_id);
-                break;
+
+            outgoing = rx.recv() => {
+                match outgoing {
+                    Some(msg) => {
+                        let closing = matches!(msg, Message::Close(_));
+                        if let Err(e) = socket.send(msg).await {
+                            error!("向节点推送消息失败: {}", e);
+                            break NodeStatus::Unreachable;
+                        }
+                        if closing {
+                            info!("🔒 服务端主动关闭连接, 节点ID: {}", node_id);
+                            break NodeStatus::Offline;
+                        }
+                    }
+                    None => break NodeStatus::Offline,
+                }
             }
-            _ => {
-                info!("📨 收到非文本消息, 节点ID: {}", node_id);
+
+            _ = shutdown.changed() => {
+                info!("🛑 服务关闭，断开节点连接: {}", node_id);
+                send_shutdown_notice(&mut socket).await;
+                break NodeStatus::Offline;
+            }
+
+            _ = ping_interval.tick() => {
+                if awaiting_pong_since.is_some_and(|since| since.elapsed() >= pong_timeout) {
+                    warn!("⚠️ 节点 {} 超过{}秒未响应ping，断开连接", node_id, pong_timeout.as_secs());
+                    break NodeStatus::Unreachable;
+                }
+                if let Err(e) = socket.send(Message::Ping(Vec::new().into())).await {
+                    error!("向节点发送ping失败: {}", e);
+                    break NodeStatus::Unreachable;
+                }
+                awaiting_pong_since.get_or_insert_with(Instant::now);
             }
         }
-    }
+    };
 
     info!("👋 WebSocket连接结束, 节点ID: {}", node_id);
     
-    // 处理节点断开连接
-    handle_node_disconnect(&node_id, &state).await;
+    // 连接异常中断时可能只是网络抖动，等待宽限期后再处理离线；正常关闭的连接立即处理。
+    // 期间节点重连会替换发送通道，离线处理随之跳过，避免状态反复变化
+    let grace = Duration::from_secs(state.config.session_resume_grace_secs);
+    let resumable = disconnect_status == NodeStatus::Unreachable;
+    if resumable && !grace.is_zero() && !*shutdown.borrow() {
+        debug!("⏳ {}秒内未重连再处理节点离线: {}", grace.as_secs(), node_id);
+        // 持有关闭信号的接收端，Core关闭时等待离线处理完成
+        tokio::spawn(async move {
+            tokio::select! {
+                _ = tokio::time::sleep(grace) => {}
+                _ = shutdown.changed() => {}
+            }
+            handle_node_disconnect(&node_id, &tx, &state, disconnect_status).await;
+        });
+    } else {
+        handle_node_disconnect(&node_id, &tx, &state, disconnect_status).await;
+    }
+}
+
+/// 节点连接建立时确定的会话信息
+#[derive(Debug, Clone, Copy)]
+struct NodeSession {
+    /// 连接时协商的协议版本
+    protocol_version: u32,
+    /// 节点的连接地址
+    client_ip: IpAddr,
+    /// 节点出示了有效的会话恢复令牌，延续断线前的会话；只对该连接上的第一次注册有效
+    resumed: bool,
+}
+
+/// 解压节点发送的gzip二进制消息，解压后超过`max_bytes`时返回错误（防止压缩炸弹）
+fn decompress_message(data: &[u8], max_bytes: usize) -> anyhow::Result<String> {
+    use std::io::Read;
+
+    let mut text = String::new();
+    flate2::read::GzDecoder::new(data)
+        .take(max_bytes as u64 + 1)
+        .read_to_string(&mut text)?;
+    if text.len() > max_bytes {
+        return Err(anyhow::anyhow!("解压后消息超过{}字节", max_bytes));
+    }
+    Ok(text)
+}
+
+/// gzip数据的文件头
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// 节点消息的原始内容
+enum NodePayload {
+    /// JSON文本（包括解压后的gzip消息）
+    Json(String),
+    /// MessagePack编码的二进制消息
+    MessagePack(Vec<u8>),
+}
+
+impl NodePayload {
+    /// 解析节点发送的二进制帧：已协商MessagePack编码时按MessagePack处理，否则按gzip压缩或UTF-8编码的JSON处理
+    fn from_binary(data: &[u8], msgpack: bool, max_bytes: usize) -> anyhow::Result<Self> {
+        if msgpack {
+            return Ok(NodePayload::MessagePack(data.to_vec()));
+        }
+        if data.starts_with(&GZIP_MAGIC) {
+            return decompress_message(data, max_bytes).map(NodePayload::Json);
+        }
+        String::from_utf8(data.to_vec())
+            .map(NodePayload::Json)
+            .map_err(|_| anyhow::anyhow!("既不是gzip压缩的消息，也不是UTF-8编码的JSON"))
+    }
+
+    /// 消息字节数
+    fn size(&self) -> usize {
+        match self {
+            NodePayload::Json(text) => text.len(),
+            NodePayload::MessagePack(data) => data.len(),
+        }
+    }
+
+    /// 解析为WebSocket消息
+    fn decode(&self) -> anyhow::Result<WebSocketMessage> {
+        Ok(match self {
+            NodePayload::Json(text) => serde_json::from_str(text)?,
+            NodePayload::MessagePack(data) => rmp_serde::from_slice(data)?,
+        })
+    }
+}
+
+/// 通知对端服务即将关闭并发送关闭帧
+async fn send_shutdown_notice(socket: &mut WebSocket) {
+    let notice = json!({
+        "type": "server_shutdown",
+        "id": Uuid::new_v4().to_string(),
+        "timestamp": chrono::Utc::now().to_rfc3339(),
+        "data": {
+            "message": "服务器正在关闭，请稍后重连"
+        }
+    });
+    let _ = socket.send(Message::Text(notice.to_string().into())).await;
+    let _ = socket.send(close_frame(CLOSE_CODE_GOING_AWAY, "服务器正在关闭")).await;
+}
+
+/// 构建发送给节点的错误消息
+fn error_response(error_code: &str, message: &str, details: String) -> Message {
+    let error_msg = json!({
+        "type": "error",
+        "id": Uuid::new_v4().to_string(),
+        "timestamp": chrono::Utc::now().to_rfc3339(),
+        "data": {
+            "error_code": error_code,
+            "message": message,
+            "details": details
+        }
+    });
+    Message::Text(error_msg.to_string().into())
 }
 
 /// 处理节点断开连接
-async fn handle_node_disconnect(node_id: &str, state: &Arc<AppState>) {
-    let db = state.database.lock().await;
-    
-    // 1. 将数据库中的节点状态标记为离线
-    if let Err(e) = crate::models::Node::mark_offline(&db.pool, node_id).await {
-        error!("标记节点离线失败: {}", e);
-    } else {
-        info!("✅ 节点已标记为离线: {}", node_id);
+async fn handle_node_disconnect(
+    node_id: &str,
+    sender: &mpsc::UnboundedSender<Message>,
+    state: &Arc<AppState>,
+    status: NodeStatus,
+) {
+    // 移除本连接的发送通道；若节点已通过新连接重连，则保持其在线状态
+    if !state.connection_manager.remove_sender(node_id, sender).await {
+        info!("🔁 节点已重新连接，跳过离线处理: {}", node_id);
+        return;
     }
+    // 节点已离线，之后的连接视为新会话
+    state.connection_manager.end_session(node_id).await;
     
-    // 2. 从连接管理器中移除连接
-    state.connection_manager.remove_connection(node_id).await;
+    let db = &state.database;
     
-    // 3. 向所有客户端广播节点状态变化
-    let status_change_message = crate::services::nodes::ClientBroadcastMessage {
-        message_type: "node_status_change".to_string(),
-        id: uuid::Uuid::new_v4().to_string(),
-        timestamp: chrono::Utc::now().to_rfc3339(),
-        data: serde_json::json!({
-            "node_id": node_id,
-            "status": "offline",
-            "timestamp": chrono::Utc::now().to_rfc3339()
-        }),
+    // 1. 将数据库中的节点状态标记为离线或失联（已主动注销的节点保持注销状态）
+    let marked = match crate::models::Node::update_status(&db.pool, node_id, status).await {
+        Ok(marked) => {
+            if marked && status.needs_attention() {
+                warn!("⚠️ 节点连接异常中断，已标记为{}: {}", status, node_id);
+            } else if marked {
+                info!("✅ 节点已标记为{}: {}", status, node_id);
+            }
+            marked
+        }
+        Err(e) => {
+            error!("更新节点状态失败: {}", e);
+            true
+        }
     };
     
-    state.broadcast_to_clients(status_change_message);
-    info!("📢 广播节点状态变化: {} -> offline", node_id);
+    // 2. 从连接管理器中移除连接
+    state.connection_manager.remove_connection(node_id).await;
+    
+    // 3. 向所有客户端广播节点状态变化并记录事件
+    if marked {
+        state.broadcast_node_status(node_id, status);
+        record_event(state, Some(node_id), EVENT_NODE_DISCONNECTED, json!({ "status": status })).await;
+    }
 }
 
 /// 处理WebSocket消息
+///
+/// 处理失败时以`error`消息回复节点，只有需要断开连接的错误才返回给调用方
 async fn handle_message(
-    text: &str,
+    payload: &NodePayload,
     socket: &mut WebSocket,
     state: &Arc<AppState>,
     connection_node_id: &str,
-) -> Result<(), anyhow::Error> {
-    info!("📨 收到消息 from {}: {}", connection_node_id, text);
+    session: &mut NodeSession,
+) -> Result<(), WebSocketError> {
+    // 解析前检查消息大小，避免解析超大消息占用大量内存
+    let max_bytes = state.config.max_message_bytes;
+    let size = payload.size();
+    if size > max_bytes {
+        warn!("⚠️ 消息过大已丢弃 from {}: {}字节", connection_node_id, size);
+        return report_error(socket, None, WebSocketError::MessageTooLarge { size, max: max_bytes }).await;
+    }
+
+    match payload {
+        NodePayload::Json(text) => info!("📨 收到消息 from {}: {}", connection_node_id, text),
+        NodePayload::MessagePack(_) => info!("📨 收到MessagePack消息 from {}: {}字节", connection_node_id, size),
+    }
     
-    let msg: WebSocketMessage = match serde_json::from_str(text) {
+    let msg = match payload.decode() {
         Ok(msg) => msg,
-        Err(e) => {
-            // 发送解析错误响应
-            let error_msg = json!({
-                "type": "error",
-                "id": Uuid::new_v4().to_string(),
-                "timestamp": chrono::Utc::now().to_rfc3339(),
-                "data": {
-                    "error_code": "PARSE_ERROR",
-                    "message": "消息解析失败",
-                    "details": e.to_string()
-                }
-            });
-            socket.send(Message::Text(error_msg.to_string().into())).await?;
-            return Err(e.into());
-        }
+        Err(e) => return report_error(socket, None, WebSocketError::Parse(e)).await,
     };
 
-    // 确定要使用的节点ID：优先使用消息中的node_id，如果没有则使用连接时的node_id
-    let node_id = if let Some(msg_node_id) = extract_node_id_from_message(&msg) {
-        msg_node_id
-    } else {
-        connection_node_id.to_string()
-    };
+    let message_id = msg.id.clone();
+    match dispatch_message(msg, socket, state, connection_node_id, session).await {
+        Ok(()) => Ok(()),
+        Err(e) => report_error(socket, Some(&message_id), e).await,
+    }
+}
 
-    match msg.message_type.as_str() {
-        "node_register" => handle_node_register(msg, socket, state, &node_id).await,
-        "heartbeat" => handle_heartbeat(msg, socket, state, &node_id).await,
-        "metrics" => handle_metrics(msg, socket, state, &node_id).await,
-        "command_result" => handle_command_result(msg, socket, state, &node_id).await,
-        _ => {
-            // 发送未知消息类型错误
-            let error_msg = json!({
-                "type": "error",
-                "id": Uuid::new_v4().to_string(),
-                "timestamp": chrono::Utc::now().to_rfc3339(),
-                "data": {
-                    "error_code": "UNKNOWN_MESSAGE_TYPE",
-                    "message": format!("未知的消息类型: {}", msg.message_type),
-                    "details": "支持的消息类型: node_register, heartbeat, metrics, command_result"
-                }
-            });
-            socket.send(Message::Text(error_msg.to_string().into())).await?;
-            Ok(())
+/// 按消息类型分发给对应的处理函数
+async fn dispatch_message(
+    msg: WebSocketMessage,
+    socket: &mut WebSocket,
+    state: &Arc<AppState>,
+    connection_node_id: &str,
+    session: &mut NodeSession,
+) -> Result<(), WebSocketError> {
+    if !NODE_MESSAGE_TYPES.contains(&msg.message_type.as_str()) {
+        return Err(WebSocketError::UnknownMessageType {
+            message_type: msg.message_type,
+            supported: NODE_MESSAGE_TYPES.join(", "),
+        });
+    }
+
+    let WebSocketMessage { message_type, id, timestamp, data } = msg;
+    let incoming = IncomingMessage::from_parts(&message_type, data).map_err(|source| {
+        let error = WebSocketError::InvalidData { message_type, source };
+        warn!("⚠️ {} from {}: {}", error.message(), connection_node_id, error);
+        error
+    })?;
+
+    // 令牌只校验了连接时的node_id，消息只能代表该节点；携带其他节点ID的消息直接拒绝
+    if let Some(message_node_id) = incoming.node_id().filter(|id| *id != connection_node_id) {
+        warn!("❌ 消息中的节点ID与连接不一致: {} -> {}", connection_node_id, message_node_id);
+        return Err(WebSocketError::NodeIdMismatch {
+            connection: connection_node_id.to_string(),
+            message: message_node_id.to_string(),
+        });
+    }
+    let node_id = connection_node_id;
+
+    match incoming {
+        IncomingMessage::NodeRegister(data) => handle_node_register(&id, data, socket, state, node_id, session).await,
+        IncomingMessage::NodeDeregister(data) => handle_node_deregister(&id, data, socket, state, node_id).await,
+        IncomingMessage::Heartbeat(data) => {
+            let metric_time = message_metric_time(&timestamp, node_id);
+            handle_heartbeat(&id, data, metric_time, socket, state, node_id).await
+        }
+        IncomingMessage::Metrics(data) => {
+            let metric_time = message_metric_time(&timestamp, node_id);
+            handle_metrics(&id, data, metric_time, socket, state, node_id).await
         }
+        IncomingMessage::MetricsBatch(data) => handle_metrics_batch(&id, data, socket, state, node_id).await,
+        IncomingMessage::CommandOutput(data) => handle_command_output(data, state, node_id).await,
+        IncomingMessage::CommandResult(data) => handle_command_result(&id, data, socket, state, node_id).await,
     }
 }
 
-/// 从消息中提取节点ID
-fn extract_node_id_from_message(msg: &WebSocketMessage) -> Option<String> {
-    // 尝试从data字段中提取node_id
-    if let Some(node_id) = msg.data.get("node_id").and_then(|v| v.as_str()) {
-        return Some(node_id.to_string());
+/// 以`error`消息回复处理失败的原因，需要断开连接的错误在回复后原样返回
+async fn report_error(socket: &mut WebSocket, message_id: Option<&str>, error: WebSocketError) -> Result<(), WebSocketError> {
+    if matches!(error, WebSocketError::Send(_)) {
+        return Err(error);
     }
-    
-    // 如果消息类型是node_register，尝试从注册数据中提取
-    if msg.message_type == "node_register" {
-        if let Ok(register_data) = serde_json::from_value::<NodeRegisterData>(msg.data.clone()) {
-            return register_data.node_id;
+    send_error(socket, message_id, &error).await?;
+    if error.is_fatal() {
+        Err(error)
+    } else {
+        Ok(())
+    }
+}
+
+/// 发送错误对应的`error`消息，未注册的节点同时要求其重新注册
+async fn send_error(socket: &mut WebSocket, message_id: Option<&str>, error: &WebSocketError) -> Result<(), WebSocketError> {
+    match message_id {
+        Some(message_id) => send_error_response(socket, message_id, error.error_code(), &error.message(), &error.to_string()).await?,
+        None => socket.send(error_response(error.error_code(), &error.message(), error.to_string())).await?,
+    }
+    if let WebSocketError::NodeNotRegistered(node_id) = error {
+        send_register_required(socket, node_id).await?;
+    }
+    Ok(())
+}
+
+/// 节点发送给Core的消息，按`type`字段区分，载荷位于`data`字段
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", content = "data")]
+enum IncomingMessage {
+    #[serde(rename = "node_register")]
+    NodeRegister(NodeRegisterData),
+    #[serde(rename = "node_deregister")]
+    NodeDeregister(NodeDeregisterData),
+    #[serde(rename = "heartbeat")]
+    Heartbeat(HeartbeatData),
+    #[serde(rename = "metrics")]
+    Metrics(MetricsData),
+    #[serde(rename = "metrics_batch")]
+    MetricsBatch(MetricsBatchData),
+    #[serde(rename = "command_output")]
+    CommandOutput(CommandOutputData),
+    #[serde(rename = "command_result")]
+    CommandResult(CommandResultData),
+}
+
+impl IncomingMessage {
+    /// 按消息类型解析载荷
+    fn from_parts(message_type: &str, data: serde_json::Value) -> Result<Self, serde_json::Error> {
+        serde_json::from_value(json!({ "type": message_type, "data": data }))
+    }
+
+    /// 消息中携带的节点ID
+    fn node_id(&self) -> Option<&str> {
+        match self {
+            IncomingMessage::NodeRegister(data) => data.node_id.as_deref(),
+            IncomingMessage::NodeDeregister(data) => data.node_id.as_deref(),
+            IncomingMessage::Heartbeat(data) => data.node_id.as_deref(),
+            IncomingMessage::Metrics(data) => data.node_id.as_deref(),
+            IncomingMessage::MetricsBatch(data) => data.node_id.as_deref(),
+            IncomingMessage::CommandOutput(data) => data.node_id.as_deref(),
+            IncomingMessage::CommandResult(data) => data.node_id.as_deref(),
         }
     }
-    
-    None
 }
 
 /// 节点注册数据结构
@@ -230,42 +769,45 @@ fn extract_node_id_from_message(msg: &WebSocketMessage) -> Option<String> {
 struct NodeRegisterData {
     node_id: Option<String>,
     hostname: String,
+    /// 节点自行上报的地址，注册时会被替换为Core看到的连接地址
+    #[serde(default)]
     ip_address: String,
     os_info: Option<String>,
+    os_name: Option<String>,
+    os_version: Option<String>,
+    kernel_version: Option<String>,
+    /// 节点代理版本
+    agent_version: Option<String>,
+    cpu_count: Option<i64>,
+    total_memory: Option<i64>,
+    /// 节点期望的监控数据保留天数
+    metrics_retention_days: Option<i64>,
 }
 
 /// 处理节点注册消息
 async fn handle_node_register(
-    msg: WebSocketMessage,
+    message_id: &str,
+    mut register_data: NodeRegisterData,
     socket: &mut WebSocket,
     state: &Arc<AppState>,
     connection_node_id: &str,
-) -> Result<(), anyhow::Error> {
+    session: &mut NodeSession,
+) -> Result<(), WebSocketError> {
     info!("📋 节点注册请求: {}", connection_node_id);
+    let protocol_version = session.protocol_version;
     
-    // 解析注册数据
-    let register_data: NodeRegisterData = match serde_json::from_value(msg.data.clone()) {
-        Ok(data) => data,
-        Err(e) => {
-            let error_msg = json!({
-                "type": "error",
-                "id": msg.id,
-                "timestamp": chrono::Utc::now().to_rfc3339(),
-                "data": {
-                    "error_code": "INVALID_REGISTER_DATA",
-                    "message": "注册数据格式错误",
-                    "details": e.to_string()
-                }
-            });
-            socket.send(Message::Text(error_msg.to_string().into())).await?;
-            return Err(e.into());
-        }
-    };
+    // 以Core看到的连接地址为准，节点自行上报的地址在NAT或多网卡环境下往往不准确
+    let observed_ip = session.client_ip.to_string();
+    if register_data.ip_address != observed_ip {
+        debug!("节点上报地址 {} 与连接地址 {} 不一致，使用连接地址: {}", register_data.ip_address, observed_ip, connection_node_id);
+    }
+    register_data.ip_address = observed_ip;
     
-    // 使用连接中的node_id或注册数据中的node_id
-    let node_id = register_data.node_id.clone().unwrap_or_else(|| connection_node_id.to_string());
+    // 节点ID以连接认证时的node_id为准，消息中的node_id已在分发时校验
+    let node_id = connection_node_id.to_string();
     
-    let db = state.database.lock().await;
+    let db = &state.database;
+    let metrics_retention_days = register_data.metrics_retention_days.filter(|days| *days > 0);
     
     // 检查节点是否已存在
     let existing_node = match crate::models::Node::find_by_node_id(&db.pool, &node_id).await {
@@ -277,13 +819,34 @@ async fn handle_node_register(
         }
     };
     
+    // 恢复会话且节点仍为在线状态时，节点实际上没有离开，不再广播上线；
+    // 同一连接上之后的注册（如配置变更后重新注册）不再视为恢复
+    let resumed = std::mem::take(&mut session.resumed);
+    let continuing = resumed && existing_node.as_ref().is_some_and(|node| node.status == NodeStatus::Online);
+    
+    // 注册数据会被创建节点的流程取走，提前整理事件详情
+    let event_details = json!({
+        "hostname": register_data.hostname,
+        "ip_address": register_data.ip_address,
+        "agent_version": register_data.agent_version,
+        "previous_status": existing_node.as_ref().map(|node| node.status),
+        "resumed": continuing
+    });
+    
     if existing_node.is_some() {
         // 节点已存在，更新信息
         let update_data = crate::models::NodeUpdate {
             hostname: Some(register_data.hostname.clone()),
             ip_address: Some(register_data.ip_address.clone()),
             os_info: register_data.os_info.clone(),
-            status: Some("online".to_string()),
+            os_name: register_data.os_name.clone(),
+            os_version: register_data.os_version.clone(),
+            kernel_version: register_data.kernel_version.clone(),
+            agent_version: register_data.agent_version.clone(),
+            cpu_count: register_data.cpu_count,
+            total_memory: register_data.total_memory,
+            status: Some(NodeStatus::Online),
+            tags: None,
         };
         
         match crate::models::Node::update(&db.pool, &node_id, update_data).await {
@@ -291,31 +854,36 @@ async fn handle_node_register(
                 info!("✅ 节点信息已更新: {}", node_id);
                 
                 // 添加到连接管理器
-                state.connection_manager.add_connection(node_id.clone()).await;
+                state.connection_manager.add_connection(node_id.clone(), protocol_version).await;
                 
-                // 广播节点状态变化
-                let status_change_message = crate::services::nodes::ClientBroadcastMessage {
-                    message_type: "node_status_change".to_string(),
-                    id: uuid::Uuid::new_v4().to_string(),
-                    timestamp: chrono::Utc::now().to_rfc3339(),
-                    data: serde_json::json!({
-                        "node_id": node_id,
-                        "status": "online",
-                        "timestamp": chrono::Utc::now().to_rfc3339()
-                    }),
-                };
-                state.broadcast_to_clients(status_change_message);
-                info!("📢 广播节点状态变化: {} -> online", node_id);
+                if continuing {
+                    info!("🔁 节点延续之前的会话，不广播状态变化: {}", node_id);
+                } else {
+                    // 广播节点状态变化
+                    let status_change_message = crate::services::nodes::ClientBroadcastMessage {
+                        message_type: "node_status_change".to_string(),
+                        id: uuid::Uuid::new_v4().to_string(),
+                        timestamp: chrono::Utc::now().to_rfc3339(),
+                        data: serde_json::json!({
+                            "node_id": node_id,
+                            "status": "online",
+                            "timestamp": chrono::Utc::now().to_rfc3339()
+                        }),
+                    };
+                    state.broadcast_to_clients(status_change_message);
+                    info!("📢 广播节点状态变化: {} -> online", node_id);
+                }
                 
                 let response = json!({
                     "type": "register_response",
-                    "id": msg.id,
+                    "id": message_id,
                     "timestamp": chrono::Utc::now().to_rfc3339(),
                     "data": {
                         "success": true,
                         "message": "节点信息已更新",
                         "node_id": node_id,
-                        "action": "updated"
+                        "action": "updated",
+                        "resumed": continuing
                     }
                 });
                 
@@ -323,35 +891,98 @@ async fn handle_node_register(
             }
             Ok(None) => {
                 // 节点不存在，创建新节点
-                create_new_node(&db.pool, socket, &msg.id, node_id, register_data, state).await?;
+                create_new_node(&db.pool, socket, message_id, node_id.clone(), register_data, state, protocol_version).await?;
             }
             Err(e) => {
                 error!("更新节点失败: {}", e);
-                send_error_response(socket, &msg.id, "UPDATE_NODE_FAILED", "更新节点失败", &e.to_string()).await?;
+                return Err(WebSocketError::database(DbOperation::UpdateNode, e));
             }
         }
     } else {
         // 节点不存在，创建新节点
-        create_new_node(&db.pool, socket, &msg.id, node_id, register_data, state).await?;
+        create_new_node(&db.pool, socket, message_id, node_id.clone(), register_data, state, protocol_version).await?;
     }
+    record_event(state, Some(&node_id), EVENT_NODE_REGISTERED, event_details).await;
+    
+    // 每次注册都以节点上报为准，节点移除配置后恢复使用全局保留天数
+    if let Err(e) = crate::models::Node::set_metrics_retention(&db.pool, &node_id, metrics_retention_days).await {
+        error!("更新节点监控数据保留天数失败: {}", e);
+    }
+    
+    // 下发节点离线期间积压的命令（在注册响应之后推送）
+    crate::services::commands::dispatch_pending_commands(state, &node_id).await;
     
     Ok(())
 }
 
+/// 节点注销消息载荷
+#[derive(Debug, Deserialize)]
+struct NodeDeregisterData {
+    node_id: Option<String>,
+    /// 注销原因（如`shutdown`）
+    reason: Option<String>,
+}
+
+/// 处理节点注销消息：节点主动下线且不会再重连，标记为`decommissioned`而非离线
+async fn handle_node_deregister(
+    message_id: &str,
+    deregister: NodeDeregisterData,
+    socket: &mut WebSocket,
+    state: &Arc<AppState>,
+    node_id: &str,
+) -> Result<(), WebSocketError> {
+    info!("👋 节点注销: {} (原因: {})", node_id, deregister.reason.as_deref().unwrap_or("未说明"));
+    
+    let found = crate::models::Node::update_status(&state.database.pool, node_id, NodeStatus::Decommissioned)
+        .await
+        .map_err(|e| WebSocketError::database(DbOperation::UpdateNode, e))?;
+    // 注销的节点不会再重连，不保留会话
+    state.connection_manager.end_session(node_id).await;
+    if found {
+        state.connection_manager.remove_connection(node_id).await;
+        state.broadcast_node_status(node_id, NodeStatus::Decommissioned);
+        record_event(state, Some(node_id), EVENT_NODE_DEREGISTERED, json!({ "reason": deregister.reason })).await;
+    } else {
+        warn!("⚠️ 注销的节点不存在: {}", node_id);
+    }
+    
+    let response = json!({
+        "type": "deregister_response",
+        "id": message_id,
+        "timestamp": chrono::Utc::now().to_rfc3339(),
+        "data": {
+            "success": found,
+            "node_id": node_id,
+            "status": NodeStatus::Decommissioned
+        }
+    });
+    
+    socket.send(Message::Text(response.to_string().into())).await?;
+    Ok(())
+}
+
 /// 创建新节点
 async fn create_new_node(
-    pool: &sqlx::SqlitePool,
+    pool: &crate::database::DbPool,
     socket: &mut WebSocket,
     message_id: &str,
     node_id: String,
     register_data: NodeRegisterData,
     state: &Arc<AppState>,
-) -> Result<(), anyhow::Error> {
+    protocol_version: u32,
+) -> Result<(), WebSocketError> {
     let node_data = crate::models::NodeCreate {
         node_id: node_id.clone(),
         hostname: register_data.hostname,
         ip_address: register_data.ip_address,
         os_info: register_data.os_info,
+        os_name: register_data.os_name,
+        os_version: register_data.os_version,
+        kernel_version: register_data.kernel_version,
+        agent_version: register_data.agent_version,
+        cpu_count: register_data.cpu_count,
+        total_memory: register_data.total_memory,
+        tags: Vec::new(),
     };
     
     match crate::models::Node::create(pool, node_data).await {
@@ -359,7 +990,7 @@ async fn create_new_node(
             info!("✅ 新节点注册成功: {}", node_id);
             
             // 添加到连接管理器
-            state.connection_manager.add_connection(node_id.clone()).await;
+            state.connection_manager.add_connection(node_id.clone(), protocol_version).await;
             
             // 广播节点状态变化
             let status_change_message = crate::services::nodes::ClientBroadcastMessage {
@@ -397,7 +1028,7 @@ async fn create_new_node(
         }
         Err(e) => {
             error!("创建节点失败: {}", e);
-            send_error_response(socket, message_id, "CREATE_NODE_FAILED", "创建节点失败", &e.to_string()).await?;
+            return Err(WebSocketError::database(DbOperation::CreateNode, e));
         }
     }
     
@@ -411,7 +1042,7 @@ async fn send_error_response(
     error_code: &str,
     message: &str,
     details: &str,
-) -> Result<(), anyhow::Error> {
+) -> Result<(), axum::Error> {
     let error_msg = json!({
         "type": "error",
         "id": message_id,
@@ -427,9 +1058,40 @@ async fn send_error_response(
     Ok(())
 }
 
-/// 监控数据结构
+/// 要求节点重新发送node_register（如Core数据库被重置而节点仍保持连接）
+async fn send_register_required(socket: &mut WebSocket, node_id: &str) -> Result<(), axum::Error> {
+    let message = json!({
+        "type": "register_required",
+        "id": Uuid::new_v4().to_string(),
+        "timestamp": chrono::Utc::now().to_rfc3339(),
+        "data": {
+            "node_id": node_id,
+            "reason": "节点未注册"
+        }
+    });
+    
+    socket.send(Message::Text(message.to_string().into())).await?;
+    Ok(())
+}
+
+/// 确认节点已注册，未注册时返回`NodeNotRegistered`，回复时会要求节点重新注册
+async fn ensure_registered(state: &Arc<AppState>, node_id: &str) -> Result<(), WebSocketError> {
+    match crate::models::Node::find_by_node_id(&state.database.pool, node_id).await {
+        Ok(Some(_)) => Ok(()),
+        Ok(None) => {
+            warn!("⚠️ 收到未注册节点的数据，要求重新注册: {}", node_id);
+            Err(WebSocketError::NodeNotRegistered(node_id.to_string()))
+        }
+        Err(e) => {
+            error!("检查节点存在失败: {}", e);
+            Err(WebSocketError::database(DbOperation::CheckNode, e))
+        }
+    }
+}
+
+/// 监控数据结构，实时上报和历史数据导入共用同一套数值校验
 #[derive(Debug, Deserialize)]
-struct MetricData {
+pub struct MetricData {
     cpu_usage: Option<f64>,
     memory_usage: Option<f64>,
     disk_usage: Option<f64>,
@@ -439,141 +1101,255 @@ struct MetricData {
     memory_available: Option<f64>,
     disk_total: Option<f64>,
     disk_available: Option<f64>,
-    network_rx: Option<f64>,
-    network_tx: Option<f64>,
     uptime: Option<f64>,
+    // 网络、磁盘I/O的累计字节数
+    network_rx_bytes: Option<f64>,
+    network_tx_bytes: Option<f64>,
+    disk_read_bytes: Option<f64>,
+    disk_write_bytes: Option<f64>,
+    // 采集时间（RFC 3339），用于批量补发的数据
+    timestamp: Option<String>,
 }
 
-/// 处理心跳消息（包含监控数据）
-async fn handle_heartbeat(
-    msg: WebSocketMessage,
-    socket: &mut WebSocket,
+/// 心跳消息载荷，监控数据位于`metrics`字段
+#[derive(Debug, Deserialize)]
+struct HeartbeatData {
+    node_id: Option<String>,
+    metrics: Option<MetricData>,
+}
+
+/// metrics消息载荷，监控数据直接位于`data`中
+#[derive(Debug, Deserialize)]
+struct MetricsData {
+    node_id: Option<String>,
+    #[serde(flatten)]
+    metric: MetricData,
+}
+
+/// 百分比指标超过100但不超过该值时视为采集误差，截断为100；更大的值直接丢弃
+const PERCENT_CLAMP_LIMIT: f64 = 105.0;
+
+/// 监控数据中被修正或丢弃的值
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MetricValueIssue {
+    pub field: &'static str,
+    pub value: f64,
+    /// `clamped`（已截断）或`dropped`（已丢弃，该字段按未上报保存）
+    pub action: &'static str,
+    pub reason: String,
+}
+
+impl MetricValueIssue {
+    fn dropped(field: &'static str, value: f64, reason: impl Into<String>) -> Self {
+        Self { field, value, action: "dropped", reason: reason.into() }
+    }
+}
+
+impl MetricData {
+    /// 校验指标数值，返回被修正或丢弃的值
+    ///
+    /// 百分比略超过100时截断，负数、非有限值、明显超出范围或可用量大于总量的值丢弃，其余字段照常保存
+    pub fn sanitize(&mut self) -> Vec<MetricValueIssue> {
+        let mut issues = Vec::new();
+
+        let percentages = [
+            ("cpu_usage", &mut self.cpu_usage),
+            ("memory_usage", &mut self.memory_usage),
+            ("disk_usage", &mut self.disk_usage),
+        ];
+        for (field, slot) in percentages {
+            let Some(value) = *slot else { continue };
+            if !value.is_finite() || value < 0.0 {
+                issues.push(MetricValueIssue::dropped(field, value, "百分比不能为负数或非有限值"));
+                *slot = None;
+            } else if value > PERCENT_CLAMP_LIMIT {
+                issues.push(MetricValueIssue::dropped(field, value, "百分比超出0~100范围"));
+                *slot = None;
+            } else if value > 100.0 {
+                issues.push(MetricValueIssue { field, value, action: "clamped", reason: "百分比超过100，已截断为100".to_string() });
+                *slot = Some(100.0);
+            }
+        }
+
+        let non_negative = [
+            ("load_average", &mut self.load_average),
+            ("memory_total", &mut self.memory_total),
+            ("memory_available", &mut self.memory_available),
+            ("disk_total", &mut self.disk_total),
+            ("disk_available", &mut self.disk_available),
+            ("uptime", &mut self.uptime),
+            ("network_rx_bytes", &mut self.network_rx_bytes),
+            ("network_tx_bytes", &mut self.network_tx_bytes),
+            ("disk_read_bytes", &mut self.disk_read_bytes),
+            ("disk_write_bytes", &mut self.disk_write_bytes),
+        ];
+        for (field, slot) in non_negative {
+            if let Some(value) = slot.filter(|value| !value.is_finite() || *value < 0.0) {
+                issues.push(MetricValueIssue::dropped(field, value, "不能为负数或非有限值"));
+                *slot = None;
+            }
+        }
+
+        let capacities = [
+            ("memory_available", &mut self.memory_available, self.memory_total, "memory_total"),
+            ("disk_available", &mut self.disk_available, self.disk_total, "disk_total"),
+        ];
+        for (field, slot, total, total_field) in capacities {
+            if let (Some(value), Some(total)) = (*slot, total) {
+                if value > total {
+                    issues.push(MetricValueIssue::dropped(field, value, format!("大于{}({})", total_field, total)));
+                    *slot = None;
+                }
+            }
+        }
+
+        issues
+    }
+
+    /// 转换为待保存的监控记录
+    pub fn into_metric_create(self, node_id: &str, metric_time: Option<DateTime<Utc>>) -> MetricCreate {
+        MetricCreate {
+            node_id: node_id.to_string(),
+            cpu_usage: self.cpu_usage,
+            memory_usage: self.memory_usage,
+            disk_usage: self.disk_usage,
+            disk_total: self.disk_total.map(|v| v as i64),
+            disk_available: self.disk_available.map(|v| v as i64),
+            load_average: self.load_average,
+            memory_total: self.memory_total.map(|v| v as i64),
+            memory_available: self.memory_available.map(|v| v as i64),
+            uptime: self.uptime.map(|v| v as i64),
+            network_rx_bytes: self.network_rx_bytes.map(|v| v as i64),
+            network_tx_bytes: self.network_tx_bytes.map(|v| v as i64),
+            disk_read_bytes: self.disk_read_bytes.map(|v| v as i64),
+            disk_write_bytes: self.disk_write_bytes.map(|v| v as i64),
+            metric_time,
+        }
+    }
+}
+
+/// 保存单条实时监控数据，成功后广播给监控客户端、评估告警并与上一个样本对比检测节点重启
+///
+/// 心跳和metrics消息共用，调用前需确认节点已注册
+async fn persist_and_broadcast_metric(
     state: &Arc<AppState>,
     node_id: &str,
-) -> Result<(), anyhow::Error> {
-    info!("💓 心跳消息 from: {}", node_id);
+    mut metric_data: MetricData,
+    metric_time: Option<DateTime<Utc>>,
+) -> Result<(NodeMetric, Vec<MetricValueIssue>), anyhow::Error> {
+    let issues = metric_data.sanitize();
+    for issue in &issues {
+        warn!("⚠️ metric_validation: 节点 {} 的{}数值异常({})，{}: {}", node_id, issue.field, issue.value, issue.action, issue.reason);
+    }
     
-    // 解析监控数据 - 从heartbeat消息的metrics字段中提取
-    let metric_data: MetricData = match msg.data.get("metrics").and_then(|v| serde_json::from_value(v.clone()).ok()) {
-        Some(data) => data,
-        None => {
-            warn!("心跳消息中缺少metrics字段或格式错误");
-            // 即使数据格式错误，也继续处理心跳
-            MetricData {
-                cpu_usage: None,
-                memory_usage: None,
-                memory_total: None,
-                memory_available: None,
-                disk_usage: None,
-                disk_total: None,
-                disk_available: None,
-                network_rx: None,
-                network_tx: None,
-                load_average: None,
-                uptime: None,
-            }
-        }
+    let db = &state.database;
+    let previous = match metric_data.uptime {
+        Some(_) => NodeMetric::get_latest_by_node(&db.pool, node_id).await.unwrap_or_else(|e| {
+            warn!("⚠️ 查询节点 {} 的上一条监控数据失败，跳过重启检测: {}", node_id, e);
+            None
+        }),
+        None => None,
     };
+    let metric_create = metric_data.into_metric_create(node_id, metric_time);
+    let metric = db
+        .write_with_retry("保存监控数据", || NodeMetric::create(&db.pool, metric_create.clone()))
+        .await?;
+    state.ingestion.record(1);
+    debug!("✅ 监控数据保存成功: {}", node_id);
     
-    // 保存监控数据到数据库
-    let db = state.database.lock().await;
+    // 广播新的监控数据给所有客户端（NodeMetric包含完整的原始数据）
+    state.broadcast_to_clients(ClientBroadcastMessage {
+        message_type: "metrics_update".to_string(),
+        id: Uuid::new_v4().to_string(),
+        timestamp: chrono::Utc::now().to_rfc3339(),
+        data: json!({
+            "metrics": [&metric]
+        }),
+    });
+    crate::services::alerts::process_metric(state, &metric).await;
+    crate::services::anomaly::process_metric(state, &metric).await;
+    crate::services::events::process_metric(state, previous.as_ref(), &metric).await;
     
-    // 首先检查节点是否存在，如果不存在则创建
-    let node_exists = match crate::models::Node::find_by_node_id(&db.pool, node_id).await {
-        Ok(Some(_)) => true,
-        Ok(None) => false,
-        Err(e) => {
-            error!("检查节点存在失败: {}", e);
-            false
+    Ok((metric, issues))
+}
+
+/// 通知节点监控数据中有被修正或丢弃的值，便于排查节点采集逻辑
+async fn send_metric_validation(
+    socket: &mut WebSocket,
+    message_id: &str,
+    node_id: &str,
+    issues: serde_json::Value,
+) -> Result<(), axum::Error> {
+    let message = json!({
+        "type": "metric_validation",
+        "id": message_id,
+        "timestamp": chrono::Utc::now().to_rfc3339(),
+        "data": {
+            "node_id": node_id,
+            "message": "监控数据中有无效的数值，已修正或丢弃",
+            "issues": issues
         }
-    };
+    });
     
-    if !node_exists {
-        // 节点不存在，自动创建节点
-        let node_data = crate::models::NodeCreate {
-            node_id: node_id.to_string(),
-            hostname: "unknown".to_string(),
-            ip_address: "0.0.0.0".to_string(),
-            os_info: None,
-        };
-        
-        match crate::models::Node::create(&db.pool, node_data).await {
-            Ok(_) => {
-                info!("✅ 自动创建节点: {}", node_id);
-                // 添加到连接管理器
-                state.connection_manager.add_connection(node_id.to_string()).await;
-            }
-            Err(e) => {
-                error!("❌ 自动创建节点失败: {}", e);
-            }
-        }
-    }
+    socket.send(Message::Text(message.to_string().into())).await
+}
+
+/// 处理心跳消息，携带metrics字段时同时保存监控数据
+async fn handle_heartbeat(
+    message_id: &str,
+    heartbeat: HeartbeatData,
+    metric_time: Option<DateTime<Utc>>,
+    socket: &mut WebSocket,
+    state: &Arc<AppState>,
+    node_id: &str,
+) -> Result<(), WebSocketError> {
+    info!("💓 心跳消息 from: {}", node_id);
     
-    let metric_create = crate::models::MetricCreate {
-        node_id: node_id.to_string(),
-        cpu_usage: metric_data.cpu_usage,
-        memory_usage: metric_data.memory_usage,
-        disk_usage: metric_data.disk_usage,
-        disk_total: metric_data.disk_total.map(|v| v as i64),
-        disk_available: metric_data.disk_available.map(|v| v as i64),
-        load_average: metric_data.load_average,
-        memory_total: metric_data.memory_total.map(|v| v as i64),
-        memory_available: metric_data.memory_available.map(|v| v as i64),
-        uptime: metric_data.uptime.map(|v| v as i64),
-    };
+    // 未注册的节点需先发送node_register，不自动创建占位节点
+    ensure_registered(state, node_id).await?;
+    
+    let db = &state.database;
     
     // 更新节点心跳时间和在线状态
-    if let Err(e) = crate::models::Node::update_heartbeat(&db.pool, node_id).await {
+    if let Err(e) = db.write_with_retry("更新节点心跳", || crate::models::Node::update_heartbeat(&db.pool, node_id)).await {
         error!("❌ 更新节点心跳失败: {}", e);
     }
     
     // 更新连接管理器中的活动时间
     state.connection_manager.update_activity(node_id).await;
     
-    match crate::models::NodeMetric::create(&db.pool, metric_create).await {
-        Ok(metric) => {
-            debug!("✅ 监控数据保存成功: {}", node_id);
-            
-            // 广播新的监控数据给所有客户端（包含完整的原始数据）
-            let enhanced_metric = json!({
-                "id": metric.id,
-                "node_id": metric.node_id,
-                "metric_time": metric.metric_time,
-                "cpu_usage": metric.cpu_usage,
-                "memory_usage": metric.memory_usage,
-                "disk_usage": metric.disk_usage,
-                "disk_total": metric.disk_total,
-                "disk_available": metric.disk_available,
-                "load_average": metric.load_average,
-                "memory_total": metric.memory_total,
-                "memory_available": metric.memory_available,
-                "uptime": metric.uptime,
-                "created_at": metric.created_at,
-            });
-            
-            let broadcast_msg = ClientBroadcastMessage {
-                message_type: "metrics_update".to_string(),
-                id: Uuid::new_v4().to_string(),
-                timestamp: chrono::Utc::now().to_rfc3339(),
-                data: json!({
-                    "metrics": [enhanced_metric]
-                }),
-            };
-            state.broadcast_to_clients(broadcast_msg);
+    // 监控数据在heartbeat消息的metrics字段中，节点发送只表示在线的轻量心跳时不携带
+    let metrics_saved = match heartbeat.metrics {
+        Some(metric_data) => {
+            match persist_and_broadcast_metric(state, node_id, metric_data, metric_time).await {
+                Ok((_, issues)) => {
+                    if !issues.is_empty() {
+                        send_metric_validation(socket, message_id, node_id, json!(issues)).await?;
+                    }
+                    true
+                }
+                Err(e) => {
+                    error!("❌ 保存监控数据失败: {}", e);
+                    // 保存失败时仍然确认心跳
+                    send_error(socket, Some(message_id), &WebSocketError::database(DbOperation::SaveMetrics, e)).await?;
+                    false
+                }
+            }
         }
-        Err(e) => {
-            error!("❌ 保存监控数据失败: {}", e);
+        None => {
+            debug!("💓 心跳不包含监控数据，仅更新在线状态: {}", node_id);
+            false
         }
-    }
+    };
     
     let response = json!({
         "type": "heartbeat_ack",
-        "id": msg.id,
+        "id": message_id,
         "timestamp": chrono::Utc::now().to_rfc3339(),
         "data": {
             "received": true,
             "node_id": node_id,
-            "metrics_saved": true
+            "metrics_saved": metrics_saved
         }
     });
     
@@ -581,140 +1357,275 @@ async fn handle_heartbeat(
     Ok(())
 }
 
-/// 处理专门的监控数据消息
-async fn handle_metrics(
-    msg: WebSocketMessage,
+/// 处理专门的监控数据消息
+async fn handle_metrics(
+    message_id: &str,
+    metrics: MetricsData,
+    metric_time: Option<DateTime<Utc>>,
+    socket: &mut WebSocket,
+    state: &Arc<AppState>,
+    node_id: &str,
+) -> Result<(), WebSocketError> {
+    info!("📊 监控数据消息 from: {}", node_id);
+    
+    // 未注册的节点需先发送node_register，不自动创建占位节点
+    ensure_registered(state, node_id).await?;
+    
+    match persist_and_broadcast_metric(state, node_id, metrics.metric, metric_time).await {
+        Ok((metric, issues)) => {
+            if !issues.is_empty() {
+                send_metric_validation(socket, message_id, node_id, json!(issues)).await?;
+            }
+
+            let response = json!({
+                "type": "metrics_response",
+                "id": message_id,
+                "timestamp": chrono::Utc::now().to_rfc3339(),
+                "data": {
+                    "success": true,
+                    "message": "监控数据保存成功",
+                    "node_id": node_id,
+                    "metric_id": metric.id
+                }
+            });
+            
+            socket.send(Message::Text(response.to_string().into())).await?;
+        }
+        Err(e) => {
+            error!("❌ 保存监控数据失败: {}", e);
+            return Err(WebSocketError::database(DbOperation::SaveMetrics, e));
+        }
+    }
+    
+    Ok(())
+}
+
+/// 批量监控数据结构
+#[derive(Debug, Deserialize)]
+struct MetricsBatchData {
+    node_id: Option<String>,
+    metrics: Vec<MetricData>,
+}
+
+/// 单次批量消息允许的最大样本数
+const MAX_BATCH_SIZE: usize = 1000;
+
+/// 允许节点时间超前于服务器时间的最大秒数
+const MAX_FUTURE_SKEW_SECS: i64 = 300;
+
+/// 解析节点上报的采集时间，拒绝明显超前于服务器时间的值
+fn parse_metric_time(timestamp: &str) -> Result<DateTime<Utc>, String> {
+    let metric_time = DateTime::parse_from_rfc3339(timestamp)
+        .map_err(|e| format!("timestamp格式错误: {}", e))?
+        .with_timezone(&Utc);
+    
+    if metric_time > Utc::now() + chrono::Duration::seconds(MAX_FUTURE_SKEW_SECS) {
+        return Err(format!("timestamp超前服务器时间过多: {}", timestamp));
+    }
+    
+    Ok(metric_time)
+}
+
+/// 获取单条监控消息的采集时间，无效时回退为服务器当前时间
+fn message_metric_time(timestamp: &str, node_id: &str) -> Option<DateTime<Utc>> {
+    match parse_metric_time(timestamp) {
+        Ok(metric_time) => Some(metric_time),
+        Err(reason) => {
+            warn!("⚠️ 忽略节点 {} 上报的采集时间: {}", node_id, reason);
+            None
+        }
+    }
+}
+
+/// 校验批量数据中单条样本的采集时间，数值与实时监控数据一样由`sanitize`处理
+fn validate_batch_entry(metric_data: &MetricData) -> Result<DateTime<Utc>, String> {
+    let timestamp = metric_data.timestamp.as_deref().ok_or("缺少timestamp字段")?;
+    parse_metric_time(timestamp)
+}
+
+/// 处理批量监控数据消息（节点断线重连后补发的缓存数据）
+async fn handle_metrics_batch(
+    message_id: &str,
+    batch: MetricsBatchData,
     socket: &mut WebSocket,
     state: &Arc<AppState>,
     node_id: &str,
-) -> Result<(), anyhow::Error> {
-    info!("📊 监控数据消息 from: {}", node_id);
-    
-    // 解析监控数据
-    let metric_data: MetricData = match serde_json::from_value(msg.data.clone()) {
-        Ok(data) => data,
-        Err(e) => {
-            let error_msg = json!({
-                "type": "error",
-                "id": msg.id,
-                "timestamp": chrono::Utc::now().to_rfc3339(),
-                "data": {
-                    "error_code": "INVALID_METRIC_DATA",
-                    "message": "监控数据格式错误",
-                    "details": e.to_string()
-                }
-            });
-            socket.send(Message::Text(error_msg.to_string().into())).await?;
-            return Err(e.into());
-        }
-    };
-    
-    // 保存监控数据到数据库
-    let db = state.database.lock().await;
+) -> Result<(), WebSocketError> {
+    info!("📦 批量监控数据消息 from: {}", node_id);
     
-    // 首先检查节点是否存在，如果不存在则创建
-    let node_exists = match crate::models::Node::find_by_node_id(&db.pool, node_id).await {
-        Ok(Some(_)) => true,
-        Ok(None) => false,
-        Err(e) => {
-            error!("检查节点存在失败: {}", e);
-            false
-        }
-    };
+    if batch.metrics.len() > MAX_BATCH_SIZE {
+        return Err(WebSocketError::BatchTooLarge { actual: batch.metrics.len(), max: MAX_BATCH_SIZE });
+    }
     
-    if !node_exists {
-        // 节点不存在，自动创建节点
-        let node_data = crate::models::NodeCreate {
-            node_id: node_id.to_string(),
-            hostname: "unknown".to_string(),
-            ip_address: "0.0.0.0".to_string(),
-            os_info: None,
-        };
-        
-        match crate::models::Node::create(&db.pool, node_data).await {
-            Ok(_) => {
-                info!("✅ 自动创建节点: {}", node_id);
-                // 添加到连接管理器
-                state.connection_manager.add_connection(node_id.to_string()).await;
+    // 逐条校验，无效样本跳过并在响应中说明
+    let mut metrics = Vec::with_capacity(batch.metrics.len());
+    let mut rejected = Vec::new();
+    let mut adjusted = Vec::new();
+    for (index, mut metric_data) in batch.metrics.into_iter().enumerate() {
+        match validate_batch_entry(&metric_data) {
+            Ok(metric_time) => {
+                let issues = metric_data.sanitize();
+                if !issues.is_empty() {
+                    warn!("⚠️ metric_validation: 批量监控样本 #{} from {} 中有 {} 个数值被修正或丢弃", index, node_id, issues.len());
+                    adjusted.push(json!({ "index": index, "issues": issues }));
+                }
+                metrics.push(metric_data.into_metric_create(node_id, Some(metric_time)));
             }
-            Err(e) => {
-                error!("❌ 自动创建节点失败: {}", e);
-                send_error_response(socket, &msg.id, "CREATE_NODE_FAILED", "自动创建节点失败", &e.to_string()).await?;
-                return Ok(());
+            Err(reason) => {
+                warn!("⚠️ 跳过无效的批量监控样本 #{} from {}: {}", index, node_id, reason);
+                rejected.push(json!({ "index": index, "reason": reason }));
             }
         }
     }
+    let count = metrics.len();
     
-    let metric_create = crate::models::MetricCreate {
-        node_id: node_id.to_string(),
-        cpu_usage: metric_data.cpu_usage,
-        memory_usage: metric_data.memory_usage,
-        disk_usage: metric_data.disk_usage,
-        disk_total: metric_data.disk_total.map(|v| v as i64),
-        disk_available: metric_data.disk_available.map(|v| v as i64),
-        load_average: metric_data.load_average,
-        memory_total: metric_data.memory_total.map(|v| v as i64),
-        memory_available: metric_data.memory_available.map(|v| v as i64),
-        uptime: metric_data.uptime.map(|v| v as i64),
-    };
+    ensure_registered(state, node_id).await?;
     
-    match crate::models::NodeMetric::create(&db.pool, metric_create).await {
-        Ok(metric) => {
-            info!("✅ 监控数据保存成功: {}", node_id);
-            
-            // 广播新的监控数据给所有客户端
-            let broadcast_msg = ClientBroadcastMessage {
-                message_type: "metrics_update".to_string(),
-                id: Uuid::new_v4().to_string(),
-                timestamp: chrono::Utc::now().to_rfc3339(),
-                data: json!({
-                    "metrics": [&metric]
-                }),
-            };
-            state.broadcast_to_clients(broadcast_msg);
+    let db = &state.database;
+    
+    // 批量数据是断线期间缓存的历史样本，不参与告警评估
+    match db.write_with_retry("保存批量监控数据", || NodeMetric::create_batch(&db.pool, metrics.clone())).await {
+        Ok(()) => {
+            state.ingestion.record(count as u64);
+            info!("✅ 批量监控数据保存成功: {} ({}条)", node_id, count);
             
             let response = json!({
-                "type": "metrics_response",
-                "id": msg.id,
+                "type": "metrics_batch_response",
+                "id": message_id,
                 "timestamp": chrono::Utc::now().to_rfc3339(),
                 "data": {
                     "success": true,
-                    "message": "监控数据保存成功",
+                    "message": "批量监控数据保存成功",
                     "node_id": node_id,
-                    "metric_id": metric.id
+                    "saved_count": count,
+                    "rejected_count": rejected.len(),
+                    "rejected": rejected
                 }
             });
             
             socket.send(Message::Text(response.to_string().into())).await?;
+            if !adjusted.is_empty() {
+                send_metric_validation(socket, message_id, node_id, json!(adjusted)).await?;
+            }
         }
         Err(e) => {
-            error!("❌ 保存监控数据失败: {}", e);
-            send_error_response(socket, &msg.id, "SAVE_METRICS_FAILED", "保存监控数据失败", &e.to_string()).await?;
+            error!("❌ 保存批量监控数据失败: {}", e);
+            return Err(WebSocketError::database(DbOperation::SaveMetricsBatch, e));
         }
     }
     
     Ok(())
 }
 
+/// 命令实时输出消息载荷
+#[derive(Debug, Deserialize)]
+struct CommandOutputData {
+    node_id: Option<String>,
+    command_id: String,
+    /// 节点为每条命令的输出片段分配的递增序号，stdout和stderr共用
+    seq: u64,
+    /// 输出流：`stdout`或`stderr`
+    stream: String,
+    data: String,
+}
+
+/// 处理命令实时输出：确认命令属于该节点后转发给监控客户端，不回复节点也不保存（完整输出随执行结果保存）
+async fn handle_command_output(
+    output: CommandOutputData,
+    state: &Arc<AppState>,
+    node_id: &str,
+) -> Result<(), WebSocketError> {
+    debug!("📜 命令输出 from: {} ({} #{})", node_id, output.command_id, output.seq);
+
+    match Command::find_by_id(&state.database.pool, &output.command_id).await {
+        Ok(Some(command)) if command.target_node_id == node_id => {}
+        Ok(_) => {
+            warn!("❌ 未知命令的输出: {} (节点: {})", output.command_id, node_id);
+            return Err(WebSocketError::CommandNotFound(output.command_id));
+        }
+        Err(e) => {
+            error!("查询命令失败: {}", e);
+            return Err(WebSocketError::database(DbOperation::CheckCommand, e));
+        }
+    }
+
+    broadcast_command_output(state, node_id, &output.command_id, output.seq, &output.stream, &output.data);
+    Ok(())
+}
+
+/// 命令执行结果消息载荷
+#[derive(Debug, Deserialize)]
+struct CommandResultData {
+    node_id: Option<String>,
+    /// 节点上报的执行状态（如`timeout`）
+    status: Option<String>,
+    #[serde(flatten)]
+    result: CommandResultCreate,
+}
+
 /// 处理命令执行结果
 async fn handle_command_result(
-    msg: WebSocketMessage,
+    message_id: &str,
+    command_result: CommandResultData,
     socket: &mut WebSocket,
-    _state: &Arc<AppState>,
+    state: &Arc<AppState>,
     node_id: &str,
-) -> Result<(), anyhow::Error> {
+) -> Result<(), WebSocketError> {
     info!("📝 命令执行结果 from: {}", node_id);
     
-    // 这里应该保存命令结果到数据库
-    // 暂时简单响应接收确认
+    let CommandResultData { result: result_data, status: reported_status, .. } = command_result;
+    let command_id = result_data.command_id.clone();
+    // 节点上报超时优先；退出码为0视为成功，其余（包括缺失）视为失败
+    let status = if reported_status.as_deref() == Some("timeout") {
+        CommandStatus::Timeout
+    } else if result_data.exit_code == Some(0) {
+        CommandStatus::Success
+    } else {
+        CommandStatus::Failed
+    };
+    
+    let db = &state.database;
+    
+    // 确认命令存在且属于该节点
+    match Command::find_by_id(&db.pool, &command_id).await {
+        Ok(Some(command)) if command.target_node_id == node_id => {}
+        Ok(_) => {
+            warn!("❌ 未知的命令结果: {} (节点: {})", command_id, node_id);
+            return Err(WebSocketError::CommandNotFound(command_id));
+        }
+        Err(e) => {
+            error!("查询命令失败: {}", e);
+            return Err(WebSocketError::database(DbOperation::SaveCommandResult, e));
+        }
+    }
+    
+    // 保存命令结果并更新命令状态
+    let saved_result = match CommandResult::create(&db.pool, result_data).await {
+        Ok(result) => result,
+        Err(e) => {
+            error!("❌ 保存命令结果失败: {}", e);
+            return Err(WebSocketError::database(DbOperation::SaveCommandResult, e));
+        }
+    };
+    
+    let status_str = status.to_string();
+    if let Err(e) = Command::update_status(&db.pool, &command_id, status).await {
+        error!("❌ 更新命令状态失败: {}", e);
+    }
+    
+    info!("✅ 命令结果已保存: {} -> {}", command_id, status_str);
+    state.command_waiters.complete(&command_id).await;
+    broadcast_command_result(state, node_id, &saved_result, &status_str);
     
     let response = json!({
         "type": "command_received",
-        "id": msg.id,
+        "id": message_id,
         "timestamp": chrono::Utc::now().to_rfc3339(),
         "data": {
             "received": true,
-            "node_id": node_id
+            "node_id": node_id,
+            "command_id": command_id,
+            "status": status_str
         }
     });
     
@@ -722,17 +1633,52 @@ async fn handle_command_result(
     Ok(())
 }
 
-/// 健康检查端点
-pub async fn health_check() -> impl IntoResponse {
-    axum::Json(json!({
-        "success": true,
-        "message": "✅ Core服务运行正常",
+/// 健康检查中数据库连通性检查的超时时间
+const HEALTH_CHECK_DB_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// 健康检查端点（数据库不可用时返回503）
+pub async fn health_check(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    let database_ok = match tokio::time::timeout(HEALTH_CHECK_DB_TIMEOUT, state.database.health_check()).await {
+        Ok(Ok(())) => true,
+        Ok(Err(e)) => {
+            warn!("⚠️ 健康检查: 数据库不可用: {}", e);
+            false
+        }
+        Err(_) => {
+            warn!("⚠️ 健康检查: 数据库检查超时");
+            false
+        }
+    };
+
+    // 数据库可以查询但最近的关键写入失败（如磁盘已满、长时间锁定）时报告降级，仍然返回200
+    let write_health = state.database.write_health();
+    let (status_code, status, database, message) = if !database_ok {
+        (StatusCode::SERVICE_UNAVAILABLE, "unhealthy", "unavailable", "❌ 数据库不可用")
+    } else if write_health.is_failing() {
+        (StatusCode::OK, "degraded", "write_failing", "⚠️ 数据库写入失败，监控数据可能丢失")
+    } else {
+        (StatusCode::OK, "healthy", "ok", "✅ Core服务运行正常")
+    };
+
+    let body = json!({
+        "success": database_ok,
+        "message": message,
         "data": {
-            "status": "healthy",
-            "websocket": "running"
+            "status": status,
+            "version": env!("CARGO_PKG_VERSION"),
+            "git_sha": env!("SM_GIT_SHA"),
+            "build_time": env!("SM_BUILD_TIME"),
+            "uptime_secs": state.started_at.elapsed().as_secs(),
+            "database": database,
+            "database_writes": write_health,
+            "websocket": "running",
+            "connected_nodes": state.connection_manager.connected_count().await,
+            "ingestion": state.ingestion.snapshot()
         },
         "timestamp": chrono::Utc::now().to_rfc3339()
-    }))
+    });
+
+    (status_code, axum::Json(body))
 }
 
 /// 处理客户端监控WebSocket连接
@@ -740,12 +1686,14 @@ pub async fn handle_client_websocket(
     mut socket: WebSocket,
     state: Arc<AppState>,
     _query: WebSocketQuery,
+    protocol_version: u32,
 ) {
     let client_id = Uuid::new_v4().to_string();
     info!("✅ 客户端监控WebSocket连接已建立, 客户端ID: {}", client_id);
 
     // 订阅广播消息
     let mut broadcast_receiver = state.client_broadcaster.subscribe();
+    let mut shutdown = state.shutdown_receiver();
 
     // 发送欢迎消息
     let welcome_msg = json!({
@@ -755,7 +1703,9 @@ pub async fn handle_client_websocket(
         "data": {
             "message": "欢迎连接到Server Manager监控",
             "client_id": client_id,
-            "connection_type": "monitor"
+            "connection_type": "monitor",
+            "protocol_version": protocol_version,
+            "capabilities": ServerCapabilities::for_monitor()
         }
     });
     
@@ -770,6 +1720,8 @@ pub async fn handle_client_websocket(
         return;
     }
 
+    let mut subscription = ClientSubscription::default();
+
     // 处理消息循环 - 同时监听客户端消息和广播消息
     loop {
         tokio::select! {
@@ -777,7 +1729,22 @@ pub async fn handle_client_websocket(
             client_msg = socket.recv() => {
                 match client_msg {
                     Some(Ok(Message::Text(text))) => {
-                        if let Err(e) = handle_client_message(&text, &mut socket, &state, &client_id).await {
+                        if let Err(e) = handle_client_message(&text, &mut socket, &state, &client_id, &mut subscription).await {
+                            error!("处理客户端消息失败: {}", e);
+                            break;
+                        }
+                    }
+                    // 部分客户端以二进制帧发送UTF-8编码的JSON消息
+                    Some(Ok(Message::Binary(data))) => {
+                        let result = match std::str::from_utf8(&data) {
+                            Ok(text) => handle_client_message(text, &mut socket, &state, &client_id, &mut subscription).await,
+                            Err(e) => {
+                                warn!("⚠️ 客户端二进制消息不是UTF-8编码的JSON, 客户端ID: {}", client_id);
+                                let response = error_response("UNSUPPORTED_BINARY_MESSAGE", "二进制消息应为UTF-8编码的JSON", e.to_string());
+                                socket.send(response).await.map_err(Into::into)
+                            }
+                        };
+                        if let Err(e) = result {
                             error!("处理客户端消息失败: {}", e);
                             break;
                         }
@@ -786,9 +1753,11 @@ pub async fn handle_client_websocket(
                         info!("🔌 客户端监控WebSocket连接关闭, 客户端ID: {}", client_id);
                         break;
                     }
-                    Some(Ok(_)) => {
-                        info!("📨 收到客户端非文本消息, 客户端ID: {}", client_id);
+                    // 客户端的ping由axum自动回复pong
+                    Some(Ok(Message::Ping(_))) => {
+                        debug!("🏓 收到客户端ping, 客户端ID: {}", client_id);
                     }
+                    Some(Ok(Message::Pong(_))) => {}
                     Some(Err(e)) => {
                         error!("客户端消息错误: {}", e);
                         break;
@@ -800,9 +1769,16 @@ pub async fn handle_client_websocket(
                 }
             }
             
+            _ = shutdown.changed() => {
+                info!("🛑 服务关闭，断开客户端连接: {}", client_id);
+                send_shutdown_notice(&mut socket).await;
+                break;
+            }
+            
             // 处理广播消息
             broadcast_msg = broadcast_receiver.recv() => {
                 match broadcast_msg {
+                    Ok(msg) if !subscription.wants(&msg) => continue,
                     Ok(msg) => {
                         let json_msg = match serde_json::to_string(&msg) {
                             Ok(json) => json,
@@ -819,7 +1795,24 @@ pub async fn handle_client_websocket(
                         info!("📢 向客户端 {} 广播消息: {}", client_id, msg.message_type);
                     }
                     Err(broadcast::error::RecvError::Lagged(n)) => {
-                        warn!("客户端 {} 广播消息滞后 {} 条", client_id, n);
+                        // 丢失的更新无法补发，重新发送当前快照让客户端追上最新状态
+                        warn!("⚠️ 客户端 {} 广播消息滞后 {} 条，重新发送快照", client_id, n);
+                        let resync_msg = json!({
+                            "type": "resync",
+                            "id": Uuid::new_v4().to_string(),
+                            "timestamp": chrono::Utc::now().to_rfc3339(),
+                            "data": {
+                                "missed": n
+                            }
+                        });
+                        if let Err(e) = socket.send(Message::Text(resync_msg.to_string().into())).await {
+                            error!("发送重新同步消息失败: {}", e);
+                            break;
+                        }
+                        if let Err(e) = send_initial_data(&mut socket, &state).await {
+                            error!("重新发送快照失败: {}", e);
+                            break;
+                        }
                     }
                     Err(broadcast::error::RecvError::Closed) => {
                         info!("广播通道已关闭");
@@ -838,11 +1831,13 @@ async fn send_initial_data(
     socket: &mut WebSocket,
     state: &Arc<AppState>,
 ) -> Result<(), anyhow::Error> {
-    let db = state.database.lock().await;
+    let db = &state.database;
     
     // 发送节点列表
     match crate::models::Node::find_all(&db.pool).await {
         Ok(nodes) => {
+            let stale_minutes = state.config.node_stale_minutes;
+            let nodes: Vec<_> = nodes.into_iter().map(|node| node.with_liveness(stale_minutes)).collect();
             let nodes_msg = json!({
                 "type": "nodes_update",
                 "id": Uuid::new_v4().to_string(),
@@ -881,12 +1876,100 @@ async fn send_initial_data(
     Ok(())
 }
 
+/// 监控客户端订阅的节点，未订阅任何节点时接收所有节点的消息
+#[derive(Debug, Default)]
+struct ClientSubscription {
+    node_ids: HashSet<String>,
+}
+
+impl ClientSubscription {
+    /// 是否需要向客户端转发该广播消息，只有指标、状态变化、重启和命令实时输出消息按节点过滤
+    fn wants(&self, msg: &ClientBroadcastMessage) -> bool {
+        if self.node_ids.is_empty() {
+            return true;
+        }
+
+        match msg.message_type.as_str() {
+            "metrics_update" => msg.data["metrics"]
+                .as_array()
+                .is_some_and(|metrics| metrics.iter().any(|metric| self.contains(&metric["node_id"]))),
+            "node_status_change" | "node_reboot" | "anomaly" | "command_output" => self.contains(&msg.data["node_id"]),
+            _ => true,
+        }
+    }
+
+    fn contains(&self, node_id: &serde_json::Value) -> bool {
+        node_id.as_str().is_some_and(|node_id| self.node_ids.contains(node_id))
+    }
+
+    /// 按节点ID排序的订阅列表
+    fn sorted_node_ids(&self) -> BTreeSet<&str> {
+        self.node_ids.iter().map(String::as_str).collect()
+    }
+}
+
+/// 订阅/取消订阅请求
+#[derive(Debug, Deserialize)]
+struct SubscriptionData {
+    #[serde(default)]
+    node_ids: Vec<String>,
+}
+
+/// 处理客户端订阅变更，unsubscribe不指定节点时取消全部订阅
+async fn handle_client_subscription(
+    msg: WebSocketMessage,
+    socket: &mut WebSocket,
+    client_id: &str,
+    subscription: &mut ClientSubscription,
+) -> Result<(), anyhow::Error> {
+    let request: SubscriptionData = match serde_json::from_value(msg.data) {
+        Ok(request) => request,
+        Err(e) => {
+            return Ok(send_error_response(socket, &msg.id, "INVALID_SUBSCRIPTION", "订阅请求格式错误", &e.to_string()).await?);
+        }
+    };
+
+    let node_ids = request
+        .node_ids
+        .into_iter()
+        .map(|node_id| node_id.trim().to_string())
+        .filter(|node_id| !node_id.is_empty());
+
+    if msg.message_type == "subscribe" {
+        subscription.node_ids.extend(node_ids);
+    } else {
+        let node_ids: Vec<String> = node_ids.collect();
+        if node_ids.is_empty() {
+            subscription.node_ids.clear();
+        } else {
+            for node_id in &node_ids {
+                subscription.node_ids.remove(node_id);
+            }
+        }
+    }
+
+    info!("📌 客户端 {} 订阅节点: {:?}", client_id, subscription.sorted_node_ids());
+
+    let response = json!({
+        "type": "subscription_response",
+        "id": msg.id,
+        "timestamp": chrono::Utc::now().to_rfc3339(),
+        "data": {
+            "node_ids": subscription.sorted_node_ids(),
+            "all_nodes": subscription.node_ids.is_empty()
+        }
+    });
+    socket.send(Message::Text(response.to_string().into())).await?;
+    Ok(())
+}
+
 /// 处理客户端消息
 async fn handle_client_message(
     text: &str,
     socket: &mut WebSocket,
-    _state: &Arc<AppState>,
+    state: &Arc<AppState>,
     client_id: &str,
+    subscription: &mut ClientSubscription,
 ) -> Result<(), anyhow::Error> {
     info!("📨 收到客户端消息 from {}: {}", client_id, text);
     
@@ -923,6 +2006,12 @@ async fn handle_client_message(
             socket.send(Message::Text(pong_msg.to_string().into())).await?;
             info!("💓 响应客户端心跳: {}", client_id);
         }
+        "get_history" => {
+            handle_client_history_request(msg, socket, state).await?;
+        }
+        "subscribe" | "unsubscribe" => {
+            handle_client_subscription(msg, socket, client_id, subscription).await?;
+        }
         _ => {
             let error_msg = json!({
                 "type": "error",
@@ -931,7 +2020,7 @@ async fn handle_client_message(
                 "data": {
                     "error_code": "UNKNOWN_MESSAGE_TYPE",
                     "message": format!("未知的消息类型: {}", msg.message_type),
-                    "details": "支持的消息类型: ping"
+                    "details": format!("支持的消息类型: {}", CLIENT_MESSAGE_TYPES.join(", "))
                 }
             });
             socket.send(Message::Text(error_msg.to_string().into())).await?;
@@ -940,3 +2029,401 @@ async fn handle_client_message(
     
     Ok(())
 }
+
+/// 单次历史数据请求默认返回的条数
+const HISTORY_DEFAULT_LIMIT: i64 = 100;
+/// 单次历史数据请求最多返回的条数
+const HISTORY_MAX_LIMIT: i64 = 1000;
+
+/// 客户端历史监控数据请求
+#[derive(Debug, Deserialize)]
+struct HistoryRequestData {
+    node_id: String,
+    start_time: Option<DateTime<Utc>>,
+    end_time: Option<DateTime<Utc>>,
+    limit: Option<i64>,
+    offset: Option<i64>,
+}
+
+impl HistoryRequestData {
+    /// 规范化后的分页参数
+    fn page(&self) -> (i64, i64) {
+        let limit = self.limit.unwrap_or(HISTORY_DEFAULT_LIMIT).clamp(1, HISTORY_MAX_LIMIT);
+        let offset = self.offset.unwrap_or(0).max(0);
+        (limit, offset)
+    }
+}
+
+/// 处理客户端的历史监控数据请求，结果以history_response返回，消息ID与请求一致
+async fn handle_client_history_request(
+    msg: WebSocketMessage,
+    socket: &mut WebSocket,
+    state: &Arc<AppState>,
+) -> Result<(), anyhow::Error> {
+    let request: HistoryRequestData = match serde_json::from_value(msg.data) {
+        Ok(request) => request,
+        Err(e) => {
+            return Ok(send_error_response(socket, &msg.id, "INVALID_HISTORY_REQUEST", "历史数据请求格式错误", &e.to_string()).await?);
+        }
+    };
+
+    let db = &state.database;
+
+    match crate::models::Node::find_by_node_id(&db.pool, &request.node_id).await {
+        Ok(Some(_)) => {}
+        Ok(None) => {
+            return Ok(send_error_response(socket, &msg.id, "NODE_NOT_FOUND", "节点不存在", &request.node_id).await?);
+        }
+        Err(e) => {
+            error!("查询节点失败: {}", e);
+            return Ok(send_error_response(socket, &msg.id, "HISTORY_QUERY_FAILED", "获取监控数据失败", &e.to_string()).await?);
+        }
+    }
+
+    let (limit, offset) = request.page();
+
+    match NodeMetric::find_by_node_id_with_range(
+        &db.pool,
+        &request.node_id,
+        request.start_time,
+        request.end_time,
+        limit,
+        offset
+    ).await {
+        Ok((metrics, total)) => {
+            let response = json!({
+                "type": "history_response",
+                "id": msg.id,
+                "timestamp": chrono::Utc::now().to_rfc3339(),
+                "data": {
+                    "node_id": request.node_id,
+                    "metrics": metrics,
+                    "total": total,
+                    "limit": limit,
+                    "offset": offset
+                }
+            });
+            socket.send(Message::Text(response.to_string().into())).await?;
+            debug!("📈 向客户端返回历史数据: {} ({}条)", request.node_id, metrics.len());
+            Ok(())
+        }
+        Err(e) => {
+            error!("获取监控历史数据失败: {}", e);
+            Ok(send_error_response(socket, &msg.id, "HISTORY_QUERY_FAILED", "获取监控数据失败", &e.to_string()).await?)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metric_data(value: serde_json::Value) -> MetricData {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn test_validate_batch_entry() {
+        // 有效样本返回其采集时间
+        let valid = metric_data(json!({
+            "cpu_usage": 12.5,
+            "memory_total": 1024.0,
+            "timestamp": "2025-01-21T10:00:00Z"
+        }));
+        let metric_time = validate_batch_entry(&valid).unwrap();
+        assert_eq!(metric_time.to_rfc3339(), "2025-01-21T10:00:00+00:00");
+        
+        // 缺少时间戳
+        let missing_time = metric_data(json!({ "cpu_usage": 12.5 }));
+        assert!(validate_batch_entry(&missing_time).is_err());
+        
+        // 明显超前的时间戳
+        let future = metric_data(json!({
+            "cpu_usage": 12.5,
+            "timestamp": (Utc::now() + chrono::Duration::days(1)).to_rfc3339()
+        }));
+        assert!(validate_batch_entry(&future).is_err());
+        
+        // 无效数值不影响整条样本，交由sanitize丢弃对应字段
+        let mut negative = metric_data(json!({
+            "cpu_usage": 12.5,
+            "memory_available": -1.0,
+            "timestamp": "2025-01-21T10:00:00Z"
+        }));
+        assert!(validate_batch_entry(&negative).is_ok());
+        let issues = negative.sanitize();
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].field, "memory_available");
+        assert_eq!(negative.cpu_usage, Some(12.5));
+        assert_eq!(negative.memory_available, None);
+    }
+
+    #[test]
+    fn test_sanitize_metric_data() {
+        let mut valid = metric_data(json!({
+            "cpu_usage": 100.0,
+            "memory_usage": 0.0,
+            "memory_total": 1024.0,
+            "memory_available": 1024.0,
+            "uptime": 60.0
+        }));
+        assert!(valid.sanitize().is_empty());
+
+        let mut invalid = metric_data(json!({
+            "cpu_usage": 5000.0,
+            "memory_usage": 100.4,
+            "disk_usage": -3.0,
+            "load_average": 1.5,
+            "memory_total": 1024.0,
+            "memory_available": 2048.0,
+            "network_rx_bytes": -10.0
+        }));
+        let issues = invalid.sanitize();
+        let summary: Vec<(&str, &str)> = issues.iter().map(|issue| (issue.field, issue.action)).collect();
+        assert_eq!(summary, vec![
+            ("cpu_usage", "dropped"),
+            ("memory_usage", "clamped"),
+            ("disk_usage", "dropped"),
+            ("network_rx_bytes", "dropped"),
+            ("memory_available", "dropped"),
+        ]);
+        assert_eq!(issues[0].value, 5000.0);
+
+        // 有效字段照常保存
+        let metric = invalid.into_metric_create("node-1", None);
+        assert_eq!(metric.cpu_usage, None);
+        assert_eq!(metric.memory_usage, Some(100.0));
+        assert_eq!(metric.disk_usage, None);
+        assert_eq!(metric.load_average, Some(1.5));
+        assert_eq!(metric.memory_total, Some(1024));
+        assert_eq!(metric.memory_available, None);
+        assert_eq!(metric.network_rx_bytes, None);
+    }
+
+    #[test]
+    fn test_decompress_message() {
+        use std::io::Write;
+
+        let gzip = |data: &[u8]| {
+            let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+            encoder.write_all(data).unwrap();
+            encoder.finish().unwrap()
+        };
+
+        let text = r#"{"type":"heartbeat","id":"1","timestamp":"2025-01-21T10:00:00Z","data":{}}"#;
+        assert_eq!(decompress_message(&gzip(text.as_bytes()), 1024).unwrap(), text);
+
+        // 非gzip数据和解压后超限的数据都被拒绝
+        assert!(decompress_message(text.as_bytes(), 1024).is_err());
+        assert!(decompress_message(&gzip(&[b' '; 1025]), 1024).is_err());
+    }
+
+    #[test]
+    fn test_node_payload_decode() {
+        let message = json!({
+            "type": "metrics",
+            "id": "1",
+            "timestamp": "2025-01-21T10:00:00Z",
+            "data": { "node_id": "node-1", "cpu_usage": 12.5, "memory_total": 8_589_934_592u64 }
+        });
+
+        let packed = NodePayload::MessagePack(rmp_serde::to_vec_named(&message).unwrap());
+        let decoded = packed.decode().unwrap();
+        assert_eq!(decoded.message_type, "metrics");
+        assert_eq!(decoded.data, message["data"]);
+        assert!(packed.size() < message.to_string().len());
+
+        let text = NodePayload::Json(message.to_string());
+        assert_eq!(text.decode().unwrap().data, message["data"]);
+
+        // JSON文本不是有效的MessagePack消息
+        assert!(NodePayload::MessagePack(message.to_string().into_bytes()).decode().is_err());
+
+        // 未协商MessagePack时，二进制帧按gzip压缩或UTF-8编码的JSON解析
+        let binary = NodePayload::from_binary(message.to_string().as_bytes(), false, 1024).unwrap();
+        assert_eq!(binary.decode().unwrap().data, message["data"]);
+        assert!(NodePayload::from_binary(&[0xff, 0xfe], false, 1024).is_err());
+        assert!(matches!(
+            NodePayload::from_binary(message.to_string().as_bytes(), true, 1024).unwrap(),
+            NodePayload::MessagePack(_)
+        ));
+    }
+
+    #[test]
+    fn test_metric_data_into_metric_create() {
+        let data = metric_data(json!({
+            "cpu_usage": 12.5,
+            "memory_total": 2048.0,
+            "disk_available": 300.7,
+            "uptime": 3600.0
+        }));
+        let metric = data.into_metric_create("node-1", None);
+        assert_eq!(metric.node_id, "node-1");
+        assert_eq!(metric.cpu_usage, Some(12.5));
+        assert_eq!(metric.memory_total, Some(2048));
+        assert_eq!(metric.disk_available, Some(300));
+        assert_eq!(metric.uptime, Some(3600));
+        assert!(metric.load_average.is_none());
+        assert!(metric.metric_time.is_none());
+    }
+
+    #[test]
+    fn test_resolve_client_ip() {
+        let peer: SocketAddr = "10.0.0.5:41000".parse().unwrap();
+        let ip = |value: &str| value.parse::<IpAddr>().unwrap();
+        let mut headers = HeaderMap::new();
+        headers.insert("x-forwarded-for", "1.2.3.4, 203.0.113.7".parse().unwrap());
+
+        // 未启用trust_proxy时忽略请求头，避免节点伪造地址
+        assert_eq!(resolve_client_ip(&headers, peer, false), ip("10.0.0.5"));
+        // X-Forwarded-For使用代理追加的最后一项
+        assert_eq!(resolve_client_ip(&headers, peer, true), ip("203.0.113.7"));
+
+        headers.insert("x-real-ip", "198.51.100.9".parse().unwrap());
+        assert_eq!(resolve_client_ip(&headers, peer, true), ip("198.51.100.9"));
+
+        // 请求头无法解析时回退到TCP对端地址
+        let mut invalid = HeaderMap::new();
+        invalid.insert("x-real-ip", "unknown".parse().unwrap());
+        assert_eq!(resolve_client_ip(&invalid, peer, true), ip("10.0.0.5"));
+
+        let mapped: SocketAddr = "[::ffff:10.0.0.5]:41000".parse().unwrap();
+        assert_eq!(resolve_client_ip(&HeaderMap::new(), mapped, false), ip("10.0.0.5"));
+    }
+
+    #[test]
+    fn test_incoming_message_from_parts() {
+        let register = IncomingMessage::from_parts("node_register", json!({
+            "node_id": "node-1",
+            "hostname": "server-01",
+            "ip_address": "10.0.0.1",
+            "agent_version": "0.1.0"
+        })).unwrap();
+        assert_eq!(register.node_id(), Some("node-1"));
+        assert!(matches!(register, IncomingMessage::NodeRegister(ref data) if data.agent_version.as_deref() == Some("0.1.0")));
+
+        // 心跳的监控数据位于metrics字段，metrics消息的监控数据直接位于data中
+        let heartbeat = IncomingMessage::from_parts("heartbeat", json!({
+            "node_id": "node-1",
+            "status": "online",
+            "metrics": { "cpu_usage": 12.5, "uptime": 3600 }
+        })).unwrap();
+        assert!(matches!(heartbeat, IncomingMessage::Heartbeat(HeartbeatData { metrics: Some(ref m), .. }) if m.cpu_usage == Some(12.5)));
+        let metrics = IncomingMessage::from_parts("metrics", json!({ "cpu_usage": 40 })).unwrap();
+        assert_eq!(metrics.node_id(), None);
+        assert!(matches!(metrics, IncomingMessage::Metrics(ref data) if data.metric.cpu_usage == Some(40.0)));
+
+        let result = IncomingMessage::from_parts("command_result", json!({
+            "node_id": "node-1",
+            "command_id": "cmd-1",
+            "status": "timeout",
+            "stdout": "",
+            "exit_code": null,
+            "execution_time_ms": 30000
+        })).unwrap();
+        match result {
+            IncomingMessage::CommandResult(data) => {
+                assert_eq!(data.status.as_deref(), Some("timeout"));
+                assert_eq!(data.result.command_id, "cmd-1");
+                assert_eq!(data.result.execution_time_ms, Some(30000));
+            }
+            other => panic!("unexpected message: {:?}", other),
+        }
+
+        let output = IncomingMessage::from_parts("command_output", json!({
+            "node_id": "node-1",
+            "command_id": "cmd-1",
+            "seq": 3,
+            "stream": "stderr",
+            "data": "warning\n"
+        })).unwrap();
+        assert!(matches!(output, IncomingMessage::CommandOutput(ref data) if data.seq == 3 && data.stream == "stderr"));
+
+        // 格式错误的载荷不再静默回退为空值
+        assert!(IncomingMessage::from_parts("heartbeat", json!({ "metrics": { "cpu_usage": "high" } })).is_err());
+        assert!(IncomingMessage::from_parts("node_register", json!({ "node_id": "node-1" })).is_err());
+        assert!(IncomingMessage::from_parts("metrics_batch", json!({})).is_err());
+        assert!(IncomingMessage::from_parts("unknown", json!({})).is_err());
+        for message_type in NODE_MESSAGE_TYPES {
+            let source = IncomingMessage::from_parts(message_type, json!(null)).unwrap_err();
+            let error = WebSocketError::InvalidData { message_type: message_type.to_string(), source };
+            assert_ne!(error.error_code(), "INVALID_MESSAGE_DATA");
+        }
+    }
+
+    #[test]
+    fn test_negotiate_protocol_version() {
+        // 未声明版本的旧节点按v1处理
+        assert_eq!(negotiate_protocol_version(None), Ok(1));
+        assert_eq!(negotiate_protocol_version(Some(PROTOCOL_VERSION)), Ok(PROTOCOL_VERSION));
+        // 较新的客户端降级到Core支持的最高版本
+        assert_eq!(negotiate_protocol_version(Some(PROTOCOL_VERSION + 1)), Ok(PROTOCOL_VERSION));
+        assert!(negotiate_protocol_version(Some(0)).is_err());
+    }
+
+    #[test]
+    fn test_server_capabilities() {
+        let node = serde_json::to_value(ServerCapabilities::for_node()).unwrap();
+        assert_eq!(node["protocol_version"], PROTOCOL_VERSION);
+        assert_eq!(node["server_version"], env!("CARGO_PKG_VERSION"));
+        assert!(node["accepts"].as_array().unwrap().contains(&json!("metrics_batch")));
+        assert!(node["sends"].as_array().unwrap().contains(&json!("command")));
+        assert!(node["sends"].as_array().unwrap().contains(&json!("command_received")));
+        assert_eq!(node["compression"], json!(["gzip"]));
+        assert_eq!(node["encodings"], json!(["json", "msgpack"]));
+
+        let monitor = serde_json::to_value(ServerCapabilities::for_monitor()).unwrap();
+        assert!(monitor["accepts"].as_array().unwrap().contains(&json!("get_history")));
+        assert_eq!(monitor["compression"], json!([]));
+        assert_eq!(monitor["encodings"], json!(["json"]));
+    }
+
+    #[test]
+    fn test_history_request_data() {
+        let request: HistoryRequestData = serde_json::from_value(json!({
+            "node_id": "node-1",
+            "start_time": "2025-01-21T10:00:00Z"
+        })).unwrap();
+        assert_eq!(request.start_time.unwrap().to_rfc3339(), "2025-01-21T10:00:00+00:00");
+        assert!(request.end_time.is_none());
+        assert_eq!(request.page(), (HISTORY_DEFAULT_LIMIT, 0));
+
+        // 分页参数限制在允许范围内
+        let request: HistoryRequestData = serde_json::from_value(json!({
+            "node_id": "node-1",
+            "limit": 100000,
+            "offset": -5
+        })).unwrap();
+        assert_eq!(request.page(), (HISTORY_MAX_LIMIT, 0));
+
+        // 时间格式错误或缺少node_id时拒绝
+        assert!(serde_json::from_value::<HistoryRequestData>(json!({ "node_id": "node-1", "start_time": "yesterday" })).is_err());
+        assert!(serde_json::from_value::<HistoryRequestData>(json!({ "start_time": "2025-01-21T10:00:00Z" })).is_err());
+    }
+
+    #[test]
+    fn test_client_subscription_filter() {
+        let message = |message_type: &str, data: serde_json::Value| ClientBroadcastMessage {
+            message_type: message_type.to_string(),
+            id: "id".to_string(),
+            timestamp: "2025-01-21T10:00:00Z".to_string(),
+            data,
+        };
+        let metrics = message("metrics_update", json!({ "metrics": [{ "node_id": "node-1" }] }));
+        let status = message("node_status_change", json!({ "node_id": "node-2", "status": "offline" }));
+        let alert = message("alert_fired", json!({ "node_id": "node-2" }));
+        let output = message("command_output", json!({ "node_id": "node-2", "command_id": "cmd-1", "seq": 1 }));
+
+        // 未订阅时接收所有消息
+        let mut subscription = ClientSubscription::default();
+        assert!(subscription.wants(&metrics));
+        assert!(subscription.wants(&status));
+
+        subscription.node_ids.insert("node-1".to_string());
+        assert!(subscription.wants(&metrics));
+        assert!(!subscription.wants(&status));
+        assert!(!subscription.wants(&output));
+        // 其他类型的消息不按节点过滤
+        assert!(subscription.wants(&alert));
+    }
+}
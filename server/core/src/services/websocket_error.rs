@@ -0,0 +1,173 @@
+/// 处理节点消息时失败的数据库操作，决定回复给节点的错误码
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DbOperation {
+    CheckNode,
+    CreateNode,
+    UpdateNode,
+    SaveMetrics,
+    SaveMetricsBatch,
+    CheckCommand,
+    SaveCommandResult,
+}
+
+impl DbOperation {
+    /// 错误码和错误信息
+    fn error(self) -> (&'static str, &'static str) {
+        match self {
+            DbOperation::CheckNode => ("CHECK_NODE_FAILED", "检查节点失败"),
+            DbOperation::CreateNode => ("CREATE_NODE_FAILED", "创建节点失败"),
+            DbOperation::UpdateNode => ("UPDATE_NODE_FAILED", "更新节点失败"),
+            DbOperation::SaveMetrics => ("SAVE_METRICS_FAILED", "保存监控数据失败"),
+            DbOperation::SaveMetricsBatch => ("SAVE_METRICS_FAILED", "保存批量监控数据失败"),
+            DbOperation::CheckCommand => ("CHECK_COMMAND_FAILED", "查询命令失败"),
+            DbOperation::SaveCommandResult => ("SAVE_COMMAND_RESULT_FAILED", "保存命令结果失败"),
+        }
+    }
+}
+
+/// 节点WebSocket消息处理错误
+///
+/// 除发送失败外，所有错误都以`error`消息回复给节点，`Display`输出作为错误详情
+#[derive(Debug, thiserror::Error)]
+pub enum WebSocketError {
+    /// 消息超过大小上限
+    #[error("消息大小{size}字节，上限{max}字节")]
+    MessageTooLarge { size: usize, max: usize },
+    /// 二进制消息无法解压或不是支持的格式
+    #[error("{0}")]
+    InvalidBinary(#[source] anyhow::Error),
+    /// 消息不是有效的JSON或MessagePack，回复后断开连接
+    #[error("{0}")]
+    Parse(#[source] anyhow::Error),
+    /// 不支持的消息类型
+    #[error("支持的消息类型: {supported}")]
+    UnknownMessageType { message_type: String, supported: String },
+    /// 消息载荷不符合对应消息类型的格式
+    #[error("{source}")]
+    InvalidData {
+        message_type: String,
+        #[source]
+        source: serde_json::Error,
+    },
+    /// 消息发送过于频繁
+    #[error("每秒最多{limit}条消息")]
+    RateLimited { limit: u32 },
+    /// 批量消息的样本数超过上限
+    #[error("单次最多{max}条，实际{actual}条")]
+    BatchTooLarge { actual: usize, max: usize },
+    /// 节点尚未注册，回复时同时要求节点重新注册
+    #[error("{0}")]
+    NodeNotRegistered(String),
+    /// 消息中的节点ID与连接认证的节点ID不一致
+    #[error("连接节点: {connection}, 消息节点: {message}")]
+    NodeIdMismatch { connection: String, message: String },
+    /// 命令不存在或不属于该节点
+    #[error("{0}")]
+    CommandNotFound(String),
+    /// 数据库操作失败
+    #[error("{source}")]
+    Database {
+        operation: DbOperation,
+        #[source]
+        source: anyhow::Error,
+    },
+    /// 向对端发送消息失败，连接已不可用
+    #[error("发送消息失败: {0}")]
+    Send(#[from] axum::Error),
+}
+
+impl WebSocketError {
+    /// 数据库操作失败
+    pub fn database(operation: DbOperation, source: anyhow::Error) -> Self {
+        WebSocketError::Database { operation, source }
+    }
+
+    /// 回复给节点的错误码
+    pub fn error_code(&self) -> &'static str {
+        match self {
+            WebSocketError::MessageTooLarge { .. } => "MESSAGE_TOO_LARGE",
+            WebSocketError::InvalidBinary(_) => "DECOMPRESS_ERROR",
+            WebSocketError::Parse(_) => "PARSE_ERROR",
+            WebSocketError::UnknownMessageType { .. } => "UNKNOWN_MESSAGE_TYPE",
+            WebSocketError::InvalidData { message_type, .. } => invalid_data_error(message_type).0,
+            WebSocketError::RateLimited { .. } => "RATE_LIMITED",
+            WebSocketError::BatchTooLarge { .. } => "BATCH_TOO_LARGE",
+            WebSocketError::NodeNotRegistered(_) => "NODE_NOT_REGISTERED",
+            WebSocketError::NodeIdMismatch { .. } => "NODE_ID_MISMATCH",
+            WebSocketError::CommandNotFound(_) => "COMMAND_NOT_FOUND",
+            WebSocketError::Database { operation, .. } => operation.error().0,
+            WebSocketError::Send(_) => "SEND_FAILED",
+        }
+    }
+
+    /// 回复给节点的错误信息
+    pub fn message(&self) -> String {
+        match self {
+            WebSocketError::MessageTooLarge { .. } => "消息过大".to_string(),
+            WebSocketError::InvalidBinary(_) => "二进制消息解析失败".to_string(),
+            WebSocketError::Parse(_) => "消息解析失败".to_string(),
+            WebSocketError::UnknownMessageType { message_type, .. } => format!("未知的消息类型: {}", message_type),
+            WebSocketError::InvalidData { message_type, .. } => invalid_data_error(message_type).1.to_string(),
+            WebSocketError::RateLimited { .. } => "消息发送过于频繁，已丢弃".to_string(),
+            WebSocketError::BatchTooLarge { .. } => "批量监控数据过多".to_string(),
+            WebSocketError::NodeNotRegistered(_) => "节点未注册，请先发送node_register消息".to_string(),
+            WebSocketError::NodeIdMismatch { .. } => "消息中的节点ID与连接的节点ID不一致".to_string(),
+            WebSocketError::CommandNotFound(_) => "命令不存在".to_string(),
+            WebSocketError::Database { operation, .. } => operation.error().1.to_string(),
+            WebSocketError::Send(_) => "发送消息失败".to_string(),
+        }
+    }
+
+    /// 是否需要断开连接：发送失败说明连接已不可用，无法解析的消息说明节点协议异常
+    pub fn is_fatal(&self) -> bool {
+        matches!(self, WebSocketError::Send(_) | WebSocketError::Parse(_))
+    }
+}
+
+/// 载荷格式错误时回复的错误码和错误信息
+fn invalid_data_error(message_type: &str) -> (&'static str, &'static str) {
+    match message_type {
+        "node_register" => ("INVALID_REGISTER_DATA", "注册数据格式错误"),
+        "node_deregister" => ("INVALID_DEREGISTER_DATA", "注销数据格式错误"),
+        "heartbeat" => ("INVALID_HEARTBEAT_DATA", "心跳数据格式错误"),
+        "metrics" => ("INVALID_METRIC_DATA", "监控数据格式错误"),
+        "metrics_batch" => ("INVALID_METRIC_DATA", "批量监控数据格式错误"),
+        "command_output" => ("INVALID_COMMAND_OUTPUT", "命令输出格式错误"),
+        "command_result" => ("INVALID_COMMAND_RESULT", "命令结果格式错误"),
+        _ => ("INVALID_MESSAGE_DATA", "消息数据格式错误"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_codes() {
+        let source = serde_json::from_str::<u32>("\"x\"").unwrap_err();
+        let invalid = WebSocketError::InvalidData { message_type: "heartbeat".to_string(), source };
+        assert_eq!(invalid.error_code(), "INVALID_HEARTBEAT_DATA");
+        assert_eq!(invalid.message(), "心跳数据格式错误");
+        assert!(!invalid.is_fatal());
+
+        let db = WebSocketError::database(DbOperation::SaveMetricsBatch, anyhow::anyhow!("database is locked"));
+        assert_eq!(db.error_code(), "SAVE_METRICS_FAILED");
+        assert_eq!(db.message(), "保存批量监控数据失败");
+        assert_eq!(db.to_string(), "database is locked");
+
+        let unknown = WebSocketError::UnknownMessageType {
+            message_type: "ping".to_string(),
+            supported: "heartbeat".to_string(),
+        };
+        assert_eq!(unknown.error_code(), "UNKNOWN_MESSAGE_TYPE");
+        assert_eq!(unknown.message(), "未知的消息类型: ping");
+        assert_eq!(unknown.to_string(), "支持的消息类型: heartbeat");
+
+        assert!(WebSocketError::Parse(anyhow::anyhow!("bad")).is_fatal());
+        assert_eq!(WebSocketError::NodeNotRegistered("n".to_string()).error_code(), "NODE_NOT_REGISTERED");
+
+        let mismatch = WebSocketError::NodeIdMismatch { connection: "a".to_string(), message: "b".to_string() };
+        assert_eq!(mismatch.error_code(), "NODE_ID_MISMATCH");
+        assert!(!mismatch.is_fatal());
+    }
+}
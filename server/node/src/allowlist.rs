@@ -0,0 +1,103 @@
+use anyhow::Result;
+use regex::Regex;
+
+/// 以`sh -c`执行时可以串联或替换出其他命令的字符，按程序名放行的命令不能包含这些字符
+const SHELL_CONTROL_CHARS: &[char] = &[';', '&', '|', '<', '>', '$', '`', '(', ')', '\n', '\r'];
+
+/// 允许远程执行的命令（`advanced.allowed_commands`），为空时拒绝所有命令
+///
+/// 只包含字母、数字和`._/+-`的条目视为程序名，命令的第一个词与其完全相同且不含shell控制字符时放行；
+/// 其他条目视为正则表达式，需要匹配完整的命令（如`systemctl status [\w.-]+`，`.*`表示不限制）
+#[derive(Debug, Clone, Default)]
+pub struct CommandAllowlist {
+    programs: Vec<String>,
+    patterns: Vec<Regex>,
+}
+
+impl CommandAllowlist {
+    /// 解析配置中的允许列表，正则表达式无效时返回错误
+    pub fn new(entries: &[String]) -> Result<Self> {
+        let mut allowlist = Self::default();
+        for entry in entries.iter().map(|entry| entry.trim()).filter(|entry| !entry.is_empty()) {
+            if is_program_name(entry) {
+                allowlist.programs.push(entry.to_string());
+            } else {
+                let pattern = Regex::new(&format!("^(?:{})$", entry))
+                    .map_err(|e| anyhow::anyhow!("\"{}\" 不是有效的正则表达式: {}", entry, e))?;
+                allowlist.patterns.push(pattern);
+            }
+        }
+        Ok(allowlist)
+    }
+
+    /// 命令是否允许执行
+    pub fn permits(&self, command: &str) -> bool {
+        let command = command.trim();
+        if self.patterns.iter().any(|pattern| pattern.is_match(command)) {
+            return true;
+        }
+        if command.contains(SHELL_CONTROL_CHARS) {
+            return false;
+        }
+        command
+            .split_whitespace()
+            .next()
+            .is_some_and(|program| self.programs.iter().any(|allowed| allowed == program))
+    }
+}
+
+fn is_program_name(entry: &str) -> bool {
+    entry
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '/' | '+' | '-'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn allowlist(entries: &[&str]) -> CommandAllowlist {
+        CommandAllowlist::new(&entries.iter().map(|entry| entry.to_string()).collect::<Vec<_>>()).unwrap()
+    }
+
+    #[test]
+    fn test_command_allowlist() {
+        // 未配置时拒绝所有命令
+        assert!(!allowlist(&[]).permits("uptime"));
+
+        let allowlist = allowlist(&["uptime", "df", r"systemctl status [\w.-]+"]);
+        assert!(allowlist.permits("uptime"));
+        assert!(allowlist.permits("  df -h /"));
+        assert!(!allowlist.permits("uptimex"));
+        assert!(!allowlist.permits("/tmp/uptime"));
+        // 不能通过shell串联或替换执行其他命令
+        assert!(!allowlist.permits("df -h; rm -rf /"));
+        assert!(!allowlist.permits("df $(rm -rf /)"));
+        assert!(!allowlist.permits("uptime && reboot"));
+
+        // 正则表达式需要匹配完整的命令
+        assert!(allowlist.permits("systemctl status nginx.service"));
+        assert!(!allowlist.permits("systemctl status nginx; reboot"));
+        assert!(!allowlist.permits("systemctl restart nginx"));
+
+        assert!(CommandAllowlist::new(&[".*".to_string()]).unwrap().permits("anything | goes"));
+        assert!(CommandAllowlist::new(&["systemctl (".to_string()]).is_err());
+    }
+
+    #[test]
+    fn test_default_config_allowlist() {
+        // 随节点发布的默认配置只允许只读命令，不能修改系统时间或主机名
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/config/default.toml");
+        let config = crate::config::NodeConfig::load(Some(std::path::Path::new(path))).unwrap();
+        let defaults = CommandAllowlist::new(&config.advanced.allowed_commands).unwrap();
+        assert!(defaults.permits("uptime"));
+        assert!(defaults.permits("df -h"));
+        assert!(!defaults.permits("date -s 2000-01-01"));
+        assert!(!defaults.permits("hostname evil"));
+
+        // 以正则表达式限定完整命令时不接受其他参数
+        let hostname = allowlist(&["hostname( -f)?"]);
+        assert!(hostname.permits("hostname -f"));
+        assert!(!hostname.permits("hostname evil"));
+    }
+}
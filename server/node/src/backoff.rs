@@ -0,0 +1,83 @@
+use rand::Rng;
+use std::time::Duration;
+
+/// 指数退避重连策略（带随机抖动）
+#[derive(Debug, Clone)]
+pub struct Backoff {
+    base: Duration,
+    max: Duration,
+    attempt: u32,
+}
+
+impl Backoff {
+    /// 创建退避策略，`base`为首次重连间隔，`max`为间隔上限
+    pub fn new(base: Duration, max: Duration) -> Self {
+        Self {
+            base,
+            max: max.max(base),
+            attempt: 0,
+        }
+    }
+
+    /// 计算下一次重连前的等待时间，并递增尝试次数
+    ///
+    /// 间隔按`base * 2^attempt`增长并封顶于`max`，实际等待时间在该间隔的[1/2, 1]之间随机取值，
+    /// 避免大量节点同时重连
+    pub fn next_delay(&mut self) -> Duration {
+        let delay = self.current_ceiling();
+        self.attempt = self.attempt.saturating_add(1);
+
+        let half = delay / 2;
+        let jitter_ms = half.as_millis() as u64;
+        if jitter_ms == 0 {
+            return delay;
+        }
+        half + Duration::from_millis(rand::rng().random_range(0..=jitter_ms))
+    }
+
+    /// 已尝试次数
+    pub fn attempts(&self) -> u32 {
+        self.attempt
+    }
+
+    /// 连接成功后重置
+    pub fn reset(&mut self) {
+        self.attempt = 0;
+    }
+
+    /// 当前尝试次数对应的间隔上限（未加抖动）
+    fn current_ceiling(&self) -> Duration {
+        let factor = 2u32.saturating_pow(self.attempt.min(16));
+        self.base.saturating_mul(factor).min(self.max)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff_growth_and_cap() {
+        let mut backoff = Backoff::new(Duration::from_secs(1), Duration::from_secs(8));
+
+        let expected_ceilings = [1, 2, 4, 8, 8, 8];
+        for ceiling in expected_ceilings {
+            let delay = backoff.next_delay();
+            assert!(delay <= Duration::from_secs(ceiling));
+            assert!(delay >= Duration::from_secs(ceiling) / 2);
+        }
+        assert_eq!(backoff.attempts(), 6);
+    }
+
+    #[test]
+    fn test_backoff_reset() {
+        let mut backoff = Backoff::new(Duration::from_secs(1), Duration::from_secs(60));
+        for _ in 0..5 {
+            backoff.next_delay();
+        }
+
+        backoff.reset();
+        assert_eq!(backoff.attempts(), 0);
+        assert!(backoff.next_delay() <= Duration::from_secs(1));
+    }
+}
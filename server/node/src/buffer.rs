@@ -0,0 +1,121 @@
+use std::collections::VecDeque;
+
+use chrono::{DateTime, Utc};
+
+use crate::monitor::SystemMetrics;
+
+/// 带采集时间的监控样本
+#[derive(Debug, Clone)]
+pub struct BufferedMetrics {
+    pub timestamp: DateTime<Utc>,
+    pub metrics: SystemMetrics,
+}
+
+/// 断线期间的监控数据缓冲区（有界环形缓冲，满时丢弃最旧的样本）
+#[derive(Debug)]
+pub struct MetricsBuffer {
+    samples: VecDeque<BufferedMetrics>,
+    capacity: usize,
+}
+
+impl MetricsBuffer {
+    /// 创建指定容量的缓冲区，容量为0时不缓冲
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            samples: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    /// 缓存一个样本，返回是否因缓冲区已满而丢弃了最旧的样本
+    pub fn push(&mut self, timestamp: DateTime<Utc>, metrics: SystemMetrics) -> bool {
+        if self.capacity == 0 {
+            return true;
+        }
+
+        let dropped = if self.samples.len() >= self.capacity {
+            self.samples.pop_front();
+            true
+        } else {
+            false
+        };
+        self.samples.push_back(BufferedMetrics { timestamp, metrics });
+        dropped
+    }
+
+    /// 取出最早的最多`max`个样本
+    pub fn take_chunk(&mut self, max: usize) -> Vec<BufferedMetrics> {
+        let count = max.min(self.samples.len());
+        self.samples.drain(..count).collect()
+    }
+
+    /// 将发送失败的样本放回缓冲区头部（超出容量时丢弃最旧的部分）
+    pub fn restore(&mut self, chunk: Vec<BufferedMetrics>) {
+        for sample in chunk.into_iter().rev() {
+            if self.samples.len() >= self.capacity {
+                break;
+            }
+            self.samples.push_front(sample);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(cpu_usage: f64) -> SystemMetrics {
+        SystemMetrics {
+            cpu_usage,
+            cpu_usage_raw: cpu_usage,
+            memory_usage: 0.0,
+            memory_total: 0,
+            memory_available: 0,
+            disk_usage: None,
+            disk_total: None,
+            disk_available: None,
+            uptime: 0,
+            network_rx_bytes: 0,
+            network_tx_bytes: 0,
+            disk_read_bytes: None,
+            disk_write_bytes: None,
+        }
+    }
+
+    #[test]
+    fn test_buffer_drops_oldest_when_full() {
+        let mut buffer = MetricsBuffer::new(2);
+        assert!(!buffer.push(Utc::now(), sample(1.0)));
+        assert!(!buffer.push(Utc::now(), sample(2.0)));
+        assert!(buffer.push(Utc::now(), sample(3.0)));
+
+        let chunk = buffer.take_chunk(10);
+        let values: Vec<f64> = chunk.iter().map(|s| s.metrics.cpu_usage).collect();
+        assert_eq!(values, vec![2.0, 3.0]);
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn test_buffer_take_and_restore() {
+        let mut buffer = MetricsBuffer::new(5);
+        for i in 0..4 {
+            buffer.push(Utc::now(), sample(i as f64));
+        }
+
+        let chunk = buffer.take_chunk(3);
+        assert_eq!(chunk.len(), 3);
+        assert_eq!(buffer.len(), 1);
+
+        buffer.restore(chunk);
+        let values: Vec<f64> = buffer.take_chunk(10).iter().map(|s| s.metrics.cpu_usage).collect();
+        assert_eq!(values, vec![0.0, 1.0, 2.0, 3.0]);
+    }
+}
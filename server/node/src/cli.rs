@@ -0,0 +1,108 @@
+use clap::Parser;
+use std::path::PathBuf;
+
+use crate::config::NodeConfig;
+
+/// Server Manager 节点代理
+///
+/// 命令行参数优先于配置文件和环境变量
+#[derive(Debug, Default, Parser)]
+#[command(version = crate::VERSION, about)]
+pub struct Cli {
+    /// 配置文件路径，或包含default.toml的配置目录（默认: ./config/default.toml）
+    #[arg(short, long, value_name = "PATH")]
+    pub config: Option<PathBuf>,
+
+    /// 日志级别
+    #[arg(long, value_name = "LEVEL", value_parser = ["trace", "debug", "info", "warn", "error"])]
+    pub log_level: Option<String>,
+
+    /// 节点ID
+    #[arg(long, value_name = "ID")]
+    pub node_id: Option<String>,
+
+    /// Core服务地址，可重复指定或用逗号分隔多个地址
+    #[arg(long = "core-url", value_name = "URL", value_delimiter = ',')]
+    pub core_urls: Vec<String>,
+
+    /// 节点本地数据目录，日志文件等相对路径以此为基准（也可设置SM_NODE__ADVANCED__DATA_DIR）
+    #[arg(long, value_name = "DIR")]
+    pub data_dir: Option<PathBuf>,
+
+    /// 连接、注册、发送一次心跳后退出（也可设置SM_NODE_ONCE=1）
+    #[arg(long)]
+    pub once: bool,
+}
+
+impl Cli {
+    /// 加载配置并应用命令行覆盖，随后校验
+    pub fn load_config(&self) -> anyhow::Result<NodeConfig> {
+        let mut config = NodeConfig::load(self.config.as_deref())?;
+        self.apply_overrides(&mut config);
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// 用命令行参数覆盖配置
+    fn apply_overrides(&self, config: &mut NodeConfig) {
+        if let Some(level) = &self.log_level {
+            config.logging.level = level.clone();
+        }
+        if let Some(node_id) = &self.node_id {
+            config.core.node_id = Some(node_id.clone());
+        }
+        let urls: Vec<String> = self
+            .core_urls
+            .iter()
+            .map(|url| url.trim().to_string())
+            .filter(|url| !url.is_empty())
+            .collect();
+        if !urls.is_empty() {
+            config.core.urls = urls;
+        }
+        if let Some(data_dir) = &self.data_dir {
+            config.advanced.data_dir = Some(data_dir.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cli_overrides() {
+        let cli = Cli::try_parse_from([
+            "node",
+            "--config",
+            "/etc/server-manager/node.toml",
+            "--log-level",
+            "debug",
+            "--node-id",
+            "web-01",
+            "--core-url",
+            "ws://a:20002/api/v1/ws,ws://b:20002/api/v1/ws",
+            "--data-dir",
+            "/var/lib/sm-node",
+        ])
+        .unwrap();
+        assert_eq!(cli.config, Some(PathBuf::from("/etc/server-manager/node.toml")));
+
+        let mut config = NodeConfig::default();
+        cli.apply_overrides(&mut config);
+        assert_eq!(config.logging.level, "debug");
+        assert_eq!(config.core.node_id.as_deref(), Some("web-01"));
+        assert_eq!(config.core.urls, vec!["ws://a:20002/api/v1/ws", "ws://b:20002/api/v1/ws"]);
+        assert_eq!(config.advanced.data_dir, Some(PathBuf::from("/var/lib/sm-node")));
+
+        // 未指定的参数不覆盖配置
+        let defaults = NodeConfig::default();
+        let mut config = NodeConfig::default();
+        Cli::default().apply_overrides(&mut config);
+        assert_eq!(config.core.urls, defaults.core.urls);
+        assert_eq!(config.logging.level, defaults.logging.level);
+        assert_eq!(config.advanced.data_dir, None);
+
+        assert!(Cli::try_parse_from(["node", "--log-level", "verbose"]).is_err());
+    }
+}
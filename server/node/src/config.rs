@@ -1,8 +1,11 @@
 use anyhow::Result;
 use config::{Config, Environment, File};
-use serde::Deserialize;
-use std::path::PathBuf;
-use urlencoding;
+use serde::{Deserialize, Deserializer};
+use std::path::{Path, PathBuf};
+use tokio_tungstenite::tungstenite::http::Uri;
+
+use crate::allowlist::CommandAllowlist;
+use crate::run_as::RunAs;
 
 /// 节点配置
 #[derive(Debug, Deserialize, Clone)]
@@ -17,31 +20,90 @@ pub struct NodeConfig {
 /// Core服务配置
 #[derive(Debug, Deserialize, Clone)]
 pub struct CoreConfig {
-    pub url: String,
+    /// Core服务地址列表，按顺序尝试（兼容单个字符串，也可用逗号分隔多个地址）
+    #[serde(rename = "url", deserialize_with = "deserialize_urls")]
+    pub urls: Vec<String>,
+    /// 认证令牌（优先级：环境变量SM_NODE__CORE__TOKEN > token_file > 配置文件中的token）
+    #[serde(default)]
     pub token: String,
+    /// 从文件读取认证令牌（如挂载的密钥文件）
+    #[serde(default)]
+    pub token_file: Option<String>,
     pub node_id: Option<String>,
+    /// 自定义CA证书路径（PEM），设置后wss连接仅信任该CA
+    #[serde(default)]
+    pub ca_cert: Option<String>,
+}
+
+/// 认证令牌环境变量
+const TOKEN_ENV: &str = "SM_NODE__CORE__TOKEN";
+
+impl CoreConfig {
+    /// 确定最终使用的认证令牌：环境变量优先，其次令牌文件，最后是配置文件中的值
+    fn resolve_token(&mut self, env_token: Option<String>) -> Result<()> {
+        if let Some(token) = env_token.filter(|token| !token.trim().is_empty()) {
+            self.token = token;
+            return Ok(());
+        }
+
+        if let Some(path) = &self.token_file {
+            let contents = std::fs::read_to_string(path)
+                .map_err(|e| anyhow::anyhow!("读取令牌文件失败 {}: {}", path, e))?;
+            // 编辑器常会在文件末尾添加换行
+            self.token = contents.trim_end().to_string();
+        }
+
+        Ok(())
+    }
 }
 
 /// 监控配置
 #[derive(Debug, Deserialize, Clone)]
 pub struct MonitoringConfig {
+    /// 心跳间隔：期间未上报过监控数据时发送只表示在线的轻量心跳
     pub heartbeat_interval: u64,
+    /// 监控数据采集上报间隔，设置了metrics_every_heartbeats时不使用
     pub metrics_interval: u64,
-    pub detailed_metrics: bool,
+    /// 每N次心跳携带一次监控数据，0表示按metrics_interval独立上报
+    #[serde(default)]
+    pub metrics_every_heartbeats: u32,
+    /// CPU使用率平滑窗口：上报最近N次采样的平均值，1表示不平滑
+    #[serde(default = "default_cpu_smoothing_window")]
+    pub cpu_smoothing_window: usize,
+    /// 采集、心跳和ping定时器首次触发的最大随机推迟(秒)，不超过各自的周期，0表示不推迟
+    #[serde(default = "default_interval_jitter")]
+    pub interval_jitter: u64,
+}
+
+impl MonitoringConfig {
+    /// 第`heartbeat_count`次心跳（从0开始）是否携带监控数据，启用时首次心跳总是携带
+    pub fn heartbeat_carries_metrics(&self, heartbeat_count: u64) -> bool {
+        self.metrics_every_heartbeats > 0 && heartbeat_count.is_multiple_of(u64::from(self.metrics_every_heartbeats))
+    }
 }
 
 /// 系统配置
 #[derive(Debug, Deserialize, Clone)]
 pub struct SystemConfig {
+    /// 注册时上报的主机名，不设置时使用系统检测到的主机名
     pub hostname: Option<String>,
+    /// 是否在注册时上报操作系统、CPU和内存信息
     pub report_system_info: bool,
 }
 
+impl SystemConfig {
+    /// 配置的主机名（忽略空白值）
+    pub fn hostname_override(&self) -> Option<&str> {
+        self.hostname.as_deref().map(str::trim).filter(|hostname| !hostname.is_empty())
+    }
+}
+
 /// 日志配置
 #[derive(Debug, Deserialize, Clone)]
 pub struct LoggingConfig {
     pub level: String,
     pub file_enabled: bool,
+    /// 日志文件路径，相对路径以数据目录为基准
     pub file_path: String,
     pub console_enabled: bool,
 }
@@ -50,22 +112,160 @@ pub struct LoggingConfig {
 #[derive(Debug, Deserialize, Clone)]
 pub struct AdvancedConfig {
     pub reconnect_interval: u64,
+    /// 指数退避的最大重连间隔(秒)
+    #[serde(default = "default_max_reconnect_interval")]
+    pub max_reconnect_interval: u64,
+    /// 最大重试次数，0表示无限重试
     pub max_retries: u32,
     pub command_timeout: u64,
-    pub metrics_retention_days: u32,
+    /// 希望Core保留本节点监控数据的天数，注册时上报；未设置时使用Core的全局配置
+    #[serde(default)]
+    pub requested_retention_days: Option<u32>,
+    /// 已废弃：旧版配置文件默认写有该项，但从未生效，读取后仅用于提示迁移
+    #[serde(default)]
+    pub metrics_retention_days: Option<u32>,
+    /// 断线期间最多缓存的监控样本数，0表示不缓存
+    #[serde(default = "default_metrics_buffer_size")]
+    pub metrics_buffer_size: usize,
+    /// WebSocket ping发送间隔(秒)
+    #[serde(default = "default_ping_interval")]
+    pub ping_interval: u64,
+    /// 等待pong响应的超时时间(秒)，超时视为连接已断开
+    #[serde(default = "default_pong_timeout")]
+    pub pong_timeout: u64,
+    /// 是否请求压缩上报的消息（Core确认支持后才启用，旧版Core不受影响）
+    #[serde(default = "default_compression")]
+    pub compression: bool,
+    /// 上报消息的编码方式（Core确认支持后才启用，否则使用JSON）
+    #[serde(default)]
+    pub encoding: MessageEncoding,
+    /// 允许Core远程执行的命令（程序名或匹配完整命令的正则表达式），为空时拒绝所有命令
+    #[serde(default)]
+    pub allowed_commands: Vec<String>,
+    /// 执行远程命令使用的用户（用户名或UID），节点以root运行时用于降低命令权限
+    #[serde(default)]
+    pub run_as_user: Option<String>,
+    /// 节点本地数据目录，日志文件等相对路径以此为基准；未设置时使用当前工作目录
+    #[serde(default)]
+    pub data_dir: Option<PathBuf>,
+}
+
+impl AdvancedConfig {
+    /// 配置的命令执行用户（忽略空白值）
+    pub fn run_as_user(&self) -> Option<&str> {
+        self.run_as_user.as_deref().map(str::trim).filter(|user| !user.is_empty())
+    }
+}
+
+/// 上报消息的编码方式
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum MessageEncoding {
+    /// JSON文本消息
+    #[default]
+    Json,
+    /// MessagePack二进制消息，适合高频上报，序列化和解析开销更低
+    Msgpack,
+}
+
+impl std::fmt::Display for MessageEncoding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MessageEncoding::Json => write!(f, "json"),
+            MessageEncoding::Msgpack => write!(f, "msgpack"),
+        }
+    }
+}
+
+/// 解析Core地址：字符串（可逗号分隔）或字符串数组
+fn deserialize_urls<'de, D>(deserializer: D) -> std::result::Result<Vec<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Urls {
+        One(String),
+        Many(Vec<String>),
+    }
+
+    let urls = match Urls::deserialize(deserializer)? {
+        Urls::One(url) => url.split(',').map(str::to_string).collect(),
+        Urls::Many(urls) => urls,
+    };
+    Ok(urls
+        .into_iter()
+        .map(|url| url.trim().to_string())
+        .filter(|url| !url.is_empty())
+        .collect())
+}
+
+/// cpu_smoothing_window允许的最大值
+const MAX_CPU_SMOOTHING_WINDOW: usize = 60;
+
+/// max_retries允许的最大值，需要更多重试时应使用0（无限重试）
+const MAX_RETRIES_LIMIT: u32 = 10_000;
+
+/// 校验Core地址是否为有效的ws/wss地址
+fn validate_websocket_url(url: &str) -> std::result::Result<(), String> {
+    let uri: Uri = url.parse().map_err(|e| format!("{}", e))?;
+    match uri.scheme_str() {
+        Some("ws") | Some("wss") => {}
+        Some(scheme) => return Err(format!("不支持的协议 {}，应为 ws:// 或 wss://", scheme)),
+        None => return Err("缺少协议，应以 ws:// 或 wss:// 开头".to_string()),
+    }
+    if uri.host().is_none_or(str::is_empty) {
+        return Err("缺少主机名".to_string());
+    }
+    Ok(())
+}
+
+fn default_max_reconnect_interval() -> u64 {
+    60
+}
+
+fn default_metrics_buffer_size() -> usize {
+    360
+}
+
+fn default_ping_interval() -> u64 {
+    15
+}
+
+fn default_pong_timeout() -> u64 {
+    10
+}
+
+fn default_cpu_smoothing_window() -> usize {
+    1
+}
+
+fn default_interval_jitter() -> u64 {
+    5
+}
+
+fn default_compression() -> bool {
+    true
 }
 
 impl NodeConfig {
     /// 加载配置文件
-    pub fn load() -> Result<Self> {
-        let config_dir = Self::get_config_dir()?;
-        let default_config_path = config_dir.join("default.toml");
+    ///
+    /// `config_path`可以是配置文件或包含default.toml的目录，未指定时使用./config/default.toml
+    pub fn load(config_path: Option<&Path>) -> Result<Self> {
+        let config_file = match config_path {
+            Some(path) if path.is_dir() => path.join("default.toml"),
+            Some(path) => path.to_path_buf(),
+            None => Self::get_config_dir()?.join("default.toml"),
+        };
         
         let mut builder = Config::builder();
         
-        // 加载默认配置
-        if default_config_path.exists() {
-            builder = builder.add_source(File::from(default_config_path));
+        // 加载配置文件，显式指定的文件不存在时报错
+        if config_file.exists() {
+            builder = builder.add_source(File::from(config_file));
+        } else if config_path.is_some() {
+            return Err(anyhow::anyhow!("配置文件不存在: {}", config_file.display()));
         } else {
             tracing::warn!("未找到默认配置文件，使用内置默认值");
         }
@@ -78,11 +278,100 @@ impl NodeConfig {
         );
         
         let config = builder.build()?;
-        let node_config = config.try_deserialize()?;
+        let mut node_config: NodeConfig = config.try_deserialize()?;
+        node_config.core.resolve_token(std::env::var(TOKEN_ENV).ok())?;
         
         Ok(node_config)
     }
     
+    /// 校验配置，一次性列出所有问题
+    pub fn validate(&self) -> Result<()> {
+        let mut problems = Vec::new();
+        
+        if self.core.urls.is_empty() {
+            problems.push("core.url 未配置".to_string());
+        }
+        for url in &self.core.urls {
+            if let Err(reason) = validate_websocket_url(url) {
+                problems.push(format!("core.url \"{}\" 无效: {}", url, reason));
+            }
+        }
+        if self.core.token.trim().is_empty() {
+            problems.push("core.token 不能为空".to_string());
+        }
+        
+        if self.monitoring.heartbeat_interval == 0 {
+            problems.push("monitoring.heartbeat_interval 必须大于0".to_string());
+        }
+        if self.monitoring.metrics_interval == 0 {
+            problems.push("monitoring.metrics_interval 必须大于0".to_string());
+        }
+        if self.monitoring.cpu_smoothing_window == 0 || self.monitoring.cpu_smoothing_window > MAX_CPU_SMOOTHING_WINDOW {
+            problems.push(format!(
+                "monitoring.cpu_smoothing_window 必须在1到{}之间",
+                MAX_CPU_SMOOTHING_WINDOW
+            ));
+        }
+        if self.advanced.reconnect_interval == 0 {
+            problems.push("advanced.reconnect_interval 必须大于0".to_string());
+        }
+        if self.advanced.max_reconnect_interval < self.advanced.reconnect_interval {
+            problems.push("advanced.max_reconnect_interval 不能小于 reconnect_interval".to_string());
+        }
+        if self.advanced.max_retries > MAX_RETRIES_LIMIT {
+            problems.push(format!(
+                "advanced.max_retries 不能超过{} (0 表示无限重试)",
+                MAX_RETRIES_LIMIT
+            ));
+        }
+        if self.advanced.ping_interval == 0 {
+            problems.push("advanced.ping_interval 必须大于0".to_string());
+        }
+        if self.advanced.pong_timeout == 0 {
+            problems.push("advanced.pong_timeout 必须大于0".to_string());
+        }
+        if self.advanced.command_timeout == 0 {
+            problems.push("advanced.command_timeout 必须大于0".to_string());
+        }
+        if let Err(e) = CommandAllowlist::new(&self.advanced.allowed_commands) {
+            problems.push(format!("advanced.allowed_commands 中的 {}", e));
+        }
+        if let Some(user) = self.advanced.run_as_user() {
+            if let Err(e) = RunAs::resolve(user) {
+                problems.push(format!("advanced.run_as_user 无效: {}", e));
+            }
+        }
+        
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(anyhow::anyhow!("配置无效:\n  - {}", problems.join("\n  - ")))
+        }
+    }
+    
+    /// 节点本地数据目录的绝对路径，未设置时为当前工作目录
+    pub fn data_dir(&self) -> PathBuf {
+        let dir = self
+            .advanced
+            .data_dir
+            .clone()
+            .filter(|dir| !dir.as_os_str().is_empty())
+            .unwrap_or_else(|| PathBuf::from("."));
+        std::path::absolute(&dir).unwrap_or(dir)
+    }
+
+    /// 将节点写入的文件路径解析到数据目录下，绝对路径保持不变
+    pub fn data_path(&self, path: impl AsRef<Path>) -> PathBuf {
+        self.data_dir().join(path)
+    }
+
+    /// 创建数据目录，返回其绝对路径
+    pub fn prepare_data_dir(&self) -> Result<PathBuf> {
+        let dir = self.data_dir();
+        std::fs::create_dir_all(&dir).map_err(|e| anyhow::anyhow!("创建数据目录失败 {}: {}", dir.display(), e))?;
+        Ok(dir)
+    }
+    
     /// 获取配置目录
     fn get_config_dir() -> Result<PathBuf> {
         let mut config_dir = std::env::current_dir()?;
@@ -114,19 +403,29 @@ impl NodeConfig {
                 hostname
             };
             
-            format!("{}-{}", valid_hostname, uuid::Uuid::new_v4().to_string()[..8].to_string())
+            format!("{}-{}", valid_hostname, &uuid::Uuid::new_v4().to_string()[..8])
         })
     }
     
-    /// 获取WebSocket连接URL
-    pub fn get_websocket_url(&self, node_id: &str) -> String {
-        let mut url = self.core.url.clone();
+    /// 获取指定Core地址的WebSocket连接URL
+    pub fn get_websocket_url(&self, endpoint: &str, node_id: &str, resume_token: Option<&str>) -> String {
+        let mut url = endpoint.to_string();
         
         // 添加查询参数
         let mut params = vec![
             format!("token={}", urlencoding::encode(&self.core.token)),
             format!("node_id={}", urlencoding::encode(node_id)),
+            format!("protocol_version={}", crate::websocket::PROTOCOL_VERSION),
         ];
+        if self.advanced.compression {
+            params.push("compression=gzip".to_string());
+        }
+        if self.advanced.encoding != MessageEncoding::Json {
+            params.push(format!("encoding={}", self.advanced.encoding));
+        }
+        if let Some(resume_token) = resume_token {
+            params.push(format!("resume_token={}", urlencoding::encode(resume_token)));
+        }
         
         if url.contains('?') {
             url.push_str(&format!("&{}", params.join("&")));
@@ -143,14 +442,18 @@ impl Default for NodeConfig {
     fn default() -> Self {
         Self {
             core: CoreConfig {
-                url: "ws://0.0.0.0:9999/api/v1/ws".to_string(),
+                urls: vec!["ws://0.0.0.0:9999/api/v1/ws".to_string()],
                 token: "default-token".to_string(),
+                token_file: None,
                 node_id: None,
+                ca_cert: None,
             },
             monitoring: MonitoringConfig {
                 heartbeat_interval: 30,
                 metrics_interval: 10,
-                detailed_metrics: false,
+                metrics_every_heartbeats: 0,
+                cpu_smoothing_window: default_cpu_smoothing_window(),
+                interval_jitter: default_interval_jitter(),
             },
             system: SystemConfig {
                 hostname: None,
@@ -164,9 +467,19 @@ impl Default for NodeConfig {
             },
             advanced: AdvancedConfig {
                 reconnect_interval: 5,
+                max_reconnect_interval: default_max_reconnect_interval(),
                 max_retries: 10,
                 command_timeout: 30,
-                metrics_retention_days: 7,
+                requested_retention_days: None,
+                metrics_retention_days: None,
+                metrics_buffer_size: default_metrics_buffer_size(),
+                ping_interval: default_ping_interval(),
+                pong_timeout: default_pong_timeout(),
+                compression: default_compression(),
+                encoding: MessageEncoding::default(),
+                allowed_commands: Vec::new(),
+                run_as_user: None,
+                data_dir: None,
             },
         }
     }
@@ -179,11 +492,26 @@ mod tests {
     #[test]
     fn test_default_config() {
         let config = NodeConfig::default();
-        assert_eq!(config.core.url, "ws://0.0.0.0:9999/api/v1/ws");
+        assert_eq!(config.core.urls, vec!["ws://0.0.0.0:9999/api/v1/ws"]);
         assert_eq!(config.core.token, "default-token");
         assert!(config.core.node_id.is_none());
         assert_eq!(config.monitoring.heartbeat_interval, 30);
         assert_eq!(config.monitoring.metrics_interval, 10);
+        assert_eq!(config.monitoring.metrics_every_heartbeats, 0);
+    }
+
+    #[test]
+    fn test_heartbeat_carries_metrics() {
+        let mut monitoring = NodeConfig::default().monitoring;
+        // 未设置时心跳不携带监控数据
+        assert!((0..10).all(|count| !monitoring.heartbeat_carries_metrics(count)));
+
+        monitoring.metrics_every_heartbeats = 3;
+        let carries: Vec<u64> = (0..10).filter(|&count| monitoring.heartbeat_carries_metrics(count)).collect();
+        assert_eq!(carries, vec![0, 3, 6, 9]);
+
+        monitoring.metrics_every_heartbeats = 1;
+        assert!((0..10).all(|count| monitoring.heartbeat_carries_metrics(count)));
     }
 
     #[test]
@@ -197,9 +525,109 @@ mod tests {
     #[test]
     fn test_get_websocket_url() {
         let config = NodeConfig::default();
-        let url = config.get_websocket_url("test-node");
+        let url = config.get_websocket_url(&config.core.urls[0], "test-node", None);
         assert!(url.contains("token=default-token"));
         assert!(url.contains("node_id=test-node"));
+        assert!(url.contains("compression=gzip"));
+        assert!(url.contains("protocol_version=1"));
+        assert!(!url.contains("encoding="));
+        assert!(!url.contains("resume_token="));
         assert!(url.starts_with("ws://"));
+
+        let mut config = NodeConfig::default();
+        config.advanced.encoding = MessageEncoding::Msgpack;
+        let url = config.get_websocket_url(&config.core.urls[0], "test-node", Some("session-1"));
+        assert!(url.contains("encoding=msgpack"));
+        assert!(url.contains("resume_token=session-1"));
+    }
+
+    #[test]
+    fn test_data_path() {
+        let mut config = NodeConfig::default();
+        // 未设置时以当前工作目录为基准
+        assert_eq!(config.data_path("logs/node.log"), std::env::current_dir().unwrap().join("logs/node.log"));
+
+        config.advanced.data_dir = Some(PathBuf::from("/var/lib/sm-node"));
+        assert_eq!(config.data_path("logs/node.log"), PathBuf::from("/var/lib/sm-node/logs/node.log"));
+        // 绝对路径不受数据目录影响
+        assert_eq!(config.data_path("/var/log/sm-node.log"), PathBuf::from("/var/log/sm-node.log"));
+
+        config.advanced.data_dir = Some(PathBuf::from("data"));
+        assert!(config.data_dir().is_absolute());
+        assert!(config.data_dir().ends_with("data"));
+
+        let dir = std::env::temp_dir().join(format!("sm_node_data_{}", uuid::Uuid::new_v4()));
+        config.advanced.data_dir = Some(dir.join("nested"));
+        assert_eq!(config.prepare_data_dir().unwrap(), dir.join("nested"));
+        assert!(dir.join("nested").is_dir());
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_core_urls_parsing() {
+        let parse = |toml: &str| -> CoreConfig {
+            Config::builder()
+                .add_source(File::from_str(toml, config::FileFormat::Toml))
+                .build()
+                .unwrap()
+                .try_deserialize()
+                .unwrap()
+        };
+
+        let single = parse(r#"url = "ws://a:1/ws"
+token = "t""#);
+        assert_eq!(single.urls, vec!["ws://a:1/ws"]);
+
+        let separated = parse(r#"url = "ws://a:1/ws, ws://b:1/ws"
+token = "t""#);
+        assert_eq!(separated.urls, vec!["ws://a:1/ws", "ws://b:1/ws"]);
+
+        let list = parse(r#"url = ["ws://a:1/ws", "wss://b:1/ws"]
+token = "t""#);
+        assert_eq!(list.urls, vec!["ws://a:1/ws", "wss://b:1/ws"]);
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(NodeConfig::default().validate().is_ok());
+
+        let mut config = NodeConfig::default();
+        config.core.urls = vec!["http://example.com/ws".to_string(), "ws://".to_string()];
+        config.core.token = " ".to_string();
+        config.monitoring.metrics_interval = 0;
+        config.advanced.max_retries = MAX_RETRIES_LIMIT + 1;
+        config.advanced.allowed_commands = vec!["uptime".to_string(), "systemctl (".to_string()];
+        config.advanced.run_as_user = Some("sm-no-such-user".to_string());
+
+        let message = config.validate().unwrap_err().to_string();
+        assert!(message.contains("http://example.com/ws"));
+        assert!(message.contains("\"ws://\""));
+        assert!(message.contains("core.token"));
+        assert!(message.contains("monitoring.metrics_interval"));
+        assert!(message.contains("advanced.max_retries"));
+        assert!(message.contains("\"systemctl (\""));
+        assert!(message.contains("advanced.run_as_user"));
+        assert!(!message.contains("heartbeat_interval"));
+    }
+
+    #[test]
+    fn test_resolve_token() {
+        let path = std::env::temp_dir().join(format!("sm_node_token_{}", uuid::Uuid::new_v4()));
+        std::fs::write(&path, "file-token\n").unwrap();
+
+        let mut core = NodeConfig::default().core;
+        core.token_file = Some(path.display().to_string());
+
+        // 令牌文件优先于配置文件中的值，并去除末尾换行
+        core.resolve_token(None).unwrap();
+        assert_eq!(core.token, "file-token");
+
+        // 环境变量优先于令牌文件
+        core.resolve_token(Some("env-token".to_string())).unwrap();
+        assert_eq!(core.token, "env-token");
+
+        std::fs::remove_file(&path).ok();
+        core.token_file = Some(path.display().to_string());
+        assert!(core.resolve_token(None).is_err());
     }
 }
@@ -0,0 +1,434 @@
+use serde::{Deserialize, Serialize};
+use std::process::Stdio;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
+use tokio::process::Command;
+use tokio::sync::mpsc::{self, UnboundedSender};
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+
+use crate::run_as::RunAs;
+
+/// 服务端下发的命令
+#[derive(Debug, Deserialize, Clone)]
+pub struct CommandRequest {
+    pub command_id: String,
+    pub command: String,
+}
+
+/// 命令执行结果（与Core服务的command_result格式保持一致）
+#[derive(Debug, Serialize, Clone)]
+pub struct CommandOutput {
+    pub command_id: String,
+    pub status: String,
+    pub stdout: Option<String>,
+    pub stderr: Option<String>,
+    pub exit_code: Option<i32>,
+    pub execution_time_ms: i64,
+    /// stdout或stderr超过保留上限，只包含最后一部分输出（完整输出已通过实时片段发送）
+    pub truncated: bool,
+}
+
+/// 实时输出的刷新间隔，期间产生的多行输出合并为一个片段发送
+const OUTPUT_FLUSH_INTERVAL: Duration = Duration::from_millis(200);
+/// 单个输出片段的最大字节数，达到后立即发送
+const OUTPUT_CHUNK_MAX_BYTES: usize = 4096;
+/// 每个输出流在最终结果中最多保留的字节数，超出时只保留最后部分，避免长时间运行的命令占用过多内存
+const OUTPUT_RETAINED_MAX_BYTES: usize = 64 * 1024;
+
+/// 命令输出流
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputStream {
+    Stdout,
+    Stderr,
+}
+
+/// 命令执行过程中产生的输出片段（由一行或多行完整输出组成）
+#[derive(Debug, Serialize, Clone)]
+pub struct CommandOutputChunk {
+    pub command_id: String,
+    /// 从1开始递增的序号，stdout和stderr共用，便于按产生顺序拼接
+    pub seq: u64,
+    pub stream: OutputStream,
+    pub data: String,
+}
+
+/// 命令执行事件，按产生顺序发送，最后一个事件为执行结果
+#[derive(Debug)]
+pub enum CommandEvent {
+    Output(CommandOutputChunk),
+    Finished(CommandOutput),
+}
+
+/// 单个输出流保留的输出，超过上限后丢弃最早的部分
+#[derive(Default)]
+struct RetainedOutput {
+    data: Vec<u8>,
+    truncated: bool,
+}
+
+impl RetainedOutput {
+    fn push(&mut self, line: &[u8]) {
+        self.data.extend_from_slice(line);
+        // 超过两倍上限时才裁剪，避免每行都移动缓冲区
+        if self.data.len() > OUTPUT_RETAINED_MAX_BYTES * 2 {
+            self.trim();
+        }
+    }
+
+    /// 裁剪到上限以内，尽量从完整的行开始保留
+    fn trim(&mut self) {
+        if self.data.len() <= OUTPUT_RETAINED_MAX_BYTES {
+            return;
+        }
+        let cut = self.data.len() - OUTPUT_RETAINED_MAX_BYTES;
+        let cut = self.data[cut..]
+            .iter()
+            .position(|byte| *byte == b'\n')
+            .map(|offset| cut + offset + 1)
+            .filter(|line_start| *line_start < self.data.len())
+            .unwrap_or(cut);
+        self.data.drain(..cut);
+        self.truncated = true;
+    }
+
+    fn finish(mut self) -> (String, bool) {
+        self.trim();
+        let text = String::from_utf8_lossy(&self.data).to_string();
+        if self.truncated {
+            (format!("...（输出超过{}KB，仅保留最后部分）\n{}", OUTPUT_RETAINED_MAX_BYTES / 1024, text), true)
+        } else {
+            (text, false)
+        }
+    }
+}
+
+/// 收集命令输出：保留最后一部分输出用于最终结果，同时把新输出按片段实时发送
+struct OutputCollector<'a> {
+    command_id: String,
+    events: &'a UnboundedSender<CommandEvent>,
+    seq: u64,
+    stdout: RetainedOutput,
+    stderr: RetainedOutput,
+    /// 尚未发送的输出及其所属的流
+    pending: Vec<u8>,
+    pending_stream: OutputStream,
+}
+
+impl<'a> OutputCollector<'a> {
+    fn new(command_id: String, events: &'a UnboundedSender<CommandEvent>) -> Self {
+        Self {
+            command_id,
+            events,
+            seq: 0,
+            stdout: RetainedOutput::default(),
+            stderr: RetainedOutput::default(),
+            pending: Vec::new(),
+            pending_stream: OutputStream::Stdout,
+        }
+    }
+
+    /// 追加一行输出，切换输出流或待发送内容过多时先发送已有内容，保持片段顺序与实际输出一致
+    fn push(&mut self, stream: OutputStream, line: Vec<u8>) {
+        match stream {
+            OutputStream::Stdout => self.stdout.push(&line),
+            OutputStream::Stderr => self.stderr.push(&line),
+        }
+        if stream != self.pending_stream {
+            self.flush();
+            self.pending_stream = stream;
+        }
+        self.pending.extend_from_slice(&line);
+        if self.pending.len() >= OUTPUT_CHUNK_MAX_BYTES {
+            self.flush();
+        }
+    }
+
+    /// 发送待发送的输出
+    fn flush(&mut self) {
+        if self.pending.is_empty() {
+            return;
+        }
+        self.seq += 1;
+        let chunk = CommandOutputChunk {
+            command_id: self.command_id.clone(),
+            seq: self.seq,
+            stream: self.pending_stream,
+            data: String::from_utf8_lossy(&self.pending).to_string(),
+        };
+        self.pending.clear();
+        // 接收端已关闭时只是不再实时发送，保留的输出仍包含在最终结果中
+        self.events.send(CommandEvent::Output(chunk)).ok();
+    }
+
+    /// 发送剩余输出，返回保留的stdout、stderr以及是否有输出被截断
+    fn finish(mut self) -> (String, String, bool) {
+        self.flush();
+        let (stdout, stdout_truncated) = self.stdout.finish();
+        let (stderr, stderr_truncated) = self.stderr.finish();
+        (stdout, stderr, stdout_truncated || stderr_truncated)
+    }
+}
+
+/// 逐行读取子进程输出并转发给收集方
+fn spawn_line_reader<R>(
+    reader: R,
+    stream: OutputStream,
+    lines: UnboundedSender<(OutputStream, Vec<u8>)>,
+) -> JoinHandle<()>
+where
+    R: AsyncRead + Unpin + Send + 'static,
+{
+    tokio::spawn(async move {
+        let mut reader = BufReader::new(reader);
+        loop {
+            let mut line = Vec::new();
+            match reader.read_until(b'\n', &mut line).await {
+                Ok(0) | Err(_) => break,
+                Ok(_) => {
+                    if lines.send((stream, line)).is_err() {
+                        break;
+                    }
+                }
+            }
+        }
+    })
+}
+
+/// 执行命令，执行过程中通过`events`实时发送输出片段，超时后终止进程并返回timeout状态
+///
+/// 设置`run_as`时命令以该用户身份运行
+pub async fn execute_command(
+    request: CommandRequest,
+    timeout: Duration,
+    run_as: Option<&RunAs>,
+    events: &UnboundedSender<CommandEvent>,
+) -> CommandOutput {
+    info!("⚙️ 执行命令 [{}]: {}", request.command_id, request.command);
+    let started = Instant::now();
+    let deadline = tokio::time::Instant::now() + timeout;
+
+    let mut command = shell_command(&request.command);
+    command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        // 超时后子进程被丢弃时终止
+        .kill_on_drop(true);
+    if let Some(run_as) = run_as {
+        run_as.apply(&mut command);
+    }
+
+    let mut child = match command.spawn() {
+        Ok(child) => child,
+        Err(e) => {
+            warn!("❌ 启动命令失败 [{}]: {}", request.command_id, e);
+            return CommandOutput {
+                command_id: request.command_id,
+                status: "failed".to_string(),
+                stdout: None,
+                stderr: Some(format!("启动命令失败: {}", e)),
+                exit_code: None,
+                execution_time_ms: started.elapsed().as_millis() as i64,
+                truncated: false,
+            };
+        }
+    };
+
+    let (line_tx, mut line_rx) = mpsc::unbounded_channel();
+    let mut readers = Vec::new();
+    if let Some(stdout) = child.stdout.take() {
+        readers.push(spawn_line_reader(stdout, OutputStream::Stdout, line_tx.clone()));
+    }
+    if let Some(stderr) = child.stderr.take() {
+        readers.push(spawn_line_reader(stderr, OutputStream::Stderr, line_tx));
+    }
+
+    let mut collector = OutputCollector::new(request.command_id.clone(), events);
+    let mut flush_interval = tokio::time::interval(OUTPUT_FLUSH_INTERVAL);
+
+    // 读取输出直到两个管道都关闭，再等待进程退出
+    let result = tokio::time::timeout_at(deadline, async {
+        loop {
+            tokio::select! {
+                line = line_rx.recv() => match line {
+                    Some((stream, line)) => collector.push(stream, line),
+                    None => break,
+                },
+                _ = flush_interval.tick() => collector.flush(),
+            }
+        }
+        child.wait().await
+    })
+    .await;
+    let execution_time_ms = started.elapsed().as_millis() as i64;
+
+    // 超时时终止进程，并停止读取仍被后台子进程占用的管道
+    if result.is_err() {
+        child.kill().await.ok();
+    }
+    for reader in readers {
+        reader.abort();
+    }
+    let (stdout, stderr, truncated) = collector.finish();
+
+    match result {
+        Ok(Ok(status)) => {
+            let exit_code = status.code();
+            let status = if status.success() { "success" } else { "failed" };
+            info!("✅ 命令执行完成 [{}]: {} (退出码: {:?})", request.command_id, status, exit_code);
+            CommandOutput {
+                command_id: request.command_id,
+                status: status.to_string(),
+                stdout: Some(stdout),
+                stderr: Some(stderr),
+                exit_code,
+                execution_time_ms,
+                truncated,
+            }
+        }
+        Ok(Err(e)) => {
+            warn!("❌ 命令执行失败 [{}]: {}", request.command_id, e);
+            CommandOutput {
+                command_id: request.command_id,
+                status: "failed".to_string(),
+                stdout: Some(stdout),
+                stderr: Some(format!("{}命令执行失败: {}", stderr, e)),
+                exit_code: None,
+                execution_time_ms,
+                truncated,
+            }
+        }
+        Err(_) => {
+            warn!("⏰ 命令执行超时 [{}]: {}秒", request.command_id, timeout.as_secs());
+            // 保留超时前已产生的输出
+            CommandOutput {
+                command_id: request.command_id,
+                status: "timeout".to_string(),
+                stdout: Some(stdout),
+                stderr: Some(format!("{}命令执行超时（{}秒）", stderr, timeout.as_secs())),
+                exit_code: None,
+                execution_time_ms,
+                truncated,
+            }
+        }
+    }
+}
+
+/// 构建平台对应的shell命令
+fn shell_command(command: &str) -> Command {
+    if cfg!(target_os = "windows") {
+        let mut cmd = Command::new("cmd");
+        cmd.arg("/C").arg(command);
+        cmd
+    } else {
+        let mut cmd = Command::new("sh");
+        cmd.arg("-c").arg(command);
+        cmd
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_execute_command_success() {
+        let request = CommandRequest {
+            command_id: "cmd-1".to_string(),
+            command: "echo hello".to_string(),
+        };
+        let (events, _) = mpsc::unbounded_channel();
+        let output = execute_command(request, Duration::from_secs(5), None, &events).await;
+
+        assert_eq!(output.status, "success");
+        assert_eq!(output.exit_code, Some(0));
+        assert!(output.stdout.unwrap().contains("hello"));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_execute_command_streams_output() {
+        let request = CommandRequest {
+            command_id: "cmd-3".to_string(),
+            command: "echo one; sleep 0.5; echo two >&2; exit 3".to_string(),
+        };
+        let (events, mut receiver) = mpsc::unbounded_channel();
+        let output = execute_command(request, Duration::from_secs(5), None, &events).await;
+
+        assert_eq!(output.status, "failed");
+        assert_eq!(output.exit_code, Some(3));
+        assert_eq!(output.stdout.as_deref(), Some("one\n"));
+        assert_eq!(output.stderr.as_deref(), Some("two\n"));
+
+        // 输出在产生时分片发送，序号连续
+        let mut chunks = Vec::new();
+        while let Ok(CommandEvent::Output(chunk)) = receiver.try_recv() {
+            chunks.push(chunk);
+        }
+        assert_eq!(chunks.len(), 2);
+        assert_eq!((chunks[0].seq, chunks[0].stream, chunks[0].data.as_str()), (1, OutputStream::Stdout, "one\n"));
+        assert_eq!((chunks[1].seq, chunks[1].stream, chunks[1].data.as_str()), (2, OutputStream::Stderr, "two\n"));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_execute_command_timeout() {
+        let request = CommandRequest {
+            command_id: "cmd-2".to_string(),
+            command: "sleep 5".to_string(),
+        };
+        let (events, _) = mpsc::unbounded_channel();
+        let output = execute_command(request, Duration::from_millis(100), None, &events).await;
+
+        assert_eq!(output.status, "timeout");
+        assert!(output.exit_code.is_none());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_execute_command_truncates_retained_output() {
+        let request = CommandRequest {
+            command_id: "cmd-5".to_string(),
+            command: "seq 1 30000".to_string(),
+        };
+        let (events, mut receiver) = mpsc::unbounded_channel();
+        let output = execute_command(request, Duration::from_secs(5), None, &events).await;
+
+        // 最终结果只保留最后部分，从完整的行开始
+        assert!(output.truncated);
+        let stdout = output.stdout.unwrap();
+        let (marker, retained) = stdout.split_once('\n').unwrap();
+        assert!(marker.contains("仅保留最后部分"));
+        assert!(retained.len() <= OUTPUT_RETAINED_MAX_BYTES);
+        assert!(retained.ends_with("29999\n30000\n"));
+        assert!(retained.lines().all(|line| line.parse::<u32>().is_ok()));
+
+        // 实时片段仍包含全部输出
+        let mut streamed = String::new();
+        while let Ok(CommandEvent::Output(chunk)) = receiver.try_recv() {
+            streamed.push_str(&chunk.data);
+        }
+        assert!(streamed.starts_with("1\n2\n"));
+        assert_eq!(streamed.lines().count(), 30000);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_execute_command_as_user() {
+        // 只有root可以切换用户
+        let Ok(nobody) = RunAs::resolve("nobody") else {
+            return;
+        };
+        let request = CommandRequest {
+            command_id: "cmd-4".to_string(),
+            command: "id -u; echo $USER".to_string(),
+        };
+        let (events, _) = mpsc::unbounded_channel();
+        let output = execute_command(request, Duration::from_secs(5), Some(&nobody), &events).await;
+
+        assert_eq!(output.status, "success");
+        assert_eq!(output.stdout, Some(format!("{}\nnobody\n", nobody.uid)));
+    }
+}
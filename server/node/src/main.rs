@@ -1,20 +1,41 @@
 use anyhow::Result;
-use tracing::{error, info, warn};
-use tracing_subscriber;
+use clap::Parser;
+use tracing::{debug, error, info, warn};
 use std::time::Duration;
 
+mod allowlist;
+mod backoff;
+mod buffer;
+mod cli;
 mod config;
+mod executor;
 mod monitor;
+mod once;
+mod reload;
+mod run_as;
+mod ticker;
 mod websocket;
 
+use crate::allowlist::CommandAllowlist;
+use crate::backoff::Backoff;
+use crate::buffer::MetricsBuffer;
+use crate::cli::Cli;
 use crate::config::NodeConfig;
+use crate::executor::{CommandEvent, CommandOutput, CommandRequest};
 use crate::monitor::{SystemMonitor, SystemMetrics};
-use crate::websocket::WebSocketClient;
+use crate::reload::{ConfigChanges, ReloadSignal};
+use crate::run_as::RunAs;
+use crate::websocket::{WebSocketClient, WebSocketMessage};
+
+/// 版本信息（包含构建时的git提交和时间）
+pub const VERSION: &str = concat!(env!("CARGO_PKG_VERSION"), " (", env!("SM_GIT_SHA"), ", ", env!("SM_BUILD_TIME"), ")");
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    // 加载配置
-    let config = match NodeConfig::load() {
+    let cli = Cli::parse();
+    
+    // 加载配置，命令行参数优先
+    let config = match cli.load_config() {
         Ok(config) => config,
         Err(e) => {
             eprintln!("❌ 加载配置失败: {}", e);
@@ -22,11 +43,24 @@ async fn main() -> Result<()> {
         }
     };
 
+    // 创建数据目录，节点写入的文件均位于其中（日志尚未初始化，失败时直接输出）
+    let data_dir = match config.prepare_data_dir() {
+        Ok(data_dir) => data_dir,
+        Err(e) => {
+            eprintln!("❌ {}", e);
+            std::process::exit(1);
+        }
+    };
+    
     // 初始化日志
     init_logging(&config)?;
     
-    info!("🤖 Server Manager Node 启动中...");
+    info!("🤖 Server Manager Node {} 启动中...", VERSION);
     info!("📋 配置加载成功");
+    info!("📁 数据目录: {}", data_dir.display());
+    if config.advanced.metrics_retention_days.is_some() {
+        warn!("⚠️ advanced.metrics_retention_days 已废弃且不再生效，如需指定Core保留本节点监控数据的天数，请改用 advanced.requested_retention_days");
+    }
     
     // 获取节点ID
     let node_id = config.get_node_id();
@@ -34,11 +68,15 @@ async fn main() -> Result<()> {
     
     // 创建监控采集器
     let mut monitor = SystemMonitor::new();
+    monitor.set_cpu_smoothing_window(config.monitoring.cpu_smoothing_window);
     
     // 显示系统信息
     let system_info = monitor.get_system_info();
     info!("💻 系统信息:");
     info!("  - 主机名: {}", system_info.hostname);
+    if let Some(hostname) = config.system.hostname_override() {
+        info!("  - 上报主机名: {}", hostname);
+    }
     info!("  - 操作系统: {} {}", system_info.os_name, system_info.os_version);
     info!("  - 内核版本: {}", system_info.kernel_version);
     info!("  - CPU: {} ({}核心)", system_info.cpu_name, system_info.cpu_count);
@@ -54,14 +92,25 @@ async fn main() -> Result<()> {
     
     info!("✅ Node代理启动成功");
     
+    // 单次模式：发送一次监控数据后退出，失败时以非零退出码结束
+    if once::once_requested(cli.once) {
+        if let Err(e) = once::run_once(config, node_id, monitor).await {
+            error!("❌ 单次模式失败: {}", e);
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+    
     // 启动监控循环
-    start_monitoring_loop(config, node_id, monitor).await?;
+    start_monitoring_loop(&cli, config, node_id, monitor).await?;
     
     Ok(())
 }
 
-/// 初始化日志系统
+/// 初始化日志系统，启用文件日志时追加写入数据目录下的日志文件
 fn init_logging(config: &NodeConfig) -> Result<()> {
+    use tracing_subscriber::{filter::LevelFilter, fmt, prelude::*};
+    
     let log_level = match config.logging.level.as_str() {
         "trace" => tracing::Level::TRACE,
         "debug" => tracing::Level::DEBUG,
@@ -71,19 +120,32 @@ fn init_logging(config: &NodeConfig) -> Result<()> {
         _ => tracing::Level::INFO,
     };
     
-    let subscriber = tracing_subscriber::fmt()
-        .with_max_level(log_level)
-        .with_target(false)
-        .with_thread_ids(false)
-        .with_thread_names(false);
+    let console_layer = config.logging.console_enabled.then(|| fmt::layer().with_target(false));
     
-    if config.logging.console_enabled {
-        subscriber.init();
-    }
+    let file_layer = if config.logging.file_enabled {
+        let path = config.data_path(&config.logging.file_path);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .map_err(|e| anyhow::anyhow!("创建日志目录失败 {}: {}", parent.display(), e))?;
+        }
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .map_err(|e| anyhow::anyhow!("打开日志文件失败 {}: {}", path.display(), e))?;
+        Some(fmt::layer().with_target(false).with_ansi(false).with_writer(std::sync::Mutex::new(file)))
+    } else {
+        None
+    };
+    
+    tracing_subscriber::registry()
+        .with(LevelFilter::from_level(log_level))
+        .with(console_layer)
+        .with(file_layer)
+        .init();
     
-    // TODO: 实现文件日志输出
     if config.logging.file_enabled {
-        warn!("文件日志功能尚未实现");
+        info!("📝 日志文件: {}", config.data_path(&config.logging.file_path).display());
     }
     
     Ok(())
@@ -92,7 +154,11 @@ fn init_logging(config: &NodeConfig) -> Result<()> {
 /// 记录监控指标
 fn log_metrics(metrics: &SystemMetrics) {
     info!("📈 监控指标:");
-    info!("  - CPU使用率: {:.1}%", metrics.cpu_usage);
+    if metrics.cpu_usage_raw != metrics.cpu_usage {
+        info!("  - CPU使用率: {:.1}% (本次采样 {:.1}%)", metrics.cpu_usage, metrics.cpu_usage_raw);
+    } else {
+        info!("  - CPU使用率: {:.1}%", metrics.cpu_usage);
+    }
     info!("  - 内存使用率: {:.1}%", metrics.memory_usage);
     info!("  - 内存总量: {:.1} GB", metrics.memory_total as f64 / 1024.0 / 1024.0 / 1024.0);
     info!("  - 可用内存: {:.1} GB", metrics.memory_available as f64 / 1024.0 / 1024.0 / 1024.0);
@@ -106,29 +172,63 @@ fn log_metrics(metrics: &SystemMetrics) {
 
 /// 启动监控循环（集成WebSocket功能）
 async fn start_monitoring_loop(
-    config: NodeConfig,
+    cli: &Cli,
+    mut config: NodeConfig,
     node_id: String,
     mut monitor: SystemMonitor,
 ) -> Result<()> {
     let metrics_interval = Duration::from_secs(config.monitoring.metrics_interval);
     let heartbeat_interval = Duration::from_secs(config.monitoring.heartbeat_interval);
     let reconnect_interval = Duration::from_secs(config.advanced.reconnect_interval);
+    let max_reconnect_interval = Duration::from_secs(config.advanced.max_reconnect_interval);
+    let mut command_timeout = Duration::from_secs(config.advanced.command_timeout);
+    // 配置已通过校验，允许列表可以正常解析
+    let mut allowlist = CommandAllowlist::new(&config.advanced.allowed_commands)?;
+    let mut run_as = resolve_run_as(&config)?;
     
     info!("🔄 启动监控循环:");
-    info!("  - 监控采集间隔: {}秒", config.monitoring.metrics_interval);
-    info!("  - 心跳间隔: {}秒", config.monitoring.heartbeat_interval);
-    info!("  - 重连间隔: {}秒", config.advanced.reconnect_interval);
+    if config.monitoring.metrics_every_heartbeats > 0 {
+        info!(
+            "  - 心跳间隔: {}秒，每{}次心跳上报一次监控数据",
+            config.monitoring.heartbeat_interval, config.monitoring.metrics_every_heartbeats
+        );
+    } else {
+        info!("  - 监控采集间隔: {}秒", config.monitoring.metrics_interval);
+        info!("  - 心跳间隔: {}秒（期间已上报监控数据时跳过）", config.monitoring.heartbeat_interval);
+    }
+    info!("  - 重连间隔: {}秒 (最大 {}秒)", config.advanced.reconnect_interval, config.advanced.max_reconnect_interval);
+    info!("  - 定时器随机抖动: 最多{}秒", config.monitoring.interval_jitter);
+    log_allowed_commands(&config);
     
-    let mut metrics_interval = tokio::time::interval(metrics_interval);
-    let mut heartbeat_interval = tokio::time::interval(heartbeat_interval);
+    // 首次触发时间随机推迟，避免大量节点同时启动时集中上报
+    let mut max_jitter = Duration::from_secs(config.monitoring.interval_jitter);
+    let now = tokio::time::Instant::now();
+    let mut metrics_interval = ticker::jittered_interval(now, metrics_interval, max_jitter);
+    let mut heartbeat_interval = ticker::jittered_interval(now, heartbeat_interval, max_jitter);
+    let mut ping_interval = live_interval(config.advanced.ping_interval, max_jitter);
     
-    let mut metrics_count = 0;
-    let mut retry_count = 0;
+    let mut metrics_count: u64 = 0;
+    let mut heartbeat_count: u64 = 0;
+    let mut metrics_sent_since_heartbeat = false;
+    let mut backoff = Backoff::new(reconnect_interval, max_reconnect_interval);
+    let mut next_reconnect = tokio::time::Instant::now();
+    let mut retries_exhausted = false;
+    let mut metrics_buffer = MetricsBuffer::new(config.advanced.metrics_buffer_size);
     let mut ws_client = WebSocketClient::new(config.clone(), node_id.clone());
     
+    // 命令在独立任务中执行，输出和结果按产生顺序通过通道回传给主循环发送
+    let (result_tx, mut result_rx) = tokio::sync::mpsc::unbounded_channel::<CommandEvent>();
+    let mut reload_signal = ReloadSignal::new()?;
+    
     // 初始连接尝试
     if let Err(e) = ws_client.connect().await {
         error!("❌ 初始WebSocket连接失败: {}", e);
+        if e.is_retryable() {
+            next_reconnect = tokio::time::Instant::now() + backoff.next_delay();
+        } else {
+            error!("❌ 连接配置错误，停止重连，修正配置后发送SIGHUP重新加载");
+            retries_exhausted = true;
+        }
     } else {
         // 发送注册消息
         if let Err(e) = ws_client.send_register_message(&monitor).await {
@@ -138,75 +238,202 @@ async fn start_monitoring_loop(
     
     loop {
         tokio::select! {
-            _ = metrics_interval.tick() => {
-                // 采集监控数据
-                let metrics = monitor.get_metrics();
-                metrics_count += 1;
-                
-                if metrics_count % 10 == 0 {
-                    // 每10次采集记录一次详细日志
-                    log_metrics(&metrics);
-                } else {
-                    // 简要日志
-                    info!("📊 监控数据 - CPU: {:.1}%, 内存: {:.1}%", 
-                        metrics.cpu_usage,
-                        metrics.memory_usage
-                    );
+            // 设置了metrics_every_heartbeats时监控数据随心跳上报，不使用独立的采集定时器
+            _ = metrics_interval.tick(), if config.monitoring.metrics_every_heartbeats == 0 => {
+                if send_metrics(&mut monitor, &mut ws_client, &mut metrics_buffer, &mut metrics_count).await {
+                    metrics_sent_since_heartbeat = true;
                 }
-                
-                // 如果WebSocket连接正常，发送监控数据
-                if ws_client.is_connected() {
-                    if let Err(e) = ws_client.send_heartbeat(&metrics).await {
-                        error!("❌ 发送监控数据失败: {}", e);
-                        ws_client.close().await.ok();
-                    }
+            }
+            
+            // 连接断开时按指数退避重连
+            _ = tokio::time::sleep_until(next_reconnect), if !ws_client.is_connected() && !retries_exhausted => {
+                let max_retries = config.advanced.max_retries;
+                if max_retries > 0 && backoff.attempts() >= max_retries {
+                    error!("❌ 达到最大重试次数，停止重连");
+                    retries_exhausted = true;
                 } else {
-                    // 尝试重连
-                    if retry_count < config.advanced.max_retries {
-                        retry_count += 1;
-                        info!("🔄 尝试重连 ({}/{})", retry_count, config.advanced.max_retries);
+                    if max_retries > 0 {
+                        info!("🔄 尝试重连 ({}/{})", backoff.attempts() + 1, max_retries);
+                    } else {
+                        info!("🔄 尝试重连 (第{}次)", backoff.attempts() + 1);
+                    }
+                    
+                    if let Err(e) = ws_client.connect().await {
+                        if e.is_retryable() {
+                            let delay = backoff.next_delay();
+                            error!("❌ 重连失败: {}，{:.1}秒后重试", e, delay.as_secs_f64());
+                            next_reconnect = tokio::time::Instant::now() + delay;
+                        } else {
+                            error!("❌ 重连失败: {}，连接配置错误，停止重连，修正配置后发送SIGHUP重新加载", e);
+                            retries_exhausted = true;
+                        }
+                    } else {
+                        backoff.reset();
+                        info!("✅ 重连成功");
                         
-                        if let Err(e) = ws_client.connect().await {
-                            error!("❌ 重连失败: {}", e);
-                            tokio::time::sleep(reconnect_interval).await;
+                        // 重新发送注册消息
+                        if let Err(e) = ws_client.send_register_message(&monitor).await {
+                            error!("❌ 重新发送注册消息失败: {}", e);
                         } else {
-                            retry_count = 0;
-                            info!("✅ 重连成功");
-                            
-                            // 重新发送注册消息
+                            flush_metrics_buffer(&mut ws_client, &mut metrics_buffer).await;
+                        }
+                    }
+                }
+            }
+            
+            // 定期发送ping，超时未收到pong视为连接已断开（如半开的TCP连接）
+            _ = ping_interval.tick(), if ws_client.is_connected() => {
+                let pong_timeout = Duration::from_secs(config.advanced.pong_timeout);
+                if ws_client.pong_overdue(pong_timeout) {
+                    warn!("⚠️ {}秒内未收到pong响应，连接可能已断开，准备重连", config.advanced.pong_timeout);
+                    ws_client.close().await.ok();
+                } else if let Err(e) = ws_client.send_ping().await {
+                    error!("❌ {}", e);
+                    ws_client.close().await.ok();
+                }
+            }
+            
+            _ = heartbeat_interval.tick() => {
+                let carries_metrics = config.monitoring.heartbeat_carries_metrics(heartbeat_count);
+                heartbeat_count += 1;
+                if carries_metrics {
+                    send_metrics(&mut monitor, &mut ws_client, &mut metrics_buffer, &mut metrics_count).await;
+                } else if std::mem::take(&mut metrics_sent_since_heartbeat) {
+                    // 携带监控数据的心跳已经表明节点在线
+                    debug!("💓 上一个心跳周期内已上报监控数据，跳过轻量心跳");
+                } else if ws_client.is_connected() {
+                    debug!("💓 发送心跳");
+                    if let Err(e) = ws_client.send_heartbeat(None).await {
+                        error!("❌ 发送心跳失败: {}", e);
+                        ws_client.close().await.ok();
+                    }
+                }
+            }
+            
+            // 处理服务器消息
+            message = ws_client.receive_message(), if ws_client.is_connected() => {
+                match message {
+                    Ok(Some(message)) => {
+                        info!("📥 收到服务器消息: {}", message);
+                        if handle_server_message(&message, &result_tx, command_timeout, &allowlist, run_as.as_ref()) {
                             if let Err(e) = ws_client.send_register_message(&monitor).await {
                                 error!("❌ 重新发送注册消息失败: {}", e);
                             }
                         }
-                    } else {
-                        error!("❌ 达到最大重试次数，停止重连");
+                    }
+                    Ok(None) => {
+                        info!("📭 连接已关闭");
+                        ws_client.close().await.ok();
+                    }
+                    Err(e) => {
+                        error!("❌ 接收消息错误: {}", e);
+                        ws_client.close().await.ok();
                     }
                 }
             }
             
-            _ = heartbeat_interval.tick() => {
-                // 发送心跳信号
-                info!("💓 心跳信号");
-                
-                // 如果WebSocket连接正常，处理服务器消息
-                if ws_client.is_connected() {
-                    match ws_client.receive_message().await {
-                        Ok(Some(message)) => {
-                            info!("📥 收到服务器消息: {}", message);
+            // 回传命令的实时输出和执行结果
+            Some(event) = result_rx.recv() => match event {
+                CommandEvent::Output(chunk) => {
+                    // 实时输出仅供查看，连接未建立时直接丢弃，完整输出包含在执行结果中
+                    if ws_client.is_connected() {
+                        if let Err(e) = ws_client.send_command_output(&chunk).await {
+                            error!("❌ 发送命令输出失败: {}", e);
                         }
-                        Ok(None) => {
-                            info!("📭 连接已关闭");
-                            ws_client.close().await.ok();
+                    }
+                }
+                CommandEvent::Finished(output) => {
+                    if ws_client.is_connected() {
+                        if let Err(e) = ws_client.send_command_result(&output).await {
+                            error!("❌ 发送命令结果失败: {}", e);
                         }
-                        Err(e) => {
-                            error!("❌ 接收消息错误: {}", e);
+                    } else {
+                        warn!("⚠️ 连接未建立，丢弃命令结果: {}", output.command_id);
+                    }
+                }
+            },
+            
+            // 收到SIGHUP时重新加载配置，间隔调整无需断开连接
+            _ = reload_signal.recv() => {
+                info!("🔄 收到重新加载信号，重新读取配置");
+                // 重新加载时保留命令行参数的覆盖
+                let new_config = match cli.load_config() {
+                    Ok(new_config) => new_config,
+                    Err(e) => {
+                        error!("❌ 重新加载配置失败，继续使用当前配置: {}", e);
+                        continue;
+                    }
+                };
+                
+                let changes = ConfigChanges::between(&config, &new_config);
+                if changes.is_empty() {
+                    info!("📋 配置没有变化");
+                    continue;
+                }
+                for description in &changes.descriptions {
+                    info!("📋 配置变更 {}", description);
+                }
+                if !changes.restart_required.is_empty() {
+                    warn!("⚠️ 以下配置需要重启后生效: {}", changes.restart_required.join(", "));
+                }
+                
+                if changes.intervals_changed {
+                    max_jitter = Duration::from_secs(new_config.monitoring.interval_jitter);
+                    metrics_interval = live_interval(new_config.monitoring.metrics_interval, max_jitter);
+                    heartbeat_interval = live_interval(new_config.monitoring.heartbeat_interval, max_jitter);
+                    heartbeat_count = 0;
+                    ping_interval = live_interval(new_config.advanced.ping_interval, max_jitter);
+                }
+                command_timeout = Duration::from_secs(new_config.advanced.command_timeout);
+                if new_config.advanced.allowed_commands != config.advanced.allowed_commands {
+                    match CommandAllowlist::new(&new_config.advanced.allowed_commands) {
+                        Ok(new_allowlist) => {
+                            allowlist = new_allowlist;
+                            log_allowed_commands(&new_config);
                         }
+                        Err(e) => error!("❌ 解析命令允许列表失败，继续使用当前列表: {}", e),
+                    }
+                }
+                if new_config.advanced.run_as_user() != config.advanced.run_as_user() {
+                    match resolve_run_as(&new_config) {
+                        Ok(new_run_as) => run_as = new_run_as,
+                        Err(e) => error!("❌ 解析命令执行用户失败，继续使用当前用户: {}", e),
+                    }
+                }
+                if new_config.advanced.reconnect_interval != config.advanced.reconnect_interval
+                    || new_config.advanced.max_reconnect_interval != config.advanced.max_reconnect_interval
+                {
+                    backoff = Backoff::new(
+                        Duration::from_secs(new_config.advanced.reconnect_interval),
+                        Duration::from_secs(new_config.advanced.max_reconnect_interval),
+                    );
+                }
+                
+                if changes.connection_changed {
+                    info!("🔌 Core连接配置已变更，重新连接");
+                    ws_client.close().await.ok();
+                    retries_exhausted = false;
+                    next_reconnect = tokio::time::Instant::now();
+                }
+                monitor.set_cpu_smoothing_window(new_config.monitoring.cpu_smoothing_window);
+                ws_client.update_config(new_config.clone());
+                config = new_config;
+                
+                if changes.registration_changed && !changes.connection_changed && ws_client.is_connected() {
+                    if let Err(e) = ws_client.send_register_message(&monitor).await {
+                        error!("❌ 重新发送注册消息失败: {}", e);
                     }
                 }
             }
             
             _ = tokio::signal::ctrl_c() => {
                 info!("👋 Node代理正在关闭...");
+                // 先通知Core节点主动下线，避免被当作异常离线
+                if ws_client.is_connected() {
+                    if let Err(e) = ws_client.send_deregister("shutdown").await {
+                        warn!("⚠️ 发送注销消息失败: {}", e);
+                    }
+                }
                 // 关闭WebSocket连接
                 ws_client.close().await.ok();
                 break Ok(());
@@ -214,3 +441,153 @@ async fn start_monitoring_loop(
         }
     }
 }
+
+/// 记录允许远程执行的命令
+fn log_allowed_commands(config: &NodeConfig) {
+    if config.advanced.allowed_commands.is_empty() {
+        info!("🔒 未配置advanced.allowed_commands，拒绝执行所有远程命令");
+    } else {
+        info!("🔒 允许远程执行的命令: {}", config.advanced.allowed_commands.join(", "));
+    }
+}
+
+/// 解析执行远程命令使用的用户，未配置时命令以节点进程的用户运行
+fn resolve_run_as(config: &NodeConfig) -> Result<Option<RunAs>> {
+    let Some(user) = config.advanced.run_as_user() else {
+        return Ok(None);
+    };
+    let run_as = RunAs::resolve(user)?;
+    info!("👤 远程命令以用户 {} (uid={}, gid={}) 执行", run_as.name, run_as.uid, run_as.gid);
+    Ok(Some(run_as))
+}
+
+/// 创建首次触发在一个周期之后（再加随机抖动）的定时器（用于ping及热加载后重建的定时器，避免立即额外触发一次）
+fn live_interval(secs: u64, max_jitter: Duration) -> tokio::time::Interval {
+    let period = Duration::from_secs(secs);
+    ticker::jittered_interval(tokio::time::Instant::now() + period, period, max_jitter)
+}
+
+/// 采集并通过心跳上报监控数据，连接不可用或发送失败时缓存待重连后补发，返回是否已发送
+async fn send_metrics(
+    monitor: &mut SystemMonitor,
+    ws_client: &mut WebSocketClient,
+    metrics_buffer: &mut MetricsBuffer,
+    metrics_count: &mut u64,
+) -> bool {
+    let metrics = monitor.get_metrics();
+    *metrics_count += 1;
+    
+    if metrics_count.is_multiple_of(10) {
+        // 每10次采集记录一次详细日志
+        log_metrics(&metrics);
+    } else {
+        // 简要日志
+        info!("📊 监控数据 - CPU: {:.1}%, 内存: {:.1}%", 
+            metrics.cpu_usage,
+            metrics.memory_usage
+        );
+    }
+    
+    let collected_at = chrono::Utc::now();
+    let mut delivered = false;
+    if ws_client.is_connected() {
+        match ws_client.send_heartbeat(Some(&metrics)).await {
+            Ok(_) => delivered = true,
+            Err(e) => {
+                error!("❌ 发送监控数据失败: {}", e);
+                ws_client.close().await.ok();
+            }
+        }
+    }
+    if !delivered && metrics_buffer.push(collected_at, metrics) {
+        warn!("⚠️ 监控数据缓冲区已满，丢弃最旧的样本");
+    }
+    delivered
+}
+
+/// 补发断线期间缓存的监控数据
+async fn flush_metrics_buffer(ws_client: &mut WebSocketClient, buffer: &mut MetricsBuffer) {
+    const BATCH_SIZE: usize = 100;
+    
+    if buffer.is_empty() {
+        return;
+    }
+    info!("📦 补发缓存的监控数据: {}条", buffer.len());
+    
+    while !buffer.is_empty() {
+        let chunk = buffer.take_chunk(BATCH_SIZE);
+        if let Err(e) = ws_client.send_metrics_batch(&chunk).await {
+            error!("❌ 补发监控数据失败: {}", e);
+            buffer.restore(chunk);
+            ws_client.close().await.ok();
+            break;
+        }
+    }
+}
+
+/// 处理服务器消息，返回是否需要重新发送注册消息
+fn handle_server_message(
+    text: &str,
+    result_tx: &tokio::sync::mpsc::UnboundedSender<CommandEvent>,
+    command_timeout: Duration,
+    allowlist: &CommandAllowlist,
+    run_as: Option<&RunAs>,
+) -> bool {
+    let message: WebSocketMessage = match serde_json::from_str(text) {
+        Ok(message) => message,
+        Err(e) => {
+            warn!("⚠️ 无法解析服务器消息: {}", e);
+            return false;
+        }
+    };
+    
+    if message.message_type == "server_shutdown" {
+        warn!("⚠️ Core服务正在关闭，连接断开后将自动重连");
+        return false;
+    }
+    
+    if message.message_type == "register_required" {
+        warn!("⚠️ Core要求重新注册本节点");
+        return true;
+    }
+    
+    // Core修正或丢弃了上报的异常数值，通常说明采集逻辑有问题
+    if message.message_type == "metric_validation" {
+        warn!("⚠️ Core拒绝了部分监控数值: {}", message.data["issues"]);
+        return false;
+    }
+    
+    if message.message_type == "command" {
+        let request: CommandRequest = match serde_json::from_value(message.data) {
+            Ok(request) => request,
+            Err(e) => {
+                error!("❌ 命令消息格式错误: {}", e);
+                return false;
+            }
+        };
+        
+        // 不在允许列表中的命令直接返回失败结果，不执行
+        if !allowlist.permits(&request.command) {
+            warn!("🚫 拒绝执行不在允许列表中的命令 [{}]: {}", request.command_id, request.command);
+            result_tx.send(CommandEvent::Finished(CommandOutput {
+                command_id: request.command_id,
+                status: "failed".to_string(),
+                stdout: None,
+                stderr: Some("命令不被允许执行：不在节点的advanced.allowed_commands允许列表中".to_string()),
+                exit_code: None,
+                execution_time_ms: 0,
+                truncated: false,
+            })).ok();
+            return false;
+        }
+        
+        let result_tx = result_tx.clone();
+        let run_as = run_as.cloned();
+        tokio::spawn(async move {
+            let output = executor::execute_command(request, command_timeout, run_as.as_ref(), &result_tx).await;
+            result_tx.send(CommandEvent::Finished(output)).ok();
+        });
+    }
+    
+    false
+}
@@ -1,10 +1,16 @@
 use serde::Serialize;
-use sysinfo::{System, Disks};
+use std::collections::VecDeque;
+use std::time::Instant;
+use std::collections::HashSet;
+use sysinfo::{System, Disks, Networks, MINIMUM_CPU_UPDATE_INTERVAL};
 
 /// 系统监控数据
 #[derive(Debug, Serialize, Clone)]
 pub struct SystemMetrics {
+    /// CPU使用率（按平滑窗口取平均）
     pub cpu_usage: f64,
+    /// 本次采样的CPU使用率（未平滑）
+    pub cpu_usage_raw: f64,
     pub memory_usage: f64,
     pub memory_total: u64,
     pub memory_available: u64,
@@ -12,6 +18,14 @@ pub struct SystemMetrics {
     pub disk_total: Option<u64>,
     pub disk_available: Option<u64>,
     pub uptime: u64,
+    /// 所有非回环网卡累计接收的字节数（开机以来，重启后归零）
+    pub network_rx_bytes: u64,
+    /// 所有非回环网卡累计发送的字节数
+    pub network_tx_bytes: u64,
+    /// 所有磁盘累计读取的字节数，无法获取磁盘I/O统计时为None
+    pub disk_read_bytes: Option<u64>,
+    /// 所有磁盘累计写入的字节数
+    pub disk_write_bytes: Option<u64>,
 }
 
 /// 系统信息
@@ -26,37 +40,60 @@ pub struct SystemInfo {
     pub total_memory: u64,
 }
 
-/// 磁盘信息
-#[derive(Debug, Serialize, Clone)]
-pub struct DiskInfo {
-    pub name: String,
-    pub mount_point: String,
-    pub total_space: u64,
-    pub available_space: u64,
-    pub file_system: String,
-}
-
 /// 监控采集器
 pub struct SystemMonitor {
     sys: System,
     disks: Disks,
+    networks: Networks,
+    /// 最近的CPU使用率采样，用于平滑
+    cpu_samples: VecDeque<f64>,
+    /// CPU使用率平滑窗口大小
+    cpu_smoothing_window: usize,
+    /// 上次刷新CPU使用率的时间
+    last_cpu_refresh: Instant,
 }
 
 impl SystemMonitor {
     /// 创建新的监控采集器
+    ///
+    /// CPU使用率需要间隔一段时间的两次刷新才能计算，创建时先预热一次，避免首次上报的CPU使用率无意义（通常为0）
     pub fn new() -> Self {
         let mut sys = System::new_all();
         sys.refresh_all();
+        std::thread::sleep(MINIMUM_CPU_UPDATE_INTERVAL);
+        sys.refresh_cpu_usage();
         let disks = Disks::new_with_refreshed_list();
+        let networks = Networks::new_with_refreshed_list();
         
-        Self { sys, disks }
+        Self {
+            sys,
+            disks,
+            networks,
+            cpu_samples: VecDeque::new(),
+            cpu_smoothing_window: 1,
+            last_cpu_refresh: Instant::now(),
+        }
+    }
+    
+    /// 设置CPU使用率平滑窗口（最近N次采样取平均，1表示不平滑）
+    pub fn set_cpu_smoothing_window(&mut self, window: usize) {
+        self.cpu_smoothing_window = window.max(1);
+        while self.cpu_samples.len() > self.cpu_smoothing_window {
+            self.cpu_samples.pop_front();
+        }
     }
     
     /// 刷新系统信息
+    ///
+    /// 距上次刷新不足`MINIMUM_CPU_UPDATE_INTERVAL`时保留上次的CPU使用率，间隔过短的两次刷新得不到有效值
     pub fn refresh(&mut self) {
-        self.sys.refresh_cpu_all();
+        if self.last_cpu_refresh.elapsed() >= MINIMUM_CPU_UPDATE_INTERVAL {
+            self.sys.refresh_cpu_all();
+            self.last_cpu_refresh = Instant::now();
+        }
         self.sys.refresh_memory();
         self.disks.refresh(true);
+        self.networks.refresh(true);
     }
     
     /// 获取系统信息
@@ -80,8 +117,9 @@ impl SystemMonitor {
     pub fn get_metrics(&mut self) -> SystemMetrics {
         self.refresh();
         
-        // CPU使用率
-        let cpu_usage = self.calculate_cpu_usage();
+        // CPU使用率，单次采样受刷新时机影响较大，按窗口平滑后上报
+        let cpu_usage_raw = self.calculate_cpu_usage();
+        let cpu_usage = self.smooth_cpu_usage(cpu_usage_raw);
         
         // 内存使用率
         let memory_usage = self.calculate_memory_usage();
@@ -89,8 +127,12 @@ impl SystemMonitor {
         // 磁盘使用率（使用根分区）
         let disk_usage = self.calculate_disk_usage();
         
+        let (network_rx_bytes, network_tx_bytes) = self.calculate_network_bytes();
+        let disk_io = self.calculate_disk_io_bytes();
+        
         SystemMetrics {
             cpu_usage,
+            cpu_usage_raw,
             memory_usage,
             memory_total: self.sys.total_memory(),
             memory_available: self.calculate_available_memory(),
@@ -98,6 +140,10 @@ impl SystemMonitor {
             disk_total: disk_usage.map(|(_, total, _)| total),
             disk_available: disk_usage.map(|(_, _, available)| available),
             uptime: System::uptime(),
+            network_rx_bytes,
+            network_tx_bytes,
+            disk_read_bytes: disk_io.map(|(read, _)| read),
+            disk_write_bytes: disk_io.map(|(_, written)| written),
         }
     }
     
@@ -112,6 +158,15 @@ impl SystemMonitor {
         (total_usage / cpus.len() as f32) as f64
     }
     
+    /// 记录CPU采样并返回窗口内的平均值
+    fn smooth_cpu_usage(&mut self, sample: f64) -> f64 {
+        if self.cpu_samples.len() >= self.cpu_smoothing_window {
+            self.cpu_samples.pop_front();
+        }
+        self.cpu_samples.push_back(sample);
+        self.cpu_samples.iter().sum::<f64>() / self.cpu_samples.len() as f64
+    }
+    
     /// 计算内存使用率
     fn calculate_memory_usage(&self) -> f64 {
         let total_memory = self.sys.total_memory() as f64;
@@ -136,11 +191,7 @@ impl SystemMonitor {
             // 如果 available_memory() 返回0，则用 total - used 计算
             let total = self.sys.total_memory();
             let used = self.sys.used_memory();
-            if total > used {
-                total - used
-            } else {
-                0
-            }
+            total.saturating_sub(used)
         }
     }
     
@@ -167,17 +218,26 @@ impl SystemMonitor {
         None
     }
     
-    /// 获取所有磁盘信息
-    pub fn get_all_disks(&self) -> Vec<DiskInfo> {
-        self.disks.iter().map(|disk| {
-            DiskInfo {
-                name: disk.name().to_string_lossy().to_string(),
-                mount_point: disk.mount_point().to_string_lossy().to_string(),
-                total_space: disk.total_space(),
-                available_space: disk.available_space(),
-                file_system: disk.file_system().to_string_lossy().to_string(),
-            }
-        }).collect()
+    /// 计算所有非回环网卡累计收发的字节数
+    fn calculate_network_bytes(&self) -> (u64, u64) {
+        self.networks
+            .iter()
+            .filter(|(name, _)| !name.starts_with("lo"))
+            .fold((0, 0), |(rx, tx), (_, data)| {
+                (rx + data.total_received(), tx + data.total_transmitted())
+            })
+    }
+    
+    /// 计算所有磁盘累计读写的字节数（同一设备挂载多次时只统计一次）
+    fn calculate_disk_io_bytes(&self) -> Option<(u64, u64)> {
+        let mut devices = HashSet::new();
+        let mut total = None;
+        for disk in self.disks.iter().filter(|disk| devices.insert(disk.name().to_os_string())) {
+            let usage = disk.usage();
+            let (read, written) = total.unwrap_or((0, 0));
+            total = Some((read + usage.total_read_bytes, written + usage.total_written_bytes));
+        }
+        total
     }
 }
 
@@ -194,7 +254,7 @@ mod tests {
     #[test]
     fn test_monitor_creation() {
         let monitor = SystemMonitor::new();
-        assert!(monitor.sys.cpus().len() > 0);
+        assert!(!monitor.sys.cpus().is_empty());
     }
 
     #[test]
@@ -216,5 +276,28 @@ mod tests {
         assert!(metrics.cpu_usage >= 0.0 && metrics.cpu_usage <= 100.0);
         assert!(metrics.memory_usage >= 0.0 && metrics.memory_usage <= 100.0);
         assert!(metrics.memory_total > 0);
+        
+        // 累计计数器不会随采集减少
+        let next = monitor.get_metrics();
+        assert!(next.network_rx_bytes >= metrics.network_rx_bytes);
+        assert!(next.network_tx_bytes >= metrics.network_tx_bytes);
+    }
+
+    #[test]
+    fn test_cpu_smoothing() {
+        let mut monitor = SystemMonitor::new();
+        // 默认不平滑
+        assert_eq!(monitor.smooth_cpu_usage(100.0), 100.0);
+        assert_eq!(monitor.smooth_cpu_usage(0.0), 0.0);
+
+        monitor.set_cpu_smoothing_window(3);
+        assert_eq!(monitor.smooth_cpu_usage(30.0), 15.0);
+        assert_eq!(monitor.smooth_cpu_usage(60.0), 30.0);
+        // 超出窗口的旧采样被丢弃
+        assert_eq!(monitor.smooth_cpu_usage(90.0), 60.0);
+
+        // 缩小窗口时只保留最近的采样
+        monitor.set_cpu_smoothing_window(2);
+        assert_eq!(monitor.smooth_cpu_usage(10.0), 50.0);
     }
 }
@@ -0,0 +1,112 @@
+use anyhow::Result;
+use std::time::Duration;
+use tracing::info;
+
+use crate::config::NodeConfig;
+use crate::monitor::SystemMonitor;
+use crate::websocket::{WebSocketClient, WebSocketMessage};
+
+/// 等待Core响应的超时时间
+const REPLY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// 是否以单次模式运行（`--once`参数或`SM_NODE_ONCE=1`）
+pub fn once_requested(cli_flag: bool) -> bool {
+    let env_enabled = std::env::var("SM_NODE_ONCE")
+        .map(|value| matches!(value.trim().to_ascii_lowercase().as_str(), "1" | "true" | "yes" | "on"))
+        .unwrap_or(false);
+    cli_flag || env_enabled
+}
+
+/// 单次模式：连接、注册、发送一次心跳并输出Core的响应后退出，任一步骤失败时返回错误
+///
+/// 用于验证新部署的配置和连通性
+pub async fn run_once(config: NodeConfig, node_id: String, mut monitor: SystemMonitor) -> Result<()> {
+    info!("🧪 单次模式：发送一次监控数据后退出");
+    let mut ws_client = WebSocketClient::new(config, node_id);
+
+    ws_client.connect().await?;
+
+    ws_client.send_register_message(&monitor).await?;
+    let register_response = wait_for_reply(&mut ws_client, "register_response").await?;
+    println!("{}", register_response);
+
+    let metrics = monitor.get_metrics();
+    ws_client.send_heartbeat(Some(&metrics)).await?;
+    let heartbeat_ack = wait_for_reply(&mut ws_client, "heartbeat_ack").await?;
+    println!("{}", heartbeat_ack);
+
+    ws_client.close().await.ok();
+    info!("✅ 单次模式完成");
+    Ok(())
+}
+
+/// 等待指定类型的响应，跳过欢迎消息等无关消息
+async fn wait_for_reply(ws_client: &mut WebSocketClient, expected_type: &str) -> Result<serde_json::Value> {
+    let wait = async {
+        loop {
+            let Some(text) = ws_client.receive_message().await? else {
+                return Err(anyhow::anyhow!("等待{}时连接已关闭", expected_type));
+            };
+            if let Some(reply) = check_reply(&text, expected_type)? {
+                return Ok(reply);
+            }
+        }
+    };
+
+    tokio::time::timeout(REPLY_TIMEOUT, wait)
+        .await
+        .map_err(|_| anyhow::anyhow!("{}秒内未收到{}", REPLY_TIMEOUT.as_secs(), expected_type))?
+}
+
+/// 检查服务器消息：期望的响应返回其内容，错误或失败的响应返回错误，其他消息返回None
+fn check_reply(text: &str, expected_type: &str) -> Result<Option<serde_json::Value>> {
+    let message: WebSocketMessage = serde_json::from_str(text)?;
+    let data = &message.data;
+
+    if message.message_type == "error" {
+        return Err(anyhow::anyhow!(
+            "Core返回错误 {}: {} ({})",
+            data["error_code"].as_str().unwrap_or_default(),
+            data["message"].as_str().unwrap_or_default(),
+            data["details"].as_str().unwrap_or_default()
+        ));
+    }
+    if message.message_type != expected_type {
+        return Ok(None);
+    }
+
+    // register_response以success表示结果，heartbeat_ack以metrics_saved表示监控数据是否保存
+    let succeeded = match expected_type {
+        "heartbeat_ack" => data["metrics_saved"].as_bool(),
+        _ => data["success"].as_bool(),
+    };
+    if succeeded != Some(true) {
+        return Err(anyhow::anyhow!("{}表示失败: {}", expected_type, data));
+    }
+
+    Ok(Some(serde_json::to_value(&message)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_reply() {
+        let welcome = r#"{"type":"welcome","id":"1","timestamp":"2025-01-01T00:00:00Z","data":{"node_id":"n"}}"#;
+        assert!(check_reply(welcome, "register_response").unwrap().is_none());
+
+        let registered = r#"{"type":"register_response","id":"2","timestamp":"2025-01-01T00:00:00Z","data":{"success":true}}"#;
+        let reply = check_reply(registered, "register_response").unwrap().unwrap();
+        assert_eq!(reply["type"], "register_response");
+
+        let saved = r#"{"type":"heartbeat_ack","id":"3","timestamp":"2025-01-01T00:00:00Z","data":{"metrics_saved":true}}"#;
+        assert!(check_reply(saved, "heartbeat_ack").unwrap().is_some());
+        let not_saved = r#"{"type":"heartbeat_ack","id":"3","timestamp":"2025-01-01T00:00:00Z","data":{"metrics_saved":false}}"#;
+        assert!(check_reply(not_saved, "heartbeat_ack").is_err());
+
+        let error = r#"{"type":"error","id":"4","timestamp":"2025-01-01T00:00:00Z","data":{"error_code":"NODE_NOT_REGISTERED","message":"节点未注册","details":"n"}}"#;
+        let err = check_reply(error, "heartbeat_ack").unwrap_err();
+        assert!(err.to_string().contains("NODE_NOT_REGISTERED"));
+    }
+}
@@ -0,0 +1,229 @@
+use anyhow::Result;
+
+use crate::config::NodeConfig;
+
+/// 重新加载配置后的变化
+#[derive(Debug, Default)]
+pub struct ConfigChanges {
+    /// 变化描述（用于日志）
+    pub descriptions: Vec<String>,
+    /// 监控采集、心跳或ping间隔发生变化
+    pub intervals_changed: bool,
+    /// Core地址、令牌、证书或压缩设置发生变化，需要重新连接
+    pub connection_changed: bool,
+    /// 注册时上报的信息发生变化，需要重新发送注册消息
+    pub registration_changed: bool,
+    /// 需要重启才能生效的配置项
+    pub restart_required: Vec<&'static str>,
+}
+
+impl ConfigChanges {
+    /// 比较新旧配置
+    pub fn between(old: &NodeConfig, new: &NodeConfig) -> Self {
+        let mut changes = Self::default();
+
+        if old.monitoring.metrics_interval != new.monitoring.metrics_interval {
+            changes.intervals_changed = true;
+            changes.describe("monitoring.metrics_interval", old.monitoring.metrics_interval, new.monitoring.metrics_interval);
+        }
+        if old.monitoring.heartbeat_interval != new.monitoring.heartbeat_interval {
+            changes.intervals_changed = true;
+            changes.describe("monitoring.heartbeat_interval", old.monitoring.heartbeat_interval, new.monitoring.heartbeat_interval);
+        }
+        if old.monitoring.metrics_every_heartbeats != new.monitoring.metrics_every_heartbeats {
+            changes.intervals_changed = true;
+            changes.describe(
+                "monitoring.metrics_every_heartbeats",
+                old.monitoring.metrics_every_heartbeats,
+                new.monitoring.metrics_every_heartbeats,
+            );
+        }
+        if old.monitoring.interval_jitter != new.monitoring.interval_jitter {
+            changes.intervals_changed = true;
+            changes.describe("monitoring.interval_jitter", old.monitoring.interval_jitter, new.monitoring.interval_jitter);
+        }
+        if old.advanced.ping_interval != new.advanced.ping_interval {
+            changes.intervals_changed = true;
+            changes.describe("advanced.ping_interval", old.advanced.ping_interval, new.advanced.ping_interval);
+        }
+        if old.monitoring.cpu_smoothing_window != new.monitoring.cpu_smoothing_window {
+            changes.describe("monitoring.cpu_smoothing_window", old.monitoring.cpu_smoothing_window, new.monitoring.cpu_smoothing_window);
+        }
+        if old.advanced.pong_timeout != new.advanced.pong_timeout {
+            changes.describe("advanced.pong_timeout", old.advanced.pong_timeout, new.advanced.pong_timeout);
+        }
+        if old.advanced.reconnect_interval != new.advanced.reconnect_interval {
+            changes.describe("advanced.reconnect_interval", old.advanced.reconnect_interval, new.advanced.reconnect_interval);
+        }
+        if old.advanced.max_reconnect_interval != new.advanced.max_reconnect_interval {
+            changes.describe("advanced.max_reconnect_interval", old.advanced.max_reconnect_interval, new.advanced.max_reconnect_interval);
+        }
+        if old.advanced.max_retries != new.advanced.max_retries {
+            changes.describe("advanced.max_retries", old.advanced.max_retries, new.advanced.max_retries);
+        }
+        if old.advanced.requested_retention_days != new.advanced.requested_retention_days {
+            // 保留天数随注册消息上报
+            changes.registration_changed = true;
+            changes.describe(
+                "advanced.requested_retention_days",
+                format!("{:?}", old.advanced.requested_retention_days),
+                format!("{:?}", new.advanced.requested_retention_days),
+            );
+        }
+        if old.system.hostname_override() != new.system.hostname_override() {
+            changes.registration_changed = true;
+            changes.describe(
+                "system.hostname",
+                format!("{:?}", old.system.hostname_override()),
+                format!("{:?}", new.system.hostname_override()),
+            );
+        }
+        if old.system.report_system_info != new.system.report_system_info {
+            changes.registration_changed = true;
+            changes.describe("system.report_system_info", old.system.report_system_info, new.system.report_system_info);
+        }
+        if old.advanced.command_timeout != new.advanced.command_timeout {
+            changes.describe("advanced.command_timeout", old.advanced.command_timeout, new.advanced.command_timeout);
+        }
+        if old.advanced.run_as_user() != new.advanced.run_as_user() {
+            changes.describe(
+                "advanced.run_as_user",
+                format!("{:?}", old.advanced.run_as_user()),
+                format!("{:?}", new.advanced.run_as_user()),
+            );
+        }
+        if old.advanced.allowed_commands != new.advanced.allowed_commands {
+            changes.describe(
+                "advanced.allowed_commands",
+                format!("{:?}", old.advanced.allowed_commands),
+                format!("{:?}", new.advanced.allowed_commands),
+            );
+        }
+
+        if old.core.urls != new.core.urls {
+            changes.connection_changed = true;
+            changes.describe("core.url", old.core.urls.join(", "), new.core.urls.join(", "));
+        }
+        if old.core.token != new.core.token {
+            changes.connection_changed = true;
+            // 不在日志中输出令牌
+            changes.descriptions.push("core.token: 已更新".to_string());
+        }
+        if old.core.ca_cert != new.core.ca_cert {
+            changes.connection_changed = true;
+            changes.describe("core.ca_cert", format!("{:?}", old.core.ca_cert), format!("{:?}", new.core.ca_cert));
+        }
+
+        if old.advanced.compression != new.advanced.compression {
+            // 压缩在连接时协商
+            changes.connection_changed = true;
+            changes.describe("advanced.compression", old.advanced.compression, new.advanced.compression);
+        }
+
+        if old.advanced.encoding != new.advanced.encoding {
+            // 编码方式在连接时协商
+            changes.connection_changed = true;
+            changes.describe("advanced.encoding", old.advanced.encoding, new.advanced.encoding);
+        }
+
+        if old.core.node_id != new.core.node_id {
+            changes.restart_required.push("core.node_id");
+        }
+        if old.logging.level != new.logging.level
+            || old.logging.console_enabled != new.logging.console_enabled
+            || old.logging.file_enabled != new.logging.file_enabled
+            || old.logging.file_path != new.logging.file_path
+        {
+            changes.restart_required.push("logging");
+        }
+        if old.advanced.metrics_buffer_size != new.advanced.metrics_buffer_size {
+            changes.restart_required.push("advanced.metrics_buffer_size");
+        }
+        if old.advanced.data_dir != new.advanced.data_dir {
+            changes.restart_required.push("advanced.data_dir");
+        }
+
+        changes
+    }
+
+    /// 配置是否没有任何变化
+    pub fn is_empty(&self) -> bool {
+        self.descriptions.is_empty() && self.restart_required.is_empty()
+    }
+
+    fn describe(&mut self, key: &str, old: impl std::fmt::Display, new: impl std::fmt::Display) {
+        self.descriptions.push(format!("{}: {} -> {}", key, old, new));
+    }
+}
+
+/// 配置重新加载信号（Unix下为SIGHUP，其他平台永不触发）
+pub struct ReloadSignal {
+    #[cfg(unix)]
+    signal: tokio::signal::unix::Signal,
+}
+
+impl ReloadSignal {
+    pub fn new() -> Result<Self> {
+        Ok(Self {
+            #[cfg(unix)]
+            signal: tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup())?,
+        })
+    }
+
+    /// 等待下一次重新加载信号
+    pub async fn recv(&mut self) {
+        #[cfg(unix)]
+        {
+            if self.signal.recv().await.is_some() {
+                return;
+            }
+        }
+        std::future::pending::<()>().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_config_changes() {
+        let old = NodeConfig::default();
+        assert!(ConfigChanges::between(&old, &old.clone()).is_empty());
+
+        let mut new = old.clone();
+        new.monitoring.metrics_interval = 5;
+        let changes = ConfigChanges::between(&old, &new);
+        assert!(changes.intervals_changed);
+        assert!(!changes.connection_changed);
+        assert_eq!(changes.descriptions, vec!["monitoring.metrics_interval: 10 -> 5"]);
+
+        let mut new = old.clone();
+        new.monitoring.metrics_every_heartbeats = 4;
+        let changes = ConfigChanges::between(&old, &new);
+        assert!(changes.intervals_changed);
+        assert_eq!(changes.descriptions, vec!["monitoring.metrics_every_heartbeats: 0 -> 4"]);
+
+        let mut new = old.clone();
+        new.core.token = "new-token".to_string();
+        new.core.node_id = Some("renamed".to_string());
+        let changes = ConfigChanges::between(&old, &new);
+        assert!(changes.connection_changed);
+        assert!(!changes.intervals_changed);
+        assert!(!changes.descriptions.iter().any(|line| line.contains("new-token")));
+        assert_eq!(changes.restart_required, vec!["core.node_id"]);
+
+        let mut new = old.clone();
+        new.advanced.requested_retention_days = Some(30);
+        let changes = ConfigChanges::between(&old, &new);
+        assert!(changes.registration_changed);
+        assert!(!changes.connection_changed);
+        assert_eq!(changes.descriptions, vec!["advanced.requested_retention_days: None -> Some(30)"]);
+
+        let mut new = old.clone();
+        new.system.hostname = Some("web-01".to_string());
+        let changes = ConfigChanges::between(&old, &new);
+        assert!(changes.registration_changed);
+        assert_eq!(changes.descriptions, vec!["system.hostname: None -> Some(\"web-01\")"]);
+    }
+}
@@ -0,0 +1,95 @@
+use anyhow::Result;
+
+/// 执行远程命令使用的系统用户（`advanced.run_as_user`），节点以root运行时用于降低命令权限
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RunAs {
+    pub name: String,
+    pub uid: u32,
+    pub gid: u32,
+    pub home: String,
+}
+
+impl RunAs {
+    /// 按用户名或数字UID查找用户，并确认当前进程可以切换到该用户
+    #[cfg(unix)]
+    pub fn resolve(user: &str) -> Result<Self> {
+        let run_as = lookup_user(user)?.ok_or_else(|| anyhow::anyhow!("用户 \"{}\" 不存在", user))?;
+        if run_as.uid == 0 {
+            anyhow::bail!("不能使用root用户执行命令");
+        }
+        // SAFETY: geteuid没有前置条件，总是成功
+        let euid = unsafe { libc::geteuid() };
+        if euid != 0 && euid != run_as.uid {
+            anyhow::bail!("节点未以root运行，无法切换到用户 \"{}\"", run_as.name);
+        }
+        Ok(run_as)
+    }
+
+    #[cfg(not(unix))]
+    pub fn resolve(_user: &str) -> Result<Self> {
+        anyhow::bail!("仅支持Unix系统")
+    }
+
+    /// 设置子进程以该用户身份运行（root切换用户时标准库会同时清除附加组）
+    pub fn apply(&self, command: &mut tokio::process::Command) {
+        #[cfg(unix)]
+        command.uid(self.uid).gid(self.gid);
+        command
+            .env("HOME", &self.home)
+            .env("USER", &self.name)
+            .env("LOGNAME", &self.name);
+    }
+}
+
+/// 查询系统用户数据库（包括NSS配置的LDAP等来源），用户不存在时返回None
+#[cfg(unix)]
+fn lookup_user(user: &str) -> Result<Option<RunAs>> {
+    use std::ffi::{CStr, CString};
+
+    // SAFETY: passwd只包含整数和指针字段，全零是有效值，由getpw*_r填充
+    let mut passwd: libc::passwd = unsafe { std::mem::zeroed() };
+    let mut result: *mut libc::passwd = std::ptr::null_mut();
+    let mut buffer = vec![0 as libc::c_char; 16 * 1024];
+
+    // SAFETY: 所有指针在调用期间有效，buffer长度与传入的大小一致
+    let status = match user.parse::<u32>() {
+        Ok(uid) => unsafe {
+            libc::getpwuid_r(uid, &mut passwd, buffer.as_mut_ptr(), buffer.len(), &mut result)
+        },
+        Err(_) => {
+            let name = CString::new(user)?;
+            unsafe { libc::getpwnam_r(name.as_ptr(), &mut passwd, buffer.as_mut_ptr(), buffer.len(), &mut result) }
+        }
+    };
+    if status != 0 {
+        anyhow::bail!("查询用户 \"{}\" 失败: {}", user, std::io::Error::from_raw_os_error(status));
+    }
+    if result.is_null() {
+        return Ok(None);
+    }
+
+    // SAFETY: 查询成功时pw_name和pw_dir指向buffer中以NUL结尾的字符串
+    let text = |ptr: *const libc::c_char| unsafe { CStr::from_ptr(ptr) }.to_string_lossy().to_string();
+    Ok(Some(RunAs {
+        name: text(passwd.pw_name),
+        uid: passwd.pw_uid,
+        gid: passwd.pw_gid,
+        home: text(passwd.pw_dir),
+    }))
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_run_as_user() {
+        let root = lookup_user("root").unwrap().unwrap();
+        assert_eq!((root.uid, root.gid), (0, 0));
+        assert_eq!(lookup_user("0").unwrap(), Some(root));
+        assert!(lookup_user("sm-no-such-user").unwrap().is_none());
+
+        assert!(RunAs::resolve("root").unwrap_err().to_string().contains("root"));
+        assert!(RunAs::resolve("sm-no-such-user").unwrap_err().to_string().contains("不存在"));
+    }
+}
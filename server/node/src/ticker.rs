@@ -0,0 +1,37 @@
+use rand::Rng;
+use std::time::Duration;
+use tokio::time::{Instant, Interval};
+
+/// 定时器首次触发的随机相位偏移，在[0, min(max_jitter, period))内取值
+///
+/// 大量节点同时启动时（如Core重启或批量部署后），各节点的定时器会对齐并同时上报，
+/// 打散首次触发时间可以平滑Core的写入负载，之后仍按原周期触发，平均频率不变
+pub fn phase_offset(period: Duration, max_jitter: Duration) -> Duration {
+    let bound_ms = max_jitter.min(period).as_millis() as u64;
+    if bound_ms == 0 {
+        return Duration::ZERO;
+    }
+    Duration::from_millis(rand::rng().random_range(0..bound_ms))
+}
+
+/// 创建首次在`start`之后随机推迟触发的定时器
+pub fn jittered_interval(start: Instant, period: Duration, max_jitter: Duration) -> Interval {
+    tokio::time::interval_at(start + phase_offset(period, max_jitter), period)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_phase_offset() {
+        // 未启用抖动
+        assert_eq!(phase_offset(Duration::from_secs(10), Duration::ZERO), Duration::ZERO);
+
+        for _ in 0..100 {
+            assert!(phase_offset(Duration::from_secs(10), Duration::from_secs(3)) < Duration::from_secs(3));
+            // 偏移不超过一个周期
+            assert!(phase_offset(Duration::from_secs(1), Duration::from_secs(5)) < Duration::from_secs(1));
+        }
+    }
+}
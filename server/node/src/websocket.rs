@@ -1,24 +1,49 @@
 use anyhow::Result;
 use futures_util::{SinkExt, StreamExt};
-use serde::Serialize;
-use std::net::{IpAddr, UdpSocket};
+use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tokio::net::TcpStream;
-use tokio_tungstenite::{connect_async, tungstenite::protocol::Message, MaybeTlsStream, WebSocketStream};
-use tracing::{error, info, warn};
+use tokio_tungstenite::{
+    connect_async_tls_with_config, tungstenite::protocol::Message, Connector, MaybeTlsStream,
+    WebSocketStream,
+};
+use tracing::{debug, error, info, warn};
+use rustls::pki_types::{pem::PemObject, CertificateDer};
 use uuid::Uuid;
 
-use crate::config::NodeConfig;
-use crate::monitor::{SystemMetrics, SystemMonitor};
+use crate::buffer::BufferedMetrics;
+use crate::config::{MessageEncoding, NodeConfig};
+use crate::executor::{CommandOutput, CommandOutputChunk};
+use crate::monitor::{SystemInfo, SystemMetrics, SystemMonitor};
+
+/// 节点使用的WebSocket协议版本（与Core保持一致）
+pub const PROTOCOL_VERSION: u32 = 1;
 
 /// WebSocket客户端
 pub struct WebSocketClient {
     stream: Option<WebSocketStream<MaybeTlsStream<TcpStream>>>,
     config: NodeConfig,
     node_id: String,
+    /// 下次连接时优先尝试的Core地址（上次连接成功的地址）
+    endpoint_index: usize,
+    /// 最早一个尚未收到pong的ping发送时间
+    ping_sent_at: Option<Instant>,
+    /// Core已在欢迎消息中确认支持gzip压缩
+    compression: bool,
+    /// Core在欢迎消息中确认的消息编码方式
+    encoding: MessageEncoding,
+    /// Core已在欢迎消息中声明接受命令实时输出
+    stream_output: bool,
+    /// 尚未收到本次连接的欢迎消息
+    awaiting_welcome: bool,
+    /// Core在欢迎消息中签发的会话恢复令牌，断线重连时出示以延续会话
+    resume_token: Option<String>,
 }
 
 /// WebSocket消息格式（与Core服务保持一致）
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct WebSocketMessage {
     #[serde(rename = "type")]
     pub message_type: String,
@@ -34,135 +59,347 @@ impl WebSocketClient {
             stream: None,
             config,
             node_id,
+            endpoint_index: 0,
+            ping_sent_at: None,
+            compression: false,
+            encoding: MessageEncoding::Json,
+            stream_output: false,
+            awaiting_welcome: false,
+            resume_token: None,
         }
     }
 
     /// 连接到Core服务的WebSocket服务器
-    pub async fn connect(&mut self) -> Result<()> {
-        let url = self.config.get_websocket_url(&self.node_id);
-        info!("🔗 连接到WebSocket服务器: {}", url);
+    ///
+    /// 从上次连接成功的地址开始依次尝试所有配置的Core地址，全部失败时返回错误（优先返回可重试的错误）
+    pub async fn connect(&mut self) -> Result<(), WebSocketError> {
+        let mut last_error = None;
+
+        for index in self.endpoint_order() {
+            let endpoint = self.config.core.urls[index].clone();
+            match self.connect_endpoint(&endpoint).await {
+                Ok(()) => {
+                    self.endpoint_index = index;
+                    return Ok(());
+                }
+                Err(e) => {
+                    warn!("⚠️ 连接Core服务失败 {}: {}", endpoint, e);
+                    // 只要有一个地址可以重试，整体就应继续重连
+                    if e.is_retryable() || last_error.is_none() {
+                        last_error = Some(e);
+                    }
+                }
+            }
+        }
+
+        Err(last_error.unwrap_or(WebSocketError::NoEndpoint))
+    }
+
+    /// 连接单个Core地址
+    async fn connect_endpoint(&mut self, endpoint: &str) -> Result<(), WebSocketError> {
+        let url = self.config.get_websocket_url(endpoint, &self.node_id, self.resume_token.as_deref());
+        info!("🔗 连接到WebSocket服务器: {}", endpoint);
+
+        // wss连接使用显式的TLS配置，证书校验失败时直接报错，不会降级为明文连接
+        let connector = build_tls_connector(endpoint, self.config.core.ca_cert.as_deref())
+            .map_err(|e| WebSocketError::Tls(e.to_string()))?;
 
-        match connect_async(&url).await {
+        match connect_async_tls_with_config(&url, None, false, connector).await {
             Ok((ws_stream, response)) => {
-                info!("✅ WebSocket连接成功");
+                info!("✅ WebSocket连接成功: {}", endpoint);
                 info!("📡 服务器响应: {:?}", response.status());
                 
                 self.stream = Some(ws_stream);
+                self.ping_sent_at = None;
+                self.compression = false;
+                self.encoding = MessageEncoding::Json;
+                self.stream_output = false;
+                self.awaiting_welcome = true;
                 Ok(())
             }
             Err(e) => {
                 error!("❌ WebSocket连接失败: {}", e);
-                Err(anyhow::anyhow!("WebSocket连接失败: {}", e))
+                Err(WebSocketError::from_connect_error(e))
             }
         }
     }
 
+    /// 本次连接尝试Core地址的顺序：从优先地址开始轮转
+    fn endpoint_order(&self) -> impl Iterator<Item = usize> {
+        let count = self.config.core.urls.len();
+        let start = self.endpoint_index;
+        (0..count).map(move |offset| (start + offset) % count)
+    }
+
     /// 发送节点注册消息
-    pub async fn send_register_message(&mut self, monitor: &SystemMonitor) -> Result<()> {
+    pub async fn send_register_message(&mut self, monitor: &SystemMonitor) -> Result<(), WebSocketError> {
         let system_info = monitor.get_system_info();
         
-        // 获取本机IP地址
-        let ip_address = get_local_ip().unwrap_or_else(|| "127.0.0.1".to_string());
+        // 使用与Core通信的连接所在的本机地址
+        let ip_address = self
+            .stream
+            .as_ref()
+            .and_then(local_ip)
+            .map(|ip| ip.to_string())
+            .unwrap_or_else(|| "127.0.0.1".to_string());
         
         let message = WebSocketMessage {
             message_type: "node_register".to_string(),
             id: Uuid::new_v4().to_string(),
             timestamp: chrono::Utc::now().to_rfc3339(),
+            data: self.register_data(&system_info, &ip_address),
+        };
+
+        self.send_message(message).await
+    }
+
+    /// 构建注册消息数据：优先使用配置的主机名，关闭`report_system_info`时不上报系统详情
+    fn register_data(&self, system_info: &SystemInfo, ip_address: &str) -> serde_json::Value {
+        let system = &self.config.system;
+        let hostname = system.hostname_override().unwrap_or(&system_info.hostname);
+        let mut data = serde_json::json!({
+            "node_id": self.node_id,
+            "hostname": hostname,
+            "ip_address": ip_address,
+            "agent_version": env!("CARGO_PKG_VERSION"),
+            "metrics_retention_days": self.config.advanced.requested_retention_days.filter(|days| *days > 0),
+        });
+
+        if system.report_system_info {
+            data["os_info"] = serde_json::json!(format!("{} {}", system_info.os_name, system_info.os_version));
+            data["os_name"] = serde_json::json!(system_info.os_name);
+            data["os_version"] = serde_json::json!(system_info.os_version);
+            data["kernel_version"] = serde_json::json!(system_info.kernel_version);
+            data["cpu_count"] = serde_json::json!(system_info.cpu_count);
+            data["total_memory"] = serde_json::json!(system_info.total_memory);
+        }
+
+        data
+    }
+
+    /// 发送心跳消息，携带监控数据时由Core同时保存，不携带时只表示节点在线
+    pub async fn send_heartbeat(&mut self, metrics: Option<&SystemMetrics>) -> Result<(), WebSocketError> {
+        let mut data = serde_json::json!({
+            "node_id": self.node_id,
+            "status": "online",
+        });
+        if let Some(metrics) = metrics {
+            data["metrics"] = metrics_payload(metrics);
+        }
+        let message = WebSocketMessage {
+            message_type: "heartbeat".to_string(),
+            id: Uuid::new_v4().to_string(),
+            timestamp: chrono::Utc::now().to_rfc3339(),
+            data,
+        };
+
+        self.send_message(message).await
+    }
+
+    /// 发送注销消息，通知Core节点主动下线（不再重连）
+    pub async fn send_deregister(&mut self, reason: &str) -> Result<(), WebSocketError> {
+        let message = WebSocketMessage {
+            message_type: "node_deregister".to_string(),
+            id: Uuid::new_v4().to_string(),
+            timestamp: chrono::Utc::now().to_rfc3339(),
             data: serde_json::json!({
                 "node_id": self.node_id,
-                "hostname": system_info.hostname,
-                "ip_address": ip_address,
-                "os_info": format!("{} {}", system_info.os_name, system_info.os_version),
-                "cpu_count": system_info.cpu_count,
-                "total_memory": system_info.total_memory,
+                "reason": reason,
             }),
         };
 
         self.send_message(message).await
     }
 
-    /// 发送心跳消息（包含监控数据）
-    pub async fn send_heartbeat(&mut self, metrics: &SystemMetrics) -> Result<()> {
+    /// 批量发送断线期间缓存的监控数据
+    pub async fn send_metrics_batch(&mut self, samples: &[BufferedMetrics]) -> Result<(), WebSocketError> {
+        let metrics: Vec<serde_json::Value> = samples
+            .iter()
+            .map(|sample| {
+                let mut payload = metrics_payload(&sample.metrics);
+                payload["timestamp"] = serde_json::json!(sample.timestamp.to_rfc3339());
+                payload
+            })
+            .collect();
+
         let message = WebSocketMessage {
-            message_type: "heartbeat".to_string(),
+            message_type: "metrics_batch".to_string(),
             id: Uuid::new_v4().to_string(),
             timestamp: chrono::Utc::now().to_rfc3339(),
             data: serde_json::json!({
                 "node_id": self.node_id,
-                "status": "online",
-                "metrics": {
-                    "cpu_usage": metrics.cpu_usage,
-                    "memory_usage": metrics.memory_usage,
-                    "memory_total": metrics.memory_total,
-                    "memory_available": metrics.memory_available,
-                    "disk_usage": metrics.disk_usage,
-                    "disk_total": metrics.disk_total,
-                    "disk_available": metrics.disk_available,
-                    "uptime": metrics.uptime,
-                }
+                "metrics": metrics,
             }),
         };
 
         self.send_message(message).await
     }
 
+    /// 发送命令执行结果
+    pub async fn send_command_result(&mut self, output: &CommandOutput) -> Result<(), WebSocketError> {
+        let mut data = serde_json::to_value(output).map_err(|e| WebSocketError::Encode(e.to_string()))?;
+        data["node_id"] = serde_json::json!(self.node_id);
+
+        let message = WebSocketMessage {
+            message_type: "command_result".to_string(),
+            id: Uuid::new_v4().to_string(),
+            timestamp: chrono::Utc::now().to_rfc3339(),
+            data,
+        };
+
+        self.send_message(message).await
+    }
+
+    /// 发送命令的实时输出，Core未声明支持时不发送（完整输出仍包含在执行结果中）
+    pub async fn send_command_output(&mut self, chunk: &CommandOutputChunk) -> Result<(), WebSocketError> {
+        if !self.stream_output {
+            return Ok(());
+        }
+        let mut data = serde_json::to_value(chunk).map_err(|e| WebSocketError::Encode(e.to_string()))?;
+        data["node_id"] = serde_json::json!(self.node_id);
+
+        let message = WebSocketMessage {
+            message_type: "command_output".to_string(),
+            id: Uuid::new_v4().to_string(),
+            timestamp: chrono::Utc::now().to_rfc3339(),
+            data,
+        };
+
+        self.send_message(message).await
+    }
+
     /// 发送WebSocket消息
-    async fn send_message(&mut self, message: WebSocketMessage) -> Result<()> {
-        if let Some(stream) = &mut self.stream {
-            let json_message = serde_json::to_string(&message)?;
-            
-            match stream.send(Message::Text(json_message.into())).await {
-                Ok(_) => {
-                    info!("📤 消息发送成功: {}", message.message_type);
-                    Ok(())
-                }
-                Err(e) => {
-                    error!("❌ 消息发送失败: {}", e);
-                    Err(anyhow::anyhow!("消息发送失败: {}", e))
-                }
+    async fn send_message(&mut self, message: WebSocketMessage) -> Result<(), WebSocketError> {
+        let frame = encode_message(&message, self.encoding, self.compression)
+            .map_err(|e| WebSocketError::Encode(e.to_string()))?;
+        let Some(stream) = &mut self.stream else {
+            return Err(WebSocketError::NotConnected);
+        };
+
+        match stream.send(frame).await {
+            Ok(_) => {
+                info!("📤 消息发送成功: {}", message.message_type);
+                Ok(())
+            }
+            Err(e) => {
+                error!("❌ 消息发送失败: {}", e);
+                Err(WebSocketError::Send(e))
             }
-        } else {
-            Err(anyhow::anyhow!("WebSocket连接未建立"))
         }
     }
 
+    /// 发送WebSocket ping帧
+    ///
+    /// 上一个ping尚未收到pong时保留最早的发送时间，用于判断连接是否无响应
+    pub async fn send_ping(&mut self) -> Result<(), WebSocketError> {
+        let Some(stream) = &mut self.stream else {
+            return Err(WebSocketError::NotConnected);
+        };
+
+        stream
+            .send(Message::Ping(Vec::new().into()))
+            .await
+            .map_err(WebSocketError::Send)?;
+        self.ping_sent_at.get_or_insert_with(Instant::now);
+        debug!("🏓 发送ping");
+        Ok(())
+    }
+
+    /// 是否有ping超过指定时间仍未收到pong
+    pub fn pong_overdue(&self, timeout: Duration) -> bool {
+        self.ping_sent_at.is_some_and(|sent_at| sent_at.elapsed() >= timeout)
+    }
+
     /// 接收消息（用于处理服务器响应）
-    pub async fn receive_message(&mut self) -> Result<Option<String>> {
-        if let Some(stream) = &mut self.stream {
+    ///
+    /// 控制帧在内部处理，二进制消息解析后以JSON文本返回；返回None表示连接已关闭
+    pub async fn receive_message(&mut self) -> Result<Option<String>, WebSocketError> {
+        let Some(stream) = &mut self.stream else {
+            return Err(WebSocketError::NotConnected);
+        };
+
+        loop {
             match stream.next().await {
                 Some(Ok(message)) => {
-                    match message {
-                        Message::Text(text) => {
-                            info!("📥 收到消息: {}", text);
-                            Ok(Some(text.to_string()))
+                    let text = match message {
+                        Message::Text(text) => text.to_string(),
+                        Message::Binary(data) => match decode_binary_message(&data) {
+                            Ok(text) => text,
+                            Err(e) => {
+                                warn!("⚠️ 无法解析Core发送的二进制消息({}字节): {}", data.len(), e);
+                                continue;
+                            }
+                        },
+                        Message::Close(frame) => {
+                            match frame {
+                                Some(frame) => warn!("🔌 Core关闭连接: {} {}", u16::from(frame.code), frame.reason),
+                                None => info!("🔌 收到关闭消息"),
+                            }
+                            return Ok(None);
                         }
-                        Message::Close(_) => {
-                            info!("🔌 收到关闭消息");
-                            Ok(None)
+                        Message::Pong(_) => {
+                            if let Some(sent_at) = self.ping_sent_at.take() {
+                                debug!("🏓 收到pong，往返时间 {}ms", sent_at.elapsed().as_millis());
+                            }
+                            continue;
                         }
-                        _ => {
-                            warn!("⚠️ 收到未知类型的消息");
-                            Ok(None)
+                        // 服务器的ping由tungstenite自动回复pong，下次读写时发出
+                        Message::Ping(_) => {
+                            debug!("🏓 收到Core的ping");
+                            continue;
+                        }
+                        Message::Frame(_) => continue,
+                    };
+
+                    info!("📥 收到消息: {}", text);
+                    if self.awaiting_welcome {
+                        self.awaiting_welcome = false;
+                        if let Some(version) = welcome_protocol_version(&text) {
+                            if version < PROTOCOL_VERSION {
+                                warn!("⚠️ Core使用较旧的协议版本v{}（本节点v{}）", version, PROTOCOL_VERSION);
+                            }
+                        }
+                        self.compression = self.config.advanced.compression && welcome_accepts_gzip(&text);
+                        if self.compression {
+                            info!("🗜️ Core已确认启用gzip消息压缩");
+                        }
+                        self.stream_output = welcome_accepts_message(&text, "command_output");
+                        let (resume_token, resumed) = welcome_session(&text);
+                        if resumed {
+                            info!("🔁 Core已恢复断线前的会话");
+                        }
+                        self.resume_token = resume_token;
+                        if self.config.advanced.encoding == MessageEncoding::Msgpack {
+                            self.encoding = welcome_encoding(&text);
+                            match self.encoding {
+                                MessageEncoding::Msgpack => info!("📦 Core已确认使用MessagePack消息编码"),
+                                MessageEncoding::Json => warn!("⚠️ Core不支持MessagePack消息编码，继续使用JSON"),
+                            }
                         }
                     }
+                    return Ok(Some(text));
                 }
                 Some(Err(e)) => {
                     error!("❌ 接收消息错误: {}", e);
-                    Err(anyhow::anyhow!("接收消息错误: {}", e))
+                    return Err(WebSocketError::Receive(e));
                 }
                 None => {
                     info!("📭 连接已关闭");
-                    Ok(None)
+                    return Ok(None);
                 }
             }
-        } else {
-            Err(anyhow::anyhow!("WebSocket连接未建立"))
         }
     }
 
     /// 关闭WebSocket连接
-    pub async fn close(&mut self) -> Result<()> {
-        if let Some(stream) = &mut self.stream {
+    ///
+    /// 已建立的连接断开后，下次重连优先尝试下一个Core地址，避免反复连接已失效的地址
+    pub async fn close(&mut self) -> Result<(), WebSocketError> {
+        if let Some(mut stream) = self.stream.take() {
+            self.ping_sent_at = None;
+            let count = self.config.core.urls.len().max(1);
+            self.endpoint_index = (self.endpoint_index + 1) % count;
+
             match stream.close(None).await {
                 Ok(_) => {
                     info!("👋 WebSocket连接已关闭");
@@ -170,7 +407,7 @@ impl WebSocketClient {
                 }
                 Err(e) => {
                     error!("❌ 关闭连接失败: {}", e);
-                    Err(anyhow::anyhow!("关闭连接失败: {}", e))
+                    Err(WebSocketError::Send(e))
                 }
             }
         } else {
@@ -178,55 +415,232 @@ impl WebSocketClient {
         }
     }
 
+    /// 替换配置（用于配置热加载），从第一个Core地址重新开始尝试
+    pub fn update_config(&mut self, config: NodeConfig) {
+        self.config = config;
+        self.endpoint_index = 0;
+    }
+
     /// 检查连接状态
     pub fn is_connected(&self) -> bool {
         self.stream.is_some()
     }
+}
+
+/// WebSocket客户端错误
+#[derive(Debug, thiserror::Error)]
+pub enum WebSocketError {
+    /// 尚未建立连接
+    #[error("WebSocket连接未建立")]
+    NotConnected,
+    /// 没有配置任何Core地址
+    #[error("未配置Core服务地址")]
+    NoEndpoint,
+    /// TLS配置错误（如CA证书无法读取），修改配置前重试无效
+    #[error("TLS配置错误: {0}")]
+    Tls(String),
+    /// Core在握手阶段拒绝连接（如认证失败、来源地址不在允许范围内或协议版本不兼容）
+    #[error("Core拒绝连接: HTTP {0}")]
+    Rejected(u16),
+    /// 建立连接失败（网络不可达、连接被拒绝等）
+    #[error("WebSocket连接失败: {0}")]
+    Connect(tokio_tungstenite::tungstenite::Error),
+    /// 发送消息失败
+    #[error("消息发送失败: {0}")]
+    Send(tokio_tungstenite::tungstenite::Error),
+    /// 接收消息失败
+    #[error("接收消息错误: {0}")]
+    Receive(tokio_tungstenite::tungstenite::Error),
+    /// 消息序列化或压缩失败
+    #[error("消息编码失败: {0}")]
+    Encode(String),
+}
+
+impl WebSocketError {
+    /// 区分握手阶段被Core拒绝和其他连接错误
+    fn from_connect_error(error: tokio_tungstenite::tungstenite::Error) -> Self {
+        match error {
+            tokio_tungstenite::tungstenite::Error::Http(response) => WebSocketError::Rejected(response.status().as_u16()),
+            error => WebSocketError::Connect(error),
+        }
+    }
 
-    /// 获取节点ID
-    pub fn get_node_id(&self) -> &str {
-        &self.node_id
+    /// 重连是否可能成功：本地配置错误需要修改配置后才能恢复，其余错误（包括被Core拒绝）可能随Core端变化而恢复
+    pub fn is_retryable(&self) -> bool {
+        !matches!(self, WebSocketError::NoEndpoint | WebSocketError::Tls(_))
     }
 }
 
-/// WebSocket错误处理
-#[derive(Debug)]
-pub enum WebSocketError {
-    ConnectionError(String),
-    SendError(String),
-    ReceiveError(String),
+
+/// 小于该长度的消息压缩收益不明显，直接以文本发送
+const COMPRESSION_MIN_BYTES: usize = 128;
+
+/// 按协商的编码方式构建消息帧
+fn encode_message(message: &WebSocketMessage, encoding: MessageEncoding, compression: bool) -> Result<Message> {
+    Ok(match encoding {
+        MessageEncoding::Msgpack => Message::Binary(rmp_serde::to_vec_named(message)?.into()),
+        MessageEncoding::Json => {
+            let json_message = serde_json::to_string(message)?;
+            if compression && json_message.len() >= COMPRESSION_MIN_BYTES {
+                Message::Binary(gzip(json_message.as_bytes())?.into())
+            } else {
+                Message::Text(json_message.into())
+            }
+        }
+    })
+}
+
+/// gzip压缩消息内容
+fn gzip(data: &[u8]) -> Result<Vec<u8>> {
+    use std::io::Write;
+
+    let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(data)?;
+    Ok(encoder.finish()?)
 }
 
-impl std::fmt::Display for WebSocketError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            WebSocketError::ConnectionError(msg) => write!(f, "连接错误: {}", msg),
-            WebSocketError::SendError(msg) => write!(f, "发送错误: {}", msg),
-            WebSocketError::ReceiveError(msg) => write!(f, "接收错误: {}", msg),
+/// 二进制消息解压后的最大长度，防止压缩炸弹
+const MAX_BINARY_MESSAGE_BYTES: usize = 16 * 1024 * 1024;
+
+/// 解析Core发送的二进制消息，支持gzip压缩的JSON、UTF-8编码的JSON和MessagePack，统一转换为JSON文本
+fn decode_binary_message(data: &[u8]) -> Result<String> {
+    use std::io::Read;
+
+    if data.starts_with(&[0x1f, 0x8b]) {
+        let mut text = String::new();
+        flate2::read::GzDecoder::new(data)
+            .take(MAX_BINARY_MESSAGE_BYTES as u64 + 1)
+            .read_to_string(&mut text)?;
+        if text.len() > MAX_BINARY_MESSAGE_BYTES {
+            return Err(anyhow::anyhow!("解压后消息超过{}字节", MAX_BINARY_MESSAGE_BYTES));
+        }
+        return Ok(text);
+    }
+
+    if let Ok(text) = std::str::from_utf8(data) {
+        if serde_json::from_str::<WebSocketMessage>(text).is_ok() {
+            return Ok(text.to_string());
         }
     }
+
+    let message: WebSocketMessage = rmp_serde::from_slice(data)
+        .map_err(|e| anyhow::anyhow!("不是有效的JSON或MessagePack消息: {}", e))?;
+    Ok(serde_json::to_string(&message)?)
 }
 
-impl std::error::Error for WebSocketError {}
+/// 欢迎消息中Core是否确认了gzip压缩
+fn welcome_accepts_gzip(text: &str) -> bool {
+    serde_json::from_str::<WebSocketMessage>(text)
+        .map(|message| message.message_type == "welcome" && message.data["compression"] == "gzip")
+        .unwrap_or(false)
+}
 
+/// 欢迎消息的capabilities中Core是否声明接受指定类型的消息（旧版Core不返回capabilities）
+fn welcome_accepts_message(text: &str, message_type: &str) -> bool {
+    serde_json::from_str::<WebSocketMessage>(text)
+        .ok()
+        .filter(|message| message.message_type == "welcome")
+        .and_then(|message| {
+            message.data["capabilities"]["accepts"]
+                .as_array()
+                .map(|accepts| accepts.iter().any(|accepted| accepted == message_type))
+        })
+        .unwrap_or(false)
+}
 
-/// 获取本地IP地址
-fn get_local_ip() -> Option<String> {
-    // 尝试连接到外部地址来获取本地IP
-    let socket = match UdpSocket::bind("0.0.0.0:0") {
-        Ok(s) => s,
-        Err(_) => return None,
-    };
-    
-    match socket.connect("8.8.8.8:80") {
-        Ok(()) => (),
-        Err(_) => return None,
-    };
-    
-    match socket.local_addr() {
-        Ok(addr) => Some(addr.ip().to_string()),
-        Err(_) => None,
+/// 欢迎消息中Core确认的消息编码方式，旧版Core未确认时为JSON
+fn welcome_encoding(text: &str) -> MessageEncoding {
+    serde_json::from_str::<WebSocketMessage>(text)
+        .ok()
+        .filter(|message| message.message_type == "welcome" && message.data["encoding"] == "msgpack")
+        .map_or(MessageEncoding::Json, |_| MessageEncoding::Msgpack)
+}
+
+/// 读取欢迎消息中的会话恢复令牌及本次连接是否恢复了之前的会话（旧版Core不签发令牌）
+fn welcome_session(text: &str) -> (Option<String>, bool) {
+    serde_json::from_str::<WebSocketMessage>(text)
+        .ok()
+        .filter(|message| message.message_type == "welcome")
+        .map_or((None, false), |message| {
+            let token = message.data["resume_token"].as_str().map(str::to_string);
+            (token, message.data["resumed"] == true)
+        })
+}
+
+/// 读取欢迎消息中协商的协议版本（旧版Core不返回）
+fn welcome_protocol_version(text: &str) -> Option<u32> {
+    let message = serde_json::from_str::<WebSocketMessage>(text).ok()?;
+    if message.message_type != "welcome" {
+        return None;
+    }
+    message.data["protocol_version"].as_u64().map(|version| version as u32)
+}
+
+/// 监控数据的消息格式
+fn metrics_payload(metrics: &SystemMetrics) -> serde_json::Value {
+    serde_json::json!({
+        "cpu_usage": metrics.cpu_usage,
+        "memory_usage": metrics.memory_usage,
+        "memory_total": metrics.memory_total,
+        "memory_available": metrics.memory_available,
+        "disk_usage": metrics.disk_usage,
+        "disk_total": metrics.disk_total,
+        "disk_available": metrics.disk_available,
+        "uptime": metrics.uptime,
+        "network_rx_bytes": metrics.network_rx_bytes,
+        "network_tx_bytes": metrics.network_tx_bytes,
+        "disk_read_bytes": metrics.disk_read_bytes,
+        "disk_write_bytes": metrics.disk_write_bytes,
+    })
+}
+
+/// 根据配置构建TLS连接器（仅wss://需要）
+fn build_tls_connector(endpoint: &str, ca_cert: Option<&str>) -> Result<Option<Connector>> {
+    if !endpoint.starts_with("wss://") {
+        if ca_cert.is_some() {
+            warn!("⚠️ 已配置ca_cert，但Core地址不是wss://，证书配置将被忽略");
+        }
+        return Ok(None);
+    }
+
+    let mut root_store = rustls::RootCertStore::empty();
+    match ca_cert {
+        Some(path) => {
+            // 仅信任指定的CA证书
+            let certs = CertificateDer::pem_file_iter(path)
+                .map_err(|e| anyhow::anyhow!("读取CA证书失败 {}: {}", path, e))?;
+            for cert in certs {
+                let cert = cert.map_err(|e| anyhow::anyhow!("解析CA证书失败 {}: {}", path, e))?;
+                root_store.add(cert)?;
+            }
+            if root_store.is_empty() {
+                return Err(anyhow::anyhow!("CA证书文件中没有有效证书: {}", path));
+            }
+            info!("🔐 使用自定义CA证书: {}", path);
+        }
+        None => {
+            root_store.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+        }
     }
+
+    let tls_config = rustls::ClientConfig::builder_with_provider(Arc::new(
+        rustls::crypto::ring::default_provider(),
+    ))
+    .with_safe_default_protocol_versions()?
+    .with_root_certificates(root_store)
+    .with_no_client_auth();
+
+    Ok(Some(Connector::Rustls(Arc::new(tls_config))))
+}
+
+/// 获取连接Core所使用的本机地址（即通往Core的路由所在网卡的地址），不依赖外网连通性
+fn local_ip(stream: &WebSocketStream<MaybeTlsStream<TcpStream>>) -> Option<IpAddr> {
+    let tcp = match stream.get_ref() {
+        MaybeTlsStream::Plain(tcp) => tcp,
+        MaybeTlsStream::Rustls(tls) => tls.get_ref().0,
+        _ => return None,
+    };
+    tcp.local_addr().ok().map(|addr| addr.ip().to_canonical())
 }
 
 #[cfg(test)]
@@ -249,12 +663,256 @@ mod tests {
     }
 
     #[test]
-    fn test_get_local_ip() {
-        let ip = get_local_ip();
-        assert!(ip.is_some());
-        // IP地址应该不是回环地址
-        if let Some(ip_str) = ip {
-            assert_ne!(ip_str, "127.0.0.1");
+    fn test_build_tls_connector() {
+        // ws://不需要TLS
+        assert!(build_tls_connector("ws://example.com/api/v1/ws", None).unwrap().is_none());
+
+        // wss://默认使用内置根证书
+        assert!(build_tls_connector("wss://example.com/api/v1/ws", None).unwrap().is_some());
+
+        // CA证书不存在时明确报错
+        assert!(build_tls_connector("wss://example.com/api/v1/ws", Some("/nonexistent/ca.pem")).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_connect_error_kinds() {
+        // CA证书不存在属于配置错误，不再重试
+        let mut config = NodeConfig::default();
+        config.core.urls = vec!["wss://127.0.0.1:1/api/v1/ws".to_string()];
+        config.core.ca_cert = Some("/nonexistent/ca.pem".to_string());
+        let mut client = WebSocketClient::new(config, "test-node".to_string());
+        let err = client.connect().await.unwrap_err();
+        assert!(matches!(err, WebSocketError::Tls(_)));
+        assert!(!err.is_retryable());
+
+        // 连接被拒绝可以重试
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        drop(listener);
+        let mut config = NodeConfig::default();
+        config.core.urls = vec![format!("ws://{}/api/v1/ws", addr)];
+        let mut client = WebSocketClient::new(config, "test-node".to_string());
+        let err = client.connect().await.unwrap_err();
+        assert!(matches!(err, WebSocketError::Connect(_)));
+        assert!(err.is_retryable());
+
+        assert!(matches!(client.send_ping().await, Err(WebSocketError::NotConnected)));
+    }
+
+    #[tokio::test]
+    async fn test_endpoint_rotation() {
+        let mut config = NodeConfig::default();
+        config.core.urls = vec![
+            "ws://a/ws".to_string(),
+            "ws://b/ws".to_string(),
+            "ws://c/ws".to_string(),
+        ];
+        let mut client = WebSocketClient::new(config, "test-node".to_string());
+        assert_eq!(client.endpoint_order().collect::<Vec<_>>(), vec![0, 1, 2]);
+
+        // 记住上次成功的地址，并从它开始尝试
+        client.endpoint_index = 2;
+        assert_eq!(client.endpoint_order().collect::<Vec<_>>(), vec![2, 0, 1]);
+
+        // 未建立连接时关闭不改变优先地址
+        client.close().await.unwrap();
+        assert_eq!(client.endpoint_index, 2);
+    }
+
+    #[test]
+    fn test_welcome_accepts_gzip() {
+        let welcome = |compression: serde_json::Value| {
+            serde_json::json!({
+                "type": "welcome",
+                "id": "1",
+                "timestamp": "2025-01-01T00:00:00Z",
+                "data": { "node_id": "test", "compression": compression }
+            })
+            .to_string()
+        };
+
+        assert!(welcome_accepts_gzip(&welcome(serde_json::json!("gzip"))));
+        // 旧版Core的欢迎消息没有compression字段
+        assert!(!welcome_accepts_gzip(&welcome(serde_json::Value::Null)));
+        assert!(!welcome_accepts_gzip("not json"));
+    }
+
+    #[test]
+    fn test_welcome_accepts_message() {
+        let welcome = serde_json::json!({
+            "type": "welcome",
+            "id": "1",
+            "timestamp": "2025-01-01T00:00:00Z",
+            "data": { "node_id": "test", "capabilities": { "accepts": ["heartbeat", "command_output"] } }
+        })
+        .to_string();
+
+        assert!(welcome_accepts_message(&welcome, "command_output"));
+        assert!(!welcome_accepts_message(&welcome, "metrics_batch"));
+        // 旧版Core的欢迎消息没有capabilities字段
+        let legacy = r#"{"type":"welcome","id":"1","timestamp":"2025-01-01T00:00:00Z","data":{"node_id":"test"}}"#;
+        assert!(!welcome_accepts_message(legacy, "command_output"));
+    }
+
+    #[test]
+    fn test_decode_binary_message() {
+        let message = WebSocketMessage {
+            message_type: "command".to_string(),
+            id: "1".to_string(),
+            timestamp: "2025-01-01T00:00:00Z".to_string(),
+            data: serde_json::json!({"command_id": "c1", "command": "uptime"}),
+        };
+        let json = serde_json::to_string(&message).unwrap();
+        let parse = |text: String| serde_json::from_str::<serde_json::Value>(&text).unwrap();
+
+        assert_eq!(decode_binary_message(json.as_bytes()).unwrap(), json);
+        assert_eq!(decode_binary_message(&gzip(json.as_bytes()).unwrap()).unwrap(), json);
+        let packed = rmp_serde::to_vec_named(&message).unwrap();
+        assert_eq!(parse(decode_binary_message(&packed).unwrap()), parse(json));
+
+        assert!(decode_binary_message(&[0xc1, 0x00]).is_err());
+    }
+
+    #[test]
+    fn test_welcome_encoding() {
+        let welcome = |encoding: serde_json::Value| {
+            serde_json::json!({
+                "type": "welcome",
+                "id": "1",
+                "timestamp": "2025-01-01T00:00:00Z",
+                "data": { "node_id": "test", "encoding": encoding }
+            })
+            .to_string()
+        };
+
+        assert_eq!(welcome_encoding(&welcome(serde_json::json!("msgpack"))), MessageEncoding::Msgpack);
+        assert_eq!(welcome_encoding(&welcome(serde_json::json!("json"))), MessageEncoding::Json);
+        // 旧版Core的欢迎消息没有encoding字段
+        assert_eq!(welcome_encoding(&welcome(serde_json::Value::Null)), MessageEncoding::Json);
+    }
+
+    #[test]
+    fn test_welcome_session() {
+        let welcome = serde_json::json!({
+            "type": "welcome",
+            "id": "1",
+            "timestamp": "2025-01-01T00:00:00Z",
+            "data": { "node_id": "test", "resume_token": "session-2", "resumed": true }
+        });
+        assert_eq!(welcome_session(&welcome.to_string()), (Some("session-2".to_string()), true));
+
+        let legacy = r#"{"type":"welcome","id":"1","timestamp":"2025-01-01T00:00:00Z","data":{"node_id":"test"}}"#;
+        assert_eq!(welcome_session(legacy), (None, false));
+        assert_eq!(welcome_session("not json"), (None, false));
+    }
+
+    #[test]
+    fn test_welcome_protocol_version() {
+        let welcome = serde_json::json!({
+            "type": "welcome",
+            "id": "1",
+            "timestamp": "2025-01-01T00:00:00Z",
+            "data": { "node_id": "test", "protocol_version": 1 }
+        });
+        assert_eq!(welcome_protocol_version(&welcome.to_string()), Some(1));
+        // 旧版Core不返回协议版本
+        let legacy = serde_json::json!({
+            "type": "welcome",
+            "id": "1",
+            "timestamp": "2025-01-01T00:00:00Z",
+            "data": { "node_id": "test" }
+        });
+        assert_eq!(welcome_protocol_version(&legacy.to_string()), None);
+    }
+
+    #[tokio::test]
+    async fn test_ping_pong_tracking() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut ws = tokio_tungstenite::accept_async(stream).await.unwrap();
+            // 读取ping时自动回复pong，随后的发送会一并刷新
+            assert!(matches!(ws.next().await, Some(Ok(Message::Ping(_)))));
+            ws.send(Message::Text("hello".into())).await.unwrap();
+            ws.close(None).await.unwrap();
+        });
+
+        let mut config = NodeConfig::default();
+        config.core.urls = vec![format!("ws://{}/api/v1/ws", addr)];
+        let mut client = WebSocketClient::new(config, "test-node".to_string());
+        client.connect().await.unwrap();
+
+        client.send_ping().await.unwrap();
+        assert!(client.pong_overdue(Duration::ZERO));
+
+        // pong在内部处理，只返回文本消息和关闭
+        assert_eq!(client.receive_message().await.unwrap().as_deref(), Some("hello"));
+        assert_eq!(client.receive_message().await.unwrap(), None);
+        assert!(!client.pong_overdue(Duration::ZERO));
+
+        server.await.unwrap();
+    }
+
+    #[test]
+    fn test_register_data() {
+        let system_info = SystemInfo {
+            hostname: "detected".to_string(),
+            os_name: "Ubuntu".to_string(),
+            os_version: "22.04".to_string(),
+            kernel_version: "5.15.0".to_string(),
+            cpu_count: 4,
+            cpu_name: "cpu".to_string(),
+            total_memory: 1024,
+        };
+
+        let mut config = NodeConfig::default();
+        let client = WebSocketClient::new(config.clone(), "test-node".to_string());
+        let data = client.register_data(&system_info, "10.0.0.2");
+        assert_eq!(data["hostname"], "detected");
+        assert_eq!(data["os_name"], "Ubuntu");
+        assert_eq!(data["cpu_count"], 4);
+        assert!(data["metrics_retention_days"].is_null());
+
+        // 旧配置中遗留的metrics_retention_days不再上报，只有requested_retention_days生效
+        config.advanced.metrics_retention_days = Some(7);
+        let client = WebSocketClient::new(config.clone(), "test-node".to_string());
+        assert!(client.register_data(&system_info, "10.0.0.2")["metrics_retention_days"].is_null());
+        config.advanced.requested_retention_days = Some(30);
+        let client = WebSocketClient::new(config.clone(), "test-node".to_string());
+        assert_eq!(client.register_data(&system_info, "10.0.0.2")["metrics_retention_days"], 30);
+
+        // 配置的主机名优先，关闭系统信息上报时只保留必要字段
+        config.system.hostname = Some(" web-01 ".to_string());
+        config.system.report_system_info = false;
+        let client = WebSocketClient::new(config, "test-node".to_string());
+        let data = client.register_data(&system_info, "10.0.0.2");
+        assert_eq!(data["hostname"], "web-01");
+        assert_eq!(data["ip_address"], "10.0.0.2");
+        for field in ["os_info", "os_name", "os_version", "kernel_version", "cpu_count", "total_memory"] {
+            assert!(data.get(field).is_none(), "{} should be omitted", field);
         }
     }
+
+    #[tokio::test]
+    async fn test_local_ip() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut ws = tokio_tungstenite::accept_async(stream).await.unwrap();
+            ws.close(None).await.unwrap();
+        });
+
+        let mut config = NodeConfig::default();
+        config.core.urls = vec![format!("ws://{}/api/v1/ws", addr)];
+        let mut client = WebSocketClient::new(config, "test-node".to_string());
+        client.connect().await.unwrap();
+
+        // 连接本机Core时使用回环地址，无需访问外网
+        let ip = client.stream.as_ref().and_then(local_ip);
+        assert_eq!(ip, Some("127.0.0.1".parse().unwrap()));
+
+        server.await.unwrap();
+    }
 }